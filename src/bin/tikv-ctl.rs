@@ -53,6 +53,8 @@ use tikv::util::{escape, unescape};
 use tikv::util::security::{SecurityConfig, SecurityManager};
 use tikv::util::rocksdb as rocksdb_util;
 use tikv::raftstore::store::{keys, Engines};
+use tikv::raftstore::store::util as raftstore_util;
+use tikv::raftstore::store::log_engine::RaftLogEngine;
 use tikv::server::debug::{Debugger, RegionInfo};
 use tikv::storage::{CF_DEFAULT, CF_LOCK, CF_WRITE};
 use tikv::pd::{Config as PdConfig, PdClient, RpcClient};
@@ -94,9 +96,17 @@ fn new_debug_executor(
             let raft_db =
                 rocksdb_util::new_engine_opt(&raft_path, raft_db_opts, raft_db_cf_opts).unwrap();
 
-            Box::new(Debugger::new(
-                Engines::new(Arc::new(kv_db), Arc::new(raft_db)),
-            )) as Box<DebugExecutor>
+            let mut engines = Engines::new(Arc::new(kv_db), Arc::new(raft_db));
+            if cfg.raft_log_engine.enabled {
+                let mut log_engine_cfg = cfg.raft_log_engine.clone();
+                if log_engine_cfg.dir.is_empty() {
+                    log_engine_cfg.dir = format!("{}/../raft-log", kv_path);
+                }
+                let log_engine = RaftLogEngine::new(log_engine_cfg).unwrap();
+                engines.raft_log_engine = Some(Arc::new(log_engine));
+            }
+
+            Box::new(Debugger::new(engines)) as Box<DebugExecutor>
         }
         (Some(remote), None) => {
             let env = Arc::new(Environment::new(1));
@@ -174,8 +184,12 @@ trait DebugExecutor {
         println!("entry {:?}", entry);
         println!("msg len: {}", data.len());
 
+        // Large entries are compressed by the raftstore.
+        let data = raftstore_util::decompress_entry_data(&data)
+            .unwrap_or_else(|e| perror_and_exit("decompress entry data", e));
         let mut msg = RaftCmdRequest::new();
-        msg.merge_from_bytes(&data).unwrap();
+        msg.merge_from_bytes(&data)
+            .unwrap_or_else(|e| perror_and_exit("parse entry data", e));
         println!("{:?}", msg);
     }
 
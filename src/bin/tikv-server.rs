@@ -66,10 +66,11 @@ use tikv::util::transport::SendCh;
 use tikv::util::worker::FutureWorker;
 use tikv::util::io_limiter::IOLimiter;
 use tikv::storage::DEFAULT_ROCKSDB_SUB_DIR;
-use tikv::server::{create_raft_storage, Node, Server, DEFAULT_CLUSTER_ID};
+use tikv::server::{create_raft_storage, Node, Server, StatusServer, DEFAULT_CLUSTER_ID};
 use tikv::server::transport::ServerRaftStoreRouter;
 use tikv::server::resolve;
 use tikv::raftstore::store::{self, Engines, SnapManager};
+use tikv::raftstore::store::log_engine::{self, RaftLogEngine};
 use tikv::raftstore::coprocessor::CoprocessorHost;
 use tikv::pd::{PdClient, RpcClient};
 use tikv::util::time::Monitor;
@@ -164,11 +165,14 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     }
 
     // Initialize raftstore channels.
-    let mut event_loop = store::create_event_loop(&cfg.raft_store)
-        .unwrap_or_else(|e| fatal!("failed to create event loop: {:?}", e));
-    let store_sendch = SendCh::new(event_loop.channel(), "raftstore");
+    let event_loops = store::create_event_loops(&cfg.raft_store)
+        .unwrap_or_else(|e| fatal!("failed to create event loops: {:?}", e));
+    let store_sendch = SendCh::new(event_loops[0].channel(), "raftstore");
     let (significant_msg_sender, significant_msg_receiver) = mpsc::channel();
-    let raft_router = ServerRaftStoreRouter::new(store_sendch.clone(), significant_msg_sender);
+    let raft_router = ServerRaftStoreRouter::new(
+        store::create_router(&event_loops),
+        significant_msg_sender,
+    );
 
     // Create kv engine, storage.
     let kv_db_opts = cfg.rocksdb.build_opt();
@@ -190,11 +194,26 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
             raft_db_cf_opts,
         ).unwrap_or_else(|s| fatal!("failed to create raft engine: {:?}", s)),
     );
-    let engines = Engines::new(kv_engine.clone(), raft_engine.clone());
+    let mut engines = Engines::new(kv_engine.clone(), raft_engine.clone());
+    if cfg.raft_log_engine.enabled {
+        let raft_log_engine = RaftLogEngine::new(cfg.raft_log_engine.clone())
+            .unwrap_or_else(|e| fatal!("failed to create raft log engine: {:?}", e));
+        engines.raft_log_engine = Some(Arc::new(raft_log_engine));
+    } else if Path::new(&cfg.raft_log_engine.dir).exists() {
+        // The raft log engine is disabled, move raft logs back to the raft engine.
+        let raft_log_engine = RaftLogEngine::new(cfg.raft_log_engine.clone())
+            .unwrap_or_else(|e| fatal!("failed to create raft log engine: {:?}", e));
+        let count = log_engine::migrate_to_raft_db(&raft_log_engine, &raft_engine)
+            .unwrap_or_else(|e| fatal!("failed to migrate raft logs to raft engine: {:?}", e));
+        if count > 0 {
+            info!("migrate raft logs of {} regions back to raft engine", count);
+        }
+    }
 
     // Create pd client and pd work, snapshot manager, server.
     let pd_client = Arc::new(pd_client);
     let pd_worker = FutureWorker::new("pd worker");
+    let pd_scheduler = pd_worker.scheduler();
     let (mut worker, resolver) = resolve::new_resolver(pd_client.clone())
         .unwrap_or_else(|e| fatal!("failed to start address resolver: {:?}", e));
     let limiter = if cfg.server.snap_max_write_bytes_per_sec.0 > 0 {
@@ -226,13 +245,13 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     let trans = server.transport();
 
     // Create node.
-    let mut node = Node::new(&mut event_loop, &server_cfg, &cfg.raft_store, pd_client);
+    let mut node = Node::new(&event_loops, &server_cfg, &cfg.raft_store, pd_client);
 
     // Create CoprocessorHost.
-    let coprocessor_host = CoprocessorHost::new(cfg.coprocessor.clone(), node.get_sendch());
+    let coprocessor_host = CoprocessorHost::new(cfg.coprocessor.clone(), node.get_router());
 
     node.start(
-        event_loop,
+        event_loops,
         engines.clone(),
         trans,
         snap_mgr,
@@ -242,8 +261,11 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
     ).unwrap_or_else(|e| fatal!("failed to start node: {:?}", e));
     initial_metric(&cfg.metric, Some(node.id()));
 
+    let mut status_server = StatusServer::new(pd_scheduler.clone());
+
     // Start storage.
     info!("start storage");
+    storage.set_pd_sender(pd_scheduler);
     if let Err(e) = storage.start(&cfg.storage) {
         fatal!("failed to start storage, error: {:?}", e);
     }
@@ -258,6 +280,13 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
         error!("failed to start metrics flusher, error: {:?}", e);
     }
 
+    // Start the status server.
+    if !cfg.server.status_addr.is_empty() {
+        if let Err(e) = status_server.start(&cfg.server.status_addr) {
+            error!("failed to start status server, error: {:?}", e);
+        }
+    }
+
     // Run server.
     server
         .start(server_cfg, security_mgr)
@@ -270,6 +299,7 @@ fn run_raft_server(pd_client: RpcClient, cfg: &TiKvConfig, security_mgr: Arc<Sec
         .unwrap_or_else(|e| fatal!("failed to stop server: {:?}", e));
 
     metrics_flusher.stop();
+    status_server.stop();
 
     node.stop()
         .unwrap_or_else(|e| fatal!("failed to stop node: {:?}", e));
@@ -25,6 +25,7 @@ use pd::Config as PdConfig;
 use raftstore::coprocessor::Config as CopConfig;
 use raftstore::store::Config as RaftstoreConfig;
 use raftstore::store::keys::region_raft_prefix_len;
+use raftstore::store::log_engine::Config as RaftLogEngineConfig;
 use storage::{Config as StorageConfig, CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE, DEFAULT_DATA_DIR,
               DEFAULT_ROCKSDB_SUB_DIR};
 use util::config::{self, compression_type_level_serde, ReadableDuration, ReadableSize, GB, KB, MB};
@@ -646,6 +647,7 @@ pub struct TiKvConfig {
     pub coprocessor: CopConfig,
     pub rocksdb: DbConfig,
     pub raftdb: RaftDbConfig,
+    pub raft_log_engine: RaftLogEngineConfig,
     pub security: SecurityConfig,
 }
 
@@ -661,6 +663,7 @@ impl Default for TiKvConfig {
             pd: PdConfig::default(),
             rocksdb: DbConfig::default(),
             raftdb: RaftDbConfig::default(),
+            raft_log_engine: RaftLogEngineConfig::default(),
             storage: StorageConfig::default(),
             security: SecurityConfig::default(),
         }
@@ -698,10 +701,21 @@ impl TiKvConfig {
         if !db_exist(&kv_db_path) && db_exist(&self.raft_store.raftdb_path) {
             return Err("default rocksdb not exist, buf raftdb exist".into());
         }
+        self.raft_log_engine.dir = if self.raft_log_engine.dir.is_empty() {
+            config::canonicalize_sub_path(&self.storage.data_dir, "raft-log")?
+        } else {
+            config::canonicalize_path(&self.raft_log_engine.dir)?
+        };
+        if self.raft_log_engine.dir == self.raft_store.raftdb_path {
+            return Err(
+                "raft_log_engine.dir can not same with raft_store.raftdb_path".into(),
+            );
+        }
 
         self.rocksdb.validate()?;
         self.server.validate()?;
         self.raft_store.validate()?;
+        self.raft_log_engine.validate()?;
         self.pd.validate()?;
         self.coprocessor.validate()?;
         self.security.validate()?;
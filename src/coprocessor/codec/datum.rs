@@ -28,7 +28,8 @@ use util::codec::bytes::BytesEncoder;
 use coprocessor::select::xeval::EvalContext;
 use super::{convert, Result};
 use super::mysql::{self, parse_json_path_expr, Decimal, DecimalDecoder, DecimalEncoder, Duration,
-                   Json, JsonDecoder, JsonEncoder, PathExpression, Time, DEFAULT_FSP, MAX_FSP};
+                   Json, JsonDecoder, JsonEncoder, JsonRef, PathExpression, Time, DEFAULT_FSP,
+                   MAX_FSP};
 
 pub const NIL_FLAG: u8 = 0;
 const BYTES_FLAG: u8 = 1;
@@ -950,12 +951,7 @@ pub fn split_datum(buf: &[u8], desc: bool) -> Result<(&[u8], &[u8])> {
             v.decode_var_u64()?;
             l - v.len()
         }
-        JSON_FLAG => {
-            let mut v = &buf[1..];
-            let l = v.len();
-            v.decode_json()?;
-            l - v.len()
-        }
+        JSON_FLAG => JsonRef::new(&buf[1..])?.binary_len(),
         f => return Err(invalid_type!("unsupported data type `{}`", f)),
     };
     if buf.len() < pos + 1 {
@@ -964,6 +960,15 @@ pub fn split_datum(buf: &[u8], desc: bool) -> Result<(&[u8], &[u8])> {
     Ok(buf.split_at(1 + pos))
 }
 
+/// Returns the json binary of a datum encoded by `encode_value`, or `None` if
+/// the datum is not a json.
+pub fn json_binary(buf: &[u8]) -> Option<&[u8]> {
+    match buf.first() {
+        Some(&JSON_FLAG) => Some(&buf[1..]),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1734,6 +1739,13 @@ mod test {
                 let (act, rem) = split_datum(buf, false).unwrap();
                 let exp_bs = encode_value(as_slice(exp)).unwrap();
                 assert_eq!(exp_bs, act);
+                if let Datum::Json(ref j) = *exp {
+                    let mut binary = vec![];
+                    binary.encode_json(j).unwrap();
+                    assert_eq!(json_binary(act), Some(binary.as_slice()));
+                } else {
+                    assert_eq!(json_binary(act), None);
+                }
                 buf = rem;
             }
             assert!(buf.is_empty());
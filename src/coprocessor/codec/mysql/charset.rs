@@ -34,3 +34,23 @@ pub const COLLATION_LATIN1: &'static str = "latin1_bin";
 
 // All utf8 charsets.
 pub const UTF8_CHARSETS: &'static [&'static str] = &[CHARSET_UTF8, CHARSET_UTF8MB4, CHARSET_ASCII];
+
+// `COLLATION_ID_*` are the ids of collations in MySQL, which are carried by field types.
+pub const COLLATION_ID_UTF8_GENERAL_CI: i32 = 33;
+pub const COLLATION_ID_UTF8MB4_GENERAL_CI: i32 = 45;
+pub const COLLATION_ID_UTF8MB4_BIN: i32 = 46;
+pub const COLLATION_ID_BIN: i32 = 63;
+pub const COLLATION_ID_UTF8_BIN: i32 = 83;
+pub const COLLATION_ID_UTF8_UNICODE_CI: i32 = 192;
+pub const COLLATION_ID_UTF8MB4_UNICODE_CI: i32 = 224;
+
+/// Returns whether the collation compares strings case-insensitively.
+pub fn is_ci_collation(collation_id: i32) -> bool {
+    match collation_id {
+        COLLATION_ID_UTF8_GENERAL_CI |
+        COLLATION_ID_UTF8MB4_GENERAL_CI |
+        COLLATION_ID_UTF8_UNICODE_CI |
+        COLLATION_ID_UTF8MB4_UNICODE_CI => true,
+        _ => false,
+    }
+}
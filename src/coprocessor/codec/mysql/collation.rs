@@ -0,0 +1,156 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::str;
+
+use super::charset::{self, COLLATION_ID_UTF8MB4_UNICODE_CI, COLLATION_ID_UTF8_UNICODE_CI};
+
+// The weight MySQL gives to characters outside the basic multilingual plane.
+const WEIGHT_REPLACEMENT: u16 = 0xFFFD;
+
+/// Compares two strings under the collation.
+pub fn compare(collation_id: i32, lhs: &[u8], rhs: &[u8]) -> Ordering {
+    if !charset::is_ci_collation(collation_id) {
+        return lhs.cmp(rhs);
+    }
+    sort_key(collation_id, lhs).cmp(&sort_key(collation_id, rhs))
+}
+
+/// Returns the key of a string under the collation, two strings are equal
+/// under the collation iff their keys are equal, and the keys sort in the
+/// same order as the strings do.
+///
+/// For case-insensitive collations the key is the big-endian weights of the
+/// characters, with trailing spaces removed as MySQL pads strings with spaces
+/// before comparing them. `*_general_ci` folds case and the accents of
+/// Latin-1 letters; `*_unicode_ci` additionally expands `ß` and `æ` into two
+/// letters, which covers the common differences from full UCA weights.
+/// Strings which are not valid utf8 are compared as bytes.
+pub fn sort_key(collation_id: i32, s: &[u8]) -> Cow<[u8]> {
+    if !charset::is_ci_collation(collation_id) {
+        return Cow::Borrowed(s);
+    }
+    let s = match str::from_utf8(s) {
+        Ok(s) => s.trim_right_matches(' '),
+        Err(_) => return Cow::Borrowed(s),
+    };
+    let expand = collation_id == COLLATION_ID_UTF8_UNICODE_CI ||
+        collation_id == COLLATION_ID_UTF8MB4_UNICODE_CI;
+    let mut key = Vec::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        if expand {
+            match c {
+                'ß' => {
+                    push_weight(&mut key, 'S' as u16);
+                    push_weight(&mut key, 'S' as u16);
+                    continue;
+                }
+                'Æ' | 'æ' => {
+                    push_weight(&mut key, 'A' as u16);
+                    push_weight(&mut key, 'E' as u16);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        push_weight(&mut key, general_ci_weight(c));
+    }
+    Cow::Owned(key)
+}
+
+#[inline]
+fn push_weight(key: &mut Vec<u8>, weight: u16) {
+    key.push((weight >> 8) as u8);
+    key.push(weight as u8);
+}
+
+fn general_ci_weight(c: char) -> u16 {
+    let weight = match c {
+        'À'...'Å' | 'à'...'å' => 'A',
+        'Ç' | 'ç' => 'C',
+        'È'...'Ë' | 'è'...'ë' => 'E',
+        'Ì'...'Ï' | 'ì'...'ï' => 'I',
+        'Ñ' | 'ñ' => 'N',
+        'Ò'...'Ö' | 'ò'...'ö' => 'O',
+        'Ù'...'Ü' | 'ù'...'ü' => 'U',
+        'Ý' | 'ý' | 'ÿ' => 'Y',
+        'ß' => 'S',
+        'æ' => 'Æ',
+        'ð' => 'Ð',
+        'ø' => 'Ø',
+        'þ' => 'Þ',
+        _ => {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(u), None) => u,
+                _ => c,
+            }
+        }
+    };
+    let weight = weight as u32;
+    if weight > 0xFFFF {
+        WEIGHT_REPLACEMENT
+    } else {
+        weight as u16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use super::super::charset::*;
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let cases = vec![
+            (COLLATION_ID_UTF8MB4_BIN, "a", "A", Ordering::Greater),
+            (COLLATION_ID_UTF8MB4_BIN, "a ", "a", Ordering::Greater),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "a", "A", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "abc ", "ABC", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "Ä", "a", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "é", "E", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "a", "B", Ordering::Less),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "Z", "a", Ordering::Greater),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "ab", "a", Ordering::Greater),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "ß", "s", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "ß", "ss", Ordering::Less),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "Σ", "σ", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_GENERAL_CI, "😀", "😃", Ordering::Equal),
+            (COLLATION_ID_UTF8_GENERAL_CI, "Hello", "hELLO", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_UNICODE_CI, "ß", "ss", Ordering::Equal),
+            (COLLATION_ID_UTF8MB4_UNICODE_CI, "Æ", "ae", Ordering::Equal),
+            (COLLATION_ID_UTF8_UNICODE_CI, "straße", "STRASSE", Ordering::Equal),
+        ];
+        for (collation, lhs, rhs, exp) in cases {
+            let got = compare(collation, lhs.as_bytes(), rhs.as_bytes());
+            assert_eq!(got, exp, "{:?} cmp {:?} in {}", lhs, rhs, collation);
+            let got = compare(collation, rhs.as_bytes(), lhs.as_bytes());
+            assert_eq!(got, exp.reverse(), "{:?} cmp {:?} in {}", rhs, lhs, collation);
+        }
+    }
+
+    #[test]
+    fn test_sort_key() {
+        let key = sort_key(COLLATION_ID_UTF8MB4_GENERAL_CI, b"aB ");
+        assert_eq!(key.as_ref(), &[0, b'A', 0, b'B'][..]);
+        let key = sort_key(COLLATION_ID_UTF8MB4_BIN, b"aB ");
+        assert_eq!(key.as_ref(), b"aB ");
+        // invalid utf8 is kept as is.
+        let key = sort_key(COLLATION_ID_UTF8MB4_GENERAL_CI, b"\xffa");
+        assert_eq!(key.as_ref(), b"\xffa");
+    }
+}
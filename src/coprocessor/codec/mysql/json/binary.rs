@@ -21,27 +21,27 @@ use util::codec::number::{NumberDecoder, NumberEncoder};
 use super::super::Result;
 use super::{Json, ERR_CONVERT_FAILED};
 
-const TYPE_CODE_OBJECT: u8 = 0x01;
-const TYPE_CODE_ARRAY: u8 = 0x03;
-const TYPE_CODE_LITERAL: u8 = 0x04;
-const TYPE_CODE_I64: u8 = 0x09;
-const TYPE_CODE_U64: u8 = 0x0a;
-const TYPE_CODE_DOUBLE: u8 = 0x0b;
-const TYPE_CODE_STRING: u8 = 0x0c;
-
-const JSON_LITERAL_NIL: u8 = 0x00;
-const JSON_LITERAL_TRUE: u8 = 0x01;
-const JSON_LITERAL_FALSE: u8 = 0x02;
-
-const TYPE_LEN: usize = 1;
-const LITERAL_LEN: usize = 1;
-const U16_LEN: usize = 2;
-const U32_LEN: usize = 4;
-const NUMBER_LEN: usize = 8;
-const KEY_ENTRY_LEN: usize = U32_LEN + U16_LEN;
-const VALUE_ENTRY_LEN: usize = TYPE_LEN + U32_LEN;
-const ELEMENT_COUNT_LEN: usize = U32_LEN;
-const SIZE_LEN: usize = U32_LEN;
+pub const TYPE_CODE_OBJECT: u8 = 0x01;
+pub const TYPE_CODE_ARRAY: u8 = 0x03;
+pub const TYPE_CODE_LITERAL: u8 = 0x04;
+pub const TYPE_CODE_I64: u8 = 0x09;
+pub const TYPE_CODE_U64: u8 = 0x0a;
+pub const TYPE_CODE_DOUBLE: u8 = 0x0b;
+pub const TYPE_CODE_STRING: u8 = 0x0c;
+
+pub const JSON_LITERAL_NIL: u8 = 0x00;
+pub const JSON_LITERAL_TRUE: u8 = 0x01;
+pub const JSON_LITERAL_FALSE: u8 = 0x02;
+
+pub const TYPE_LEN: usize = 1;
+pub const LITERAL_LEN: usize = 1;
+pub const U16_LEN: usize = 2;
+pub const U32_LEN: usize = 4;
+pub const NUMBER_LEN: usize = 8;
+pub const KEY_ENTRY_LEN: usize = U32_LEN + U16_LEN;
+pub const VALUE_ENTRY_LEN: usize = TYPE_LEN + U32_LEN;
+pub const ELEMENT_COUNT_LEN: usize = U32_LEN;
+pub const SIZE_LEN: usize = U32_LEN;
 
 // The binary Json format from `MySQL` 5.7 is in the following link:
 // (https://github.com/mysql/mysql-server/blob/5.7/sql/json_binary.h#L52)
@@ -15,7 +15,8 @@ use std::cmp::Ordering;
 use std::f64;
 
 use super::super::Result;
-use super::{Json, JsonEncoder, ERR_CONVERT_FAILED};
+use super::binary::*;
+use super::{Json, JsonEncoder, JsonRef, ERR_CONVERT_FAILED};
 
 const PRECEDENCE_BLOB: i32 = -1;
 const PRECEDENCE_BIT: i32 = -2;
@@ -126,6 +127,106 @@ impl PartialOrd for Json {
     }
 }
 
+impl<'a> JsonRef<'a> {
+    fn get_precedence(&self) -> i32 {
+        match self.get_type_code() {
+            TYPE_CODE_OBJECT => PRECEDENCE_OBJECT,
+            TYPE_CODE_ARRAY => PRECEDENCE_ARRAY,
+            TYPE_CODE_I64 | TYPE_CODE_U64 | TYPE_CODE_DOUBLE => PRECEDENCE_NUMBER,
+            TYPE_CODE_STRING => PRECEDENCE_STRING,
+            _ => if self.get_literal() == JSON_LITERAL_NIL {
+                PRECEDENCE_NULL
+            } else {
+                PRECEDENCE_BOOLEAN
+            },
+        }
+    }
+
+    // as_f64 follows `Json::as_f64`, booleans are converted from their literals.
+    fn as_f64(&self) -> Result<f64> {
+        match self.get_type_code() {
+            TYPE_CODE_DOUBLE => self.get_double(),
+            TYPE_CODE_I64 => self.get_i64().map(|d| d as f64),
+            TYPE_CODE_U64 => self.get_u64().map(|d| d as f64),
+            TYPE_CODE_LITERAL if self.get_literal() != JSON_LITERAL_NIL => {
+                Ok(self.get_literal() as f64)
+            }
+            _ => Err(invalid_type!(
+                "{:?} from json type {} to f64",
+                ERR_CONVERT_FAILED,
+                self.get_type_code()
+            )),
+        }
+    }
+
+    fn cmp_same_precedence(&self, right: &JsonRef) -> Result<Ordering> {
+        let ord = match self.get_precedence() {
+            PRECEDENCE_NUMBER => {
+                let left_data = self.as_f64()?;
+                let right_data = right.as_f64()?;
+                if (left_data - right_data).abs() < f64::EPSILON {
+                    Ordering::Equal
+                } else {
+                    left_data.partial_cmp(&right_data).unwrap_or(Ordering::Equal)
+                }
+            }
+            PRECEDENCE_BOOLEAN => {
+                let left_data = self.get_literal() == JSON_LITERAL_TRUE;
+                let right_data = right.get_literal() == JSON_LITERAL_TRUE;
+                left_data.cmp(&right_data)
+            }
+            PRECEDENCE_STRING => self.get_str()?.cmp(right.get_str()?),
+            PRECEDENCE_ARRAY => {
+                let left_count = self.get_elem_count()?;
+                let right_count = right.get_elem_count()?;
+                for i in 0..left_count.min(right_count) {
+                    let left_elem = self.array_get_elem(i)?;
+                    let right_elem = right.array_get_elem(i)?;
+                    match left_elem.cmp_json(&right_elem)? {
+                        Ordering::Equal => {}
+                        ord => return Ok(ord),
+                    }
+                }
+                left_count.cmp(&right_count)
+            }
+            // Objects are compared by their binary as `Json` does.
+            PRECEDENCE_OBJECT => self.get_body().cmp(right.get_body()),
+            _ => Ordering::Equal,
+        };
+        Ok(ord)
+    }
+
+    /// Compares two json values by the same rules as `Json`, without
+    /// decoding them.
+    pub fn cmp_json(&self, right: &JsonRef) -> Result<Ordering> {
+        let precedence_diff = self.get_precedence() - right.get_precedence();
+        if precedence_diff == 0 {
+            return self.cmp_same_precedence(right);
+        }
+        if let (Ok(left), Ok(right)) = (self.as_f64(), right.as_f64()) {
+            return Ok(left.partial_cmp(&right).unwrap_or(Ordering::Equal));
+        }
+        if precedence_diff > 0 {
+            Ok(Ordering::Greater)
+        } else {
+            Ok(Ordering::Less)
+        }
+    }
+}
+
+impl<'a, 'b> PartialEq<JsonRef<'b>> for JsonRef<'a> {
+    fn eq(&self, right: &JsonRef<'b>) -> bool {
+        self.partial_cmp(right) == Some(Ordering::Equal)
+    }
+}
+
+impl<'a, 'b> PartialOrd<JsonRef<'b>> for JsonRef<'a> {
+    // Values which can't be compared because of a corrupted binary are unordered.
+    fn partial_cmp(&self, right: &JsonRef<'b>) -> Option<Ordering> {
+        self.cmp_json(right).ok()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -172,4 +273,48 @@ mod test {
 
         assert_eq!(Json::I64(2), Json::Boolean(false));
     }
+
+    #[test]
+    fn test_cmp_json_ref() {
+        let jsons = vec![
+            "null",
+            "true",
+            "false",
+            "-3",
+            "2",
+            "2.0",
+            "18446744073709551615",
+            r#""hello""#,
+            r#""hello, world""#,
+            r#"["a", "b"]"#,
+            r#"["a", "c"]"#,
+            r#"["a"]"#,
+            r#"[1, [2, 3]]"#,
+            r#"[1, [2, 4]]"#,
+            r#"{"a": "b"}"#,
+            r#"{"a": "c"}"#,
+        ];
+        let jsons: Vec<Json> = jsons.into_iter().map(|s| s.parse().unwrap()).collect();
+        let bufs: Vec<Vec<u8>> = jsons
+            .iter()
+            .map(|j| {
+                let mut buf = vec![];
+                buf.encode_json(j).unwrap();
+                buf
+            })
+            .collect();
+        for (left, left_buf) in jsons.iter().zip(&bufs) {
+            for (right, right_buf) in jsons.iter().zip(&bufs) {
+                let left_ref = JsonRef::new(left_buf).unwrap();
+                let right_ref = JsonRef::new(right_buf).unwrap();
+                assert_eq!(
+                    left_ref.cmp_json(&right_ref).unwrap(),
+                    left.cmp(right),
+                    "{:?} cmp {:?}",
+                    left,
+                    right
+                );
+            }
+        }
+    }
 }
@@ -14,7 +14,8 @@
 // FIXME: remove following later
 #![allow(dead_code)]
 
-use super::Json;
+use super::super::Result;
+use super::{Json, JsonRef};
 use super::path_expr::{PathExpression, PathLeg, PATH_EXPR_ARRAY_INDEX_ASTERISK, PATH_EXPR_ASTERISK};
 
 impl Json {
@@ -83,10 +84,83 @@ pub fn extract_json(j: &Json, path_legs: &[PathLeg]) -> Vec<Json> {
     ret
 }
 
+impl<'a> JsonRef<'a> {
+    // extract is the same as `Json::extract`, but it walks the binary and only
+    // decodes the values matched by the path expressions.
+    pub fn extract(&self, path_expr_list: &[PathExpression]) -> Result<Option<Json>> {
+        let mut elem_list = Vec::with_capacity(path_expr_list.len());
+        for path_expr in path_expr_list {
+            extract_json_ref(*self, &path_expr.legs, &mut elem_list)?;
+        }
+        if elem_list.is_empty() {
+            return Ok(None);
+        }
+        if path_expr_list.len() == 1 && elem_list.len() == 1 {
+            return elem_list[0].to_json().map(Some);
+        }
+        let mut array = Vec::with_capacity(elem_list.len());
+        for elem in elem_list {
+            array.push(elem.to_json()?);
+        }
+        Ok(Some(Json::Array(array)))
+    }
+}
+
+// extract_json_ref appends the values in j matched by path_legs to ret, it
+// follows the same rules as extract_json.
+pub fn extract_json_ref<'a>(
+    j: JsonRef<'a>,
+    path_legs: &[PathLeg],
+    ret: &mut Vec<JsonRef<'a>>,
+) -> Result<()> {
+    if path_legs.is_empty() {
+        ret.push(j);
+        return Ok(());
+    }
+    let (current_leg, sub_path_legs) = (&path_legs[0], &path_legs[1..]);
+    match *current_leg {
+        PathLeg::Index(i) => if j.is_array() {
+            let count = j.get_elem_count()?;
+            if i == PATH_EXPR_ARRAY_INDEX_ASTERISK {
+                for k in 0..count {
+                    extract_json_ref(j.array_get_elem(k)?, sub_path_legs, ret)?;
+                }
+            } else if (i as usize) < count {
+                extract_json_ref(j.array_get_elem(i as usize)?, sub_path_legs, ret)?;
+            }
+        } else if (i == PATH_EXPR_ARRAY_INDEX_ASTERISK) || (i as usize == 0) {
+            extract_json_ref(j, sub_path_legs, ret)?;
+        },
+        PathLeg::Key(ref key) => if j.is_object() {
+            if key == PATH_EXPR_ASTERISK {
+                for k in 0..j.get_elem_count()? {
+                    extract_json_ref(j.object_get_val(k)?, sub_path_legs, ret)?;
+                }
+            } else if let Some(v) = j.object_get(key)? {
+                extract_json_ref(v, sub_path_legs, ret)?;
+            }
+        },
+        PathLeg::DoubleAsterisk => {
+            extract_json_ref(j, sub_path_legs, ret)?;
+            if j.is_array() {
+                for k in 0..j.get_elem_count()? {
+                    extract_json_ref(j.array_get_elem(k)?, path_legs, ret)?;
+                }
+            } else if j.is_object() {
+                for k in 0..j.get_elem_count()? {
+                    extract_json_ref(j.object_get_val(k)?, path_legs, ret)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
     use super::*;
+    use super::super::JsonEncoder;
     use super::super::path_expr::{PathExpressionFlag, PATH_EXPRESSION_CONTAINS_ASTERISK,
                                   PATH_EXPRESSION_CONTAINS_DOUBLE_ASTERISK,
                                   PATH_EXPR_ARRAY_INDEX_ASTERISK};
@@ -221,6 +295,11 @@ mod test {
                 expected,
                 got
             );
+
+            let mut buf = vec![];
+            buf.encode_json(&j).unwrap();
+            let got = JsonRef::new(&buf).unwrap().extract(&exprs[..]).unwrap();
+            assert_eq!(got, expected, "#{} extract from binary", i);
         }
     }
 }
@@ -0,0 +1,274 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::io::{self, ErrorKind};
+use std::str;
+
+use util::codec::Error;
+use util::codec::number::NumberDecoder;
+
+use super::super::Result;
+use super::binary::*;
+use super::{Json, JsonDecoder};
+
+/// `JsonRef` is a borrowed view over a json value in the binary format
+/// written by `JsonEncoder`. Unlike `Json` nothing is decoded until it is
+/// asked for, so reading a single field of a large document only touches
+/// the bytes on the way to it.
+#[derive(Clone, Copy, Debug)]
+pub struct JsonRef<'a> {
+    type_code: u8,
+    // The body of the value, for objects and arrays it starts with the
+    // element count, and all the offsets inside are relative to it.
+    value: &'a [u8],
+}
+
+impl<'a> JsonRef<'a> {
+    /// Creates a view over `data`, which is a type code followed by the body
+    /// of a value. Bytes after the value are ignored.
+    pub fn new(data: &'a [u8]) -> Result<JsonRef<'a>> {
+        if data.is_empty() {
+            return Err(truncated());
+        }
+        JsonRef::from_body(data[0], &data[TYPE_LEN..])
+    }
+
+    fn from_body(type_code: u8, data: &'a [u8]) -> Result<JsonRef<'a>> {
+        let len = match type_code {
+            TYPE_CODE_OBJECT | TYPE_CODE_ARRAY => {
+                let mut size = slice(data, ELEMENT_COUNT_LEN, SIZE_LEN)?;
+                size.decode_u32_le()? as usize
+            }
+            TYPE_CODE_LITERAL => LITERAL_LEN,
+            TYPE_CODE_I64 | TYPE_CODE_U64 | TYPE_CODE_DOUBLE => NUMBER_LEN,
+            TYPE_CODE_STRING => {
+                let mut d = data;
+                let str_len = d.decode_var_u64()? as usize;
+                data.len() - d.len() + str_len
+            }
+            _ => return Err(invalid_type!("unsupported type {:?}", type_code)),
+        };
+        Ok(JsonRef {
+            type_code: type_code,
+            value: slice(data, 0, len)?,
+        })
+    }
+
+    pub fn get_type_code(&self) -> u8 {
+        self.type_code
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.type_code == TYPE_CODE_OBJECT
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.type_code == TYPE_CODE_ARRAY
+    }
+
+    /// Returns the number of elements of an object or an array.
+    pub fn get_elem_count(&self) -> Result<usize> {
+        let mut d = self.value;
+        Ok(d.decode_u32_le()? as usize)
+    }
+
+    /// Returns the `i`th element of an array.
+    pub fn array_get_elem(&self, i: usize) -> Result<JsonRef<'a>> {
+        self.val_entry_get(ELEMENT_COUNT_LEN + SIZE_LEN + i * VALUE_ENTRY_LEN)
+    }
+
+    /// Returns the `i`th key of an object, keys are in ascending order.
+    pub fn object_get_key(&self, i: usize) -> Result<&'a str> {
+        let mut entry = slice(
+            self.value,
+            ELEMENT_COUNT_LEN + SIZE_LEN + i * KEY_ENTRY_LEN,
+            KEY_ENTRY_LEN,
+        )?;
+        let offset = entry.decode_u32_le()? as usize;
+        let len = entry.decode_u16_le()? as usize;
+        let key = str::from_utf8(slice(self.value, offset, len)?)?;
+        Ok(key)
+    }
+
+    /// Returns the value of the `i`th key of an object.
+    pub fn object_get_val(&self, i: usize) -> Result<JsonRef<'a>> {
+        let count = self.get_elem_count()?;
+        let offset = ELEMENT_COUNT_LEN + SIZE_LEN + count * KEY_ENTRY_LEN + i * VALUE_ENTRY_LEN;
+        self.val_entry_get(offset)
+    }
+
+    /// Looks up `key` in an object by a binary search over its sorted keys,
+    /// returns the index of the key if it exists.
+    pub fn object_search_key(&self, key: &str) -> Result<Option<usize>> {
+        let (mut lo, mut hi) = (0, self.get_elem_count()?);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.object_get_key(mid)?.cmp(key) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(Some(mid)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the value of `key` in an object.
+    pub fn object_get(&self, key: &str) -> Result<Option<JsonRef<'a>>> {
+        match self.object_search_key(key)? {
+            Some(i) => self.object_get_val(i).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn val_entry_get(&self, entry_offset: usize) -> Result<JsonRef<'a>> {
+        let mut entry = slice(self.value, entry_offset, VALUE_ENTRY_LEN)?;
+        let type_code = entry[0];
+        entry = &entry[TYPE_LEN..];
+        if type_code == TYPE_CODE_LITERAL {
+            return Ok(JsonRef {
+                type_code: type_code,
+                value: &entry[..LITERAL_LEN],
+            });
+        }
+        let offset = entry.decode_u32_le()? as usize;
+        if offset > self.value.len() {
+            return Err(truncated());
+        }
+        JsonRef::from_body(type_code, &self.value[offset..])
+    }
+
+    pub fn get_i64(&self) -> Result<i64> {
+        let mut d = self.value;
+        d.decode_i64_le()
+    }
+
+    pub fn get_u64(&self) -> Result<u64> {
+        let mut d = self.value;
+        d.decode_u64_le()
+    }
+
+    pub fn get_double(&self) -> Result<f64> {
+        let mut d = self.value;
+        d.decode_f64_le()
+    }
+
+    /// Returns the literal of a `null`, `true` or `false` value.
+    pub fn get_literal(&self) -> u8 {
+        self.value[0]
+    }
+
+    pub fn get_str(&self) -> Result<&'a str> {
+        let mut d = self.value;
+        d.decode_var_u64()?;
+        let s = str::from_utf8(d)?;
+        Ok(s)
+    }
+
+    /// Returns the binary representation of the value, without the type code.
+    pub fn get_body(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Returns the length of the binary, including the type code.
+    pub fn binary_len(&self) -> usize {
+        TYPE_LEN + self.value.len()
+    }
+
+    /// Decodes the value into a `Json`.
+    pub fn to_json(&self) -> Result<Json> {
+        let mut d = self.value;
+        d.decode_json_body(self.type_code)
+    }
+}
+
+fn slice(data: &[u8], start: usize, len: usize) -> Result<&[u8]> {
+    if start + len > data.len() {
+        return Err(truncated());
+    }
+    Ok(&data[start..start + len])
+}
+
+fn truncated() -> Error {
+    Error::Io(io::Error::new(
+        ErrorKind::UnexpectedEof,
+        "json binary is truncated",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::JsonEncoder;
+
+    fn encode(s: &str) -> Vec<u8> {
+        let j: Json = s.parse().unwrap();
+        let mut buf = vec![];
+        buf.encode_json(&j).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_json_ref_scalars() {
+        let buf = encode("-3");
+        assert_eq!(JsonRef::new(&buf).unwrap().get_i64().unwrap(), -3);
+        let buf = encode("18446744073709551615");
+        assert_eq!(JsonRef::new(&buf).unwrap().get_u64().unwrap(), u64::max_value());
+        let buf = encode("3.5");
+        assert_eq!(JsonRef::new(&buf).unwrap().get_double().unwrap(), 3.5);
+        let buf = encode(r#""hello, 世界""#);
+        assert_eq!(JsonRef::new(&buf).unwrap().get_str().unwrap(), "hello, 世界");
+        let buf = encode("true");
+        assert_eq!(JsonRef::new(&buf).unwrap().get_literal(), JSON_LITERAL_TRUE);
+    }
+
+    #[test]
+    fn test_json_ref_object_and_array() {
+        let s = r#"{"b": [1, "x", null, {"c": false}], "a": 2.5, "aa": "s"}"#;
+        let buf = encode(s);
+        let j = JsonRef::new(&buf).unwrap();
+        assert!(j.is_object());
+        assert_eq!(j.get_elem_count().unwrap(), 3);
+        let keys: Vec<_> = (0..3).map(|i| j.object_get_key(i).unwrap()).collect();
+        assert_eq!(keys, vec!["a", "aa", "b"]);
+        for (key, idx) in vec![("a", Some(0)), ("aa", Some(1)), ("b", Some(2)), ("c", None)] {
+            assert_eq!(j.object_search_key(key).unwrap(), idx);
+        }
+        assert_eq!(j.object_get("a").unwrap().unwrap().get_double().unwrap(), 2.5);
+        assert_eq!(j.object_get("aa").unwrap().unwrap().get_str().unwrap(), "s");
+
+        let b = j.object_get("b").unwrap().unwrap();
+        assert!(b.is_array());
+        assert_eq!(b.get_elem_count().unwrap(), 4);
+        assert_eq!(b.array_get_elem(0).unwrap().get_u64().unwrap(), 1);
+        assert_eq!(b.array_get_elem(1).unwrap().get_str().unwrap(), "x");
+        assert_eq!(b.array_get_elem(2).unwrap().get_literal(), JSON_LITERAL_NIL);
+        let c = b.array_get_elem(3).unwrap().object_get("c").unwrap().unwrap();
+        assert_eq!(c.get_literal(), JSON_LITERAL_FALSE);
+        assert_eq!(b.to_json().unwrap(), r#"[1, "x", null, {"c": false}]"#.parse().unwrap());
+
+        let expected: Json = s.parse().unwrap();
+        assert_eq!(j.to_json().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_json_ref_corrupted() {
+        assert!(JsonRef::new(b"").is_err());
+        assert!(JsonRef::new(&[0xff, 0]).is_err());
+        let buf = encode(r#"{"a": [1, 2]}"#);
+        assert_eq!(JsonRef::new(&buf).unwrap().binary_len(), buf.len());
+        assert!(JsonRef::new(&buf[..buf.len() - 1]).is_err());
+        let buf = encode(r#""abc""#);
+        assert!(JsonRef::new(&buf[..buf.len() - 1]).is_err());
+    }
+}
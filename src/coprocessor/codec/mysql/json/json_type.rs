@@ -11,7 +11,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::Json;
+use super::{Json, JsonRef};
+use super::binary::*;
 
 const JSON_TYPE_BOOLEAN: &'static [u8] = b"BOOLEAN";
 const JSON_TYPE_NONE: &'static [u8] = b"NULL";
@@ -39,9 +40,30 @@ impl Json {
     }
 }
 
+impl<'a> JsonRef<'a> {
+    // json_type is the same as `Json::json_type`, which only reads the type
+    // code and the literal of the binary.
+    pub fn json_type(&self) -> &'static [u8] {
+        match self.get_type_code() {
+            TYPE_CODE_OBJECT => JSON_TYPE_OBJECT,
+            TYPE_CODE_ARRAY => JSON_TYPE_ARRAY,
+            TYPE_CODE_I64 => JSON_TYPE_INTEGER,
+            TYPE_CODE_U64 => JSON_TYPE_UNSIGNED_INTEGER,
+            TYPE_CODE_DOUBLE => JSON_TYPE_DOUBLE,
+            TYPE_CODE_STRING => JSON_TYPE_STRING,
+            _ => if self.get_literal() == JSON_LITERAL_NIL {
+                JSON_TYPE_NONE
+            } else {
+                JSON_TYPE_BOOLEAN
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use super::super::JsonEncoder;
 
     #[test]
     fn test_type() {
@@ -60,6 +82,9 @@ mod test {
         for (jstr, type_name) in test_cases {
             let json: Json = jstr.parse().unwrap();
             assert_eq!(json.json_type(), type_name);
+            let mut buf = vec![];
+            buf.encode_json(&json).unwrap();
+            assert_eq!(JsonRef::new(&buf).unwrap().json_type(), type_name);
         }
     }
 }
@@ -17,7 +17,8 @@
 use std::{char, str, u32};
 
 use super::super::Result;
-use super::Json;
+use super::{Json, JsonRef};
+use super::binary::TYPE_CODE_STRING;
 
 const ESCAPED_UNICODE_BYTES_SIZE: usize = 4;
 
@@ -36,6 +37,17 @@ impl Json {
     }
 }
 
+impl<'a> JsonRef<'a> {
+    // unquote is the same as `Json::unquote`, a string is unquoted without
+    // being decoded into a `Json`.
+    pub fn unquote(&self) -> Result<String> {
+        if self.get_type_code() == TYPE_CODE_STRING {
+            return unquote_string(self.get_str()?);
+        }
+        Ok(self.to_json()?.to_string())
+    }
+}
+
 // unquote_string recognizes the escape sequences shown in:
 // https://dev.mysql.com/doc/refman/5.7/en/json-modification-functions.html#
 // json-unquote-character-escape-sequences
@@ -92,6 +104,7 @@ fn decode_escaped_unicode(s: &str) -> Result<char> {
 mod test {
     use std::collections::BTreeMap;
     use super::*;
+    use super::super::JsonEncoder;
 
     #[test]
     fn test_decode_escaped_unicode() {
@@ -138,6 +151,10 @@ mod test {
         for (i, (input, no_error, expected)) in test_cases.drain(..).enumerate() {
             let j = Json::String(String::from(input));
             let r = j.unquote();
+            let mut buf = vec![];
+            buf.encode_json(&j).unwrap();
+            let got = JsonRef::new(&buf).unwrap().unquote();
+            assert_eq!(got.ok(), r.as_ref().ok().cloned(), "#{} unquote from binary", i);
             if no_error {
                 assert!(r.is_ok(), "#{} expect unquote ok but got err {:?}", i, r);
                 let got = r.unwrap();
@@ -167,6 +184,10 @@ mod test {
         for (i, j) in test_cases.drain(..).enumerate() {
             let expected = j.to_string();
             let r = j.unquote();
+            let mut buf = vec![];
+            buf.encode_json(&j).unwrap();
+            let got = JsonRef::new(&buf).unwrap().unquote();
+            assert_eq!(got.ok(), r.as_ref().ok().cloned(), "#{} unquote from binary", i);
             assert!(r.is_ok(), "#{} expect unquote ok but got err {:?}", i, r);
             let got = r.unwrap();
             assert_eq!(
@@ -26,9 +26,11 @@ mod json_modify;
 mod json_type;
 mod json_unquote;
 mod json_remove;
+mod json_ref;
 
 use std::collections::BTreeMap;
 pub use self::binary::{JsonDecoder, JsonEncoder};
+pub use self::json_ref::JsonRef;
 pub use self::path_expr::{parse_json_path_expr, PathExpression};
 pub use self::json_modify::ModifyType;
 
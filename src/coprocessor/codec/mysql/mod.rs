@@ -61,6 +61,7 @@ fn parse_frac(s: &[u8], fsp: u8) -> Result<u32> {
 mod duration;
 pub mod decimal;
 pub mod charset;
+pub mod collation;
 pub mod types;
 mod time;
 pub mod json;
@@ -70,7 +71,7 @@ pub use self::decimal::{dec_encoded_len, Decimal, DecimalDecoder, DecimalEncoder
 pub use self::types::{has_is_boolean_flag, has_not_null_flag, has_parse_to_json_flag,
                       has_unsigned_flag};
 pub use self::time::Time;
-pub use self::json::{parse_json_path_expr, Json, JsonDecoder, JsonEncoder, ModifyType,
+pub use self::json::{parse_json_path_expr, Json, JsonDecoder, JsonEncoder, JsonRef, ModifyType,
                      PathExpression};
 
 #[cfg(test)]
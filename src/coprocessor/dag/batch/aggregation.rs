@@ -0,0 +1,180 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tipb::schema::ColumnInfo;
+use tipb::executor::Aggregation;
+use tipb::expression::{Expr, ExprType};
+
+use util::collections::{OrderMap, OrderMapEntry};
+use coprocessor::codec::datum::{self, Datum};
+use coprocessor::endpoint::SINGLE_GROUP;
+use coprocessor::select::aggregate::{self, AggrFunc};
+use coprocessor::select::xeval::EvalContext;
+use coprocessor::dag::executor::{build_aggr_row, encode_group_key, ExprColumnRefVisitor};
+use coprocessor::dag::expr::Expression;
+use coprocessor::metrics::*;
+use coprocessor::Result;
+use storage::Statistics;
+
+use super::{Batch, BatchExecutor, BATCH_MAX_SIZE};
+
+struct AggrFuncExpr {
+    args: Vec<Expression>,
+    tp: ExprType,
+}
+
+impl AggrFuncExpr {
+    fn build(ctx: &EvalContext, mut expr: Expr) -> Result<AggrFuncExpr> {
+        let args = box_try!(Expression::batch_build(
+            ctx,
+            expr.take_children().into_vec()
+        ));
+        let tp = expr.get_tp();
+        Ok(AggrFuncExpr { args: args, tp: tp })
+    }
+}
+
+pub struct BatchAggregationExecutor {
+    group_by: Vec<Expression>,
+    aggr_func: Vec<AggrFuncExpr>,
+    group_key_aggrs: OrderMap<Vec<u8>, Vec<Box<AggrFunc>>>,
+    // The output values of the groups, in the same order as `group_key_aggrs`.
+    group_values: Vec<Vec<u8>>,
+    single_group_key: Vec<u8>,
+    cursor: usize,
+    executed: bool,
+    ctx: Arc<EvalContext>,
+    cols: Arc<Vec<ColumnInfo>>,
+    related_cols_offset: Vec<usize>, // offset of related columns
+    src: Box<BatchExecutor>,
+}
+
+impl BatchAggregationExecutor {
+    pub fn new(
+        mut meta: Aggregation,
+        ctx: Arc<EvalContext>,
+        columns: Arc<Vec<ColumnInfo>>,
+        src: Box<BatchExecutor>,
+    ) -> Result<BatchAggregationExecutor> {
+        // collect all cols used in aggregation
+        let mut visitor = ExprColumnRefVisitor::new(columns.len());
+        let group_by = meta.take_group_by().into_vec();
+        visitor.batch_visit(&group_by)?;
+        let aggr_func = meta.take_agg_func().into_vec();
+        visitor.batch_visit(&aggr_func)?;
+        let aggr_func: Vec<AggrFuncExpr> = aggr_func
+            .into_iter()
+            .map(|v| AggrFuncExpr::build(&ctx, v))
+            .collect::<Result<_>>()?;
+        let single_group = Datum::Bytes(SINGLE_GROUP.to_vec());
+        COPR_EXECUTOR_COUNT
+            .with_label_values(&["batch_aggregation"])
+            .inc();
+        Ok(BatchAggregationExecutor {
+            group_by: box_try!(Expression::batch_build(&ctx, group_by)),
+            aggr_func: aggr_func,
+            group_key_aggrs: OrderMap::new(),
+            group_values: vec![],
+            single_group_key: box_try!(datum::encode_value(&[single_group])),
+            cursor: 0,
+            executed: false,
+            ctx: ctx,
+            cols: columns,
+            related_cols_offset: visitor.column_offsets(),
+            src: src,
+        })
+    }
+
+    fn batch_eval(&self, exprs: &[Expression], batch: &Batch) -> Result<Vec<Vec<Datum>>> {
+        let mut res = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            res.push(box_try!(expr.batch_eval(&self.ctx, batch.columns(), batch.len())));
+        }
+        Ok(res)
+    }
+
+    fn aggregate(&mut self) -> Result<()> {
+        while let Some(mut batch) = self.src.next_batch()? {
+            batch.decode_columns(&self.ctx, &self.cols, &self.related_cols_offset)?;
+            let mut group_by = Vec::with_capacity(self.group_by.len());
+            for vals in self.batch_eval(&self.group_by, &batch)? {
+                group_by.push(vals.into_iter());
+            }
+            let mut args = Vec::with_capacity(self.aggr_func.len());
+            for expr in &self.aggr_func {
+                let mut arg_vals = Vec::with_capacity(expr.args.len());
+                for vals in self.batch_eval(&expr.args, &batch)? {
+                    arg_vals.push(vals.into_iter());
+                }
+                args.push(arg_vals);
+            }
+
+            for _ in 0..batch.len() {
+                let (group_key, group_values) = if self.group_by.is_empty() {
+                    (self.single_group_key.clone(), self.single_group_key.clone())
+                } else {
+                    let vals: Vec<Datum> = group_by.iter_mut().map(|v| v.next().unwrap()).collect();
+                    encode_group_key(&self.group_by, &vals)?
+                };
+                let aggrs = match self.group_key_aggrs.entry(group_key) {
+                    OrderMapEntry::Vacant(e) => {
+                        let mut aggrs = Vec::with_capacity(self.aggr_func.len());
+                        for expr in &self.aggr_func {
+                            aggrs.push(aggregate::build_aggr_func(expr.tp)?);
+                        }
+                        self.group_values.push(group_values);
+                        e.insert(aggrs)
+                    }
+                    OrderMapEntry::Occupied(e) => e.into_mut(),
+                };
+                for (aggr, arg_vals) in aggrs.iter_mut().zip(&mut args) {
+                    let vals = arg_vals.iter_mut().map(|v| v.next().unwrap()).collect();
+                    aggr.update(&self.ctx, vals)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BatchExecutor for BatchAggregationExecutor {
+    fn next_batch(&mut self) -> Result<Option<Batch>> {
+        if !self.executed {
+            self.aggregate()?;
+            self.executed = true;
+        }
+
+        let mut rows = Vec::with_capacity(BATCH_MAX_SIZE);
+        let has_group_by = !self.group_by.is_empty();
+        while rows.len() < BATCH_MAX_SIZE {
+            match self.group_key_aggrs.get_index_mut(self.cursor) {
+                Some((_, aggrs)) => {
+                    let group_values = &self.group_values[self.cursor];
+                    self.cursor += 1;
+                    rows.push(build_aggr_row(group_values, aggrs, has_group_by)?);
+                }
+                None => break,
+            }
+        }
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Batch::new(rows, self.cols.len())))
+    }
+
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The batch execution engine of DAG requests.
+//!
+//! Executors here pass rows to each other in batches. Columns referenced by
+//! an executor are decoded once per batch into column vectors, on which the
+//! expressions are evaluated with `Expression::batch_eval`.
+//!
+//! The results are the same as the ones of the row engine in
+//! `dag::executor`, including the rows returned before an error.
+
+mod scan;
+mod selection;
+mod topn;
+mod limit;
+mod aggregation;
+
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+use tipb::executor::{self, ExecType};
+use tipb::schema::ColumnInfo;
+use kvproto::coprocessor::KeyRange;
+
+use coprocessor::codec::datum::Datum;
+use coprocessor::select::xeval::EvalContext;
+use coprocessor::{Error, Result};
+use storage::{SnapshotStore, Statistics};
+
+use super::executor::{build_first_executor, decode_col_for_dag, DAGExecutor, Executor, Row};
+
+pub use self::scan::BatchScanExecutor;
+pub use self::selection::BatchSelectionExecutor;
+pub use self::topn::BatchTopNExecutor;
+pub use self::limit::BatchLimitExecutor;
+pub use self::aggregation::BatchAggregationExecutor;
+
+/// The size of the first batch of a scan, following batches are doubled until
+/// `BATCH_MAX_SIZE`, so that small queries like `LIMIT 1` don't scan too much.
+pub const BATCH_INITIAL_SIZE: usize = 32;
+pub const BATCH_MAX_SIZE: usize = 1024;
+
+pub struct Batch {
+    pub rows: Vec<Row>,
+    // `columns[offset]` holds the decoded values of the column at `offset`,
+    // it's empty if the column is not decoded.
+    columns: Vec<Vec<Datum>>,
+    decoded: Vec<bool>,
+}
+
+impl Batch {
+    pub fn new(rows: Vec<Row>, cols_len: usize) -> Batch {
+        Batch {
+            rows: rows,
+            columns: vec![vec![]; cols_len],
+            decoded: vec![false; cols_len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn columns(&self) -> &[Vec<Datum>] {
+        &self.columns
+    }
+
+    /// Decodes the columns at `offsets` which are not decoded yet.
+    pub fn decode_columns(
+        &mut self,
+        ctx: &EvalContext,
+        cols: &[ColumnInfo],
+        offsets: &[usize],
+    ) -> Result<()> {
+        for &offset in offsets {
+            if self.decoded[offset] {
+                continue;
+            }
+            let col = &cols[offset];
+            let mut values = Vec::with_capacity(self.rows.len());
+            for row in &self.rows {
+                values.push(decode_col_for_dag(ctx, &row.data, col, row.handle)?);
+            }
+            self.columns[offset] = values;
+            self.decoded[offset] = true;
+        }
+        Ok(())
+    }
+
+    /// Fills `row` with the decoded values of the `i`th row.
+    pub fn fill_row(&self, i: usize, row: &mut [Datum]) {
+        for (datum, col) in row.iter_mut().zip(&self.columns) {
+            if let Some(v) = col.get(i) {
+                *datum = v.clone();
+            }
+        }
+    }
+
+    /// Keeps only the rows whose flag in `keep` is true.
+    pub fn retain(&mut self, keep: &[bool]) {
+        retain_by(&mut self.rows, keep);
+        for col in &mut self.columns {
+            if !col.is_empty() {
+                retain_by(col, keep);
+            }
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.rows.truncate(len);
+        for col in &mut self.columns {
+            col.truncate(len);
+        }
+    }
+
+    pub fn into_rows(self) -> Vec<Row> {
+        self.rows
+    }
+}
+
+fn retain_by<T>(v: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    v.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+}
+
+pub trait BatchExecutor {
+    /// Returns the next batch of rows, or `None` if there is no more row.
+    /// A returned batch is never empty.
+    fn next_batch(&mut self) -> Result<Option<Batch>>;
+    fn collect_statistics_into(&mut self, stats: &mut Statistics);
+}
+
+/// Returns the rows of a `BatchExecutor` one by one, so that it can be used
+/// where an `Executor` is expected.
+pub struct BatchRowsExecutor {
+    src: Box<BatchExecutor>,
+    rows: IntoIter<Row>,
+}
+
+impl BatchRowsExecutor {
+    pub fn new(src: Box<BatchExecutor>) -> BatchRowsExecutor {
+        BatchRowsExecutor {
+            src: src,
+            rows: vec![].into_iter(),
+        }
+    }
+}
+
+impl Executor for BatchRowsExecutor {
+    fn next(&mut self) -> Result<Option<Row>> {
+        if let Some(row) = self.rows.next() {
+            return Ok(Some(row));
+        }
+        match self.src.next_batch()? {
+            Some(batch) => {
+                self.rows = batch.into_rows().into_iter();
+                Ok(self.rows.next())
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
+
+pub fn build_batch_exec(
+    execs: Vec<executor::Executor>,
+    store: SnapshotStore,
+    ranges: Vec<KeyRange>,
+    ctx: Arc<EvalContext>,
+) -> Result<DAGExecutor> {
+    let mut execs = execs.into_iter();
+    let first = execs
+        .next()
+        .ok_or_else(|| Error::Other(box_err!("has no executor")))?;
+    let (scan, columns) = build_first_executor(first, store, ranges)?;
+    let mut src: Box<BatchExecutor> = Box::new(BatchScanExecutor::new(scan, columns.len()));
+    let mut has_aggr = false;
+    for mut exec in execs {
+        let curr: Box<BatchExecutor> = match exec.get_tp() {
+            ExecType::TypeTableScan | ExecType::TypeIndexScan => {
+                return Err(box_err!("got too much *scan exec, should be only one"))
+            }
+            ExecType::TypeSelection => Box::new(BatchSelectionExecutor::new(
+                exec.take_selection(),
+                ctx.clone(),
+                columns.clone(),
+                src,
+            )?),
+            ExecType::TypeAggregation => {
+                has_aggr = true;
+                Box::new(BatchAggregationExecutor::new(
+                    exec.take_aggregation(),
+                    ctx.clone(),
+                    columns.clone(),
+                    src,
+                )?)
+            }
+            ExecType::TypeTopN => Box::new(BatchTopNExecutor::new(
+                exec.take_topN(),
+                ctx.clone(),
+                columns.clone(),
+                src,
+            )?),
+            ExecType::TypeLimit => Box::new(BatchLimitExecutor::new(exec.take_limit(), src)),
+        };
+        src = curr;
+    }
+    Ok(DAGExecutor {
+        exec: Box::new(BatchRowsExecutor::new(src)),
+        columns: columns,
+        has_aggr: has_aggr,
+    })
+}
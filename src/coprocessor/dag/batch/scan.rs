@@ -0,0 +1,78 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use coprocessor::{Error, Result};
+use coprocessor::dag::executor::Executor;
+use storage::Statistics;
+
+use super::{Batch, BatchExecutor, BATCH_INITIAL_SIZE, BATCH_MAX_SIZE};
+
+/// Collects the rows of a table scan or an index scan into batches.
+pub struct BatchScanExecutor {
+    src: Box<Executor>,
+    cols_len: usize,
+    batch_size: usize,
+    // The error met after some rows are scanned, it's returned after
+    // these rows.
+    pending_err: Option<Error>,
+    drained: bool,
+}
+
+impl BatchScanExecutor {
+    pub fn new(src: Box<Executor>, cols_len: usize) -> BatchScanExecutor {
+        BatchScanExecutor {
+            src: src,
+            cols_len: cols_len,
+            batch_size: BATCH_INITIAL_SIZE,
+            pending_err: None,
+            drained: false,
+        }
+    }
+}
+
+impl BatchExecutor for BatchScanExecutor {
+    fn next_batch(&mut self) -> Result<Option<Batch>> {
+        if let Some(e) = self.pending_err.take() {
+            return Err(e);
+        }
+        if self.drained {
+            return Ok(None);
+        }
+        let mut rows = Vec::with_capacity(self.batch_size);
+        while rows.len() < self.batch_size {
+            match self.src.next() {
+                Ok(Some(row)) => rows.push(row),
+                Ok(None) => {
+                    self.drained = true;
+                    break;
+                }
+                Err(e) => {
+                    if rows.is_empty() {
+                        return Err(e);
+                    }
+                    self.pending_err = Some(e);
+                    break;
+                }
+            }
+        }
+        self.batch_size = BATCH_MAX_SIZE.min(self.batch_size * 2);
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Batch::new(rows, self.cols_len)))
+    }
+
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tipb::executor::Selection;
+use tipb::schema::ColumnInfo;
+
+use coprocessor::codec::datum::Datum;
+use coprocessor::metrics::*;
+use coprocessor::select::xeval::EvalContext;
+use coprocessor::dag::executor::ExprColumnRefVisitor;
+use coprocessor::dag::expr::Expression;
+use coprocessor::{Error, Result};
+use storage::Statistics;
+
+use super::{Batch, BatchExecutor};
+
+pub struct BatchSelectionExecutor {
+    conditions: Vec<Expression>,
+    cols: Arc<Vec<ColumnInfo>>,
+    related_cols_offset: Vec<usize>, // offset of related columns
+    ctx: Arc<EvalContext>,
+    // The error met by a row, it's returned after the rows before it.
+    pending_err: Option<Error>,
+    src: Box<BatchExecutor>,
+}
+
+impl BatchSelectionExecutor {
+    pub fn new(
+        mut meta: Selection,
+        ctx: Arc<EvalContext>,
+        columns_info: Arc<Vec<ColumnInfo>>,
+        src: Box<BatchExecutor>,
+    ) -> Result<BatchSelectionExecutor> {
+        let conditions = meta.take_conditions().into_vec();
+        let mut visitor = ExprColumnRefVisitor::new(columns_info.len());
+        visitor.batch_visit(&conditions)?;
+        COPR_EXECUTOR_COUNT
+            .with_label_values(&["batch_selection"])
+            .inc();
+        Ok(BatchSelectionExecutor {
+            conditions: box_try!(Expression::batch_build(ctx.as_ref(), conditions)),
+            cols: columns_info,
+            related_cols_offset: visitor.column_offsets(),
+            ctx: ctx,
+            pending_err: None,
+            src: src,
+        })
+    }
+
+    // Filters the batch by each condition in turn, so a condition is only
+    // evaluated on the rows accepted by the previous ones.
+    fn filter(&self, batch: &mut Batch) -> Result<()> {
+        for filter in &self.conditions {
+            let vals = box_try!(filter.batch_eval(&self.ctx, batch.columns(), batch.len()));
+            let mut keep = Vec::with_capacity(vals.len());
+            for val in vals {
+                keep.push(box_try!(val.into_bool(&self.ctx)).unwrap_or(false));
+            }
+            batch.retain(&keep);
+            if batch.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Filters the batch row by row like `SelectionExecutor`, returns the
+    // flags of the rows before the first failed one, and its error if any.
+    fn filter_by_row(&self, batch: &Batch) -> (Vec<bool>, Option<Error>) {
+        let mut keep = Vec::with_capacity(batch.len());
+        let mut row = vec![Datum::Null; self.cols.len()];
+        for i in 0..batch.len() {
+            batch.fill_row(i, &mut row);
+            match self.eval_row(&row) {
+                Ok(accepted) => keep.push(accepted),
+                Err(e) => return (keep, Some(e)),
+            }
+        }
+        (keep, None)
+    }
+
+    fn eval_row(&self, row: &[Datum]) -> Result<bool> {
+        for filter in &self.conditions {
+            let val = box_try!(filter.eval(&self.ctx, row));
+            if !box_try!(val.into_bool(&self.ctx)).unwrap_or(false) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl BatchExecutor for BatchSelectionExecutor {
+    fn next_batch(&mut self) -> Result<Option<Batch>> {
+        if let Some(e) = self.pending_err.take() {
+            return Err(e);
+        }
+        while let Some(mut batch) = self.src.next_batch()? {
+            batch.decode_columns(&self.ctx, &self.cols, &self.related_cols_offset)?;
+            if self.filter(&mut batch).is_err() {
+                // Rows filtered out so far are rejected without errors, find
+                // out the first row that really fails on the rest.
+                let (keep, err) = self.filter_by_row(&batch);
+                batch.truncate(keep.len());
+                batch.retain(&keep);
+                self.pending_err = err;
+            }
+            if !batch.is_empty() {
+                return Ok(Some(batch));
+            }
+            if let Some(e) = self.pending_err.take() {
+                return Err(e);
+            }
+        }
+        Ok(None)
+    }
+
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
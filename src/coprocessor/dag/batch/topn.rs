@@ -0,0 +1,121 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+use tipb::executor::TopN;
+use tipb::schema::ColumnInfo;
+use tipb::expression::ByItem;
+
+use coprocessor::Result;
+use coprocessor::select::xeval::EvalContext;
+use coprocessor::select::topn_heap::{SortRow, TopNHeap};
+use coprocessor::dag::executor::{ExprColumnRefVisitor, Row};
+use coprocessor::dag::expr::Expression;
+use coprocessor::metrics::*;
+use storage::Statistics;
+
+use super::{Batch, BatchExecutor, BATCH_MAX_SIZE};
+
+pub struct BatchTopNExecutor {
+    order_by: Arc<Vec<ByItem>>,
+    order_by_exprs: Vec<Expression>,
+    cols: Arc<Vec<ColumnInfo>>,
+    related_cols_offset: Vec<usize>, // offset of related columns
+    heap: Option<TopNHeap>,
+    iter: Option<IntoIter<SortRow>>,
+    ctx: Arc<EvalContext>,
+    src: Box<BatchExecutor>,
+}
+
+impl BatchTopNExecutor {
+    pub fn new(
+        mut meta: TopN,
+        ctx: Arc<EvalContext>,
+        columns_info: Arc<Vec<ColumnInfo>>,
+        src: Box<BatchExecutor>,
+    ) -> Result<BatchTopNExecutor> {
+        let mut order_by = meta.take_order_by().into_vec();
+
+        let mut visitor = ExprColumnRefVisitor::new(columns_info.len());
+        for by_item in &order_by {
+            visitor.visit(by_item.get_expr())?;
+        }
+        let order_by_exprs: Vec<Expression> = box_try!(
+            order_by
+                .iter_mut()
+                .map(|v| Expression::build(&ctx, v.take_expr()))
+                .collect()
+        );
+
+        COPR_EXECUTOR_COUNT
+            .with_label_values(&["batch_topn"])
+            .inc();
+        Ok(BatchTopNExecutor {
+            order_by: Arc::new(order_by),
+            order_by_exprs: order_by_exprs,
+            heap: Some(TopNHeap::new(meta.get_limit() as usize)?),
+            cols: columns_info,
+            related_cols_offset: visitor.column_offsets(),
+            iter: None,
+            ctx: ctx,
+            src: src,
+        })
+    }
+
+    fn fetch_all(&mut self) -> Result<()> {
+        while let Some(mut batch) = self.src.next_batch()? {
+            batch.decode_columns(&self.ctx, &self.cols, &self.related_cols_offset)?;
+            let mut ob_values = Vec::with_capacity(self.order_by_exprs.len());
+            for expr in &self.order_by_exprs {
+                let vals = box_try!(expr.batch_eval(&self.ctx, batch.columns(), batch.len()));
+                ob_values.push(vals.into_iter());
+            }
+            for row in batch.into_rows() {
+                let values = ob_values.iter_mut().map(|v| v.next().unwrap()).collect();
+                self.heap.as_mut().unwrap().try_add_row(
+                    row.handle,
+                    row.data,
+                    values,
+                    self.order_by.clone(),
+                    self.ctx.clone(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BatchExecutor for BatchTopNExecutor {
+    fn next_batch(&mut self) -> Result<Option<Batch>> {
+        if self.iter.is_none() {
+            self.fetch_all()?;
+            self.iter = Some(self.heap.take().unwrap().into_sorted_vec()?.into_iter());
+        }
+        let rows: Vec<_> = self.iter
+            .as_mut()
+            .unwrap()
+            .take(BATCH_MAX_SIZE)
+            .map(|sort_row| Row::new(sort_row.handle, sort_row.data))
+            .collect();
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Batch::new(rows, self.cols.len())))
+    }
+
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
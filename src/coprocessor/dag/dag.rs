@@ -25,6 +25,7 @@ use coprocessor::{Error, Result};
 use coprocessor::endpoint::{get_pk, to_pb_error, ReqContext};
 use storage::{Snapshot, SnapshotStore, Statistics};
 
+use super::batch::build_batch_exec;
 use super::executor::{build_exec, Executor, Row};
 
 pub struct DAGContext {
@@ -43,6 +44,7 @@ impl DAGContext {
         snap: Box<Snapshot>,
         req_ctx: Arc<ReqContext>,
         batch_row_limit: usize,
+        enable_batch_exec: bool,
     ) -> Result<DAGContext> {
         let eval_ctx = Arc::new(box_try!(EvalContext::new(
             req.get_time_zone_offset(),
@@ -55,7 +57,12 @@ impl DAGContext {
             req_ctx.fill_cache,
         );
 
-        let dag_executor = build_exec(req.take_executors().into_vec(), store, ranges, eval_ctx)?;
+        let execs = req.take_executors().into_vec();
+        let dag_executor = if enable_batch_exec {
+            build_batch_exec(execs, store, ranges, eval_ctx)?
+        } else {
+            build_exec(execs, store, ranges, eval_ctx)?
+        };
         Ok(DAGContext {
             columns: dag_executor.columns,
             has_aggr: dag_executor.has_aggr,
@@ -17,9 +17,11 @@ use tipb::schema::ColumnInfo;
 use tipb::executor::Aggregation;
 use tipb::expression::{Expr, ExprType};
 
-use util::collections::{OrderMap, OrderMapEntry};
+use util::codec::number::NumberDecoder;
+use util::collections::{HashSet, OrderMap, OrderMapEntry};
 use coprocessor::codec::table::RowColsDict;
 use coprocessor::codec::datum::{self, approximate_size, Datum, DatumEncoder};
+use coprocessor::codec::mysql::{charset, collation};
 use coprocessor::endpoint::SINGLE_GROUP;
 use coprocessor::select::aggregate::{self, AggrFunc};
 use coprocessor::select::xeval::EvalContext;
@@ -70,10 +72,85 @@ impl AggrFunc {
     }
 }
 
+/// Builds the output row of a group, which is the results of the aggregate
+/// functions followed by the group key if there is any group by item.
+pub fn build_aggr_row(
+    group_key: &[u8],
+    aggrs: &mut [Box<AggrFunc>],
+    has_group_by: bool,
+) -> Result<Row> {
+    let mut aggr_cols = Vec::with_capacity(2 * aggrs.len());
+
+    // calc all aggr func
+    for aggr in aggrs {
+        aggr.calc(&mut aggr_cols)?;
+    }
+
+    // construct row data
+    let value_size = group_key.len() + approximate_size(&aggr_cols, false);
+    let mut value = Vec::with_capacity(value_size);
+    box_try!(value.encode(aggr_cols.as_slice(), false));
+    if has_group_by {
+        value.extend_from_slice(group_key);
+    }
+    Ok(Row {
+        handle: 0,
+        data: RowColsDict::new(map![], value),
+    })
+}
+
+/// Encodes the values of the group by items, returns the key to group the row
+/// by and the values to output for the group.
+///
+/// Strings equal under a case-insensitive collation belong to the same group,
+/// so the key holds their sort keys instead, while the group outputs the
+/// original values of its first row.
+pub fn encode_group_key(group_by: &[Expression], vals: &[Datum]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let values = box_try!(datum::encode_value(vals));
+    let mut sort_keys = vec![];
+    let mut has_sort_key = false;
+    for (expr, v) in group_by.iter().zip(vals) {
+        let collation = expr.get_tp().get_collate();
+        if charset::is_ci_collation(collation) {
+            if let Datum::Bytes(ref bs) = *v {
+                let key = collation::sort_key(collation, bs).into_owned();
+                sort_keys.push(Datum::Bytes(key));
+                has_sort_key = true;
+                continue;
+            }
+        }
+        sort_keys.push(v.clone());
+    }
+    if !has_sort_key {
+        return Ok((values.clone(), values));
+    }
+    let key = box_try!(datum::encode_value(&sort_keys));
+    Ok((key, values))
+}
+
+fn get_group_key(
+    ctx: &EvalContext,
+    group_by: &[Expression],
+    row: &[Datum],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    if group_by.is_empty() {
+        let single_group = Datum::Bytes(SINGLE_GROUP.to_vec());
+        let key = box_try!(datum::encode_value(&[single_group]));
+        return Ok((key.clone(), key));
+    }
+    let mut vals = Vec::with_capacity(group_by.len());
+    for expr in group_by {
+        vals.push(box_try!(expr.eval(ctx, row)));
+    }
+    encode_group_key(group_by, &vals)
+}
+
 pub struct AggregationExecutor {
     group_by: Vec<Expression>,
     aggr_func: Vec<AggrFuncExpr>,
     group_key_aggrs: OrderMap<Vec<u8>, Vec<Box<AggrFunc>>>,
+    // The output values of the groups, in the same order as `group_key_aggrs`.
+    group_values: Vec<Vec<u8>>,
     cursor: usize,
     executed: bool,
     ctx: Arc<EvalContext>,
@@ -102,6 +179,7 @@ impl AggregationExecutor {
             group_by: box_try!(Expression::batch_build(&ctx, group_by)),
             aggr_func: AggrFuncExpr::batch_build(&ctx, aggr_func)?,
             group_key_aggrs: OrderMap::new(),
+            group_values: vec![],
             cursor: 0,
             executed: false,
             ctx: ctx,
@@ -111,20 +189,6 @@ impl AggregationExecutor {
         })
     }
 
-    fn get_group_key(&self, row: &[Datum]) -> Result<Vec<u8>> {
-        if self.group_by.is_empty() {
-            let single_group = Datum::Bytes(SINGLE_GROUP.to_vec());
-            return Ok(box_try!(datum::encode_value(&[single_group])));
-        }
-        let mut vals = Vec::with_capacity(self.group_by.len());
-        for expr in &self.group_by {
-            let v = box_try!(expr.eval(&self.ctx, row));
-            vals.push(v);
-        }
-        let res = box_try!(datum::encode_value(&vals));
-        Ok(res)
-    }
-
     fn aggregate(&mut self) -> Result<()> {
         while let Some(row) = self.src.next()? {
             let cols = inflate_with_col_for_dag(
@@ -134,7 +198,7 @@ impl AggregationExecutor {
                 &self.related_cols_offset,
                 row.handle,
             )?;
-            let group_key = self.get_group_key(&cols)?;
+            let (group_key, group_values) = get_group_key(&self.ctx, &self.group_by, &cols)?;
             match self.group_key_aggrs.entry(group_key) {
                 OrderMapEntry::Vacant(e) => {
                     let mut aggrs = Vec::with_capacity(self.aggr_func.len());
@@ -144,6 +208,7 @@ impl AggregationExecutor {
                         aggrs.push(aggr);
                     }
                     e.insert(aggrs);
+                    self.group_values.push(group_values);
                 }
                 OrderMapEntry::Occupied(e) => {
                     let aggrs = e.into_mut();
@@ -165,26 +230,150 @@ impl Executor for AggregationExecutor {
         }
 
         match self.group_key_aggrs.get_index_mut(self.cursor) {
-            Some((group_key, aggrs)) => {
+            Some((_, aggrs)) => {
+                let group_values = &self.group_values[self.cursor];
                 self.cursor += 1;
-                let mut aggr_cols = Vec::with_capacity(2 * self.aggr_func.len());
+                let row = build_aggr_row(group_values, aggrs, !self.group_by.is_empty())?;
+                Ok(Some(row))
+            }
+            None => Ok(None),
+        }
+    }
 
-                // calc all aggr func
-                for aggr in aggrs {
-                    aggr.calc(&mut aggr_cols)?;
-                }
+    fn collect_statistics_into(&mut self, statistics: &mut Statistics) {
+        self.src.collect_statistics_into(statistics);
+    }
+}
 
-                // construct row data
-                let value_size = group_key.len() + approximate_size(&aggr_cols, false);
-                let mut value = Vec::with_capacity(value_size);
-                box_try!(value.encode(aggr_cols.as_slice(), false));
-                if !self.group_by.is_empty() {
-                    value.extend_from_slice(group_key);
-                }
-                Ok(Some(Row {
-                    handle: 0,
-                    data: RowColsDict::new(map![], value),
-                }))
+/// Returns whether the rows sorted by their first `sorted_cols` columns are also
+/// sorted by `group_by`, so the rows of a group are adjacent. It's the case when
+/// the group by items are exactly some leading sorted columns, and none of them
+/// is a string under a case-insensitive collation, whose equal values may not be
+/// adjacent in the byte order.
+pub fn is_sorted_by_group(group_by: &[Expr], sorted_cols: usize) -> bool {
+    let mut offsets = HashSet::default();
+    for expr in group_by {
+        if expr.get_tp() != ExprType::ColumnRef ||
+            charset::is_ci_collation(expr.get_field_type().get_collate())
+        {
+            return false;
+        }
+        match expr.get_val().decode_i64() {
+            Ok(offset) if offset >= 0 && (offset as usize) < sorted_cols => {
+                offsets.insert(offset as usize);
+            }
+            _ => return false,
+        }
+    }
+    // The distinct offsets are all less than their count, so they are 0..count.
+    offsets.iter().all(|&offset| offset < offsets.len())
+}
+
+/// Aggregates the rows which are sorted by the group by items, each group is
+/// returned once a row of the next group is met, so only one group is kept
+/// in memory. The pinned tipb has no executor type for it, so it's built when
+/// `is_sorted_by_group` holds for the input, e.g. an index scan grouped by the
+/// leading index columns, or when there's no group by item.
+pub struct StreamAggExecutor {
+    group_by: Vec<Expression>,
+    aggr_func: Vec<AggrFuncExpr>,
+    // The group key, the output values and the aggregate functions of the
+    // current group.
+    cur_group_key: Option<Vec<u8>>,
+    cur_group_values: Vec<u8>,
+    cur_aggrs: Vec<Box<AggrFunc>>,
+    executed: bool,
+    ctx: Arc<EvalContext>,
+    cols: Arc<Vec<ColumnInfo>>,
+    related_cols_offset: Vec<usize>, // offset of related columns
+    src: Box<Executor>,
+}
+
+impl StreamAggExecutor {
+    pub fn new(
+        mut meta: Aggregation,
+        ctx: Arc<EvalContext>,
+        columns: Arc<Vec<ColumnInfo>>,
+        src: Box<Executor>,
+    ) -> Result<StreamAggExecutor> {
+        // collect all cols used in aggregation
+        let mut visitor = ExprColumnRefVisitor::new(columns.len());
+        let group_by = meta.take_group_by().into_vec();
+        visitor.batch_visit(&group_by)?;
+        let aggr_func = meta.take_agg_func().into_vec();
+        visitor.batch_visit(&aggr_func)?;
+        COPR_EXECUTOR_COUNT
+            .with_label_values(&["stream_aggregation"])
+            .inc();
+        Ok(StreamAggExecutor {
+            group_by: box_try!(Expression::batch_build(&ctx, group_by)),
+            aggr_func: AggrFuncExpr::batch_build(&ctx, aggr_func)?,
+            cur_group_key: None,
+            cur_group_values: vec![],
+            cur_aggrs: vec![],
+            executed: false,
+            ctx: ctx,
+            cols: columns,
+            related_cols_offset: visitor.column_offsets(),
+            src: src,
+        })
+    }
+
+    // Starts a new group, returns the result of the previous group if any.
+    fn switch_group(&mut self, group_key: Vec<u8>, group_values: Vec<u8>) -> Result<Option<Row>> {
+        let res = match self.cur_group_key.take() {
+            Some(_) => Some(build_aggr_row(
+                &self.cur_group_values,
+                &mut self.cur_aggrs,
+                !self.group_by.is_empty(),
+            )?),
+            None => None,
+        };
+        let mut aggrs = Vec::with_capacity(self.aggr_func.len());
+        for expr in &self.aggr_func {
+            aggrs.push(aggregate::build_aggr_func(expr.tp)?);
+        }
+        self.cur_aggrs = aggrs;
+        self.cur_group_key = Some(group_key);
+        self.cur_group_values = group_values;
+        Ok(res)
+    }
+}
+
+impl Executor for StreamAggExecutor {
+    fn next(&mut self) -> Result<Option<Row>> {
+        if self.executed {
+            return Ok(None);
+        }
+        while let Some(row) = self.src.next()? {
+            let cols = inflate_with_col_for_dag(
+                &self.ctx,
+                &row.data,
+                &self.cols,
+                &self.related_cols_offset,
+                row.handle,
+            )?;
+            let (group_key, group_values) = get_group_key(&self.ctx, &self.group_by, &cols)?;
+            let mut res = None;
+            if self.cur_group_key.as_ref() != Some(&group_key) {
+                res = self.switch_group(group_key, group_values)?;
+            }
+            for (expr, aggr) in self.aggr_func.iter().zip(&mut self.cur_aggrs) {
+                aggr.update_with_expr(&self.ctx, expr, &cols)?;
+            }
+            if res.is_some() {
+                return Ok(res);
+            }
+        }
+        self.executed = true;
+        match self.cur_group_key.take() {
+            Some(_) => {
+                let row = build_aggr_row(
+                    &self.cur_group_values,
+                    &mut self.cur_aggrs,
+                    !self.group_by.is_empty(),
+                )?;
+                Ok(Some(row))
             }
             None => Ok(None),
         }
@@ -365,4 +554,212 @@ mod test {
             assert_eq!(ds[4], Datum::from(expect_cols.4));
         }
     }
+
+    #[test]
+    fn test_group_key_with_collation() {
+        let ctx = EvalContext::default();
+        let rows = vec![
+            vec![Datum::Bytes(b"abc".to_vec())],
+            vec![Datum::Bytes(b"ABC ".to_vec())],
+            vec![Datum::Bytes(b"abd".to_vec())],
+        ];
+        let cases = vec![
+            (charset::COLLATION_ID_UTF8MB4_BIN, vec![0, 1, 2]),
+            (charset::COLLATION_ID_UTF8MB4_GENERAL_CI, vec![0, 0, 2]),
+        ];
+        for (collation, exp) in cases {
+            let mut expr = build_expr(ExprType::ColumnRef, Some(0), None);
+            expr.mut_field_type().set_collate(collation);
+            let group_by = vec![Expression::build(&ctx, expr).unwrap()];
+            let keys: Vec<_> = rows.iter()
+                .map(|row| get_group_key(&ctx, &group_by, row).unwrap())
+                .collect();
+            for (i, j) in exp.into_iter().enumerate() {
+                assert_eq!(keys[i].0, keys[j].0, "row {} in {}", i, collation);
+            }
+            assert!(keys[0].0 != keys[2].0);
+            // The output values are always the original ones.
+            for (row, key) in rows.iter().zip(keys) {
+                assert_eq!(key.1, datum::encode_value(row).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregation_with_collation() {
+        let tid = 1;
+        let cis = vec![
+            new_col_info(1, types::LONG_LONG),
+            new_col_info(2, types::VARCHAR),
+        ];
+        // The rows are sorted by the second column under the collation.
+        let raw_data = vec![
+            vec![Datum::I64(1), Datum::Bytes(b"abc".to_vec())],
+            vec![Datum::I64(2), Datum::Bytes(b"ABC ".to_vec())],
+            vec![Datum::I64(3), Datum::Bytes(b"Abc".to_vec())],
+            vec![Datum::I64(4), Datum::Bytes(b"abd".to_vec())],
+        ];
+        let table_data = gen_table_data(tid, &cis, &raw_data);
+        let mut test_store = TestStore::new(&table_data);
+        let mut table_scan = TableScan::new();
+        table_scan.set_table_id(tid);
+        table_scan.set_columns(RepeatedField::from_vec(cis.clone()));
+
+        // Each group outputs the value of its first row.
+        let expect_row_data = vec![(3, b"abc".as_ref()), (1, b"abd".as_ref())];
+        for streamed in vec![false, true] {
+            let key_ranges = vec![get_range(tid, i64::MIN, i64::MAX)];
+            let (snapshot, start_ts) = test_store.get_snapshot();
+            let store = SnapshotStore::new(snapshot, start_ts, IsolationLevel::SI, true);
+            let ts_ect = TableScanExecutor::new(&table_scan, key_ranges, store);
+
+            let mut aggregation = Aggregation::default();
+            let mut group_by = build_group_by(&[1]);
+            group_by[0]
+                .mut_field_type()
+                .set_collate(charset::COLLATION_ID_UTF8MB4_GENERAL_CI);
+            aggregation.set_group_by(RepeatedField::from_vec(group_by));
+            let aggr_funcs = build_aggr_func(&[(ExprType::Count, 0)]);
+            aggregation.set_agg_func(RepeatedField::from_vec(aggr_funcs));
+            let ctx = Arc::new(EvalContext::default());
+            let cols = Arc::new(cis.clone());
+            let src = Box::new(ts_ect);
+            let mut aggr_ect: Box<Executor> = if streamed {
+                Box::new(StreamAggExecutor::new(aggregation, ctx, cols, src).unwrap())
+            } else {
+                Box::new(AggregationExecutor::new(aggregation, ctx, cols, src).unwrap())
+            };
+
+            let mut row_data = vec![];
+            while let Some(row) = aggr_ect.next().unwrap() {
+                row_data.push(row.data);
+            }
+            assert_eq!(row_data.len(), expect_row_data.len());
+            for (row, &(count, value)) in row_data.into_iter().zip(&expect_row_data) {
+                let ds = row.value.as_slice().decode().unwrap();
+                assert_eq!(ds, vec![Datum::U64(count), Datum::Bytes(value.to_vec())]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_sorted_by_group() {
+        let cases = vec![
+            (vec![], 0, true),
+            (vec![0], 2, true),
+            (vec![1, 0], 2, true),
+            (vec![0, 0, 1], 2, true),
+            (vec![1], 2, false),
+            (vec![0, 2], 3, false),
+            (vec![0, 1, 2], 2, false),
+            (vec![0], 0, false),
+        ];
+        for (cols, sorted_cols, exp) in cases {
+            let group_by = build_group_by(&cols);
+            assert_eq!(is_sorted_by_group(&group_by, sorted_cols), exp, "{:?}", cols);
+        }
+
+        let mut group_by = build_group_by(&[0]);
+        group_by[0]
+            .mut_field_type()
+            .set_collate(charset::COLLATION_ID_UTF8MB4_GENERAL_CI);
+        assert!(!is_sorted_by_group(&group_by, 1));
+        let mut group_by = vec![build_expr(ExprType::Count, None, Some(group_by.remove(0)))];
+        assert!(!is_sorted_by_group(&group_by, 1));
+        group_by = build_group_by(&[-1]);
+        assert!(!is_sorted_by_group(&group_by, 1));
+    }
+
+    #[test]
+    fn test_stream_aggregation() {
+        // prepare data and store, the rows are sorted by the second column.
+        let tid = 1;
+        let cis = vec![
+            new_col_info(1, types::LONG_LONG),
+            new_col_info(2, types::VARCHAR),
+            new_col_info(3, types::NEW_DECIMAL),
+        ];
+        let raw_data = vec![
+            vec![
+                Datum::I64(1),
+                Datum::Bytes(b"a".to_vec()),
+                Datum::Dec(7.into()),
+            ],
+            vec![
+                Datum::I64(2),
+                Datum::Bytes(b"a".to_vec()),
+                Datum::Dec(8.into()),
+            ],
+            vec![
+                Datum::I64(3),
+                Datum::Bytes(b"b".to_vec()),
+                Datum::Dec(8.into()),
+            ],
+            vec![
+                Datum::I64(4),
+                Datum::Bytes(b"f".to_vec()),
+                Datum::Dec(5.into()),
+            ],
+            vec![
+                Datum::I64(5),
+                Datum::Bytes(b"f".to_vec()),
+                Datum::Dec(6.into()),
+            ],
+            vec![
+                Datum::I64(6),
+                Datum::Bytes(b"f".to_vec()),
+                Datum::Dec(7.into()),
+            ],
+        ];
+        let table_data = gen_table_data(tid, &cis, &raw_data);
+        let mut test_store = TestStore::new(&table_data);
+        let mut table_scan = TableScan::new();
+        table_scan.set_table_id(tid);
+        table_scan.set_columns(RepeatedField::from_vec(cis.clone()));
+
+        let cases = vec![
+            (
+                vec![1],
+                vec![
+                    (2, Datum::Dec(15.into()), Some(b"a".as_ref())),
+                    (1, Datum::Dec(8.into()), Some(b"b".as_ref())),
+                    (3, Datum::Dec(18.into()), Some(b"f".as_ref())),
+                ],
+            ),
+            (vec![], vec![(6, Datum::Dec(41.into()), None)]),
+        ];
+        for (group_by_cols, expect_row_data) in cases {
+            let key_ranges = vec![get_range(tid, i64::MIN, i64::MAX)];
+            let (snapshot, start_ts) = test_store.get_snapshot();
+            let store = SnapshotStore::new(snapshot, start_ts, IsolationLevel::SI, true);
+            let ts_ect = TableScanExecutor::new(&table_scan, key_ranges, store);
+
+            let mut aggregation = Aggregation::default();
+            let group_by = build_group_by(&group_by_cols);
+            aggregation.set_group_by(RepeatedField::from_vec(group_by));
+            let aggr_funcs = build_aggr_func(&[(ExprType::Count, 0), (ExprType::Sum, 2)]);
+            aggregation.set_agg_func(RepeatedField::from_vec(aggr_funcs));
+            let mut aggr_ect = StreamAggExecutor::new(
+                aggregation,
+                Arc::new(EvalContext::default()),
+                Arc::new(cis.clone()),
+                Box::new(ts_ect),
+            ).unwrap();
+
+            let mut row_data = Vec::with_capacity(expect_row_data.len());
+            while let Some(row) = aggr_ect.next().unwrap() {
+                row_data.push(row.data);
+            }
+            assert!(aggr_ect.next().unwrap().is_none());
+            assert_eq!(row_data.len(), expect_row_data.len());
+            for (row, (count, sum, group_key)) in row_data.into_iter().zip(expect_row_data) {
+                let ds = row.value.as_slice().decode().unwrap();
+                let mut expect = vec![Datum::U64(count), sum];
+                if let Some(key) = group_key {
+                    expect.push(Datum::Bytes(key.to_vec()));
+                }
+                assert_eq!(ds, expect);
+            }
+        }
+    }
 }
@@ -18,7 +18,7 @@ use tipb::expression::{Expr, ExprType};
 use tipb::schema::ColumnInfo;
 use kvproto::coprocessor::KeyRange;
 
-use coprocessor::codec::mysql;
+use coprocessor::codec::mysql::{self, types};
 use coprocessor::codec::datum::{self, Datum};
 use coprocessor::codec::table::{RowColsDict, TableDecoder};
 use coprocessor::endpoint::get_pk;
@@ -41,7 +41,8 @@ pub use self::index_scan::IndexScanExecutor;
 pub use self::selection::SelectionExecutor;
 pub use self::topn::TopNExecutor;
 pub use self::limit::LimitExecutor;
-pub use self::aggregation::AggregationExecutor;
+pub use self::aggregation::{build_aggr_row, encode_group_key, is_sorted_by_group,
+                             AggregationExecutor, StreamAggExecutor};
 pub use self::scanner::{ScanOn, Scanner};
 
 pub struct ExprColumnRefVisitor {
@@ -148,6 +149,18 @@ pub fn build_exec(
     let first = execs
         .next()
         .ok_or_else(|| Error::Other(box_err!("has no executor")))?;
+    // The rows of an index scan are sorted by the index columns, the handle
+    // column appended to them isn't.
+    let mut sorted_cols = if first.get_tp() == ExecType::TypeIndexScan {
+        first
+            .get_idx_scan()
+            .get_columns()
+            .iter()
+            .take_while(|c| !c.get_pk_handle())
+            .count()
+    } else {
+        0
+    };
     let (mut src, columns) = build_first_executor(first, store, ranges)?;
     let mut has_aggr = false;
     for mut exec in execs {
@@ -163,19 +176,37 @@ pub fn build_exec(
             )?),
             ExecType::TypeAggregation => {
                 has_aggr = true;
-                Box::new(AggregationExecutor::new(
-                    exec.take_aggregation(),
+                let aggr = exec.take_aggregation();
+                let sorted_by_group = is_sorted_by_group(aggr.get_group_by(), sorted_cols);
+                sorted_cols = 0;
+                // The rows of a group are adjacent when they're sorted by the group
+                // by items, so they can be aggregated as a stream instead of into a
+                // hash map.
+                if sorted_by_group {
+                    Box::new(StreamAggExecutor::new(
+                        aggr,
+                        ctx.clone(),
+                        columns.clone(),
+                        src,
+                    )?)
+                } else {
+                    Box::new(AggregationExecutor::new(
+                        aggr,
+                        ctx.clone(),
+                        columns.clone(),
+                        src,
+                    )?)
+                }
+            }
+            ExecType::TypeTopN => {
+                sorted_cols = 0;
+                Box::new(TopNExecutor::new(
+                    exec.take_topN(),
                     ctx.clone(),
                     columns.clone(),
                     src,
                 )?)
             }
-            ExecType::TypeTopN => Box::new(TopNExecutor::new(
-                exec.take_topN(),
-                ctx.clone(),
-                columns.clone(),
-                src,
-            )?),
             ExecType::TypeLimit => Box::new(LimitExecutor::new(exec.take_limit(), src)),
         };
         src = curr;
@@ -187,9 +218,9 @@ pub fn build_exec(
     })
 }
 
-type FirstExecutor = (Box<Executor>, Arc<Vec<ColumnInfo>>);
+pub type FirstExecutor = (Box<Executor>, Arc<Vec<ColumnInfo>>);
 
-fn build_first_executor(
+pub fn build_first_executor(
     mut first: executor::Executor,
     store: SnapshotStore,
     ranges: Vec<KeyRange>,
@@ -227,25 +258,38 @@ pub fn inflate_with_col_for_dag(
 ) -> Result<Vec<Datum>> {
     let mut res = vec![Datum::Null; columns.len()];
     for offset in offsets {
-        let col = &columns[*offset];
-        if col.get_pk_handle() {
-            let v = get_pk(col, h);
-            res[*offset] = v;
-        } else {
-            let col_id = col.get_column_id();
-            let value = match values.get(col_id) {
-                None if col.has_default_val() => {
-                    // TODO: optimize it to decode default value only once.
-                    box_try!(col.get_default_val().decode_col_value(ctx, col))
-                }
-                None if mysql::has_not_null_flag(col.get_flag() as u64) => {
-                    return Err(box_err!("column {} of {} is missing", col_id, h));
-                }
-                None => Datum::Null,
-                Some(mut bs) => box_try!(bs.decode_col_value(ctx, col)),
-            };
-            res[*offset] = value;
-        }
+        res[*offset] = decode_col_for_dag(ctx, values, &columns[*offset], h)?;
     }
     Ok(res)
 }
+
+/// Decodes the value of column `col` from the row `values` whose handle is `h`.
+///
+/// A json value is kept in its binary form as `Datum::Bytes`, so the json
+/// functions can read it in place, see `Column` in `dag::expr`.
+pub fn decode_col_for_dag(
+    ctx: &EvalContext,
+    values: &RowColsDict,
+    col: &ColumnInfo,
+    h: i64,
+) -> Result<Datum> {
+    if col.get_pk_handle() {
+        return Ok(get_pk(col, h));
+    }
+    let col_id = col.get_column_id();
+    let value = match values.get(col_id) {
+        None if col.has_default_val() => {
+            // TODO: optimize it to decode default value only once.
+            box_try!(col.get_default_val().decode_col_value(ctx, col))
+        }
+        None if mysql::has_not_null_flag(col.get_flag() as u64) => {
+            return Err(box_err!("column {} of {} is missing", col_id, h));
+        }
+        None => Datum::Null,
+        Some(mut bs) => match datum::json_binary(bs) {
+            Some(binary) if col.get_tp() as u8 == types::JSON => Datum::Bytes(binary.to_vec()),
+            _ => box_try!(bs.decode_col_value(ctx, col)),
+        },
+    };
+    Ok(value)
+}
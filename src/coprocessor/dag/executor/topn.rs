@@ -39,7 +39,12 @@ impl OrderBy {
         let exprs: Vec<Expression> = box_try!(
             order_by
                 .iter_mut()
-                .map(|v| Expression::build(ctx, v.take_expr()))
+                .map(|v| {
+                    // The heap compares strings by the collation of the item.
+                    let expr = v.take_expr();
+                    v.mut_expr().set_field_type(expr.get_field_type().clone());
+                    Expression::build(ctx, expr)
+                })
                 .collect()
         );
         Ok(OrderBy {
@@ -0,0 +1,439 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vectorized evaluation of expressions on a batch of rows.
+//!
+//! The rows are passed in columnar form: `cols[offset]` holds the values of
+//! the column at `offset` for all the rows, or is empty if the column is not
+//! referenced. Functions that have no vectorized implementation are evaluated
+//! row by row.
+
+use std::cmp::Ordering;
+
+use tipb::expression::ScalarFuncSig;
+
+use coprocessor::codec::{datum, mysql, Datum};
+use super::{Error, Expression, FnCall, Result, StatementContext};
+use super::compare::{do_compare, CmpOp};
+
+impl Expression {
+    /// Evaluates the expression on each of the `rows` rows in `cols`.
+    pub fn batch_eval(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Datum>> {
+        match self.batch_eval_vectorized(ctx, cols, rows) {
+            Ok(res) => Ok(res),
+            // Vectorized functions always evaluate all of their arguments while
+            // the row engine may skip some, so an error may not be a real one.
+            Err(_) => eval_by_row(cols, rows, |row| self.eval(ctx, row)),
+        }
+    }
+
+    fn batch_eval_vectorized(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Datum>> {
+        match *self {
+            Expression::Constant(ref constant) => Ok(vec![constant.eval(); rows]),
+            Expression::ColumnRef(ref column) => cols[column.offset]
+                .iter()
+                .map(|d| column.eval_datum(d))
+                .collect(),
+            Expression::ScalarFn(ref f) => if f.is_vectorized_int() {
+                let unsigned = mysql::has_unsigned_flag(f.tp.get_flag() as u64);
+                let res = f.batch_eval_int(ctx, cols, rows)?;
+                Ok(res.into_iter()
+                    .map(|v| match v {
+                        None => Datum::Null,
+                        Some(i) if unsigned => Datum::U64(i as u64),
+                        Some(i) => Datum::I64(i),
+                    })
+                    .collect())
+            } else if f.is_vectorized_real() {
+                let res = f.batch_eval_real(ctx, cols, rows)?;
+                Ok(res.into_iter().map(Datum::from).collect())
+            } else {
+                eval_by_row(cols, rows, |row| f.eval(ctx, row))
+            },
+        }
+    }
+
+    fn batch_eval_int(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Option<i64>>> {
+        match *self {
+            Expression::Constant(ref constant) => Ok(vec![constant.eval_int()?; rows]),
+            Expression::ColumnRef(ref column) => {
+                cols[column.offset].iter().map(|d| d.as_int()).collect()
+            }
+            Expression::ScalarFn(ref f) => f.batch_eval_int(ctx, cols, rows),
+        }
+    }
+
+    fn batch_eval_real(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Option<f64>>> {
+        match *self {
+            Expression::Constant(ref constant) => Ok(vec![constant.eval_real()?; rows]),
+            Expression::ColumnRef(ref column) => {
+                cols[column.offset].iter().map(|d| d.as_real()).collect()
+            }
+            Expression::ScalarFn(ref f) => f.batch_eval_real(ctx, cols, rows),
+        }
+    }
+}
+
+impl FnCall {
+    fn is_vectorized_int(&self) -> bool {
+        match self.sig {
+            ScalarFuncSig::LTInt |
+            ScalarFuncSig::LEInt |
+            ScalarFuncSig::GTInt |
+            ScalarFuncSig::GEInt |
+            ScalarFuncSig::EQInt |
+            ScalarFuncSig::NEInt |
+            ScalarFuncSig::NullEQInt |
+            ScalarFuncSig::LTReal |
+            ScalarFuncSig::LEReal |
+            ScalarFuncSig::GTReal |
+            ScalarFuncSig::GEReal |
+            ScalarFuncSig::EQReal |
+            ScalarFuncSig::NEReal |
+            ScalarFuncSig::NullEQReal |
+            ScalarFuncSig::PlusInt |
+            ScalarFuncSig::MinusInt |
+            ScalarFuncSig::MultiplyInt |
+            ScalarFuncSig::LogicalAnd |
+            ScalarFuncSig::LogicalOr |
+            ScalarFuncSig::UnaryNot |
+            ScalarFuncSig::IntIsNull |
+            ScalarFuncSig::RealIsNull => true,
+            _ => false,
+        }
+    }
+
+    fn is_vectorized_real(&self) -> bool {
+        match self.sig {
+            ScalarFuncSig::PlusReal | ScalarFuncSig::MinusReal | ScalarFuncSig::MultiplyReal => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn has_unsigned_child(&self) -> bool {
+        self.children
+            .iter()
+            .any(|c| mysql::has_unsigned_flag(c.get_tp().get_flag()))
+    }
+
+    fn batch_eval_int(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Option<i64>>> {
+        match self.sig {
+            ScalarFuncSig::LTInt => self.batch_compare_int(ctx, cols, rows, CmpOp::LT),
+            ScalarFuncSig::LEInt => self.batch_compare_int(ctx, cols, rows, CmpOp::LE),
+            ScalarFuncSig::GTInt => self.batch_compare_int(ctx, cols, rows, CmpOp::GT),
+            ScalarFuncSig::GEInt => self.batch_compare_int(ctx, cols, rows, CmpOp::GE),
+            ScalarFuncSig::EQInt => self.batch_compare_int(ctx, cols, rows, CmpOp::EQ),
+            ScalarFuncSig::NEInt => self.batch_compare_int(ctx, cols, rows, CmpOp::NE),
+            ScalarFuncSig::NullEQInt => self.batch_compare_int(ctx, cols, rows, CmpOp::NullEQ),
+            ScalarFuncSig::LTReal => self.batch_compare_real(ctx, cols, rows, CmpOp::LT),
+            ScalarFuncSig::LEReal => self.batch_compare_real(ctx, cols, rows, CmpOp::LE),
+            ScalarFuncSig::GTReal => self.batch_compare_real(ctx, cols, rows, CmpOp::GT),
+            ScalarFuncSig::GEReal => self.batch_compare_real(ctx, cols, rows, CmpOp::GE),
+            ScalarFuncSig::EQReal => self.batch_compare_real(ctx, cols, rows, CmpOp::EQ),
+            ScalarFuncSig::NEReal => self.batch_compare_real(ctx, cols, rows, CmpOp::NE),
+            ScalarFuncSig::NullEQReal => self.batch_compare_real(ctx, cols, rows, CmpOp::NullEQ),
+            ScalarFuncSig::PlusInt => self.batch_arithmetic_int(ctx, cols, rows, i64::checked_add),
+            ScalarFuncSig::MinusInt => self.batch_arithmetic_int(ctx, cols, rows, i64::checked_sub),
+            ScalarFuncSig::MultiplyInt => {
+                self.batch_arithmetic_int(ctx, cols, rows, i64::checked_mul)
+            }
+            ScalarFuncSig::LogicalAnd => self.batch_logical(ctx, cols, rows, |lhs, rhs| {
+                if lhs == Some(0) || rhs == Some(0) {
+                    Some(0)
+                } else if lhs.is_none() || rhs.is_none() {
+                    None
+                } else {
+                    Some(1)
+                }
+            }),
+            ScalarFuncSig::LogicalOr => self.batch_logical(ctx, cols, rows, |lhs, rhs| {
+                if lhs.map_or(false, |v| v != 0) || rhs.map_or(false, |v| v != 0) {
+                    Some(1)
+                } else if rhs.is_none() {
+                    None
+                } else {
+                    lhs
+                }
+            }),
+            ScalarFuncSig::UnaryNot => {
+                let args = self.children[0].batch_eval_int(ctx, cols, rows)?;
+                Ok(args.into_iter().map(|v| v.map(|v| (v == 0) as i64)).collect())
+            }
+            ScalarFuncSig::IntIsNull => {
+                let args = self.children[0].batch_eval_int(ctx, cols, rows)?;
+                Ok(args.into_iter().map(|v| Some(v.is_none() as i64)).collect())
+            }
+            ScalarFuncSig::RealIsNull => {
+                let args = self.children[0].batch_eval_real(ctx, cols, rows)?;
+                Ok(args.into_iter().map(|v| Some(v.is_none() as i64)).collect())
+            }
+            _ => eval_by_row(cols, rows, |row| self.eval_int(ctx, row)),
+        }
+    }
+
+    fn batch_eval_real(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+    ) -> Result<Vec<Option<f64>>> {
+        match self.sig {
+            ScalarFuncSig::PlusReal => self.batch_arithmetic_real(ctx, cols, rows, |l, r| l + r),
+            ScalarFuncSig::MinusReal => self.batch_arithmetic_real(ctx, cols, rows, |l, r| l - r),
+            ScalarFuncSig::MultiplyReal => {
+                self.batch_arithmetic_real(ctx, cols, rows, |l, r| l * r)
+            }
+            _ => eval_by_row(cols, rows, |row| self.eval_real(ctx, row)),
+        }
+    }
+
+    fn batch_compare_int(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+        op: CmpOp,
+    ) -> Result<Vec<Option<i64>>> {
+        if self.has_unsigned_child() {
+            return eval_by_row(cols, rows, |row| self.compare_int(ctx, row, op));
+        }
+        let lhs = self.children[0].batch_eval_int(ctx, cols, rows)?;
+        let rhs = self.children[1].batch_eval_int(ctx, cols, rows)?;
+        batch_compare(lhs, rhs, op, |l, r| Ok(l.cmp(&r)))
+    }
+
+    fn batch_compare_real(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+        op: CmpOp,
+    ) -> Result<Vec<Option<i64>>> {
+        let lhs = self.children[0].batch_eval_real(ctx, cols, rows)?;
+        let rhs = self.children[1].batch_eval_real(ctx, cols, rows)?;
+        batch_compare(lhs, rhs, op, |l, r| datum::cmp_f64(l, r).map_err(Error::from))
+    }
+
+    fn batch_arithmetic_int<F>(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+        f: F,
+    ) -> Result<Vec<Option<i64>>>
+    where
+        F: Fn(i64, i64) -> Option<i64>,
+    {
+        if self.has_unsigned_child() {
+            return eval_by_row(cols, rows, |row| self.eval_int(ctx, row));
+        }
+        let lhs = self.children[0].batch_eval_int(ctx, cols, rows)?;
+        let rhs = self.children[1].batch_eval_int(ctx, cols, rows)?;
+        lhs.into_iter()
+            .zip(rhs)
+            .map(|(l, r)| match (l, r) {
+                (Some(l), Some(r)) => f(l, r).ok_or(Error::Overflow).map(Some),
+                _ => Ok(None),
+            })
+            .collect()
+    }
+
+    fn batch_arithmetic_real<F>(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+        f: F,
+    ) -> Result<Vec<Option<f64>>>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let lhs = self.children[0].batch_eval_real(ctx, cols, rows)?;
+        let rhs = self.children[1].batch_eval_real(ctx, cols, rows)?;
+        lhs.into_iter()
+            .zip(rhs)
+            .map(|(l, r)| match (l, r) {
+                (Some(l), Some(r)) => {
+                    let res = f(l, r);
+                    if !res.is_finite() {
+                        return Err(Error::Overflow);
+                    }
+                    Ok(Some(res))
+                }
+                _ => Ok(None),
+            })
+            .collect()
+    }
+
+    fn batch_logical<F>(
+        &self,
+        ctx: &StatementContext,
+        cols: &[Vec<Datum>],
+        rows: usize,
+        f: F,
+    ) -> Result<Vec<Option<i64>>>
+    where
+        F: Fn(Option<i64>, Option<i64>) -> Option<i64>,
+    {
+        let lhs = self.children[0].batch_eval_int(ctx, cols, rows)?;
+        let rhs = self.children[1].batch_eval_int(ctx, cols, rows)?;
+        Ok(lhs.into_iter().zip(rhs).map(|(l, r)| f(l, r)).collect())
+    }
+}
+
+fn batch_compare<T, F>(
+    lhs: Vec<Option<T>>,
+    rhs: Vec<Option<T>>,
+    op: CmpOp,
+    get_order: F,
+) -> Result<Vec<Option<i64>>>
+where
+    T: Copy,
+    F: Fn(T, T) -> Result<Ordering>,
+{
+    lhs.into_iter()
+        .zip(rhs)
+        .map(|(l, r)| do_compare(|i| Ok(if i == 0 { l } else { r }), op, &get_order))
+        .collect()
+}
+
+// Evaluates `f` on each row, only the referenced columns are filled.
+fn eval_by_row<T, F>(cols: &[Vec<Datum>], rows: usize, f: F) -> Result<Vec<T>>
+where
+    F: Fn(&[Datum]) -> Result<T>,
+{
+    let mut row = vec![Datum::Null; cols.len()];
+    let mut res = Vec::with_capacity(rows);
+    for i in 0..rows {
+        for (datum, col) in row.iter_mut().zip(cols) {
+            if let Some(v) = col.get(i) {
+                *datum = v.clone();
+            }
+        }
+        res.push(f(&row)?);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::i64;
+
+    use tipb::expression::{Expr, ScalarFuncSig};
+
+    use coprocessor::codec::Datum;
+    use coprocessor::codec::mysql::types;
+    use coprocessor::dag::expr::{Expression, StatementContext};
+    use coprocessor::dag::expr::test::fncall_expr;
+    use coprocessor::select::xeval::evaluator::test::{col_expr, datum_expr};
+
+    fn check_batch_eval(ctx: &StatementContext, expr: Expr, cols: &[Vec<Datum>], rows: usize) {
+        let e = Expression::build(ctx, expr).unwrap();
+        let res = e.batch_eval(ctx, cols, rows).unwrap();
+        assert_eq!(res.len(), rows);
+        for (i, got) in res.into_iter().enumerate() {
+            let row: Vec<Datum> = cols.iter()
+                .map(|col| col.get(i).cloned().unwrap_or(Datum::Null))
+                .collect();
+            let expect = e.eval(ctx, &row).unwrap();
+            assert_eq!(got, expect, "row {}", i);
+        }
+    }
+
+    #[test]
+    fn test_batch_eval() {
+        let ctx = StatementContext::default();
+        let cols = vec![
+            vec![Datum::I64(1), Datum::Null, Datum::I64(-3), Datum::I64(4)],
+            vec![],
+            vec![Datum::F64(1.5), Datum::F64(-2.0), Datum::Null, Datum::F64(0.0)],
+            vec![Datum::I64(0), Datum::I64(2), Datum::Null, Datum::I64(4)],
+        ];
+        let int_col = col_expr(0);
+        let real_col = col_expr(2);
+        let int_col2 = col_expr(3);
+        let cases = vec![
+            fncall_expr(ScalarFuncSig::LTInt, &[int_col.clone(), int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::NullEQInt, &[int_col.clone(), int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::GEReal, &[real_col.clone(), datum_expr(Datum::F64(0.0))]),
+            fncall_expr(ScalarFuncSig::PlusInt, &[int_col.clone(), int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::MultiplyReal, &[real_col.clone(), real_col.clone()]),
+            fncall_expr(ScalarFuncSig::LogicalAnd, &[int_col.clone(), int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::LogicalOr, &[int_col.clone(), int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::UnaryNot, &[int_col2.clone()]),
+            fncall_expr(ScalarFuncSig::RealIsNull, &[real_col.clone()]),
+            // not vectorized
+            fncall_expr(ScalarFuncSig::CastIntAsReal, &[int_col.clone()]),
+            fncall_expr(
+                ScalarFuncSig::LTReal,
+                &[
+                    fncall_expr(ScalarFuncSig::CastIntAsReal, &[int_col.clone()]),
+                    real_col.clone(),
+                ],
+            ),
+        ];
+        for expr in cases {
+            check_batch_eval(&ctx, expr, &cols, 4);
+        }
+    }
+
+    #[test]
+    fn test_batch_eval_fallback() {
+        let ctx = StatementContext::default();
+        let cols = vec![
+            vec![Datum::I64(0), Datum::I64(0)],
+            vec![Datum::I64(i64::MAX), Datum::I64(1)],
+        ];
+        // The addition overflows on the first row, but it's never evaluated
+        // by the row engine since the left side is false.
+        let overflow = fncall_expr(ScalarFuncSig::PlusInt, &[col_expr(1), col_expr(1)]);
+        let expr = fncall_expr(ScalarFuncSig::LogicalAnd, &[col_expr(0), overflow.clone()]);
+        check_batch_eval(&ctx, expr, &cols, 2);
+
+        let e = Expression::build(&ctx, overflow).unwrap();
+        assert!(e.batch_eval(&ctx, &cols, 2).is_err());
+
+        // unsigned arguments are evaluated row by row.
+        let mut lhs = col_expr(1);
+        lhs.mut_field_type().set_flag(types::UNSIGNED_FLAG as u32);
+        let expr = fncall_expr(ScalarFuncSig::LTInt, &[lhs, col_expr(0)]);
+        check_batch_eval(&ctx, expr, &cols, 2);
+    }
+}
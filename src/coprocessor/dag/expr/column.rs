@@ -14,12 +14,38 @@
 use std::borrow::Cow;
 
 use coprocessor::codec::Datum;
-use coprocessor::codec::mysql::{Decimal, Duration, Json, Time};
+use coprocessor::codec::mysql::{types, Decimal, Duration, Json, JsonRef, Time};
 use super::{Column, Result};
 
 impl Column {
-    pub fn eval(&self, row: &[Datum]) -> Datum {
-        row[self.offset].clone()
+    pub fn eval(&self, row: &[Datum]) -> Result<Datum> {
+        self.eval_datum(&row[self.offset])
+    }
+
+    /// Returns the value of `datum` of the column, a json in its binary form
+    /// is decoded.
+    pub fn eval_datum(&self, datum: &Datum) -> Result<Datum> {
+        match self.as_json_ref(datum)? {
+            Some(j) => Ok(Datum::Json(j.to_json()?)),
+            None => Ok(datum.clone()),
+        }
+    }
+
+    /// Returns the binary of a json column without decoding it, or `None` if
+    /// the value is null or is decoded already.
+    pub fn eval_json_ref<'a>(&self, row: &'a [Datum]) -> Result<Option<JsonRef<'a>>> {
+        self.as_json_ref(&row[self.offset])
+    }
+
+    // A json is kept in its binary form as `Datum::Bytes` by
+    // `decode_col_for_dag`.
+    fn as_json_ref<'a>(&self, datum: &'a Datum) -> Result<Option<JsonRef<'a>>> {
+        match *datum {
+            Datum::Bytes(ref bs) if self.tp.get_tp() as u8 == types::JSON => {
+                Ok(Some(JsonRef::new(bs)?))
+            }
+            _ => Ok(None),
+        }
     }
 
     #[inline]
@@ -39,6 +65,9 @@ impl Column {
 
     #[inline]
     pub fn eval_string<'a>(&self, row: &'a [Datum]) -> Result<Option<Cow<'a, [u8]>>> {
+        if self.as_json_ref(&row[self.offset])?.is_some() {
+            return Err(box_err!("Can't eval_string from Datum"));
+        }
         row[self.offset].as_string()
     }
 
@@ -54,7 +83,10 @@ impl Column {
 
     #[inline]
     pub fn eval_json<'a>(&self, row: &'a [Datum]) -> Result<Option<Cow<'a, Json>>> {
-        row[self.offset].as_json()
+        match self.eval_json_ref(row)? {
+            Some(j) => Ok(Some(Cow::Owned(j.to_json()?))),
+            None => row[self.offset].as_json(),
+        }
     }
 }
 
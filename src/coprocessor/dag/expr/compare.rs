@@ -17,7 +17,7 @@ use std::cmp::Ordering;
 use std::borrow::Cow;
 
 use coprocessor::codec::{datum, mysql, Datum};
-use coprocessor::codec::mysql::{Decimal, Duration, Json, Time};
+use coprocessor::codec::mysql::{charset, collation, Decimal, Duration, Json, Time};
 use coprocessor::dag::expr::Expression;
 use super::{Error, FnCall, Result, StatementContext};
 
@@ -79,7 +79,8 @@ impl FnCall {
         op: CmpOp,
     ) -> Result<Option<i64>> {
         let e = |i: usize| self.children[i].eval_string(ctx, row);
-        do_compare(e, op, |l, r| Ok(l.cmp(&r)))
+        let collation = self.string_collation();
+        do_compare(e, op, |l, r| Ok(collation::compare(collation, &l, &r)))
     }
 
     pub fn compare_time(
@@ -108,6 +109,13 @@ impl FnCall {
         row: &[Datum],
         op: CmpOp,
     ) -> Result<Option<i64>> {
+        let lhs = self.children[0].eval_json_ref(row)?;
+        let rhs = self.children[1].eval_json_ref(row)?;
+        if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+            // Both are json columns, compare them without decoding.
+            let e = |i: usize| Ok(Some(if i == 0 { lhs } else { rhs }));
+            return do_compare(e, op, |l, r| l.cmp_json(&r).map_err(Error::from));
+        }
         let e = |i: usize| self.children[i].eval_json(ctx, row);
         do_compare(e, op, |l, r| Ok(l.cmp(&r)))
     }
@@ -199,7 +207,12 @@ impl FnCall {
     }
 
     pub fn in_string(&self, ctx: &StatementContext, row: &[Datum]) -> Result<Option<i64>> {
-        do_in(self, |v| v.eval_string(ctx, row), |l, r| Ok(l.cmp(r)))
+        let collation = self.string_collation();
+        do_in(
+            self,
+            |v| v.eval_string(ctx, row),
+            |l, r| Ok(collation::compare(collation, l, r)),
+        )
     }
 
     pub fn in_json(&self, ctx: &StatementContext, row: &[Datum]) -> Result<Option<i64>> {
@@ -216,9 +229,23 @@ impl FnCall {
         let escape = try_opt!(self.children[2].eval_int(ctx, row)) as u32;
         Ok(Some(like(&target, &pattern, escape, 0)? as i64))
     }
+
+    /// Returns the collation to compare the string arguments with, it's the
+    /// first case-insensitive one among the arguments if there is any.
+    fn string_collation(&self) -> i32 {
+        let collations = self.children.iter().map(|c| c.get_tp().get_collate());
+        let mut first = None;
+        for collation in collations {
+            if charset::is_ci_collation(collation) {
+                return collation;
+            }
+            first = first.or(Some(collation));
+        }
+        first.unwrap_or(charset::COLLATION_ID_BIN)
+    }
 }
 
-fn do_compare<T, E, F>(e: E, op: CmpOp, get_order: F) -> Result<Option<i64>>
+pub fn do_compare<T, E, F>(e: E, op: CmpOp, get_order: F) -> Result<Option<i64>>
 where
     E: Fn(usize) -> Result<Option<T>>,
     F: Fn(T, T) -> Result<Ordering>,
@@ -388,6 +415,7 @@ mod test {
     use coprocessor::select::xeval::evaluator::test::{col_expr, datum_expr};
     use coprocessor::codec::mysql::{Decimal, Duration, Json, Time};
     use coprocessor::codec::Datum;
+    use coprocessor::codec::mysql::charset;
     use coprocessor::dag::expr::{Expression, StatementContext};
     use coprocessor::dag::expr::test::fncall_expr;
     use super::*;
@@ -675,4 +703,57 @@ mod test {
             assert_eq!(got, exp, "{:?} like {:?}", target_str, pattern_str);
         }
     }
+
+    // Builds a call of `sig` on strings, only the collation of the first
+    // argument is set.
+    fn new_string_call(sig: ScalarFuncSig, args: &[Datum], collation: i32) -> Expression {
+        let mut children: Vec<_> = args.iter().map(|d| datum_expr(d.clone())).collect();
+        children[0].mut_field_type().set_collate(collation);
+        Expression::build(&StatementContext::default(), fncall_expr(sig, &children)).unwrap()
+    }
+
+    #[test]
+    fn test_string_collation() {
+        let cases = vec![
+            ("abc", "ABC", charset::COLLATION_ID_UTF8MB4_BIN, ScalarFuncSig::EQString, 0),
+            ("abc", "ABC", charset::COLLATION_ID_UTF8MB4_GENERAL_CI, ScalarFuncSig::EQString, 1),
+            ("abc ", "ABC", charset::COLLATION_ID_UTF8MB4_GENERAL_CI, ScalarFuncSig::EQString, 1),
+            ("a", "B", charset::COLLATION_ID_UTF8MB4_BIN, ScalarFuncSig::LTString, 0),
+            ("a", "B", charset::COLLATION_ID_UTF8MB4_GENERAL_CI, ScalarFuncSig::LTString, 1),
+            (
+                "Straße",
+                "STRASSE",
+                charset::COLLATION_ID_UTF8MB4_GENERAL_CI,
+                ScalarFuncSig::EQString,
+                0,
+            ),
+            (
+                "Straße",
+                "STRASSE",
+                charset::COLLATION_ID_UTF8MB4_UNICODE_CI,
+                ScalarFuncSig::EQString,
+                1,
+            ),
+        ];
+        let ctx = StatementContext::default();
+        for (lhs, rhs, collation, sig, exp) in cases {
+            let mut args = vec![
+                Datum::Bytes(lhs.as_bytes().to_vec()),
+                Datum::Bytes(rhs.as_bytes().to_vec()),
+            ];
+            // The collation of the first argument is used for both of them.
+            let call = new_string_call(sig, &args, collation);
+            let got = call.eval_int(&ctx, &[]).unwrap();
+            assert_eq!(got, Some(exp), "{:?} {:?} in {}", lhs, rhs, collation);
+
+            args.push(Datum::Null);
+            let call = new_string_call(ScalarFuncSig::InString, &args, collation);
+            let exp = if sig == ScalarFuncSig::EQString && exp == 1 {
+                Some(1)
+            } else {
+                None
+            };
+            assert_eq!(call.eval_int(&ctx, &[]).unwrap(), exp);
+        }
+    }
 }
@@ -25,6 +25,9 @@ impl FnCall {
         ctx: &StatementContext,
         row: &'a [Datum],
     ) -> Result<Option<Cow<'a, [u8]>>> {
+        if let Some(j) = self.children[0].eval_json_ref(row)? {
+            return Ok(Some(Cow::Borrowed(j.json_type())));
+        }
         let j = try_opt!(self.children[0].eval_json(ctx, row));
         Ok(Some(Cow::Borrowed(j.json_type())))
     }
@@ -35,6 +38,9 @@ impl FnCall {
         ctx: &StatementContext,
         row: &'a [Datum],
     ) -> Result<Option<Cow<'a, [u8]>>> {
+        if let Some(j) = self.children[0].eval_json_ref(row)? {
+            return Ok(Some(Cow::Owned(j.unquote()?.into_bytes())));
+        }
         let j = try_opt!(self.children[0].eval_json(ctx, row));
         j.unquote()
             .map_err(Error::from)
@@ -72,8 +78,13 @@ impl FnCall {
         row: &'a [Datum],
     ) -> Result<Option<Cow<'a, Json>>> {
         // TODO: We can cache the PathExpressions if children are Constant.
-        let j = try_opt!(self.children[0].eval_json(ctx, row));
         let parser = JsonFuncArgsParser::new(ctx, row);
+        if let Some(j) = self.children[0].eval_json_ref(row)? {
+            // Only the values matched by the paths are decoded.
+            let path_exprs: Vec<_> = try_opt!(parser.get_path_exprs(&self.children[1..]));
+            return Ok(j.extract(&path_exprs)?.map(Cow::Owned));
+        }
+        let j = try_opt!(self.children[0].eval_json(ctx, row));
         let path_exprs: Vec<_> = try_opt!(parser.get_path_exprs(&self.children[1..]));
         Ok(j.extract(&path_exprs).map(Cow::Owned))
     }
@@ -187,12 +198,12 @@ impl<'a> JsonFuncArgsParser<'a> {
 
 #[cfg(test)]
 mod test {
-    use tipb::expression::ScalarFuncSig;
+    use tipb::expression::{Expr, ScalarFuncSig};
     use coprocessor::codec::Datum;
-    use coprocessor::codec::mysql::Json;
+    use coprocessor::codec::mysql::{types, Json, JsonEncoder};
     use coprocessor::dag::expr::{Expression, StatementContext};
     use coprocessor::dag::expr::test::{fncall_expr, make_null_datums};
-    use coprocessor::select::xeval::evaluator::test::datum_expr;
+    use coprocessor::select::xeval::evaluator::test::{col_expr, datum_expr};
 
     #[test]
     fn test_json_type() {
@@ -263,6 +274,74 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_json_binary_column() {
+        let jsons = vec![
+            r#"{"a": [1, "b", {"c": "d"}], "e": 2.5}"#,
+            r#""\u597d""#,
+            r#"[1, 2]"#,
+        ];
+        let mut row: Vec<_> = jsons
+            .iter()
+            .map(|s| {
+                let j: Json = s.parse().unwrap();
+                let mut binary = vec![];
+                binary.encode_json(&j).unwrap();
+                Datum::Bytes(binary)
+            })
+            .collect();
+        row.push(Datum::Null);
+        let col = |offset: i64| -> Expr {
+            let mut expr = col_expr(offset);
+            expr.mut_field_type().set_tp(types::JSON as i32);
+            expr
+        };
+        let path = |s: &str| datum_expr(Datum::Bytes(s.as_bytes().to_vec()));
+        let obj: Json = jsons[0].parse().unwrap();
+
+        let cases = vec![
+            (ScalarFuncSig::JsonTypeSig, vec![col(0)], Datum::Bytes(b"OBJECT".to_vec())),
+            (ScalarFuncSig::JsonTypeSig, vec![col(3)], Datum::Null),
+            (
+                ScalarFuncSig::JsonUnquoteSig,
+                vec![col(0)],
+                Datum::Bytes(obj.to_string().into_bytes()),
+            ),
+            (
+                ScalarFuncSig::JsonUnquoteSig,
+                vec![col(1)],
+                Datum::Bytes("好".as_bytes().to_vec()),
+            ),
+            (
+                ScalarFuncSig::JsonExtractSig,
+                vec![col(0), path("$.a[2].c")],
+                Datum::Json(Json::String("d".to_owned())),
+            ),
+            (
+                ScalarFuncSig::JsonExtractSig,
+                vec![col(0), path("$.e"), path("$.a[0]")],
+                Datum::Json(r#"[2.5, 1]"#.parse().unwrap()),
+            ),
+            (ScalarFuncSig::JsonExtractSig, vec![col(0), path("$.x")], Datum::Null),
+            (ScalarFuncSig::JsonExtractSig, vec![col(3), path("$.a")], Datum::Null),
+            (ScalarFuncSig::EQJson, vec![col(2), col(2)], Datum::I64(1)),
+            (ScalarFuncSig::LTJson, vec![col(2), col(0)], Datum::I64(0)),
+            (ScalarFuncSig::GTJson, vec![col(0), col(1)], Datum::I64(1)),
+        ];
+        let ctx = StatementContext::default();
+        for (sig, args, exp) in cases {
+            let op = fncall_expr(sig, &args);
+            let op = Expression::build(&ctx, op).unwrap();
+            let got = op.eval(&ctx, &row).unwrap();
+            assert_eq!(got, exp, "{:?}", sig);
+        }
+
+        // Other expressions get the decoded value.
+        let e = Expression::build(&ctx, col(0)).unwrap();
+        assert_eq!(e.eval(&ctx, &row).unwrap(), Datum::Json(obj));
+        assert!(e.eval_string(&ctx, &row).is_err());
+    }
+
     #[test]
     fn test_json_object() {
         let cases = vec![
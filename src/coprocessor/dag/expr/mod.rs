@@ -21,6 +21,7 @@ mod compare;
 mod arithmetic;
 mod math;
 mod json;
+mod batch;
 
 use std::{error, io, str};
 use std::borrow::Cow;
@@ -29,7 +30,7 @@ use std::str::Utf8Error;
 
 use tipb::expression::{Expr, ExprType, FieldType, ScalarFuncSig};
 
-use coprocessor::codec::mysql::{Decimal, Duration, Json, Res, Time, MAX_FSP};
+use coprocessor::codec::mysql::{Decimal, Duration, Json, JsonRef, Res, Time, MAX_FSP};
 use coprocessor::codec::mysql::decimal::DecimalDecoder;
 use coprocessor::codec::mysql::json::JsonDecoder;
 use coprocessor::codec::mysql::{charset, types};
@@ -143,7 +144,7 @@ impl Expression {
     }
 
     #[inline]
-    fn get_tp(&self) -> &FieldType {
+    pub fn get_tp(&self) -> &FieldType {
         match *self {
             Expression::Constant(ref c) => &c.tp,
             Expression::ColumnRef(ref c) => &c.tp,
@@ -256,6 +257,15 @@ impl Expression {
         }
     }
 
+    /// Returns the binary of a json column without decoding it. It returns
+    /// `None` for other expressions, which should be evaluated by `eval_json`.
+    fn eval_json_ref<'a>(&self, row: &'a [Datum]) -> Result<Option<JsonRef<'a>>> {
+        match *self {
+            Expression::ColumnRef(ref column) => column.eval_json_ref(row),
+            _ => Ok(None),
+        }
+    }
+
     /// IsHybridType checks whether a ClassString expression is a hybrid type value which will
     /// return different types of value in different context.
     /// For ENUM/SET which is consist of a string attribute `Name` and an int attribute `Value`,
@@ -280,7 +290,7 @@ impl Expression {
     pub fn eval(&self, ctx: &StatementContext, row: &[Datum]) -> Result<Datum> {
         match *self {
             Expression::Constant(ref constant) => Ok(constant.eval()),
-            Expression::ColumnRef(ref column) => Ok(column.eval(row)),
+            Expression::ColumnRef(ref column) => column.eval(row),
             Expression::ScalarFn(ref f) => f.eval(ctx, row),
         }
     }
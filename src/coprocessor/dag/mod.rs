@@ -13,4 +13,5 @@
 pub mod executor;
 pub mod dag;
 pub mod expr;
+pub mod batch;
 pub use self::dag::DAGContext;
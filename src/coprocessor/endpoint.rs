@@ -31,9 +31,10 @@ use util::worker::{BatchRunnable, FutureScheduler, Scheduler};
 use util::collections::HashMap;
 use util::threadpool::{Context, ContextFactory, ThreadPool, ThreadPoolBuilder};
 use server::{Config, OnResponse};
-use storage::{self, engine, Engine, FlowStatistics, Snapshot, Statistics, StatisticsSummary};
+use storage::{self, engine, Engine, Key, Snapshot, Statistics, StatisticsSummary};
 use storage::engine::Error as EngineError;
-use pd::PdTask;
+use pd::{self, PdTask, RegionLoads, RegionReadFlows};
+use pd::split_controller;
 
 use super::codec::mysql;
 use super::codec::datum::Datum;
@@ -72,9 +73,10 @@ pub struct Host {
     high_priority_pool: ThreadPool<CopContext>,
     max_running_task_count: usize,
     batch_row_limit: usize,
+    enable_batch_exec: bool,
 }
 
-pub type CopRequestStatistics = HashMap<u64, FlowStatistics>;
+pub type CopRequestStatistics = RegionReadFlows;
 
 pub trait CopSender: Send + Clone {
     fn send(&self, CopRequestStatistics) -> Result<()>;
@@ -91,6 +93,7 @@ impl ContextFactory<CopContext> for CopContextFactory {
             select_stats: Default::default(),
             index_stats: Default::default(),
             request_stats: HashMap::default(),
+            request_loads: HashMap::default(),
         }
     }
 }
@@ -99,6 +102,7 @@ struct CopContext {
     select_stats: StatisticsSummary,
     index_stats: StatisticsSummary,
     request_stats: CopRequestStatistics,
+    request_loads: RegionLoads,
     sender: FutureScheduler<PdTask>,
 }
 
@@ -119,11 +123,13 @@ impl CopContext {
     }
 
     fn add_statistics_by_region(&mut self, region_id: u64, stats: &Statistics) {
-        let flow_stats = self.request_stats
-            .entry(region_id)
-            .or_insert_with(FlowStatistics::default);
-        flow_stats.add(&stats.write.flow_stats);
-        flow_stats.add(&stats.data.flow_stats);
+        pd::record_read_flow(&mut self.request_stats, region_id, stats);
+    }
+
+    fn add_load_by_region(&mut self, region_id: u64, key: Option<Vec<u8>>) {
+        if let Some(key) = key {
+            split_controller::record_load(&mut self.request_loads, region_id, &key);
+        }
     }
 }
 
@@ -153,6 +159,15 @@ impl Context for CopContext {
                 error!("send coprocessor statistics: {:?}", e);
             };
         }
+        if !self.request_loads.is_empty() {
+            let mut to_send_loads = HashMap::default();
+            mem::swap(&mut to_send_loads, &mut self.request_loads);
+            if let Err(e) = self.sender.schedule(PdTask::LoadStats {
+                loads: to_send_loads,
+            }) {
+                error!("send coprocessor load statistics: {:?}", e);
+            };
+        }
     }
 }
 
@@ -170,6 +185,7 @@ impl Host {
             last_req_id: 0,
             max_running_task_count: cfg.end_point_max_tasks,
             batch_row_limit: cfg.end_point_batch_row_limit,
+            enable_batch_exec: cfg.end_point_enable_batch_exec,
             pool: ThreadPoolBuilder::new(
                 thd_name!("endpoint-normal-pool"),
                 CopContextFactory { sender: r.clone() },
@@ -212,6 +228,7 @@ impl Host {
         }
 
         let batch_row_limit = self.batch_row_limit;
+        let enable_batch_exec = self.enable_batch_exec;
         for req in reqs {
             let pri = req.priority();
             let pri_str = get_req_pri_str(pri);
@@ -219,7 +236,7 @@ impl Host {
             COPR_PENDING_REQS
                 .with_label_values(&[type_str, pri_str])
                 .add(1.0);
-            let end_point = TiDbEndPoint::new(snap.clone());
+            let end_point = TiDbEndPoint::new(snap.clone(), enable_batch_exec);
 
             let pool = match pri {
                 CommandPri::Low => &mut self.low_priority_pool,
@@ -228,9 +245,11 @@ impl Host {
             };
             pool.execute(move |ctx: &mut CopContext| {
                 let region_id = req.req.get_context().get_region_id();
+                let sample_key = req.sample_key();
                 let stats = end_point.handle_request(req, batch_row_limit);
                 ctx.add_statistics(type_str, &stats);
                 ctx.add_statistics_by_region(region_id, &stats);
+                ctx.add_load_by_region(region_id, sample_key);
                 COPR_PENDING_REQS
                     .with_label_values(&[type_str, pri_str])
                     .dec();
@@ -429,6 +448,15 @@ impl RequestTask {
     pub fn priority(&self) -> CommandPri {
         self.req.get_context().get_priority()
     }
+
+    /// Returns the encoded start key of the first range, which is used for
+    /// load-based split.
+    fn sample_key(&self) -> Option<Vec<u8>> {
+        self.req
+            .get_ranges()
+            .get(0)
+            .map(|r| Key::from_raw(r.get_start()).encoded().clone())
+    }
 }
 
 impl Display for RequestTask {
@@ -617,11 +645,15 @@ fn respond(resp: Response, mut t: RequestTask) -> Statistics {
 
 pub struct TiDbEndPoint {
     snap: Box<Snapshot>,
+    enable_batch_exec: bool,
 }
 
 impl TiDbEndPoint {
-    pub fn new(snap: Box<Snapshot>) -> TiDbEndPoint {
-        TiDbEndPoint { snap: snap }
+    pub fn new(snap: Box<Snapshot>, enable_batch_exec: bool) -> TiDbEndPoint {
+        TiDbEndPoint {
+            snap: snap,
+            enable_batch_exec: enable_batch_exec,
+        }
     }
 }
 
@@ -664,7 +696,14 @@ impl TiDbEndPoint {
         batch_row_limit: usize,
     ) -> Result<Response> {
         let ranges = t.req.take_ranges().into_vec();
-        let mut ctx = DAGContext::new(dag, ranges, self.snap, t.ctx.clone(), batch_row_limit)?;
+        let mut ctx = DAGContext::new(
+            dag,
+            ranges,
+            self.snap,
+            t.ctx.clone(),
+            batch_row_limit,
+            self.enable_batch_exec,
+        )?;
         let res = ctx.handle_request();
         ctx.collect_statistics_into(&mut t.statistics);
         res
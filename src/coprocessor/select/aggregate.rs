@@ -11,7 +11,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{mem, u64};
 use std::cmp::Ordering;
+
 use tipb::expression::ExprType;
 
 use coprocessor::codec::Datum;
@@ -19,6 +21,10 @@ use coprocessor::Result;
 
 use super::xeval::{evaluator, EvalContext};
 
+/// The default of MySQL's `group_concat_max_len`, the request doesn't carry
+/// the session value yet.
+pub const GROUP_CONCAT_MAX_LEN: usize = 1024;
+
 pub fn build_aggr_func(tp: ExprType) -> Result<Box<AggrFunc>> {
     match tp {
         ExprType::Count => Ok(box Count { c: 0 }),
@@ -30,6 +36,22 @@ pub fn build_aggr_func(tp: ExprType) -> Result<Box<AggrFunc>> {
         }),
         ExprType::Max => Ok(box Extremum::new(Ordering::Less)),
         ExprType::Min => Ok(box Extremum::new(Ordering::Greater)),
+        ExprType::Agg_BitAnd => Ok(box BitOp::new(u64::MAX, |l, r| l & r)),
+        ExprType::Agg_BitOr => Ok(box BitOp::new(0, |l, r| l | r)),
+        ExprType::Agg_BitXor => Ok(box BitOp::new(0, |l, r| l ^ r)),
+        ExprType::GroupConcat => Ok(box GroupConcat::new(GROUP_CONCAT_MAX_LEN)),
+        ExprType::Std |
+        ExprType::Stddev |
+        ExprType::StddevPop |
+        ExprType::StddevSamp |
+        ExprType::VarPop |
+        ExprType::VarSamp |
+        ExprType::Variance => Ok(box Variance {
+            cnt: 0,
+            sum: Sum { res: None },
+            mean: 0f64,
+            m2: 0f64,
+        }),
         et => Err(box_err!("unsupport AggrExprType: {:?}", et)),
     }
 }
@@ -192,3 +214,261 @@ impl AggrFunc for Extremum {
         Ok(())
     }
 }
+
+struct BitOp {
+    c: u64,
+    op: fn(u64, u64) -> u64,
+}
+
+impl BitOp {
+    fn new(init: u64, op: fn(u64, u64) -> u64) -> BitOp {
+        BitOp { c: init, op: op }
+    }
+}
+
+impl AggrFunc for BitOp {
+    fn update(&mut self, ctx: &EvalContext, mut args: Vec<Datum>) -> Result<()> {
+        if args.len() != 1 {
+            return Err(box_err!(
+                "bit operations only support one column, but got {}",
+                args.len()
+            ));
+        }
+        let v = match args.pop().unwrap() {
+            Datum::Null => return Ok(()),
+            Datum::I64(i) => i as u64,
+            Datum::U64(u) => u,
+            // MySQL rounds the other values to integers.
+            d => box_try!(d.into_f64(ctx)).round() as i64 as u64,
+        };
+        self.c = (self.op)(self.c, v);
+        Ok(())
+    }
+
+    fn calc(&mut self, collector: &mut Vec<Datum>) -> Result<()> {
+        collector.push(Datum::U64(self.c));
+        Ok(())
+    }
+}
+
+/// `GroupConcat` concats the values of each row with the separator passed as
+/// its last argument, rows with any null value are skipped. The partial result
+/// is the concated string truncated to `max_len` bytes, or null if there is no
+/// row.
+struct GroupConcat {
+    max_len: usize,
+    has_row: bool,
+    res: Vec<u8>,
+}
+
+impl GroupConcat {
+    fn new(max_len: usize) -> GroupConcat {
+        GroupConcat {
+            max_len: max_len,
+            has_row: false,
+            res: vec![],
+        }
+    }
+}
+
+impl AggrFunc for GroupConcat {
+    fn update(&mut self, _: &EvalContext, mut args: Vec<Datum>) -> Result<()> {
+        if args.len() < 2 {
+            return Err(box_err!(
+                "group_concat needs values and a separator, but got {} args",
+                args.len()
+            ));
+        }
+        let sep = args.pop().unwrap();
+        if args.iter().any(|v| *v == Datum::Null) {
+            return Ok(());
+        }
+        if self.has_row {
+            // The rows after the truncated one never show up in the result.
+            if self.res.len() >= self.max_len {
+                return Ok(());
+            }
+            if sep != Datum::Null {
+                self.res
+                    .extend_from_slice(box_try!(sep.into_string()).as_bytes());
+            }
+        }
+        self.has_row = true;
+        for arg in args {
+            self.res
+                .extend_from_slice(box_try!(arg.into_string()).as_bytes());
+        }
+        Ok(())
+    }
+
+    fn calc(&mut self, collector: &mut Vec<Datum>) -> Result<()> {
+        if !self.has_row {
+            collector.push(Datum::Null);
+            return Ok(());
+        }
+        let mut res = mem::replace(&mut self.res, vec![]);
+        res.truncate(self.max_len);
+        self.has_row = false;
+        collector.push(Datum::Bytes(res));
+        Ok(())
+    }
+}
+
+/// `Variance` is the partial aggregation of the variance and standard
+/// deviation functions, it returns the count, sum and population variance of
+/// the values, from which TiDB merges the partial results and calculates the
+/// final result. The variance is calculated with Welford's online algorithm.
+struct Variance {
+    cnt: u64,
+    sum: Sum,
+    mean: f64,
+    // The sum of squares of differences from the current mean.
+    m2: f64,
+}
+
+impl AggrFunc for Variance {
+    fn update(&mut self, ctx: &EvalContext, mut args: Vec<Datum>) -> Result<()> {
+        if args.len() != 1 {
+            return Err(box_err!(
+                "variance only support one column, but got {}",
+                args.len()
+            ));
+        }
+        let a = args.pop().unwrap();
+        if a == Datum::Null {
+            return Ok(());
+        }
+        let v = box_try!(a.clone().into_f64(ctx));
+        self.sum.add_asssign(ctx, vec![a])?;
+        self.cnt += 1;
+        let delta = v - self.mean;
+        self.mean += delta / self.cnt as f64;
+        self.m2 += delta * (v - self.mean);
+        Ok(())
+    }
+
+    fn calc(&mut self, collector: &mut Vec<Datum>) -> Result<()> {
+        collector.push(Datum::U64(self.cnt));
+        self.sum.calc(collector)?;
+        if self.cnt == 0 {
+            collector.push(Datum::Null);
+        } else {
+            collector.push(Datum::F64(self.m2 / self.cnt as f64));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::u64;
+
+    use tipb::expression::ExprType;
+
+    use coprocessor::codec::Datum;
+    use coprocessor::codec::mysql::Decimal;
+    use coprocessor::select::xeval::EvalContext;
+
+    use super::*;
+
+    fn aggregate(aggr: &mut AggrFunc, rows: Vec<Vec<Datum>>) -> Vec<Datum> {
+        let ctx = EvalContext::default();
+        for row in rows {
+            aggr.update(&ctx, row).unwrap();
+        }
+        let mut res = vec![];
+        aggr.calc(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_bit_op() {
+        let rows = vec![
+            vec![Datum::I64(7)],
+            vec![Datum::Null],
+            vec![Datum::U64(13)],
+            vec![Datum::F64(2.6)],
+        ];
+        let cases = vec![
+            (ExprType::Agg_BitAnd, 1),
+            (ExprType::Agg_BitOr, 15),
+            (ExprType::Agg_BitXor, 7 ^ 13 ^ 3),
+        ];
+        for (tp, exp) in cases {
+            let mut aggr = build_aggr_func(tp).unwrap();
+            let res = aggregate(aggr.as_mut(), rows.clone());
+            assert_eq!(res, vec![Datum::U64(exp)], "{:?}", tp);
+        }
+
+        // The result of an empty group is the identity of the operation.
+        let mut aggr = build_aggr_func(ExprType::Agg_BitAnd).unwrap();
+        assert_eq!(aggregate(aggr.as_mut(), vec![]), vec![Datum::U64(u64::MAX)]);
+        let mut aggr = build_aggr_func(ExprType::Agg_BitOr).unwrap();
+        assert!(aggr.update(&EvalContext::default(), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_group_concat() {
+        let sep = || Datum::Bytes(b",".to_vec());
+        let rows = vec![
+            vec![Datum::Bytes(b"b".to_vec()), Datum::I64(1), sep()],
+            vec![Datum::Bytes(b"a".to_vec()), Datum::I64(2), sep()],
+            vec![Datum::Null, Datum::I64(3), sep()],
+            vec![Datum::Bytes(b"c".to_vec()), Datum::I64(3), sep()],
+        ];
+        let mut aggr = build_aggr_func(ExprType::GroupConcat).unwrap();
+        let res = aggregate(aggr.as_mut(), rows);
+        assert_eq!(res, vec![Datum::Bytes(b"b1,a2,c3".to_vec())]);
+
+        assert_eq!(aggregate(aggr.as_mut(), vec![]), vec![Datum::Null]);
+        assert!(aggr.update(&EvalContext::default(), vec![sep()]).is_err());
+    }
+
+    #[test]
+    fn test_group_concat_max_len() {
+        let rows: Vec<_> = (0..10)
+            .map(|i| vec![Datum::I64(i), Datum::Bytes(b",".to_vec())])
+            .collect();
+        let cases = vec![
+            (1, "0"),
+            (4, "0,1,"),
+            (5, "0,1,2"),
+            (100, "0,1,2,3,4,5,6,7,8,9"),
+        ];
+        for (max_len, exp) in cases {
+            let mut aggr = GroupConcat::new(max_len);
+            let res = aggregate(&mut aggr, rows.clone());
+            assert_eq!(res, vec![Datum::Bytes(exp.as_bytes().to_vec())], "{}", max_len);
+        }
+    }
+
+    #[test]
+    fn test_variance() {
+        let rows = vec![
+            vec![Datum::I64(1)],
+            vec![Datum::Null],
+            vec![Datum::I64(2)],
+            vec![Datum::Dec("1.5".parse::<Decimal>().unwrap())],
+        ];
+        for tp in vec![ExprType::Variance, ExprType::StddevSamp, ExprType::VarPop] {
+            let mut aggr = build_aggr_func(tp).unwrap();
+            let res = aggregate(aggr.as_mut(), rows.clone());
+            assert_eq!(res[..2], [Datum::U64(3), Datum::Dec("4.5".parse().unwrap())]);
+            match res[2] {
+                Datum::F64(f) => assert!((f - 1.0 / 6.0).abs() < 1e-9, "{:?} {}", tp, f),
+                ref d => panic!("unexpected variance {:?}", d),
+            }
+        }
+
+        // Large values with a small variance keep their precision.
+        let mut aggr = build_aggr_func(ExprType::VarPop).unwrap();
+        let base = 1e9 as i64;
+        let rows = (0..4).map(|i| vec![Datum::I64(base + i)]).collect();
+        let res = aggregate(aggr.as_mut(), rows);
+        assert_eq!(res[2], Datum::F64(1.25));
+
+        let mut aggr = build_aggr_func(ExprType::Std).unwrap();
+        let res = aggregate(aggr.as_mut(), vec![]);
+        assert_eq!(res, vec![Datum::U64(0), Datum::Null, Datum::Null]);
+    }
+}
@@ -20,6 +20,7 @@ use tipb::expression::ByItem;
 
 use coprocessor::codec::table::RowColsDict;
 use coprocessor::codec::datum::Datum;
+use coprocessor::codec::mysql::collation;
 use coprocessor::Result;
 
 use super::xeval::EvalContext;
@@ -59,7 +60,14 @@ impl SortRow {
         self.check_err()?;
         let values = self.key.iter().zip(right.key.iter());
         for (col, (v1, v2)) in self.order_cols.as_ref().iter().zip(values) {
-            match v1.cmp(self.ctx.as_ref(), v2) {
+            let order = match (v1, v2) {
+                (&Datum::Bytes(ref l), &Datum::Bytes(ref r)) => {
+                    let collation = col.get_expr().get_field_type().get_collate();
+                    Ok(collation::compare(collation, l, r))
+                }
+                _ => v1.cmp(self.ctx.as_ref(), v2),
+            };
+            match order {
                 Ok(Ordering::Equal) => {
                     continue;
                 }
@@ -184,6 +192,7 @@ mod tests {
     use util::collections::HashMap;
     use util::codec::number::*;
     use coprocessor::codec::Datum;
+    use coprocessor::codec::mysql::charset;
     use coprocessor::codec::table::RowColsDict;
     use coprocessor::select::xeval::EvalContext;
 
@@ -456,6 +465,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_topn_heap_with_collation() {
+        let mut order_cols = Vec::new();
+        order_cols.push(new_order_by(0, false));
+        order_cols.push(new_order_by(1, false));
+        order_cols[0]
+            .mut_expr()
+            .mut_field_type()
+            .set_collate(charset::COLLATION_ID_UTF8MB4_GENERAL_CI);
+        let order_cols = Arc::new(order_cols);
+        let ctx = Arc::new(EvalContext::default());
+        let mut topn_heap = TopNHeap::new(10).unwrap();
+        let test_data = vec![
+            (1, b"b".to_vec(), 1),
+            (2, b"A".to_vec(), 2),
+            (3, b"a".to_vec(), 1),
+            (4, b"C".to_vec(), 1),
+            (5, b"B".to_vec(), 0),
+        ];
+        for (handle, name, count) in test_data {
+            let cur_key = vec![Datum::Bytes(name), Datum::I64(count)];
+            let row_data = RowColsDict::new(HashMap::default(), vec![]);
+            topn_heap
+                .try_add_row(handle, row_data, cur_key, order_cols.clone(), ctx.clone())
+                .unwrap();
+        }
+
+        let result = topn_heap.into_sorted_vec().unwrap();
+        let handles: Vec<i64> = result.iter().map(|row| row.handle).collect();
+        assert_eq!(handles, vec![3, 2, 5, 1, 4]);
+    }
+
     #[test]
     fn test_topn_limit_oom() {
         let topn_heap = TopNHeap::new(usize::MAX - 1);
@@ -47,6 +47,7 @@ extern crate kvproto;
 extern crate time;
 extern crate tipb;
 extern crate libc;
+extern crate lz4_sys;
 extern crate crc;
 extern crate alloc;
 extern crate chrono;
@@ -249,8 +249,11 @@ impl PdClient for RpcClient {
         req.set_bytes_written(region_stat.written_bytes);
         req.set_keys_written(region_stat.written_keys);
         req.set_bytes_read(region_stat.read_bytes);
-        req.set_keys_read(region_stat.read_bytes);
+        req.set_keys_read(region_stat.read_keys);
+        // TODO: report `region_stat.read_qps` once pdpb has a field for it, PD can only
+        // derive it from the read bytes and keys for now.
         req.set_approximate_size(region_stat.approximate_size);
+        req.set_approximate_keys(region_stat.approximate_keys);
 
         let executor = |client: &RwLock<Inner>, req: pdpb::RegionHeartbeatRequest| {
             let mut inner = client.wl();
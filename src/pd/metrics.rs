@@ -69,4 +69,10 @@ lazy_static! {
             "Histogram of keys written for regions",
              exponential_buckets(1.0, 2.0, 20).unwrap()
         ).unwrap();
+
+    pub static ref PD_LOAD_SPLIT_COUNTER: Counter =
+        register_counter!(
+            "tikv_pd_load_split_total",
+            "Total number of regions split by load."
+        ).unwrap();
 }
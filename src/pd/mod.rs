@@ -18,6 +18,7 @@ mod util;
 
 pub mod errors;
 pub mod pd;
+pub mod split_controller;
 mod config;
 pub use self::errors::{Error, Result};
 pub use self::client::RpcClient;
@@ -25,11 +26,16 @@ pub use self::util::validate_endpoints;
 pub use self::pd::{Runner as PdRunner, Task as PdTask};
 pub use self::util::RECONNECT_INTERVAL_SEC;
 pub use self::config::Config;
+pub use self::split_controller::{AutoSplitController, RegionLoad, RegionLoads};
+pub use self::pd::{FlowType, HotRegion};
 
 use kvproto::metapb;
 use kvproto::pdpb;
 use futures::Future;
 
+use storage::Statistics;
+use util::collections::HashMap;
+
 pub type Key = Vec<u8>;
 pub type PdFuture<T> = Box<Future<Item = T, Error = Error> + Send>;
 
@@ -41,7 +47,10 @@ pub struct RegionStat {
     pub written_keys: u64,
     pub read_bytes: u64,
     pub read_keys: u64,
+    // Read requests per second during the last heartbeat interval.
+    pub read_qps: f64,
     pub approximate_size: u64,
+    pub approximate_keys: u64,
 }
 
 impl RegionStat {
@@ -52,7 +61,9 @@ impl RegionStat {
         written_keys: u64,
         read_bytes: u64,
         read_keys: u64,
+        read_qps: f64,
         approximate_size: u64,
+        approximate_keys: u64,
     ) -> RegionStat {
         RegionStat {
             down_peers: down_peers,
@@ -61,11 +72,49 @@ impl RegionStat {
             written_keys: written_keys,
             read_bytes: read_bytes,
             read_keys: read_keys,
+            read_qps: read_qps,
             approximate_size: approximate_size,
+            approximate_keys: approximate_keys,
+        }
+    }
+}
+
+/// The read flow of a region served by the storage and the coprocessor.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct RegionReadFlow {
+    pub read_bytes: u64,
+    pub read_keys: u64,
+    /// Number of read requests served.
+    pub read_requests: u64,
+}
+
+impl RegionReadFlow {
+    /// Record the statistics of a read request.
+    pub fn add_request(&mut self, stats: &Statistics) {
+        for flow_stats in &[stats.write.flow_stats, stats.data.flow_stats] {
+            self.read_bytes += flow_stats.read_bytes as u64;
+            self.read_keys += flow_stats.read_keys as u64;
         }
+        self.read_requests += 1;
+    }
+
+    pub fn add(&mut self, other: &RegionReadFlow) {
+        self.read_bytes += other.read_bytes;
+        self.read_keys += other.read_keys;
+        self.read_requests += other.read_requests;
     }
 }
 
+pub type RegionReadFlows = HashMap<u64, RegionReadFlow>;
+
+/// Record a read request of `region_id` into `flows`.
+pub fn record_read_flow(flows: &mut RegionReadFlows, region_id: u64, stats: &Statistics) {
+    flows
+        .entry(region_id)
+        .or_insert_with(RegionReadFlow::default)
+        .add_request(stats);
+}
+
 pub const INVALID_ID: u64 = 0;
 
 // Client to communicate with placement driver (pd) for special cluster.
@@ -13,6 +13,8 @@
 
 use std::sync::Arc;
 use std::fmt::{self, Display, Formatter};
+use std::boxed::FnBox;
+use std::time::Instant;
 
 use futures::Future;
 use tokio_core::reactor::Handle;
@@ -23,19 +25,18 @@ use kvproto::raft_cmdpb::{AdminCmdType, AdminRequest, RaftCmdRequest};
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::pdpb;
 use rocksdb::DB;
-use fs2;
 
 use util::worker::FutureRunnable as Runnable;
 use util::escape;
-use util::transport::SendCh;
 use util::rocksdb::*;
-use pd::{PdClient, RegionStat};
-use raftstore::store::Msg;
-use raftstore::store::util::{get_region_approximate_size, is_epoch_stale};
+use pd::{AutoSplitController, PdClient, RegionLoads, RegionReadFlow, RegionReadFlows, RegionStat};
+use raftstore::store::{Msg, RouterSendCh};
+use raftstore::store::util::{get_region_approximate_keys, get_region_approximate_size,
+                             is_epoch_stale};
 use raftstore::store::store::StoreInfo;
 use raftstore::store::Callback;
-use storage::FlowStatistics;
 use util::collections::HashMap;
+use util::time::duration_to_sec;
 use prometheus::local::LocalHistogram;
 use super::metrics::*;
 
@@ -57,6 +58,7 @@ pub enum Task {
         written_bytes: u64,
         written_keys: u64,
         region_size: Option<u64>,
+        region_keys: Option<u64>,
     },
     StoreHeartbeat {
         stats: pdpb::StoreStats,
@@ -70,10 +72,47 @@ pub enum Task {
         region: metapb::Region,
         peer: metapb::Peer,
     },
-    ReadStats { read_stats: HashMap<u64, FlowStatistics>, },
+    ReadStats { read_stats: RegionReadFlows },
+    LoadStats { loads: RegionLoads },
     DestroyPeer { region_id: u64 },
+    GetHotRegions {
+        flow_type: FlowType,
+        limit: usize,
+        callback: HotRegionsCallback,
+    },
+}
+
+/// The flow that hot regions are ranked by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowType {
+    Read,
+    Write,
+}
+
+/// The flow of a region during its last heartbeat interval.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct HotRegion {
+    pub region_id: u64,
+    pub interval_secs: f64,
+    pub read_bytes: u64,
+    pub read_keys: u64,
+    pub read_qps: f64,
+    pub written_bytes: u64,
+    pub written_keys: u64,
 }
 
+impl HotRegion {
+    fn bytes_per_sec(&self, flow_type: FlowType) -> f64 {
+        let bytes = match flow_type {
+            FlowType::Read => self.read_bytes,
+            FlowType::Write => self.written_bytes,
+        };
+        bytes as f64 / self.interval_secs
+    }
+}
+
+pub type HotRegionsCallback = Box<FnBox(Vec<HotRegion>) + Send>;
+
 pub struct StoreStat {
     pub engine_total_bytes_read: u64,
     pub engine_total_keys_read: u64,
@@ -106,10 +145,59 @@ impl Default for StoreStat {
 pub struct PeerStat {
     pub read_bytes: u64,
     pub read_keys: u64,
+    pub read_requests: u64,
     pub last_read_bytes: u64,
     pub last_read_keys: u64,
+    pub last_read_requests: u64,
     pub last_written_bytes: u64,
     pub last_written_keys: u64,
+    pub last_report_time: Option<Instant>,
+    // The flow between the last two heartbeats, used to find the hot regions.
+    pub last_flow: Option<HotRegion>,
+}
+
+impl PeerStat {
+    fn update_flow(&mut self, region_id: u64, read: RegionReadFlow, written: (u64, u64)) {
+        let now = Instant::now();
+        let last_report_time = self.last_report_time.take();
+        self.last_report_time = Some(now);
+        let interval_secs = match last_report_time {
+            Some(t) => duration_to_sec(now.duration_since(t)),
+            None => return,
+        };
+        if interval_secs <= 0.0 {
+            return;
+        }
+        self.last_flow = Some(HotRegion {
+            region_id: region_id,
+            interval_secs: interval_secs,
+            read_bytes: read.read_bytes,
+            read_keys: read.read_keys,
+            read_qps: read.read_requests as f64 / interval_secs,
+            written_bytes: written.0,
+            written_keys: written.1,
+        });
+    }
+}
+
+/// Returns at most `limit` regions with the most bytes per second of `flow_type`.
+fn top_hot_regions(
+    region_peers: &HashMap<u64, PeerStat>,
+    flow_type: FlowType,
+    limit: usize,
+) -> Vec<HotRegion> {
+    let mut regions: Vec<_> = region_peers
+        .values()
+        .filter_map(|stat| stat.last_flow.clone())
+        .filter(|flow| flow.bytes_per_sec(flow_type) > 0.0)
+        .collect();
+    regions.sort_by(|a, b| {
+        b.bytes_per_sec(flow_type)
+            .partial_cmp(&a.bytes_per_sec(flow_type))
+            .unwrap()
+    });
+    regions.truncate(limit);
+    regions
 }
 
 impl Display for Task {
@@ -149,7 +237,13 @@ impl Display for Task {
             Task::ReadStats { ref read_stats } => {
                 write!(f, "get the read statistics {:?}", read_stats)
             }
+            Task::LoadStats { ref loads } => {
+                write!(f, "get the load statistics of {} regions", loads.len())
+            }
             Task::DestroyPeer { ref region_id } => write!(f, "destroy peer {}", region_id),
+            Task::GetHotRegions {
+                flow_type, limit, ..
+            } => write!(f, "get top {} hot {:?} regions", limit, flow_type),
         }
     }
 }
@@ -157,15 +251,22 @@ impl Display for Task {
 pub struct Runner<T: PdClient> {
     store_id: u64,
     pd_client: Arc<T>,
-    ch: SendCh<Msg>,
+    ch: RouterSendCh,
     db: Arc<DB>,
     region_peers: HashMap<u64, PeerStat>,
     store_stat: StoreStat,
     is_hb_receiver_scheduled: bool,
+    auto_split_controller: AutoSplitController,
 }
 
 impl<T: PdClient> Runner<T> {
-    pub fn new(store_id: u64, pd_client: Arc<T>, ch: SendCh<Msg>, db: Arc<DB>) -> Runner<T> {
+    pub fn new(
+        store_id: u64,
+        pd_client: Arc<T>,
+        ch: RouterSendCh,
+        db: Arc<DB>,
+        auto_split_controller: AutoSplitController,
+    ) -> Runner<T> {
         Runner {
             store_id: store_id,
             pd_client: pd_client,
@@ -174,6 +275,7 @@ impl<T: PdClient> Runner<T> {
             is_hb_receiver_scheduled: false,
             region_peers: HashMap::default(),
             store_stat: StoreStat::default(),
+            auto_split_controller: auto_split_controller,
         }
     }
 
@@ -255,7 +357,11 @@ impl<T: PdClient> Runner<T> {
         mut stats: pdpb::StoreStats,
         store_info: StoreInfo,
     ) {
-        let disk_stats = match fs2::statvfs(store_info.engine.path()) {
+        let disk_stats = match get_disk_stats(
+            store_info.engine.clone(),
+            store_info.capacity,
+            stats.get_used_size(),
+        ) {
             Err(e) => {
                 error!(
                     "get disk stat for rocksdb {} failed: {}",
@@ -266,33 +372,9 @@ impl<T: PdClient> Runner<T> {
             }
             Ok(stats) => stats,
         };
-
-        let disk_cap = disk_stats.total_space();
-        let capacity = if store_info.capacity == 0 || disk_cap < store_info.capacity {
-            disk_cap
-        } else {
-            store_info.capacity
-        };
-        stats.set_capacity(capacity);
-
-        let used_size = stats.get_used_size() + get_engine_used_size(store_info.engine.clone());
-        stats.set_used_size(used_size);
-
-
-        let mut available = if capacity > used_size {
-            capacity - used_size
-        } else {
-            warn!("no available space");
-            0
-        };
-
-        // We only care rocksdb SST file size, so we should
-        // check disk available here.
-        if available > disk_stats.free_space() {
-            available = disk_stats.free_space();
-        }
-
-        stats.set_available(available);
+        stats.set_capacity(disk_stats.capacity);
+        stats.set_used_size(disk_stats.used_size);
+        stats.set_available(disk_stats.available);
         stats.set_bytes_read(
             self.store_stat.engine_total_bytes_read - self.store_stat.engine_last_total_bytes_read,
         );
@@ -309,15 +391,37 @@ impl<T: PdClient> Runner<T> {
 
         STORE_SIZE_GAUGE_VEC
             .with_label_values(&["capacity"])
-            .set(capacity as f64);
+            .set(disk_stats.capacity as f64);
         STORE_SIZE_GAUGE_VEC
             .with_label_values(&["available"])
-            .set(available as f64);
+            .set(disk_stats.available as f64);
 
         let f = self.pd_client.store_heartbeat(stats).map_err(|e| {
             error!("store heartbeat failed {:?}", e);
         });
         handle.spawn(f);
+
+        self.check_load_split();
+    }
+
+    fn check_load_split(&mut self) {
+        for (mut region, split_key) in self.auto_split_controller.flush() {
+            PD_LOAD_SPLIT_COUNTER.inc();
+            let region_id = region.get_id();
+            let msg = Msg::SplitRegion {
+                region_id: region_id,
+                region_epoch: region.take_region_epoch(),
+                split_key: split_key,
+                callback: None,
+            };
+            if let Err(e) = self.ch.try_send(msg) {
+                error!(
+                    "[region {}] failed to send load split request: {:?}",
+                    region_id,
+                    e
+                );
+            }
+        }
     }
 
     fn handle_report_split(&self, handle: &Handle, left: metapb::Region, right: metapb::Region) {
@@ -440,19 +544,25 @@ impl<T: PdClient> Runner<T> {
         self.is_hb_receiver_scheduled = true;
     }
 
-    fn handle_read_stats(&mut self, read_stats: HashMap<u64, FlowStatistics>) {
+    fn handle_read_stats(&mut self, read_stats: RegionReadFlows) {
         for (region_id, stats) in read_stats {
             let peer_stat = self.region_peers
                 .entry(region_id)
                 .or_insert_with(PeerStat::default);
-            peer_stat.read_bytes += stats.read_bytes as u64;
-            peer_stat.read_keys += stats.read_keys as u64;
-            self.store_stat.engine_total_bytes_read += stats.read_bytes as u64;
-            self.store_stat.engine_total_keys_read += stats.read_keys as u64;
+            peer_stat.read_bytes += stats.read_bytes;
+            peer_stat.read_keys += stats.read_keys;
+            peer_stat.read_requests += stats.read_requests;
+            self.store_stat.engine_total_bytes_read += stats.read_bytes;
+            self.store_stat.engine_total_keys_read += stats.read_keys;
         }
     }
 
+    fn handle_load_stats(&mut self, loads: RegionLoads) {
+        self.auto_split_controller.add_loads(loads);
+    }
+
     fn handle_destroy_peer(&mut self, region_id: u64) {
+        self.auto_split_controller.remove(region_id);
         match self.region_peers.remove(&region_id) {
             None => return,
             Some(_) => info!("[region {}] remove peer statistic record in pd", region_id),
@@ -484,29 +594,41 @@ impl<T: PdClient> Runnable<Task> for Runner<T> {
                 written_bytes,
                 written_keys,
                 region_size,
+                region_keys,
             } => {
+                self.auto_split_controller.update_region(&region);
                 let approximate_size = match region_size {
                     Some(size) => size,
                     None => get_region_approximate_size(&self.db, &region).unwrap_or(0),
                 };
-                let (read_bytes_delta, read_keys_delta, written_bytes_delta, written_keys_delta) = {
+                let approximate_keys = match region_keys {
+                    Some(keys) => keys,
+                    None => get_region_approximate_keys(&self.db, &region).unwrap_or(0),
+                };
+                let (read_delta, read_qps, written_bytes_delta, written_keys_delta) = {
                     let peer_stat = self.region_peers
                         .entry(region.get_id())
                         .or_insert_with(PeerStat::default);
-                    let read_bytes_delta = peer_stat.read_bytes - peer_stat.last_read_bytes;
-                    let read_keys_delta = peer_stat.read_keys - peer_stat.last_read_keys;
+                    let read_delta = RegionReadFlow {
+                        read_bytes: peer_stat.read_bytes - peer_stat.last_read_bytes,
+                        read_keys: peer_stat.read_keys - peer_stat.last_read_keys,
+                        read_requests: peer_stat.read_requests - peer_stat.last_read_requests,
+                    };
                     let written_bytes_delta = written_bytes - peer_stat.last_written_bytes;
                     let written_keys_delta = written_keys - peer_stat.last_written_keys;
                     peer_stat.last_written_bytes = written_bytes;
                     peer_stat.last_written_keys = written_keys;
                     peer_stat.last_read_bytes = peer_stat.read_bytes;
                     peer_stat.last_read_keys = peer_stat.read_keys;
-                    (
-                        read_bytes_delta,
-                        read_keys_delta,
-                        written_bytes_delta,
-                        written_keys_delta,
-                    )
+                    peer_stat.last_read_requests = peer_stat.read_requests;
+                    peer_stat.update_flow(
+                        region.get_id(),
+                        read_delta,
+                        (written_bytes_delta, written_keys_delta),
+                    );
+                    // The same QPS as the one served by the status server.
+                    let read_qps = peer_stat.last_flow.as_ref().map_or(0.0, |f| f.read_qps);
+                    (read_delta, read_qps, written_bytes_delta, written_keys_delta)
                 };
                 self.handle_heartbeat(
                     handle,
@@ -517,9 +639,11 @@ impl<T: PdClient> Runnable<Task> for Runner<T> {
                         pending_peers,
                         written_bytes_delta,
                         written_keys_delta,
-                        read_bytes_delta,
-                        read_keys_delta,
+                        read_delta.read_bytes,
+                        read_delta.read_keys,
+                        read_qps,
                         approximate_size,
+                        approximate_keys,
                     ),
                 )
             }
@@ -529,7 +653,13 @@ impl<T: PdClient> Runnable<Task> for Runner<T> {
             Task::ReportSplit { left, right } => self.handle_report_split(handle, left, right),
             Task::ValidatePeer { region, peer } => self.handle_validate_peer(handle, region, peer),
             Task::ReadStats { read_stats } => self.handle_read_stats(read_stats),
+            Task::LoadStats { loads } => self.handle_load_stats(loads),
             Task::DestroyPeer { region_id } => self.handle_destroy_peer(region_id),
+            Task::GetHotRegions {
+                flow_type,
+                limit,
+                callback,
+            } => callback(top_hot_regions(&self.region_peers, flow_type, limit)),
         };
     }
 }
@@ -565,7 +695,7 @@ fn new_transfer_leader_request(peer: metapb::Peer) -> AdminRequest {
 }
 
 fn send_admin_request(
-    ch: &SendCh<Msg>,
+    ch: &RouterSendCh,
     region_id: u64,
     epoch: metapb::RegionEpoch,
     peer: metapb::Peer,
@@ -596,7 +726,7 @@ fn send_admin_request(
 
 // send a raft message to destroy the specified stale peer
 fn send_destroy_peer_message(
-    ch: SendCh<Msg>,
+    ch: RouterSendCh,
     local_region: metapb::Region,
     peer: metapb::Peer,
     pd_region: metapb::Region,
@@ -615,3 +745,70 @@ fn send_destroy_peer_message(
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use util::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_peer_stat_update_flow() {
+        let mut stat = PeerStat::default();
+        let read = RegionReadFlow {
+            read_bytes: 10,
+            read_keys: 1,
+            read_requests: 1,
+        };
+        // The first report only records the time.
+        stat.update_flow(1, read, (20, 2));
+        assert!(stat.last_flow.is_none());
+
+        thread::sleep(Duration::from_millis(10));
+        stat.update_flow(1, read, (20, 2));
+        let flow = stat.last_flow.unwrap();
+        assert_eq!(flow.region_id, 1);
+        assert_eq!(flow.read_bytes, 10);
+        assert_eq!(flow.read_keys, 1);
+        assert_eq!(flow.written_bytes, 20);
+        assert_eq!(flow.written_keys, 2);
+        assert!(flow.interval_secs > 0.0);
+        assert!(flow.read_qps > 0.0);
+    }
+
+    #[test]
+    fn test_top_hot_regions() {
+        let mut region_peers = HashMap::default();
+        for &(id, read_bytes, written_bytes) in &[(1, 100, 0), (2, 300, 50), (3, 200, 100)] {
+            let mut stat = PeerStat::default();
+            stat.last_flow = Some(HotRegion {
+                region_id: id,
+                interval_secs: 10.0,
+                read_bytes: read_bytes,
+                written_bytes: written_bytes,
+                ..Default::default()
+            });
+            region_peers.insert(id, stat);
+        }
+        // Regions without flow are ignored.
+        region_peers.insert(4, PeerStat::default());
+
+        let ids = |regions: Vec<HotRegion>| -> Vec<u64> {
+            regions.into_iter().map(|r| r.region_id).collect()
+        };
+        assert_eq!(
+            ids(top_hot_regions(&region_peers, FlowType::Read, 2)),
+            vec![2, 3]
+        );
+        assert_eq!(
+            ids(top_hot_regions(&region_peers, FlowType::Read, 10)),
+            vec![2, 3, 1]
+        );
+        assert_eq!(
+            ids(top_hot_regions(&region_peers, FlowType::Write, 10)),
+            vec![3, 2]
+        );
+    }
+}
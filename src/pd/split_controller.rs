@@ -0,0 +1,308 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+
+use rand::{self, Rng};
+use kvproto::metapb::Region;
+
+use util::collections::HashMap;
+use util::time::{duration_to_sec, Instant};
+
+/// How many keys are sampled for a region by a collector between two flushes.
+pub const DEFAULT_SAMPLE_NUM: usize = 20;
+/// How many keys are kept for a region by the controller between two checks.
+const MAX_SAMPLE_NUM: usize = 200;
+/// A region with less samples than this is not split, the result is not reliable.
+const MIN_SAMPLE_NUM: usize = 10;
+// If the difference of requests between the two halves exceeds this ratio,
+// the split key is considered useless, for example most requests hit one key.
+const BALANCE_TOLERANCE: f64 = 0.5;
+
+/// Load of a region collected by the storage scheduler or the coprocessor.
+#[derive(Debug, Default, Clone)]
+pub struct RegionLoad {
+    /// Number of requests served.
+    pub count: u64,
+    /// Encoded keys sampled from the served requests.
+    pub samples: Vec<Vec<u8>>,
+}
+
+impl RegionLoad {
+    /// Record a request, the key is kept with reservoir sampling.
+    pub fn add(&mut self, key: &[u8]) {
+        self.count += 1;
+        if self.samples.len() < DEFAULT_SAMPLE_NUM {
+            self.samples.push(key.to_vec());
+            return;
+        }
+        let i = rand::thread_rng().gen_range(0, self.count) as usize;
+        if i < DEFAULT_SAMPLE_NUM {
+            self.samples[i] = key.to_vec();
+        }
+    }
+
+    fn merge(&mut self, other: RegionLoad) {
+        self.count += other.count;
+        self.samples.extend(other.samples);
+        if self.samples.len() > MAX_SAMPLE_NUM {
+            rand::thread_rng().shuffle(&mut self.samples);
+            self.samples.truncate(MAX_SAMPLE_NUM);
+        }
+    }
+}
+
+pub type RegionLoads = HashMap<u64, RegionLoad>;
+
+/// Record a request of `region_id` on `key` into `loads`.
+pub fn record_load(loads: &mut RegionLoads, region_id: u64, key: &[u8]) {
+    loads
+        .entry(region_id)
+        .or_insert_with(RegionLoad::default)
+        .add(key);
+}
+
+struct Recorder {
+    // The latest region reported by the leader heartbeat.
+    region: Option<Region>,
+    load: RegionLoad,
+    hot_times: u64,
+    last_check: Instant,
+}
+
+impl Recorder {
+    fn new() -> Recorder {
+        Recorder {
+            region: None,
+            load: RegionLoad::default(),
+            hot_times: 0,
+            last_check: Instant::now_coarse(),
+        }
+    }
+}
+
+/// `AutoSplitController` decides whether a region should be split by its load.
+///
+/// A region is split when its QPS stays above `qps_threshold` for `detect_times`
+/// continuous checks. The split key is chosen from the sampled keys so that
+/// requests are balanced between the two new regions.
+pub struct AutoSplitController {
+    qps_threshold: u64,
+    detect_times: u64,
+    recorders: HashMap<u64, Recorder>,
+}
+
+impl AutoSplitController {
+    pub fn new(qps_threshold: u64, detect_times: u64) -> AutoSplitController {
+        AutoSplitController {
+            qps_threshold: qps_threshold,
+            detect_times: detect_times,
+            recorders: HashMap::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.qps_threshold > 0
+    }
+
+    pub fn add_loads(&mut self, loads: RegionLoads) {
+        if !self.is_enabled() {
+            return;
+        }
+        for (region_id, load) in loads {
+            self.recorders
+                .entry(region_id)
+                .or_insert_with(Recorder::new)
+                .load
+                .merge(load);
+        }
+    }
+
+    /// Update the region info, only regions that have been reported by leader
+    /// heartbeat can be split.
+    pub fn update_region(&mut self, region: &Region) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.recorders
+            .entry(region.get_id())
+            .or_insert_with(Recorder::new)
+            .region = Some(region.clone());
+    }
+
+    pub fn remove(&mut self, region_id: u64) {
+        self.recorders.remove(&region_id);
+    }
+
+    /// Check all recorded regions, returns the regions that need to be split
+    /// along with their split keys.
+    pub fn flush(&mut self) -> Vec<(Region, Vec<u8>)> {
+        let mut res = vec![];
+        if !self.is_enabled() {
+            return res;
+        }
+        let now = Instant::now_coarse();
+        let mut idle_regions = vec![];
+        for (region_id, recorder) in &mut self.recorders {
+            let elapsed = duration_to_sec(now.duration_since(recorder.last_check));
+            recorder.last_check = now;
+            let mut load = RegionLoad::default();
+            ::std::mem::swap(&mut load, &mut recorder.load);
+            if load.count == 0 && recorder.hot_times == 0 {
+                idle_regions.push(*region_id);
+                continue;
+            }
+
+            let qps = load.count as f64 / elapsed.max(1.0);
+            if qps < self.qps_threshold as f64 {
+                recorder.hot_times = 0;
+                continue;
+            }
+            recorder.hot_times += 1;
+            if recorder.hot_times < self.detect_times {
+                continue;
+            }
+            let region = match recorder.region {
+                Some(ref region) => region,
+                None => continue,
+            };
+            if let Some(key) = find_split_key(region, load.samples) {
+                info!(
+                    "[region {}] qps {:.0} >= {} for {} times, try to split by load",
+                    region_id,
+                    qps,
+                    self.qps_threshold,
+                    recorder.hot_times
+                );
+                recorder.hot_times = 0;
+                res.push((region.clone(), key));
+            }
+        }
+        for region_id in idle_regions {
+            self.recorders.remove(&region_id);
+        }
+        res
+    }
+}
+
+/// Find a key in `samples` which splits the requests of the region into two
+/// balanced parts.
+pub fn find_split_key(region: &Region, mut samples: Vec<Vec<u8>>) -> Option<Vec<u8>> {
+    let (start_key, end_key) = (region.get_start_key(), region.get_end_key());
+    samples.retain(|k| {
+        k.as_slice() > start_key && (end_key.is_empty() || k.as_slice() < end_key)
+    });
+    if samples.len() < MIN_SAMPLE_NUM {
+        return None;
+    }
+    samples.sort();
+
+    let total = samples.len();
+    let key = samples[total / 2].clone();
+    let left = samples.iter().take_while(|k| **k < key).count();
+    let right = total - left;
+    let diff = cmp::max(left, right) - cmp::min(left, right);
+    if left == 0 || diff as f64 / total as f64 > BALANCE_TOLERANCE {
+        return None;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_region(start_key: &[u8], end_key: &[u8]) -> Region {
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(start_key.to_vec());
+        region.set_end_key(end_key.to_vec());
+        region
+    }
+
+    #[test]
+    fn test_region_load_sample() {
+        let mut load = RegionLoad::default();
+        for i in 0..1000 {
+            load.add(format!("k{:04}", i).as_bytes());
+        }
+        assert_eq!(load.count, 1000);
+        assert_eq!(load.samples.len(), DEFAULT_SAMPLE_NUM);
+
+        let mut merged = RegionLoad::default();
+        for _ in 0..20 {
+            merged.merge(load.clone());
+        }
+        assert_eq!(merged.count, 20000);
+        assert_eq!(merged.samples.len(), MAX_SAMPLE_NUM);
+    }
+
+    #[test]
+    fn test_find_split_key() {
+        let region = new_region(b"k", b"l");
+        let samples: Vec<_> = (0..20).map(|i| format!("k{:02}", i).into_bytes()).collect();
+        assert_eq!(
+            find_split_key(&region, samples.clone()),
+            Some(b"k10".to_vec())
+        );
+
+        // Keys out of region range are ignored.
+        let region = new_region(b"k10", b"");
+        assert_eq!(find_split_key(&region, samples.clone()), None);
+
+        // A single hot key can't be split.
+        let region = new_region(b"", b"");
+        let mut samples = vec![b"k00".to_vec(); 20];
+        assert_eq!(find_split_key(&region, samples.clone()), None);
+
+        // Unbalanced load is not split either.
+        samples.extend(vec![b"k01".to_vec(); 3]);
+        assert_eq!(find_split_key(&region, samples), None);
+    }
+
+    #[test]
+    fn test_auto_split_controller() {
+        let region = new_region(b"", b"");
+        let mut controller = AutoSplitController::new(1, 2);
+        let mut loads = RegionLoads::default();
+        for i in 0..100 {
+            record_load(&mut loads, 1, format!("k{:02}", i).as_bytes());
+        }
+        // Region is not reported by heartbeat yet.
+        controller.add_loads(loads.clone());
+        assert!(controller.flush().is_empty());
+        controller.add_loads(loads.clone());
+        assert!(controller.flush().is_empty());
+
+        controller.update_region(&region);
+        controller.add_loads(loads.clone());
+        let res = controller.flush();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, region);
+        assert!(!res[0].1.is_empty());
+
+        // Hot times should be reset after split.
+        controller.add_loads(loads.clone());
+        assert!(controller.flush().is_empty());
+
+        // Idle region is removed.
+        assert!(controller.flush().is_empty());
+        assert!(controller.flush().is_empty());
+        assert!(controller.recorders.is_empty());
+
+        let mut controller = AutoSplitController::new(0, 1);
+        controller.update_region(&region);
+        controller.add_loads(loads);
+        assert!(controller.flush().is_empty());
+    }
+}
@@ -29,6 +29,7 @@
 use std::cmp;
 
 use rand::{self, Rng};
+use byteorder::{BigEndian, ByteOrder};
 use kvproto::eraftpb::{Entry, EntryType, HardState, Message, MessageType, Snapshot};
 use protobuf::repeated::RepeatedField;
 
@@ -47,6 +48,9 @@ const CAMPAIGN_PRE_ELECTION: &'static [u8] = b"CampaignPreElection";
 const CAMPAIGN_ELECTION: &'static [u8] = b"CampaignElection";
 // CAMPAIGN_TRANSFER represents the type of leader transfer.
 const CAMPAIGN_TRANSFER: &'static [u8] = b"CampaignTransfer";
+// CAMPAIGN_PRIORITY prefixes the election priority of the candidate in the
+// context of vote requests.
+const CAMPAIGN_PRIORITY: &'static [u8] = b"CampaignPriority";
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum StateRole {
@@ -127,6 +131,18 @@ pub struct Config {
     // May affect proposal forwarding and follower read.
     pub skip_bcast_commit: bool,
 
+    /// witness specifies whether the raft is a witness, which votes and
+    /// replicates logs, but never becomes leader.
+    pub witness: bool,
+
+    /// priority is the election priority of the raft. It refuses to vote for a
+    /// candidate with a lower priority whose log is not newer than its own.
+    pub priority: u64,
+
+    /// election_delay_rounds is the number of extra election timeouts the raft waits
+    /// before campaigning, so that peers with a higher election priority win first.
+    pub election_delay_rounds: usize,
+
     /// tag is only used for logging
     pub tag: String,
 }
@@ -214,6 +230,9 @@ pub struct Raft<T: Storage> {
     pub check_quorum: bool,
     pre_vote: bool,
     skip_bcast_commit: bool,
+    witness: bool,
+    priority: u64,
+    election_delay_rounds: usize,
 
     heartbeat_timeout: usize,
     election_timeout: usize,
@@ -258,6 +277,22 @@ pub fn vote_resp_msg_type(t: MessageType) -> MessageType {
     }
 }
 
+fn encode_priority(priority: u64) -> Vec<u8> {
+    let mut ctx = CAMPAIGN_PRIORITY.to_vec();
+    ctx.extend_from_slice(&[0; 8]);
+    BigEndian::write_u64(&mut ctx[CAMPAIGN_PRIORITY.len()..], priority);
+    ctx
+}
+
+// decode_priority returns the priority carried in the context of the vote
+// request, it's 0 if the candidate doesn't have one.
+fn decode_priority(ctx: &[u8]) -> u64 {
+    if ctx.len() == CAMPAIGN_PRIORITY.len() + 8 && ctx.starts_with(CAMPAIGN_PRIORITY) {
+        return BigEndian::read_u64(&ctx[CAMPAIGN_PRIORITY.len()..]);
+    }
+    0
+}
+
 // Calculate the quorum of a Raft cluster with the specified total nodes.
 pub fn quorum(total: usize) -> usize {
     total / 2 + 1
@@ -306,6 +341,9 @@ impl<T: Storage> Raft<T> {
             heartbeat_elapsed: Default::default(),
             randomized_election_timeout: 0,
             skip_bcast_commit: c.skip_bcast_commit,
+            witness: c.witness,
+            priority: c.priority,
+            election_delay_rounds: c.election_delay_rounds,
             tag: c.tag.to_owned(),
         };
         for p in peers {
@@ -819,6 +857,8 @@ impl<T: Storage> Raft<T> {
             m.set_log_term(self.raft_log.last_term());
             if campaign_type == CAMPAIGN_TRANSFER {
                 m.set_context(campaign_type.to_vec());
+            } else if self.priority > 0 {
+                m.set_context(encode_priority(self.priority));
             }
             self.send(m);
         }
@@ -955,7 +995,9 @@ impl<T: Storage> Raft<T> {
 
 
         match m.get_msg_type() {
-            MessageType::MsgHup => if self.state != StateRole::Leader {
+            MessageType::MsgHup => if self.witness {
+                debug!("{} ignoring MsgHup because it's a witness", self.tag);
+            } else if self.state != StateRole::Leader {
                 let ents = self.raft_log
                     .slice(
                         self.raft_log.applied + 1,
@@ -992,7 +1034,8 @@ impl<T: Storage> Raft<T> {
                 // m.get_term() should always equal self.term
                 if (self.vote == INVALID_ID || m.get_term() > self.term ||
                     self.vote == m.get_from()) &&
-                    self.raft_log.is_up_to_date(m.get_index(), m.get_log_term())
+                    self.raft_log.is_up_to_date(m.get_index(), m.get_log_term()) &&
+                    self.has_priority_to_vote(&m)
                 {
                     self.log_vote_approve(&m);
                     let mut to_send =
@@ -1022,6 +1065,22 @@ impl<T: Storage> Raft<T> {
         Ok(())
     }
 
+    // has_priority_to_vote checks whether the candidate of the vote request has
+    // priority over this raft. A candidate with a lower priority is only voted if
+    // its log is newer, otherwise this raft should win the election instead. Leader
+    // transfer is always voted.
+    fn has_priority_to_vote(&self, m: &Message) -> bool {
+        if self.priority == 0 || m.get_context() == CAMPAIGN_TRANSFER {
+            return true;
+        }
+        if decode_priority(m.get_context()) >= self.priority {
+            return true;
+        }
+        let last_term = self.raft_log.last_term();
+        m.get_log_term() > last_term ||
+            (m.get_log_term() == last_term && m.get_index() > self.raft_log.last_index())
+    }
+
     fn log_vote_approve(&self, m: &Message) {
         info!(
             "{} [logterm: {}, index: {}, vote: {}] cast {:?} for {} [logterm: {}, index: {}] \
@@ -1762,9 +1821,13 @@ impl<T: Storage> Raft<T> {
     }
 
     // promotable indicates whether state machine can be promoted to leader,
-    // which is true when its own id is in progress list.
+    // which is true when its own id is in progress list and it's not a witness.
     pub fn promotable(&self) -> bool {
-        self.get_prs().contains_key(&self.id)
+        !self.witness && self.get_prs().contains_key(&self.id)
+    }
+
+    pub fn is_witness(&self) -> bool {
+        self.witness
     }
 
     pub fn add_node(&mut self, id: u64) {
@@ -1848,15 +1911,16 @@ impl<T: Storage> Raft<T> {
 
     /// `pass_election_timeout` returns true iff `election_elapsed` is greater
     /// than or equal to the randomized election timeout in
-    /// [`election_timeout`, 2 * `election_timeout` - 1].
+    /// [`election_timeout`, 2 * `election_timeout` - 1], shifted by
+    /// `election_delay_rounds` * `election_timeout`.
     pub fn pass_election_timeout(&self) -> bool {
         self.election_elapsed >= self.randomized_election_timeout
     }
 
     pub fn reset_randomized_election_timeout(&mut self) {
         let prev_timeout = self.randomized_election_timeout;
-        let timeout =
-            self.election_timeout + rand::thread_rng().gen_range(0, self.election_timeout);
+        let timeout = self.election_timeout * (1 + self.election_delay_rounds) +
+            rand::thread_rng().gen_range(0, self.election_timeout);
         debug!(
             "{} reset election timeout {} -> {} at {}",
             self.tag,
@@ -19,6 +19,7 @@ use kvproto::metapb::Region;
 
 use util::transport::{RetryableSendCh, Sender};
 use raftstore::store::msg::Msg;
+use storage::CfName;
 
 use super::*;
 
@@ -128,11 +129,17 @@ impl CoprocessorHost {
     ) -> CoprocessorHost {
         let mut registry = Registry::default();
         let split_size_check_observer =
-            SizeCheckObserver::new(cfg.region_max_size.0, cfg.region_split_size.0, ch);
+            SizeCheckObserver::new(cfg.region_max_size.0, cfg.region_split_size.0, ch.clone());
         registry.register_split_check_observer(
             SIZE_CHECK_OBSERVER_PRIORITY,
             Box::new(split_size_check_observer),
         );
+        let split_keys_check_observer =
+            KeysCheckObserver::new(cfg.region_max_keys, cfg.region_split_keys, ch);
+        registry.register_split_check_observer(
+            KEYS_CHECK_OBSERVER_PRIORITY,
+            Box::new(split_keys_check_observer),
+        );
         if cfg.split_region_on_table {
             registry.register_split_check_observer(
                 TABLE_CHECK_OBSERVER_PRIORITY,
@@ -220,6 +227,7 @@ impl CoprocessorHost {
         &self,
         region: &Region,
         split_status: &mut SplitCheckStatus,
+        cf: CfName,
         key: &[u8],
         value_size: u64,
     ) -> Option<Vec<u8>> {
@@ -227,7 +235,7 @@ impl CoprocessorHost {
         for entry in &self.registry.split_check_observers {
             if let Some(split_key) = entry
                 .observer
-                .on_split_check(&mut ob_ctx, split_status, key, value_size)
+                .on_split_check(&mut ob_ctx, split_status, cf, key, value_size)
             {
                 return Some(split_key);
             }
@@ -17,6 +17,8 @@ use kvproto::raft_cmdpb::{AdminRequest, AdminResponse, Request, Response};
 use kvproto::metapb::Region;
 use protobuf::RepeatedField;
 
+use storage::CfName;
+
 pub mod dispatcher;
 pub mod split_observer;
 pub mod config;
@@ -29,7 +31,8 @@ pub use self::config::Config;
 pub use self::region_snapshot::{RegionIterator, RegionSnapshot};
 pub use self::dispatcher::{CoprocessorHost, Registry};
 pub use self::error::{Error, Result};
-pub use self::split_check::{SizeCheckObserver, Status as SplitCheckStatus, TableCheckObserver,
+pub use self::split_check::{KeysCheckObserver, SizeCheckObserver, Status as SplitCheckStatus,
+                            TableCheckObserver, KEYS_CHECK_OBSERVER_PRIORITY,
                             SIZE_CHECK_OBSERVER_PRIORITY, TABLE_CHECK_OBSERVER_PRIORITY};
 
 /// Coprocessor is used to provide a convient way to inject code to
@@ -100,11 +103,13 @@ pub trait SplitCheckObserver: Coprocessor {
     //       so that ervery threads has its own RegionObservers.
     fn new_split_check_status(&self, _: &mut ObserverContext, _: &mut SplitCheckStatus, _: &DB) {}
 
-    /// Hook to call for every check during split.
+    /// Hook to call for every check during split, `key` is read from the
+    /// column family `cf`.
     fn on_split_check(
         &self,
         _: &mut ObserverContext,
         _: &mut SplitCheckStatus,
+        _: CfName,
         _: &[u8],
         _: u64,
     ) -> Option<Vec<u8>> {
@@ -0,0 +1,272 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rocksdb::DB;
+use raftstore::store::{util, Msg};
+use storage::{types, CfName, CF_DEFAULT, CF_WRITE};
+use util::transport::{RetryableSendCh, Sender};
+
+use super::super::{Coprocessor, ObserverContext, SplitCheckObserver};
+use super::super::metrics::*;
+use super::Status;
+
+#[derive(Default)]
+pub struct KeysStatus {
+    current_count: u64,
+    // The last user key, without timestamp, that has been counted.
+    last_key: Vec<u8>,
+    split_key: Option<Vec<u8>>,
+}
+
+pub struct KeysCheckObserver<C> {
+    region_max_keys: u64,
+    split_keys: u64,
+    ch: RetryableSendCh<Msg, C>,
+}
+
+impl<C: Sender<Msg>> KeysCheckObserver<C> {
+    pub fn new(
+        region_max_keys: u64,
+        split_keys: u64,
+        ch: RetryableSendCh<Msg, C>,
+    ) -> KeysCheckObserver<C> {
+        KeysCheckObserver {
+            region_max_keys,
+            split_keys,
+            ch,
+        }
+    }
+}
+
+impl<C> Coprocessor for KeysCheckObserver<C> {}
+
+impl<C: Sender<Msg> + Send> SplitCheckObserver for KeysCheckObserver<C> {
+    fn new_split_check_status(&self, ctx: &mut ObserverContext, status: &mut Status, engine: &DB) {
+        let keys_status = KeysStatus::default();
+        let region = ctx.region();
+        let region_id = region.get_id();
+        let region_keys = match util::get_region_approximate_keys(engine, region) {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!(
+                    "[region {}] failed to get approximate keys: {}",
+                    region_id,
+                    e
+                );
+                // Need to check keys.
+                status.keys = Some(keys_status);
+                return;
+            }
+        };
+
+        let res = Msg::ApproximateRegionKeys {
+            region_id: region_id,
+            region_keys: region_keys,
+        };
+        if let Err(e) = self.ch.try_send(res) {
+            error!(
+                "[region {}] failed to send approximate region keys: {}",
+                region_id,
+                e
+            );
+        }
+
+        REGION_KEYS_HISTOGRAM.observe(region_keys as f64);
+        if region_keys >= self.region_max_keys {
+            info!(
+                "[region {}] approximate keys {} >= {}, need to do split check",
+                region.get_id(),
+                region_keys,
+                self.region_max_keys
+            );
+            // Need to check keys.
+            status.keys = Some(keys_status);
+        } // else { Does not need to check keys. }
+    }
+
+    fn on_split_check(
+        &self,
+        _: &mut ObserverContext,
+        status: &mut Status,
+        cf: CfName,
+        key: &[u8],
+        _: u64,
+    ) -> Option<Vec<u8>> {
+        if let Some(keys_status) = status.keys.as_mut() {
+            // Versions of a key in all cfs are adjacent, count them once.
+            // Only keys in default and write cfs have a timestamp suffix.
+            let user_key = if cf == CF_DEFAULT || cf == CF_WRITE {
+                match types::split_encoded_key_on_ts(key) {
+                    Ok((k, _)) => k,
+                    Err(_) => key,
+                }
+            } else {
+                key
+            };
+            if keys_status.current_count > 0 && user_key == keys_status.last_key.as_slice() {
+                return None;
+            }
+            keys_status.last_key.clear();
+            keys_status.last_key.extend_from_slice(user_key);
+            keys_status.current_count += 1;
+            if keys_status.current_count > self.split_keys && keys_status.split_key.is_none() {
+                keys_status.split_key = Some(user_key.to_vec());
+            }
+            if keys_status.current_count >= self.region_max_keys {
+                keys_status.split_key.take()
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::mpsc;
+
+    use tempdir::TempDir;
+    use rocksdb::Writable;
+    use kvproto::metapb::{Peer, Region};
+    use rocksdb::{ColumnFamilyOptions, DBOptions};
+
+    use storage::{Key, ALL_CFS, CF_LOCK, CF_WRITE};
+    use storage::mvcc::{Write, WriteType};
+    use raftstore::store::{keys, Msg, SplitCheckRunner, SplitCheckTask};
+    use util::rocksdb::{new_engine_opt, CFOptions};
+    use util::worker::Runnable;
+    use util::transport::RetryableSendCh;
+    use util::properties::MvccPropertiesCollectorFactory;
+    use util::config::ReadableSize;
+
+    use raftstore::coprocessor::{Config, CoprocessorHost, ObserverContext, SplitCheckObserver};
+
+    use super::*;
+    use super::super::Status;
+
+    fn put_data(engine: &::rocksdb::DB, start: u64, end: u64) {
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        for i in start..end {
+            let key = format!("{:04}", i);
+            // Two versions of a key should be counted once.
+            for ts in &[2, 1] {
+                let k = Key::from_raw(key.as_bytes()).append_ts(*ts);
+                let k = keys::data_key(k.encoded());
+                let v = Write::new(WriteType::Put, *ts, None).to_bytes();
+                engine.put_cf(write_cf, &k, &v).unwrap();
+            }
+        }
+        engine.flush_cf(write_cf, true).unwrap();
+    }
+
+    #[test]
+    fn test_split_check() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-properties-collector", f);
+        let cfs_opts = ALL_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let engine = Arc::new(new_engine_opt(path_str, db_opts, cfs_opts).unwrap());
+
+        let mut region = Region::new();
+        region.set_id(1);
+        region.set_start_key(vec![]);
+        region.set_end_key(vec![]);
+        region.mut_peers().push(Peer::new());
+        region.mut_region_epoch().set_version(2);
+        region.mut_region_epoch().set_conf_ver(5);
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_size = ReadableSize::gb(1);
+        cfg.region_max_keys = 100;
+        cfg.region_split_keys = 80;
+
+        let mut runnable = SplitCheckRunner::new(
+            engine.clone(),
+            ch.clone(),
+            Arc::new(CoprocessorHost::new(cfg, ch.clone())),
+        );
+
+        put_data(&engine, 0, 90);
+        runnable.run(SplitCheckTask::new(&region));
+        // keys has not reached the max_keys 100 yet.
+        loop {
+            match rx.try_recv() {
+                Ok(Msg::ApproximateRegionSize { region_id, .. }) |
+                Ok(Msg::ApproximateRegionKeys { region_id, .. }) => {
+                    assert_eq!(region_id, region.get_id());
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                others => panic!("expect recv empty, but got {:?}", others),
+            }
+        }
+
+        put_data(&engine, 90, 160);
+        runnable.run(SplitCheckTask::new(&region));
+        loop {
+            match rx.try_recv() {
+                Ok(Msg::ApproximateRegionSize { region_id, .. }) |
+                Ok(Msg::ApproximateRegionKeys { region_id, .. }) => {
+                    assert_eq!(region_id, region.get_id());
+                }
+                Ok(Msg::SplitRegion {
+                    region_id,
+                    region_epoch,
+                    split_key,
+                    ..
+                }) => {
+                    assert_eq!(region_id, region.get_id());
+                    assert_eq!(&region_epoch, region.get_region_epoch());
+                    assert_eq!(split_key, Key::from_raw(b"0080").encoded().as_slice());
+                    break;
+                }
+                others => panic!("expect split check result, but got {:?}", others),
+            }
+        }
+
+        drop(rx);
+        // It should be safe even the result can't be sent back.
+        runnable.run(SplitCheckTask::new(&region));
+    }
+
+    #[test]
+    fn test_split_key_of_cf() {
+        let (tx, _rx) = mpsc::sync_channel(100);
+        let observer = KeysCheckObserver::new(1, 0, RetryableSendCh::new(tx, "test-split"));
+        let region = Region::new();
+        let mut ctx = ObserverContext::new(&region);
+
+        // Keys in lock cf have no timestamp, they should be kept as is.
+        let key = keys::data_key(Key::from_raw(b"k1").encoded());
+        let mut status = Status::default();
+        status.keys = Some(KeysStatus::default());
+        let split_key = observer.on_split_check(&mut ctx, &mut status, CF_LOCK, &key, 0);
+        assert_eq!(split_key, Some(key.clone()));
+
+        let ts_key = keys::data_key(Key::from_raw(b"k1").append_ts(1).encoded());
+        let mut status = Status::default();
+        status.keys = Some(KeysStatus::default());
+        let split_key = observer.on_split_check(&mut ctx, &mut status, CF_WRITE, &ts_key, 0);
+        assert_eq!(split_key, Some(key));
+    }
+}
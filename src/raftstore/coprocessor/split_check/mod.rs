@@ -13,12 +13,16 @@
 
 mod table;
 mod size;
+mod keys;
 
 use self::size::SizeStatus;
+use self::keys::KeysStatus;
 use self::table::TableStatus;
 
 pub use self::size::SizeCheckObserver;
 pub const SIZE_CHECK_OBSERVER_PRIORITY: u32 = 200;
+pub use self::keys::KeysCheckObserver;
+pub const KEYS_CHECK_OBSERVER_PRIORITY: u32 = SIZE_CHECK_OBSERVER_PRIORITY + 1;
 pub use self::table::TableCheckObserver;
 // TableCheckObserver has higher priority than TableCheckObserver.
 // Note that higher means less.
@@ -30,10 +34,12 @@ pub struct Status {
     table: Option<TableStatus>,
     // For SizeCheckObserver
     size: Option<SizeStatus>,
+    // For KeysCheckObserver
+    keys: Option<KeysStatus>,
 }
 
 impl Status {
     pub fn skip(&self) -> bool {
-        self.table.is_none() && self.size.is_none()
+        self.table.is_none() && self.size.is_none() && self.keys.is_none()
     }
 }
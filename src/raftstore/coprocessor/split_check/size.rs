@@ -13,6 +13,7 @@
 
 use rocksdb::DB;
 use raftstore::store::{util, Msg};
+use storage::CfName;
 use util::transport::{RetryableSendCh, Sender};
 
 use super::super::{Coprocessor, ObserverContext, SplitCheckObserver};
@@ -95,6 +96,7 @@ impl<C: Sender<Msg> + Send> SplitCheckObserver for SizeCheckObserver<C> {
         &self,
         _: &mut ObserverContext,
         status: &mut Status,
+        _: CfName,
         key: &[u8],
         value_size: u64,
     ) -> Option<Vec<u8>> {
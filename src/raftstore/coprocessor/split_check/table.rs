@@ -16,7 +16,7 @@ use std::cmp::Ordering;
 use rocksdb::{SeekKey, DB};
 use kvproto::metapb::Region;
 
-use storage::CF_WRITE;
+use storage::{CfName, CF_WRITE};
 use storage::types::Key;
 use raftstore::store::keys;
 use raftstore::store::engine::{IterOption, Iterable};
@@ -50,6 +50,7 @@ impl SplitCheckObserver for TableCheckObserver {
         &self,
         _: &mut ObserverContext,
         status: &mut Status,
+        _: CfName,
         key: &[u8],
         _: u64,
     ) -> Option<Vec<u8>> {
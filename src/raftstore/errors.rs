@@ -27,6 +27,7 @@ use super::coprocessor::Error as CopError;
 use util::{escape, transport};
 
 const RAFTSTORE_IS_BUSY: &'static str = "raftstore is busy";
+pub const DISK_IS_FULL: &'static str = "disk is full";
 
 quick_error!{
     #[derive(Debug)]
@@ -118,6 +119,10 @@ quick_error!{
             description("region is stale")
             display("StaleEpoch {}", msg)
         }
+        DiskFull(store_id: u64) {
+            description("disk is full")
+            display("store {} is running out of disk space", store_id)
+        }
         StaleCommand {
             description("stale command")
         }
@@ -181,6 +186,11 @@ impl Into<errorpb::Error> for Error {
             Error::StaleCommand => {
                 errorpb.set_stale_command(errorpb::StaleCommand::new());
             }
+            Error::DiskFull(store_id) => {
+                // There is no dedicated region error, it's told by the message only. It
+                // isn't reported as server busy, which clients retry on the same store.
+                errorpb.set_message(format!("{}: store {}", DISK_IS_FULL, store_id));
+            }
             Error::Transport(transport::Error::Discard(_)) => {
                 let mut server_is_busy_err = errorpb::ServerIsBusy::new();
                 server_is_busy_err.set_reason(RAFTSTORE_IS_BUSY.to_owned());
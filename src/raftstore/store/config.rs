@@ -15,8 +15,14 @@ use std::u64;
 
 use time::Duration as TimeDuration;
 
+use kvproto::metapb::StoreLabel;
+
 use raftstore::{coprocessor, Result};
-use util::config::{ReadableDuration, ReadableSize};
+use util::collections::HashMap;
+use util::config::{self, ReadableDuration, ReadableSize};
+
+// The max number of extra election timeouts a peer waits before campaigning.
+const MAX_ELECTION_DELAY_ROUNDS: usize = 3;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(default)]
@@ -24,10 +30,17 @@ use util::config::{ReadableDuration, ReadableSize};
 pub struct Config {
     // true for high reliability, prevent data loss when power failure.
     pub sync_log: bool,
+    // Writes raft logs in a dedicated thread instead of blocking the raftstore,
+    // the readies are handled after the logs are persisted.
+    pub async_write_raft_log: bool,
     pub raftdb_path: String,
 
     // store capacity. 0 means no limit.
     pub capacity: ReadableSize,
+    // When the available space is less than the ratio of capacity, the store turns
+    // read-only: only deletes, commits, rollbacks and admin commands can be proposed.
+    // 0 means disabled.
+    pub min_available_ratio: f64,
 
     // raft_base_tick_interval is a base tick interval (ms).
     pub raft_base_tick_interval: ReadableDuration,
@@ -37,6 +50,12 @@ pub struct Config {
     pub raft_max_inflight_msgs: usize,
     // When the entry exceed the max size, reject to propose it.
     pub raft_entry_max_size: ReadableSize,
+    // Memory shared by the raft entry caches of all the peers in the store.
+    pub raft_entry_cache_limit: ReadableSize,
+    // Entries not smaller than the threshold are compressed, 0 means no compression.
+    pub raft_entry_compression_threshold: ReadableSize,
+    // Write commands of a region are merged into one entry up to the limit, 0 disables it.
+    pub raft_proposal_batch_size_limit: ReadableSize,
 
     // Interval to gc unnecessary raft log (ms).
     pub raft_log_gc_tick_interval: ReadableDuration,
@@ -62,11 +81,15 @@ pub struct Config {
     pub pd_store_heartbeat_tick_interval: ReadableDuration,
     pub snap_mgr_gc_tick_interval: ReadableDuration,
     pub snap_gc_timeout: ReadableDuration,
+    // Delay cleaning up the data of destroyed peers, 0 means cleaning up at once.
+    pub clean_stale_peer_delay: ReadableDuration,
     pub lock_cf_compact_interval: ReadableDuration,
     pub lock_cf_compact_bytes_threshold: ReadableSize,
 
     pub notify_capacity: usize,
     pub messages_per_tick: usize,
+    /// Regions are sharded across this many raftstore threads by region id.
+    pub store_pool_size: usize,
 
     /// When a peer is not active for max_peer_down_duration,
     /// the peer is considered to be down and is reported to PD.
@@ -78,6 +101,9 @@ pub struct Config {
     pub max_leader_missing_duration: ReadableDuration,
 
     pub snap_apply_batch_size: ReadableSize,
+    // Max number of snapshots being generated and applied at the same time.
+    pub snap_generator_pool_size: usize,
+    pub snap_apply_pool_size: usize,
 
     // Interval (ms) to check region whether the data is consistent.
     pub consistency_check_interval: ReadableDuration,
@@ -92,6 +118,21 @@ pub struct Config {
 
     pub allow_remove_leader: bool,
 
+    /// When the QPS of a region exceeds the threshold for `region_split_qps_detect_times`
+    /// continuous store heartbeats, the region will be split by load. 0 means disabled.
+    pub region_split_qps_threshold: u64,
+    pub region_split_qps_detect_times: u64,
+
+    /// The store label whose value decides the election priority of this store, e.g. "zone".
+    pub election_priority_label: String,
+    /// Election priority of each value of `election_priority_label`, stores with an unlisted
+    /// value get the highest priority. A peer waits one more election timeout before
+    /// campaigning for every distinct priority above its own, and peers don't vote for
+    /// candidates with a lower priority unless their logs are newer, so it should be the
+    /// same across the cluster. At most 4 distinct priorities can be given.
+    #[serde(with = "config::order_map_serde")]
+    pub election_priorities: HashMap<String, u64>,
+
     // Deprecated! These two configuration has been moved to Coprocessor.
     // They are preserved for compatibility check.
     #[doc(hidden)]
@@ -107,14 +148,19 @@ impl Default for Config {
         let split_size = ReadableSize::mb(coprocessor::config::SPLIT_SIZE_MB);
         Config {
             sync_log: true,
+            async_write_raft_log: false,
             raftdb_path: String::new(),
             capacity: ReadableSize(0),
+            min_available_ratio: 0.05,
             raft_base_tick_interval: ReadableDuration::secs(1),
             raft_heartbeat_ticks: 2,
             raft_election_timeout_ticks: 10,
             raft_max_size_per_msg: ReadableSize::mb(1),
             raft_max_inflight_msgs: 256,
             raft_entry_max_size: ReadableSize::mb(8),
+            raft_entry_cache_limit: ReadableSize::gb(1),
+            raft_entry_compression_threshold: ReadableSize(0),
+            raft_proposal_batch_size_limit: ReadableSize::kb(256),
             raft_log_gc_tick_interval: ReadableDuration::secs(10),
             raft_log_gc_threshold: 50,
             // Assume the average size of entries is 1k.
@@ -130,10 +176,14 @@ impl Default for Config {
             notify_capacity: 40960,
             snap_mgr_gc_tick_interval: ReadableDuration::minutes(1),
             snap_gc_timeout: ReadableDuration::hours(4),
+            clean_stale_peer_delay: ReadableDuration::minutes(11),
             messages_per_tick: 4096,
+            store_pool_size: 1,
             max_peer_down_duration: ReadableDuration::minutes(5),
             max_leader_missing_duration: ReadableDuration::hours(2),
             snap_apply_batch_size: ReadableSize::mb(10),
+            snap_generator_pool_size: 2,
+            snap_apply_pool_size: 2,
             lock_cf_compact_interval: ReadableDuration::minutes(10),
             lock_cf_compact_bytes_threshold: ReadableSize::mb(256),
             // Disable consistency check by default as it will hurt performance.
@@ -143,6 +193,11 @@ impl Default for Config {
             raft_store_max_leader_lease: ReadableDuration::secs(9),
             right_derive_when_split: true,
             allow_remove_leader: false,
+            // Disable load-based split by default.
+            region_split_qps_threshold: 0,
+            region_split_qps_detect_times: 3,
+            election_priority_label: String::new(),
+            election_priorities: HashMap::default(),
 
             // They are preserved for compatibility check.
             region_max_size: ReadableSize(0),
@@ -160,6 +215,35 @@ impl Config {
         TimeDuration::from_std(self.raft_store_max_leader_lease.0).unwrap()
     }
 
+    fn max_election_priority(&self) -> u64 {
+        self.election_priorities.values().max().cloned().unwrap_or(0)
+    }
+
+    /// Returns the election priority of peers on the store with `labels`. Stores with
+    /// an unlisted label value get the highest priority, so they are never delayed.
+    pub fn election_priority(&self, labels: &[StoreLabel]) -> u64 {
+        labels
+            .iter()
+            .find(|l| l.get_key() == self.election_priority_label)
+            .and_then(|l| self.election_priorities.get(l.get_value()))
+            .cloned()
+            .unwrap_or_else(|| self.max_election_priority())
+    }
+
+    /// Returns how many extra election timeouts a peer on the store with `labels` should
+    /// wait before campaigning, it's the number of distinct priorities above the store's,
+    /// so the gaps between priorities don't matter.
+    pub fn election_delay_rounds(&self, labels: &[StoreLabel]) -> usize {
+        let priority = self.election_priority(labels);
+        let mut higher: Vec<_> = self.election_priorities
+            .values()
+            .filter(|&&p| p > priority)
+            .collect();
+        higher.sort();
+        higher.dedup();
+        higher.len()
+    }
+
     pub fn validate(&self) -> Result<()> {
         if self.raft_heartbeat_ticks == 0 {
             return Err(box_err!("heartbeat tick must greater than 0"));
@@ -178,6 +262,15 @@ impl Config {
             ));
         }
 
+        if self.raft_proposal_batch_size_limit.0 > self.raft_entry_max_size.0 {
+            return Err(box_err!(
+                "raft proposal batch size limit {} should not be greater than \
+                 raft entry max size {}",
+                self.raft_proposal_batch_size_limit.0,
+                self.raft_entry_max_size.0
+            ));
+        }
+
         if self.raft_log_gc_threshold < 1 {
             return Err(box_err!(
                 "raft log gc threshold must >= 1, not {}",
@@ -185,10 +278,50 @@ impl Config {
             ));
         }
 
+        if self.region_split_qps_detect_times == 0 {
+            return Err(box_err!(
+                "region split qps detect times must be greater than 0"
+            ));
+        }
+
         if self.raft_log_gc_size_limit.0 == 0 {
             return Err(box_err!("raft log gc size limit should large than 0."));
         }
 
+        if self.store_pool_size == 0 {
+            return Err(box_err!("store pool size should be greater than 0."));
+        }
+
+        if self.min_available_ratio < 0.0 || self.min_available_ratio >= 1.0 {
+            return Err(box_err!(
+                "min available ratio should be in [0, 1), not {}",
+                self.min_available_ratio
+            ));
+        }
+
+        if self.snap_generator_pool_size == 0 || self.snap_apply_pool_size == 0 {
+            return Err(box_err!(
+                "snap generator pool size and snap apply pool size should be greater than 0."
+            ));
+        }
+
+        if !self.election_priorities.is_empty() && self.election_priority_label.is_empty() {
+            return Err(box_err!(
+                "election priority label should be set when election priorities are given."
+            ));
+        }
+
+        let mut priorities: Vec<_> = self.election_priorities.values().collect();
+        priorities.sort();
+        priorities.dedup();
+        if priorities.len() > MAX_ELECTION_DELAY_ROUNDS + 1 {
+            return Err(box_err!(
+                "election priorities should have at most {} distinct values, got {}",
+                MAX_ELECTION_DELAY_ROUNDS + 1,
+                priorities.len()
+            ));
+        }
+
         let election_timeout =
             self.raft_base_tick_interval.as_millis() * self.raft_election_timeout_ticks as u64;
         let lease = self.raft_store_max_leader_lease.as_millis() as u64;
@@ -226,6 +359,10 @@ mod tests {
         cfg.raft_heartbeat_ticks = 11;
         assert!(cfg.validate().is_err());
 
+        cfg = Config::new();
+        cfg.raft_proposal_batch_size_limit = ReadableSize::mb(16);
+        assert!(cfg.validate().is_err());
+
         cfg = Config::new();
         cfg.raft_log_gc_threshold = 0;
         assert!(cfg.validate().is_err());
@@ -234,10 +371,75 @@ mod tests {
         cfg.raft_log_gc_size_limit = ReadableSize(0);
         assert!(cfg.validate().is_err());
 
+        cfg = Config::new();
+        cfg.region_split_qps_detect_times = 0;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::new();
+        cfg.store_pool_size = 0;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::new();
+        cfg.min_available_ratio = 1.0;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::new();
+        cfg.snap_generator_pool_size = 0;
+        assert!(cfg.validate().is_err());
+
+        cfg = Config::new();
+        cfg.snap_apply_pool_size = 0;
+        assert!(cfg.validate().is_err());
+
         cfg = Config::new();
         cfg.raft_base_tick_interval = ReadableDuration::secs(1);
         cfg.raft_election_timeout_ticks = 10;
         cfg.raft_store_max_leader_lease = ReadableDuration::secs(20);
         assert!(cfg.validate().is_err());
+
+        cfg = Config::new();
+        cfg.election_priorities.insert("z1".to_owned(), 1);
+        assert!(cfg.validate().is_err());
+        cfg.election_priority_label = "zone".to_owned();
+        assert!(cfg.validate().is_ok());
+        for (i, zone) in ["z2", "z3", "z4"].iter().enumerate() {
+            cfg.election_priorities.insert(zone.to_string(), 10 * i as u64);
+        }
+        assert!(cfg.validate().is_ok());
+        cfg.election_priorities.insert("z5".to_owned(), u64::MAX);
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn test_election_delay_rounds() {
+        let new_label = |k: &str, v: &str| {
+            let mut label = StoreLabel::new();
+            label.set_key(k.to_owned());
+            label.set_value(v.to_owned());
+            label
+        };
+
+        let mut cfg = Config::new();
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z1")]), 0);
+
+        cfg.election_priority_label = "zone".to_owned();
+        cfg.election_priorities.insert("z1".to_owned(), 2);
+        cfg.election_priorities.insert("z2".to_owned(), 1);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z1")]), 0);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z2")]), 1);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z3")]), 0);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("host", "z1")]), 0);
+        assert_eq!(cfg.election_delay_rounds(&[]), 0);
+        assert_eq!(cfg.election_priority(&[new_label("zone", "z1")]), 2);
+        assert_eq!(cfg.election_priority(&[new_label("zone", "z2")]), 1);
+        assert_eq!(cfg.election_priority(&[new_label("zone", "z3")]), 2);
+        assert_eq!(cfg.election_priority(&[]), 2);
+
+        // The delay only depends on the order of the priorities.
+        cfg.election_priorities.insert("z3".to_owned(), 1000);
+        cfg.election_priorities.insert("z4".to_owned(), 1000);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z3")]), 0);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z1")]), 1);
+        assert_eq!(cfg.election_delay_rounds(&[new_label("zone", "z2")]), 2);
     }
 }
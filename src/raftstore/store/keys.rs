@@ -36,6 +36,8 @@ pub const DATA_MAX_KEY: &'static [u8] = &[DATA_PREFIX + 1];
 // Following keys are all local keys, so the first byte must be 0x01.
 pub const STORE_IDENT_KEY: &'static [u8] = &[LOCAL_PREFIX, 0x01];
 pub const PREPARE_BOOTSTRAP_KEY: &'static [u8] = &[LOCAL_PREFIX, 0x02];
+// Written to the raft RocksDB when raft logs are stored in the raft log engine.
+pub const RAFT_LOG_ENGINE_KEY: &'static [u8] = &[LOCAL_PREFIX, 0x04];
 // We save two types region data in DB, for raft and other meta data.
 // When the store starts, we should iterate all region meta data to
 // construct peer, no need to travel large raft data, so we separate them
@@ -134,6 +136,18 @@ pub fn decode_raft_log_key(key: &[u8]) -> Result<(u64, u64)> {
     Ok((region_id, index))
 }
 
+/// Get the region id and suffix from the key generated by `make_region_id_key`
+/// without extra fields, like the raft state key.
+pub fn decode_region_raft_key(key: &[u8]) -> Result<(u64, u8)> {
+    if key.len() != region_raft_prefix_len() || !key.starts_with(REGION_RAFT_PREFIX_KEY) {
+        return Err(box_err!("key {} is not a valid region raft key", escape(key)));
+    }
+    let region_id = BigEndian::read_u64(
+        &key[REGION_RAFT_PREFIX_KEY.len()..REGION_RAFT_PREFIX_KEY.len() + mem::size_of::<u64>()],
+    );
+    Ok((region_id, key[key.len() - 1]))
+}
+
 pub fn raft_log_prefix(region_id: u64) -> Vec<u8> {
     make_region_id_key(region_id, RAFT_LOG_SUFFIX, 0)
 }
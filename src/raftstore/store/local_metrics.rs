@@ -242,6 +242,7 @@ pub struct RaftProposeMetrics {
     pub normal: u64,
     pub transfer_leader: u64,
     pub conf_change: u64,
+    pub batch: u64,
     pub request_wait_time: LocalHistogram,
 }
 
@@ -254,6 +255,7 @@ impl Default for RaftProposeMetrics {
             normal: 0,
             transfer_leader: 0,
             conf_change: 0,
+            batch: 0,
             request_wait_time: REQUEST_WAIT_TIME_HISTOGRAM.local(),
         }
     }
@@ -305,6 +307,13 @@ impl RaftProposeMetrics {
                 .unwrap();
             self.conf_change = 0;
         }
+        if self.batch > 0 {
+            PEER_PROPOSAL_COUNTER_VEC
+                .with_label_values(&["batch"])
+                .inc_by(self.batch as f64)
+                .unwrap();
+            self.batch = 0;
+        }
         self.request_wait_time.flush();
     }
 }
@@ -324,23 +333,31 @@ pub struct RaftMetrics {
 
 impl Default for RaftMetrics {
     fn default() -> RaftMetrics {
+        RaftMetrics::new(0)
+    }
+}
+
+impl RaftMetrics {
+    /// Creates the metrics of the raftstore poller `poller_id`.
+    pub fn new(poller_id: usize) -> RaftMetrics {
+        let poller = poller_id.to_string();
         RaftMetrics {
             ready: Default::default(),
             message: Default::default(),
             message_dropped: Default::default(),
             propose: Default::default(),
             process_tick: PEER_RAFT_PROCESS_DURATION
-                .with_label_values(&["tick"])
+                .with_label_values(&[&poller, "tick"])
                 .local(),
             process_ready: PEER_RAFT_PROCESS_DURATION
-                .with_label_values(&["ready"])
+                .with_label_values(&[&poller, "ready"])
+                .local(),
+            append_log: PEER_APPEND_LOG_HISTOGRAM
+                .with_label_values(&[&poller])
                 .local(),
-            append_log: PEER_APPEND_LOG_HISTOGRAM.local(),
         }
     }
-}
 
-impl RaftMetrics {
     /// Flushs all metrics
     pub fn flush(&mut self) {
         self.ready.flush();
@@ -0,0 +1,77 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use util::config::ReadableSize;
+use raftstore::Result;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// When it is true, raft logs are stored in the append-only log files
+    /// instead of the raft RocksDB.
+    pub enabled: bool,
+    /// Directory of the log files, `data-dir/raft-log` is used if it's empty.
+    pub dir: String,
+    /// A new log file is created once the active one exceeds this size.
+    pub target_file_size: ReadableSize,
+    /// When the total size of the log files exceeds this threshold, regions
+    /// holding the oldest files are rewritten so that these files can be removed.
+    pub purge_threshold: ReadableSize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            enabled: false,
+            dir: "".to_owned(),
+            target_file_size: ReadableSize::mb(128),
+            purge_threshold: ReadableSize::gb(10),
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<()> {
+        if self.target_file_size.0 == 0 {
+            return Err(box_err!("raft log engine target file size should not be 0"));
+        }
+        if self.purge_threshold.0 < self.target_file_size.0 {
+            return Err(box_err!(
+                "raft log engine purge threshold {} must >= target file size {}",
+                self.purge_threshold.0,
+                self.target_file_size.0
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_validate() {
+        let cfg = Config::default();
+        cfg.validate().unwrap();
+
+        let mut cfg = Config::default();
+        cfg.target_file_size = ReadableSize(0);
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = Config::default();
+        cfg.purge_threshold = ReadableSize::mb(1);
+        assert!(cfg.validate().is_err());
+    }
+}
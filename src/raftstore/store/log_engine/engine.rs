@@ -0,0 +1,641 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cmp, mem};
+use std::sync::{Condvar, Mutex, RwLock};
+
+use protobuf;
+use kvproto::eraftpb::Entry;
+use kvproto::raft_serverpb::RaftLocalState;
+
+use util::collections::HashMap;
+use raftstore::Result;
+
+use super::Config;
+use super::log_batch::{LogBatch, LogItem};
+use super::memtable::{EntryIndex, MemTable};
+use super::pipe_log::{decode_record, encode_record, PipeLog, RECORD_HEADER_LEN};
+
+// Rewritten entries are flushed once the batch exceeds this size.
+const REWRITE_BATCH_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Default)]
+struct SyncState {
+    synced_seq: u64,
+    syncing: bool,
+}
+
+/// `RaftLogEngine` stores raft logs and raft states of all regions in
+/// append-only files, with an in-memory index for every region.
+///
+/// Writes are serialized and appended to the active file, concurrent writers
+/// which require sync share one fsync. Compacted entries are only removed
+/// from the index, the space is reclaimed by removing whole files that are
+/// no longer referenced. Regions referencing too old files are rewritten
+/// into the active file, so that the old files can be removed.
+pub struct RaftLogEngine {
+    cfg: Config,
+    pipe_log: PipeLog,
+    memtables: RwLock<HashMap<u64, MemTable>>,
+    // Serializes writes so that the memtables are updated in the same order
+    // as the records are appended.
+    write_mutex: Mutex<()>,
+    sync_state: Mutex<SyncState>,
+    sync_cond: Condvar,
+}
+
+impl RaftLogEngine {
+    pub fn new(cfg: Config) -> Result<RaftLogEngine> {
+        cfg.validate()?;
+        let pipe_log = PipeLog::open(&cfg.dir, cfg.target_file_size.0)?;
+        let engine = RaftLogEngine {
+            cfg: cfg,
+            pipe_log: pipe_log,
+            memtables: RwLock::new(HashMap::default()),
+            write_mutex: Mutex::new(()),
+            sync_state: Mutex::new(SyncState::default()),
+            sync_cond: Condvar::new(),
+        };
+        engine.recover()?;
+        Ok(engine)
+    }
+
+    fn recover(&self) -> Result<()> {
+        let active_num = self.pipe_log.active_file_num();
+        let mut memtables = self.memtables.write().unwrap();
+        for num in self.pipe_log.file_nums() {
+            let content = self.pipe_log.read_file(num)?;
+            let mut offset = 0;
+            while offset < content.len() {
+                let record = match decode_record(&content[offset..]) {
+                    Some(r) => r,
+                    None if num == active_num => {
+                        warn!(
+                            "raft log file {} is corrupted at {}, truncate the tail",
+                            num,
+                            offset
+                        );
+                        self.pipe_log.truncate_active_file(offset as u64)?;
+                        break;
+                    }
+                    None => {
+                        return Err(box_err!("raft log file {} is corrupted at {}", num, offset))
+                    }
+                };
+                let (batch, indexes) = LogBatch::decode(record)?;
+                let base_offset = (offset + RECORD_HEADER_LEN) as u64;
+                apply_to_memtables(&mut memtables, batch, indexes, num, base_offset);
+                offset += RECORD_HEADER_LEN + record.len();
+            }
+        }
+        info!(
+            "raft log engine recovered {} regions from {} files",
+            memtables.len(),
+            active_num - self.pipe_log.first_file_num() + 1
+        );
+        Ok(())
+    }
+
+    /// Writes the batch to the log files, returns the written bytes.
+    pub fn write(&self, batch: LogBatch, sync: bool) -> Result<usize> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let (seq, size) = {
+            let _lock = self.write_mutex.lock().unwrap();
+            self.append_batch(batch)?
+        };
+        if sync {
+            self.sync_to(seq)?;
+        }
+        Ok(size)
+    }
+
+    // The caller must hold `write_mutex`.
+    fn append_batch(&self, batch: LogBatch) -> Result<(u64, usize)> {
+        let mut content = vec![];
+        let indexes = batch.encode_to(&mut content)?;
+        let record = encode_record(&content);
+        let (file_num, offset, seq) = self.pipe_log.append(&record)?;
+        let base_offset = offset + RECORD_HEADER_LEN as u64;
+        let mut memtables = self.memtables.write().unwrap();
+        apply_to_memtables(&mut memtables, batch, indexes, file_num, base_offset);
+        Ok((seq, record.len()))
+    }
+
+    /// Waits until the record with `seq` is synced. Only one writer does the
+    /// sync at a time, records appended before it starts are synced together.
+    fn sync_to(&self, seq: u64) -> Result<()> {
+        let mut state = self.sync_state.lock().unwrap();
+        while state.syncing && state.synced_seq < seq {
+            state = self.sync_cond.wait(state).unwrap();
+        }
+        if state.synced_seq >= seq {
+            return Ok(());
+        }
+        state.syncing = true;
+        drop(state);
+
+        let res = self.pipe_log.sync();
+
+        let mut state = self.sync_state.lock().unwrap();
+        state.syncing = false;
+        if let Ok(synced_seq) = res {
+            state.synced_seq = cmp::max(state.synced_seq, synced_seq);
+        }
+        self.sync_cond.notify_all();
+        res.map(|_| ())
+    }
+
+    /// Returns the ids of all regions which have data in the engine.
+    pub fn region_ids(&self) -> Vec<u64> {
+        let memtables = self.memtables.read().unwrap();
+        memtables.keys().cloned().collect()
+    }
+
+    pub fn get_raft_state(&self, region_id: u64) -> Option<RaftLocalState> {
+        let memtables = self.memtables.read().unwrap();
+        memtables
+            .get(&region_id)
+            .and_then(|t| t.get_state().cloned())
+    }
+
+    pub fn first_index(&self, region_id: u64) -> Option<u64> {
+        let memtables = self.memtables.read().unwrap();
+        memtables.get(&region_id).and_then(|t| t.first_index())
+    }
+
+    pub fn last_index(&self, region_id: u64) -> Option<u64> {
+        let memtables = self.memtables.read().unwrap();
+        memtables.get(&region_id).and_then(|t| t.last_index())
+    }
+
+    /// Gets the term of the entry without reading the log files.
+    pub fn term(&self, region_id: u64, idx: u64) -> Option<u64> {
+        let memtables = self.memtables.read().unwrap();
+        memtables
+            .get(&region_id)
+            .and_then(|t| t.get_entry_index(idx))
+            .map(|e| e.term)
+    }
+
+    pub fn get_entry(&self, region_id: u64, idx: u64) -> Result<Option<Entry>> {
+        // Hold the lock while reading, so the file can't be purged.
+        let memtables = self.memtables.read().unwrap();
+        match memtables
+            .get(&region_id)
+            .and_then(|t| t.get_entry_index(idx))
+        {
+            Some(e) => self.read_entry(&e).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Fetches entries in [begin, end) until the total size exceeds `max_size`,
+    /// at least one entry is fetched if it exists. Returns the total size of
+    /// the visited entries, fetching stops at the first missing entry.
+    pub fn fetch_entries_to(
+        &self,
+        region_id: u64,
+        begin: u64,
+        end: u64,
+        max_size: u64,
+        vec: &mut Vec<Entry>,
+    ) -> Result<u64> {
+        let memtables = self.memtables.read().unwrap();
+        let table = match memtables.get(&region_id) {
+            Some(t) => t,
+            None => return Ok(0),
+        };
+        let mut indexes = Vec::with_capacity((end - begin) as usize);
+        let total_size = table.fetch_entry_indexes(begin, end, max_size, &mut indexes);
+        for e in &indexes {
+            vec.push(self.read_entry(e)?);
+        }
+        Ok(total_size)
+    }
+
+    fn read_entry(&self, e: &EntryIndex) -> Result<Entry> {
+        let buf = self.pipe_log.read(e.file_num, e.offset, e.len)?;
+        let entry: Entry = protobuf::parse_from_bytes(&buf)?;
+        if entry.get_index() != e.index {
+            return Err(box_err!(
+                "entry at {}:{} should be {}, but got {}",
+                e.file_num,
+                e.offset,
+                e.index,
+                entry.get_index()
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Removes entries before `idx`, returns the count of removed entries.
+    pub fn compact_to(&self, region_id: u64, idx: u64) -> Result<u64> {
+        let count = {
+            let memtables = self.memtables.read().unwrap();
+            match memtables.get(&region_id).and_then(|t| t.first_index()) {
+                Some(first_index) if first_index < idx => idx - first_index,
+                _ => return Ok(0),
+            }
+        };
+        let mut batch = LogBatch::new();
+        batch.compact_to(region_id, idx);
+        self.write(batch, false)?;
+        Ok(count)
+    }
+
+    /// Approximate size of all the log files.
+    pub fn total_size(&self) -> u64 {
+        self.pipe_log.total_size()
+    }
+
+    /// Removes the log files which are no longer referenced by any region.
+    /// If the total size still exceeds `purge_threshold`, regions referencing
+    /// the oldest files are rewritten first. Returns the count of removed files.
+    pub fn purge_expired_files(&self) -> Result<usize> {
+        let active_num = self.pipe_log.active_file_num();
+        if self.pipe_log.total_size() > self.cfg.purge_threshold.0 {
+            // Only keep files which can be held in the threshold.
+            let keep_count = self.cfg.purge_threshold.0 / self.cfg.target_file_size.0;
+            let rewrite_to = (active_num + 1).saturating_sub(keep_count);
+            let count = self.rewrite_regions(rewrite_to)?;
+            if count > 0 {
+                info!(
+                    "raft log engine rewrote {} regions referencing files before {}",
+                    count,
+                    rewrite_to
+                );
+            }
+        }
+
+        let min_file_num = {
+            let memtables = self.memtables.read().unwrap();
+            memtables
+                .values()
+                .filter_map(|t| t.min_file_num())
+                .min()
+                .unwrap_or(active_num)
+        };
+        self.pipe_log.purge_to(min_file_num)
+    }
+
+    // Rewrites the regions referencing files before `file_num` into the active
+    // file, returns the count of rewritten regions.
+    //
+    // Entries are read without blocking writes. A region is only rewritten if
+    // it's not modified since it was read, otherwise newer entries may be
+    // overwritten, and it's left to the next purge. Files are only removed
+    // after the rewrite by the same purge, so the old entries can be read.
+    fn rewrite_regions(&self, file_num: u64) -> Result<usize> {
+        let region_ids: Vec<u64> = {
+            let memtables = self.memtables.read().unwrap();
+            memtables
+                .iter()
+                .filter(|&(_, t)| t.min_file_num().map_or(false, |n| n < file_num))
+                .map(|(id, _)| *id)
+                .collect()
+        };
+
+        let mut pending = vec![];
+        let mut pending_size = 0;
+        let mut rewritten = 0;
+        let mut last_seq = None;
+        for region_id in region_ids {
+            if let Some(region) = self.read_region(region_id)? {
+                pending_size += region.size;
+                pending.push(region);
+            }
+            if pending_size >= REWRITE_BATCH_SIZE {
+                let regions = mem::replace(&mut pending, vec![]);
+                if let Some((seq, count)) = self.append_unchanged(regions)? {
+                    last_seq = Some(seq);
+                    rewritten += count;
+                }
+                pending_size = 0;
+            }
+        }
+        if let Some((seq, count)) = self.append_unchanged(pending)? {
+            last_seq = Some(seq);
+            rewritten += count;
+        }
+        // Rewritten data must be persisted before the old files are removed.
+        if let Some(seq) = last_seq {
+            self.sync_to(seq)?;
+        }
+        Ok(rewritten)
+    }
+
+    fn read_region(&self, region_id: u64) -> Result<Option<RewriteRegion>> {
+        let (indexes, state) = {
+            let memtables = self.memtables.read().unwrap();
+            let table = match memtables.get(&region_id) {
+                Some(t) => t,
+                None => return Ok(None),
+            };
+            let mut indexes = Vec::with_capacity(table.entries_count());
+            table.fetch_all(&mut indexes);
+            (indexes, table.get_state().cloned())
+        };
+        let mut entries = Vec::with_capacity(indexes.len());
+        let mut size = 0;
+        for e in &indexes {
+            entries.push(self.read_entry(e)?);
+            size += e.len;
+        }
+        Ok(Some(RewriteRegion {
+            region_id: region_id,
+            indexes: indexes,
+            entries: entries,
+            state: state,
+            size: size,
+        }))
+    }
+
+    // Appends the regions which are not modified since they were read,
+    // returns the sequence of the record and the count of appended regions.
+    fn append_unchanged(&self, regions: Vec<RewriteRegion>) -> Result<Option<(u64, usize)>> {
+        // Block writes only while checking and appending.
+        let _lock = self.write_mutex.lock().unwrap();
+        let mut batch = LogBatch::new();
+        let mut count = 0;
+        {
+            let memtables = self.memtables.read().unwrap();
+            for region in regions {
+                let unchanged = memtables.get(&region.region_id).map_or(false, |t| {
+                    let mut indexes = Vec::with_capacity(t.entries_count());
+                    t.fetch_all(&mut indexes);
+                    indexes == region.indexes && t.get_state() == region.state.as_ref()
+                });
+                if !unchanged {
+                    continue;
+                }
+                batch.add_entries(region.region_id, &region.entries);
+                if let Some(ref state) = region.state {
+                    batch.put_raft_state(region.region_id, state);
+                }
+                count += 1;
+            }
+        }
+        if batch.is_empty() {
+            return Ok(None);
+        }
+        let (seq, _) = self.append_batch(batch)?;
+        Ok(Some((seq, count)))
+    }
+}
+
+// The data of a region read for rewriting.
+struct RewriteRegion {
+    region_id: u64,
+    indexes: Vec<EntryIndex>,
+    entries: Vec<Entry>,
+    state: Option<RaftLocalState>,
+    size: u64,
+}
+
+fn apply_to_memtables(
+    memtables: &mut HashMap<u64, MemTable>,
+    batch: LogBatch,
+    indexes: Vec<EntryIndex>,
+    file_num: u64,
+    base_offset: u64,
+) {
+    let mut indexes = indexes.into_iter();
+    for item in batch.items {
+        match item {
+            LogItem::Entries { region_id, entries } => {
+                let ents: Vec<_> = indexes
+                    .by_ref()
+                    .take(entries.len())
+                    .map(|mut e| {
+                        e.file_num = file_num;
+                        e.offset += base_offset;
+                        e
+                    })
+                    .collect();
+                memtables
+                    .entry(region_id)
+                    .or_insert_with(|| MemTable::new(region_id))
+                    .append(&ents);
+            }
+            LogItem::RaftState { region_id, state } => {
+                memtables
+                    .entry(region_id)
+                    .or_insert_with(|| MemTable::new(region_id))
+                    .set_state(state, file_num);
+            }
+            LogItem::CompactTo { region_id, index } => {
+                if let Some(t) = memtables.get_mut(&region_id) {
+                    t.compact_to(index);
+                }
+            }
+            LogItem::Clean { region_id } => {
+                memtables.remove(&region_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use tempdir::TempDir;
+
+    use util::config::ReadableSize;
+    use super::*;
+
+    fn new_entry(index: u64, term: u64) -> Entry {
+        let mut e = Entry::new();
+        e.set_index(index);
+        e.set_term(term);
+        e.set_data(vec![b'x'; 100]);
+        e
+    }
+
+    fn new_engine(path: &str, target_file_size: u64) -> RaftLogEngine {
+        let mut cfg = Config::default();
+        cfg.enabled = true;
+        cfg.dir = path.to_owned();
+        cfg.target_file_size = ReadableSize(target_file_size);
+        cfg.purge_threshold = ReadableSize(target_file_size * 10);
+        RaftLogEngine::new(cfg).unwrap()
+    }
+
+    fn append(engine: &RaftLogEngine, region_id: u64, begin: u64, end: u64, term: u64) {
+        let entries: Vec<_> = (begin..end).map(|i| new_entry(i, term)).collect();
+        let mut batch = LogBatch::new();
+        batch.add_entries(region_id, &entries);
+        let mut state = RaftLocalState::new();
+        state.set_last_index(end - 1);
+        batch.put_raft_state(region_id, &state);
+        engine.write(batch, true).unwrap();
+    }
+
+    fn must_have_entries(engine: &RaftLogEngine, region_id: u64, begin: u64, end: u64) {
+        let mut entries = vec![];
+        engine
+            .fetch_entries_to(region_id, begin, end, u64::max_value(), &mut entries)
+            .unwrap();
+        assert_eq!(entries.len(), (end - begin) as usize);
+        for (e, i) in entries.iter().zip(begin..end) {
+            assert_eq!(e.get_index(), i);
+            assert_eq!(e.get_data().len(), 100);
+        }
+    }
+
+    #[test]
+    fn test_engine_read_write() {
+        let dir = TempDir::new("test-raft-log-engine").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let engine = new_engine(path, 1024 * 1024);
+
+        append(&engine, 1, 1, 11, 1);
+        append(&engine, 2, 5, 8, 1);
+        // Overwrite conflicting entries.
+        append(&engine, 1, 8, 15, 2);
+        must_have_entries(&engine, 1, 1, 15);
+        must_have_entries(&engine, 2, 5, 8);
+        assert_eq!(engine.term(1, 7), Some(1));
+        assert_eq!(engine.term(1, 8), Some(2));
+        assert_eq!(engine.get_raft_state(1).unwrap().get_last_index(), 14);
+        assert_eq!(engine.get_entry(1, 15).unwrap(), None);
+        assert_eq!(engine.get_entry(3, 1).unwrap(), None);
+
+        let mut entries = vec![];
+        // At least one entry is fetched.
+        engine.fetch_entries_to(1, 1, 15, 0, &mut entries).unwrap();
+        assert_eq!(entries.len(), 1);
+
+        assert_eq!(engine.compact_to(1, 5).unwrap(), 4);
+        assert_eq!(engine.compact_to(1, 5).unwrap(), 0);
+        assert_eq!(engine.first_index(1), Some(5));
+        assert_eq!(engine.last_index(1), Some(14));
+
+        let mut batch = LogBatch::new();
+        batch.clean_region(2);
+        engine.write(batch, true).unwrap();
+        assert_eq!(engine.get_raft_state(2), None);
+        drop(engine);
+
+        // All the modifications are recovered after restart.
+        let engine = new_engine(path, 1024 * 1024);
+        must_have_entries(&engine, 1, 5, 15);
+        assert_eq!(engine.first_index(1), Some(5));
+        assert_eq!(engine.term(1, 8), Some(2));
+        assert_eq!(engine.get_raft_state(1).unwrap().get_last_index(), 14);
+        assert_eq!(engine.get_raft_state(2), None);
+        assert_eq!(engine.first_index(2), None);
+    }
+
+    #[test]
+    fn test_engine_recover_torn_tail() {
+        use std::fs::OpenOptions;
+        use std::io::Write;
+
+        let dir = TempDir::new("test-raft-log-engine").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let engine = new_engine(path, 1024 * 1024);
+        append(&engine, 1, 1, 11, 1);
+        drop(engine);
+
+        let file = dir.path().join(format!("{:016}.raftlog", 1));
+        let mut f = OpenOptions::new().append(true).open(&file).unwrap();
+        f.write_all(b"torn record").unwrap();
+        drop(f);
+
+        let engine = new_engine(path, 1024 * 1024);
+        must_have_entries(&engine, 1, 1, 11);
+        append(&engine, 1, 11, 12, 1);
+        drop(engine);
+        let engine = new_engine(path, 1024 * 1024);
+        must_have_entries(&engine, 1, 1, 12);
+    }
+
+    #[test]
+    fn test_engine_purge() {
+        let dir = TempDir::new("test-raft-log-engine").unwrap();
+        let path = dir.path().to_str().unwrap();
+        // Every write is larger than 1KB, so every write creates a new file.
+        let engine = new_engine(path, 1024);
+
+        // Region 2 is inactive and holds the first file.
+        append(&engine, 2, 1, 11, 1);
+        for i in 0..20 {
+            append(&engine, 1, i * 10 + 1, i * 10 + 11, 1);
+            engine.compact_to(1, i * 10 + 1).unwrap();
+        }
+        assert_eq!(engine.pipe_log.first_file_num(), 1);
+        assert!(engine.total_size() > 10 * 1024);
+
+        // Region 2 is rewritten, and then old files can be removed.
+        assert!(engine.purge_expired_files().unwrap() > 0);
+        assert!(engine.pipe_log.first_file_num() > 1);
+        assert!(engine.total_size() <= 10 * 1024);
+        must_have_entries(&engine, 1, 191, 201);
+        must_have_entries(&engine, 2, 1, 11);
+        drop(engine);
+
+        let engine = new_engine(path, 1024);
+        must_have_entries(&engine, 1, 191, 201);
+        must_have_entries(&engine, 2, 1, 11);
+        assert_eq!(engine.get_raft_state(2).unwrap().get_last_index(), 10);
+    }
+
+    #[test]
+    fn test_engine_rewrite_modified_region() {
+        let dir = TempDir::new("test-raft-log-engine").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let engine = new_engine(path, 1024 * 1024);
+
+        append(&engine, 1, 1, 11, 1);
+        append(&engine, 2, 1, 11, 1);
+        let region1 = engine.read_region(1).unwrap().unwrap();
+        let region2 = engine.read_region(2).unwrap().unwrap();
+        // Region 1 is modified after it's read, it must not be rewritten.
+        append(&engine, 1, 8, 15, 2);
+        let (_, count) = engine
+            .append_unchanged(vec![region1, region2])
+            .unwrap()
+            .unwrap();
+        assert_eq!(count, 1);
+        must_have_entries(&engine, 1, 1, 15);
+        assert_eq!(engine.term(1, 8), Some(2));
+        assert_eq!(engine.get_raft_state(1).unwrap().get_last_index(), 14);
+        must_have_entries(&engine, 2, 1, 11);
+    }
+
+    #[test]
+    fn test_engine_group_commit() {
+        let dir = TempDir::new("test-raft-log-engine").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let engine = Arc::new(new_engine(path, 1024 * 1024));
+
+        let handles: Vec<_> = (1..9)
+            .map(|region_id| {
+                let engine = engine.clone();
+                thread::spawn(move || for i in 0..10 {
+                    append(&engine, region_id, i * 10 + 1, i * 10 + 11, 1);
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        for region_id in 1..9 {
+            must_have_entries(&engine, region_id, 1, 101);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Write;
+
+use byteorder::ReadBytesExt;
+use protobuf::{self, Message};
+use kvproto::eraftpb::Entry;
+use kvproto::raft_serverpb::RaftLocalState;
+
+use util::codec::number::{NumberDecoder, NumberEncoder};
+use raftstore::Result;
+
+use super::memtable::EntryIndex;
+
+const TYPE_ENTRIES: u8 = 0x01;
+const TYPE_RAFT_STATE: u8 = 0x02;
+const TYPE_COMPACT: u8 = 0x03;
+const TYPE_CLEAN: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogItem {
+    Entries { region_id: u64, entries: Vec<Entry> },
+    RaftState {
+        region_id: u64,
+        state: RaftLocalState,
+    },
+    // All entries before `index` are removed.
+    CompactTo { region_id: u64, index: u64 },
+    // All data of the region is removed.
+    Clean { region_id: u64 },
+}
+
+/// `LogBatch` is a group of raft log modifications which are written
+/// to the log files atomically.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LogBatch {
+    pub items: Vec<LogItem>,
+}
+
+impl LogBatch {
+    pub fn new() -> LogBatch {
+        LogBatch::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Moves all the items of `other` to the end of this batch.
+    pub fn merge(&mut self, other: LogBatch) {
+        self.items.extend(other.items);
+    }
+
+    /// Appends entries of the region, entries with index not less than
+    /// the first one in `entries` will be overwritten.
+    pub fn add_entries(&mut self, region_id: u64, entries: &[Entry]) {
+        if entries.is_empty() {
+            return;
+        }
+        self.items.push(LogItem::Entries {
+            region_id: region_id,
+            entries: entries.to_vec(),
+        });
+    }
+
+    pub fn put_raft_state(&mut self, region_id: u64, state: &RaftLocalState) {
+        self.items.push(LogItem::RaftState {
+            region_id: region_id,
+            state: state.clone(),
+        });
+    }
+
+    pub fn compact_to(&mut self, region_id: u64, index: u64) {
+        self.items.push(LogItem::CompactTo {
+            region_id: region_id,
+            index: index,
+        });
+    }
+
+    pub fn clean_region(&mut self, region_id: u64) {
+        self.items.push(LogItem::Clean {
+            region_id: region_id,
+        });
+    }
+
+    /// Encodes the batch into `buf`. Returns the indexes of all encoded
+    /// entries in order, offsets are relative to the beginning of `buf`
+    /// and `file_num` is left to be filled by the caller.
+    pub fn encode_to(&self, buf: &mut Vec<u8>) -> Result<Vec<EntryIndex>> {
+        let mut indexes = vec![];
+        buf.encode_var_u64(self.items.len() as u64)?;
+        for item in &self.items {
+            match *item {
+                LogItem::Entries {
+                    region_id,
+                    ref entries,
+                } => {
+                    buf.write_all(&[TYPE_ENTRIES])?;
+                    buf.encode_var_u64(region_id)?;
+                    buf.encode_var_u64(entries.len() as u64)?;
+                    for e in entries {
+                        let len = e.compute_size() as u64;
+                        buf.encode_var_u64(len)?;
+                        let offset = buf.len() as u64;
+                        e.write_to_vec(buf)?;
+                        indexes.push(EntryIndex {
+                            index: e.get_index(),
+                            term: e.get_term(),
+                            file_num: 0,
+                            offset: offset,
+                            len: len,
+                        });
+                    }
+                }
+                LogItem::RaftState {
+                    region_id,
+                    ref state,
+                } => {
+                    buf.write_all(&[TYPE_RAFT_STATE])?;
+                    buf.encode_var_u64(region_id)?;
+                    buf.encode_var_u64(state.compute_size() as u64)?;
+                    state.write_to_vec(buf)?;
+                }
+                LogItem::CompactTo { region_id, index } => {
+                    buf.write_all(&[TYPE_COMPACT])?;
+                    buf.encode_var_u64(region_id)?;
+                    buf.encode_var_u64(index)?;
+                }
+                LogItem::Clean { region_id } => {
+                    buf.write_all(&[TYPE_CLEAN])?;
+                    buf.encode_var_u64(region_id)?;
+                }
+            }
+        }
+        Ok(indexes)
+    }
+
+    /// Decodes a batch encoded by `encode_to`, the returned indexes are the
+    /// same as the ones returned by `encode_to`.
+    pub fn decode(data: &[u8]) -> Result<(LogBatch, Vec<EntryIndex>)> {
+        let mut buf = data;
+        let mut batch = LogBatch::new();
+        let mut indexes = vec![];
+        let count = buf.decode_var_u64()?;
+        for _ in 0..count {
+            let tp = buf.read_u8()?;
+            let region_id = buf.decode_var_u64()?;
+            match tp {
+                TYPE_ENTRIES => {
+                    let n = buf.decode_var_u64()?;
+                    let mut entries = Vec::with_capacity(n as usize);
+                    for _ in 0..n {
+                        let len = buf.decode_var_u64()?;
+                        let offset = (data.len() - buf.len()) as u64;
+                        let bytes = read_bytes(&mut buf, len)?;
+                        let e: Entry = protobuf::parse_from_bytes(bytes)?;
+                        indexes.push(EntryIndex {
+                            index: e.get_index(),
+                            term: e.get_term(),
+                            file_num: 0,
+                            offset: offset,
+                            len: len,
+                        });
+                        entries.push(e);
+                    }
+                    batch.items.push(LogItem::Entries {
+                        region_id: region_id,
+                        entries: entries,
+                    });
+                }
+                TYPE_RAFT_STATE => {
+                    let len = buf.decode_var_u64()?;
+                    let bytes = read_bytes(&mut buf, len)?;
+                    let state = protobuf::parse_from_bytes(bytes)?;
+                    batch.put_raft_state(region_id, &state);
+                }
+                TYPE_COMPACT => {
+                    let index = buf.decode_var_u64()?;
+                    batch.compact_to(region_id, index);
+                }
+                TYPE_CLEAN => batch.clean_region(region_id),
+                _ => return Err(box_err!("unknown log item type {}", tp)),
+            }
+        }
+        if !buf.is_empty() {
+            return Err(box_err!("{} bytes left after decoding log batch", buf.len()));
+        }
+        Ok((batch, indexes))
+    }
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8], len: u64) -> Result<&'a [u8]> {
+    let len = len as usize;
+    if buf.len() < len {
+        return Err(box_err!("log batch is truncated, {} < {}", buf.len(), len));
+    }
+    let (bytes, left) = buf.split_at(len);
+    *buf = left;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_entry(index: u64, term: u64) -> Entry {
+        let mut e = Entry::new();
+        e.set_index(index);
+        e.set_term(term);
+        e.set_data(vec![b'x'; index as usize]);
+        e
+    }
+
+    #[test]
+    fn test_log_batch_codec() {
+        let mut batch = LogBatch::new();
+        assert!(batch.is_empty());
+        let entries: Vec<_> = (1..10).map(|i| new_entry(i, 2)).collect();
+        batch.add_entries(1, &entries);
+        batch.add_entries(2, &[]);
+        let mut state = RaftLocalState::new();
+        state.set_last_index(9);
+        batch.put_raft_state(1, &state);
+        batch.compact_to(3, 5);
+        batch.clean_region(4);
+        assert_eq!(batch.items.len(), 4);
+
+        let mut buf = vec![];
+        let indexes = batch.encode_to(&mut buf).unwrap();
+        assert_eq!(indexes.len(), entries.len());
+        for (e, idx) in entries.iter().zip(&indexes) {
+            let start = idx.offset as usize;
+            let end = start + idx.len as usize;
+            let decoded: Entry = protobuf::parse_from_bytes(&buf[start..end]).unwrap();
+            assert_eq!(&decoded, e);
+        }
+
+        let (decoded, decoded_indexes) = LogBatch::decode(&buf).unwrap();
+        assert_eq!(decoded, batch);
+        assert_eq!(decoded_indexes, indexes);
+
+        assert!(LogBatch::decode(&buf[..buf.len() - 1]).is_err());
+        buf.push(0);
+        assert!(LogBatch::decode(&buf).is_err());
+    }
+}
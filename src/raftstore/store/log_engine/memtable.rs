@@ -0,0 +1,244 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::collections::VecDeque;
+
+use kvproto::raft_serverpb::RaftLocalState;
+
+/// The location of an entry in the log files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntryIndex {
+    pub index: u64,
+    pub term: u64,
+    pub file_num: u64,
+    // Offset of the encoded entry in the file.
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// `MemTable` is the in-memory index of all the raft logs of a region.
+pub struct MemTable {
+    region_id: u64,
+    entries: VecDeque<EntryIndex>,
+    // The latest raft state and the file it's written to.
+    state: Option<(RaftLocalState, u64)>,
+}
+
+impl MemTable {
+    pub fn new(region_id: u64) -> MemTable {
+        MemTable {
+            region_id: region_id,
+            entries: VecDeque::new(),
+            state: None,
+        }
+    }
+
+    /// Appends the entry indexes, existing entries conflicting with the new ones
+    /// are removed.
+    pub fn append(&mut self, indexes: &[EntryIndex]) {
+        if indexes.is_empty() {
+            return;
+        }
+        let first_index = indexes[0].index;
+        if let Some(last_index) = self.last_index() {
+            if last_index >= first_index {
+                let front = self.entries.front().unwrap().index;
+                let left = first_index.saturating_sub(front) as usize;
+                self.entries.truncate(left);
+            } else if last_index + 1 < first_index {
+                panic!(
+                    "[region {}] unexpected hole: {} < {}",
+                    self.region_id,
+                    last_index,
+                    first_index
+                );
+            }
+        }
+        self.entries.extend(indexes);
+    }
+
+    /// Removes all entries before `idx`, returns the count of removed entries.
+    pub fn compact_to(&mut self, idx: u64) -> usize {
+        let first_index = match self.first_index() {
+            Some(i) => i,
+            None => return 0,
+        };
+        if first_index >= idx {
+            return 0;
+        }
+        let count = cmp::min((idx - first_index) as usize, self.entries.len());
+        self.entries.drain(..count);
+        count
+    }
+
+    pub fn set_state(&mut self, state: RaftLocalState, file_num: u64) {
+        self.state = Some((state, file_num));
+    }
+
+    pub fn get_state(&self) -> Option<&RaftLocalState> {
+        self.state.as_ref().map(|&(ref s, _)| s)
+    }
+
+    pub fn first_index(&self) -> Option<u64> {
+        self.entries.front().map(|e| e.index)
+    }
+
+    pub fn last_index(&self) -> Option<u64> {
+        self.entries.back().map(|e| e.index)
+    }
+
+    pub fn entries_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get_entry_index(&self, idx: u64) -> Option<EntryIndex> {
+        let first_index = match self.first_index() {
+            Some(i) => i,
+            None => return None,
+        };
+        if idx < first_index {
+            return None;
+        }
+        self.entries.get((idx - first_index) as usize).cloned()
+    }
+
+    /// Collects indexes of entries in [begin, end), stops at the first missing one.
+    /// The first entry is always collected, others are collected only if
+    /// the total size is within `max_size`.
+    pub fn fetch_entry_indexes(
+        &self,
+        begin: u64,
+        end: u64,
+        max_size: u64,
+        vec: &mut Vec<EntryIndex>,
+    ) -> u64 {
+        let mut total_size = 0;
+        for idx in begin..end {
+            let e = match self.get_entry_index(idx) {
+                Some(e) => e,
+                None => break,
+            };
+            total_size += e.len;
+            if vec.is_empty() || total_size <= max_size {
+                vec.push(e);
+            }
+            if total_size > max_size {
+                break;
+            }
+        }
+        total_size
+    }
+
+    pub fn fetch_all(&self, vec: &mut Vec<EntryIndex>) {
+        vec.extend(&self.entries);
+    }
+
+    /// The smallest file number that is still referenced by the region.
+    pub fn min_file_num(&self) -> Option<u64> {
+        let entry_file = self.entries.front().map(|e| e.file_num);
+        let state_file = self.state.as_ref().map(|&(_, f)| f);
+        match (entry_file, state_file) {
+            (Some(e), Some(s)) => Some(cmp::min(e, s)),
+            (e, s) => e.or(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_indexes(begin: u64, end: u64, file_num: u64) -> Vec<EntryIndex> {
+        (begin..end)
+            .map(|i| {
+                EntryIndex {
+                    index: i,
+                    term: 1,
+                    file_num: file_num,
+                    offset: i * 10,
+                    len: 10,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_memtable_append_compact() {
+        let mut table = MemTable::new(1);
+        assert_eq!(table.min_file_num(), None);
+        table.append(&new_indexes(10, 20, 1));
+        assert_eq!(table.first_index(), Some(10));
+        assert_eq!(table.last_index(), Some(19));
+        assert_eq!(table.min_file_num(), Some(1));
+
+        // Conflicting entries are overwritten.
+        table.append(&new_indexes(15, 25, 2));
+        assert_eq!(table.entries_count(), 15);
+        assert_eq!(table.get_entry_index(14).unwrap().file_num, 1);
+        assert_eq!(table.get_entry_index(15).unwrap().file_num, 2);
+        assert_eq!(table.get_entry_index(25), None);
+        assert_eq!(table.get_entry_index(9), None);
+
+        table.set_state(RaftLocalState::new(), 3);
+        assert_eq!(table.min_file_num(), Some(1));
+        assert_eq!(table.compact_to(5), 0);
+        assert_eq!(table.compact_to(15), 5);
+        assert_eq!(table.first_index(), Some(15));
+        assert_eq!(table.min_file_num(), Some(2));
+        assert_eq!(table.compact_to(100), 10);
+        assert_eq!(table.entries_count(), 0);
+        assert_eq!(table.min_file_num(), Some(3));
+
+        // Entries can be appended after all of them are compacted.
+        table.append(&new_indexes(100, 101, 4));
+        assert_eq!(table.first_index(), Some(100));
+
+        // Overwrite all the entries.
+        table.append(&new_indexes(90, 95, 5));
+        assert_eq!(table.first_index(), Some(90));
+        assert_eq!(table.last_index(), Some(94));
+    }
+
+    #[test]
+    fn test_memtable_fetch() {
+        let mut table = MemTable::new(1);
+        table.append(&new_indexes(10, 20, 1));
+
+        let mut vec = vec![];
+        assert_eq!(table.fetch_entry_indexes(10, 15, u64::max_value(), &mut vec), 50);
+        assert_eq!(vec.len(), 5);
+
+        vec.clear();
+        assert_eq!(table.fetch_entry_indexes(10, 15, 25, &mut vec), 30);
+        assert_eq!(vec.len(), 2);
+
+        // At least one entry is fetched.
+        vec.clear();
+        table.fetch_entry_indexes(10, 15, 0, &mut vec);
+        assert_eq!(vec.len(), 1);
+
+        // Stop at missing entries.
+        vec.clear();
+        table.fetch_entry_indexes(18, 25, u64::max_value(), &mut vec);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_memtable_hole() {
+        let mut table = MemTable::new(1);
+        table.append(&new_indexes(10, 20, 1));
+        table.append(&new_indexes(21, 22, 1));
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod config;
+mod engine;
+mod log_batch;
+mod memtable;
+mod pipe_log;
+
+use std::mem;
+
+use protobuf;
+use rocksdb::{Writable, WriteBatch, DB};
+use kvproto::eraftpb::Entry;
+
+use raftstore::Result;
+use super::engine::{Iterable, Mutable, Peekable};
+use super::keys;
+
+pub use self::config::Config;
+pub use self::engine::RaftLogEngine;
+pub use self::log_batch::LogBatch;
+
+// Raft logs are written in batches of this size when migrating.
+const MIGRATE_BATCH_SIZE: usize = 4 * 1024 * 1024;
+// Raft logs are deleted from the raft RocksDB in batches of this count.
+const DELETE_BATCH_COUNT: usize = 10000;
+
+// Raft logs are moved between the raft RocksDB and the log engine by copying
+// them in bounded batches first, the source is only removed after the copy is
+// persisted. `RAFT_LOG_ENGINE_KEY` in the raft RocksDB tells which side holds
+// the complete data, so that an interrupted migration in either direction
+// can be finished or rolled back on the next start.
+
+/// Moves all raft logs and raft states in the raft RocksDB into the log engine,
+/// returns the count of migrated regions.
+pub fn migrate_from_raft_db(raft_engine: &DB, log_engine: &RaftLogEngine) -> Result<usize> {
+    let mut regions = 0;
+    if raft_engine.get_value(keys::RAFT_LOG_ENGINE_KEY)?.is_none() {
+        // The raft RocksDB is complete, data left in the log engine is stale.
+        clean_log_engine(log_engine)?;
+        regions = copy_to_log_engine(raft_engine, log_engine)?;
+        raft_engine.put(keys::RAFT_LOG_ENGINE_KEY, &[])?;
+        raft_engine.sync_wal()?;
+    }
+    // The data left in the raft RocksDB is either migrated or stale.
+    delete_raft_db_logs(raft_engine)?;
+    Ok(regions)
+}
+
+/// Moves all raft logs and raft states in the log engine back to the raft
+/// RocksDB, so that the log engine can be disabled. Returns the count of
+/// migrated regions.
+pub fn migrate_to_raft_db(log_engine: &RaftLogEngine, raft_engine: &DB) -> Result<usize> {
+    let mut regions = 0;
+    if raft_engine.get_value(keys::RAFT_LOG_ENGINE_KEY)?.is_some() {
+        // The log engine is complete, data left in the raft RocksDB is stale.
+        delete_raft_db_logs(raft_engine)?;
+        regions = copy_to_raft_db(log_engine, raft_engine)?;
+        raft_engine.delete(keys::RAFT_LOG_ENGINE_KEY)?;
+        raft_engine.sync_wal()?;
+    }
+    clean_log_engine(log_engine)?;
+    Ok(regions)
+}
+
+fn copy_to_log_engine(raft_engine: &DB, log_engine: &RaftLogEngine) -> Result<usize> {
+    let start_key = keys::REGION_RAFT_PREFIX_KEY;
+    let end_key = &[keys::LOCAL_PREFIX, keys::REGION_RAFT_PREFIX + 1];
+    let mut batch = LogBatch::new();
+    let mut batch_size = 0;
+    let mut regions = 0;
+    raft_engine.scan(start_key, end_key, false, &mut |key, value| {
+        if let Ok((region_id, _)) = keys::decode_raft_log_key(key) {
+            let entry: Entry = protobuf::parse_from_bytes(value)?;
+            batch.add_entries(region_id, &[entry]);
+        } else if let Ok((region_id, keys::RAFT_STATE_SUFFIX)) =
+            keys::decode_region_raft_key(key)
+        {
+            batch.put_raft_state(region_id, &protobuf::parse_from_bytes(value)?);
+            regions += 1;
+        } else {
+            return Ok(true);
+        }
+        batch_size += value.len();
+        if batch_size >= MIGRATE_BATCH_SIZE {
+            log_engine.write(mem::replace(&mut batch, LogBatch::new()), true)?;
+            batch_size = 0;
+        }
+        Ok(true)
+    })?;
+    log_engine.write(batch, true)?;
+    Ok(regions)
+}
+
+fn copy_to_raft_db(log_engine: &RaftLogEngine, raft_engine: &DB) -> Result<usize> {
+    let region_ids = log_engine.region_ids();
+    let mut wb = WriteBatch::new();
+    for region_id in &region_ids {
+        let region_id = *region_id;
+        if let (Some(first), Some(last)) = (
+            log_engine.first_index(region_id),
+            log_engine.last_index(region_id),
+        ) {
+            let mut idx = first;
+            while idx <= last {
+                let mut entries = vec![];
+                log_engine.fetch_entries_to(
+                    region_id,
+                    idx,
+                    last + 1,
+                    MIGRATE_BATCH_SIZE as u64,
+                    &mut entries,
+                )?;
+                if entries.is_empty() {
+                    return Err(box_err!("[region {}] entry {} is missing", region_id, idx));
+                }
+                for e in &entries {
+                    wb.put_msg(&keys::raft_log_key(region_id, e.get_index()), e)?;
+                }
+                idx += entries.len() as u64;
+                if wb.data_size() >= MIGRATE_BATCH_SIZE {
+                    raft_engine.write(mem::replace(&mut wb, WriteBatch::new()))?;
+                }
+            }
+        }
+        if let Some(state) = log_engine.get_raft_state(region_id) {
+            wb.put_msg(&keys::raft_state_key(region_id), &state)?;
+        }
+    }
+    if !wb.is_empty() {
+        raft_engine.write(wb)?;
+    }
+    raft_engine.sync_wal()?;
+    Ok(region_ids.len())
+}
+
+// Removes all raft logs and raft states from the raft RocksDB.
+fn delete_raft_db_logs(raft_engine: &DB) -> Result<()> {
+    let start_key = keys::REGION_RAFT_PREFIX_KEY;
+    let end_key = &[keys::LOCAL_PREFIX, keys::REGION_RAFT_PREFIX + 1];
+    let mut wb = WriteBatch::new();
+    raft_engine.scan(start_key, end_key, false, &mut |key, _| {
+        let is_raft_data = keys::decode_raft_log_key(key).is_ok() ||
+            keys::decode_region_raft_key(key)
+                .map(|(_, suffix)| suffix == keys::RAFT_STATE_SUFFIX)
+                .unwrap_or(false);
+        if is_raft_data {
+            wb.delete(key)?;
+        }
+        if wb.count() >= DELETE_BATCH_COUNT {
+            raft_engine.write(mem::replace(&mut wb, WriteBatch::new()))?;
+        }
+        Ok(true)
+    })?;
+    if !wb.is_empty() {
+        raft_engine.write(wb)?;
+        raft_engine.sync_wal()?;
+    }
+    Ok(())
+}
+
+// Removes all the data in the log engine.
+fn clean_log_engine(log_engine: &RaftLogEngine) -> Result<()> {
+    let region_ids = log_engine.region_ids();
+    if region_ids.is_empty() {
+        return Ok(());
+    }
+    let mut batch = LogBatch::new();
+    for region_id in region_ids {
+        batch.clean_region(region_id);
+    }
+    log_engine.write(batch, true)?;
+    log_engine.purge_expired_files()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempdir::TempDir;
+    use rocksdb::DB;
+    use kvproto::raft_serverpb::RaftLocalState;
+
+    use util::rocksdb::new_engine;
+    use raftstore::store::engine::{Mutable, Peekable};
+    use storage::CF_DEFAULT;
+    use super::*;
+
+    fn put_raft_logs(raft_db: &DB, region_ids: &[u64]) {
+        for region_id in region_ids {
+            let region_id = *region_id;
+            for i in 1..11 {
+                let mut e = Entry::new();
+                e.set_index(i);
+                e.set_term(region_id);
+                raft_db
+                    .put_msg(&keys::raft_log_key(region_id, i), &e)
+                    .unwrap();
+            }
+            let mut state = RaftLocalState::new();
+            state.set_last_index(10);
+            raft_db
+                .put_msg(&keys::raft_state_key(region_id), &state)
+                .unwrap();
+        }
+    }
+
+    fn must_have_raft_db_logs(raft_db: &DB, region_ids: &[u64]) {
+        for region_id in region_ids {
+            let region_id = *region_id;
+            let state: RaftLocalState = raft_db
+                .get_msg(&keys::raft_state_key(region_id))
+                .unwrap()
+                .unwrap();
+            assert_eq!(state.get_last_index(), 10);
+            for i in 1..11 {
+                let e: Entry = raft_db
+                    .get_msg(&keys::raft_log_key(region_id, i))
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(e.get_term(), region_id);
+            }
+        }
+    }
+
+    fn must_have_log_engine_logs(log_engine: &RaftLogEngine, region_ids: &[u64]) {
+        for region_id in region_ids {
+            let region_id = *region_id;
+            assert_eq!(
+                log_engine
+                    .get_raft_state(region_id)
+                    .unwrap()
+                    .get_last_index(),
+                10
+            );
+            assert_eq!(log_engine.first_index(region_id), Some(1));
+            assert_eq!(log_engine.last_index(region_id), Some(10));
+            assert_eq!(log_engine.term(region_id, 5), Some(region_id));
+        }
+    }
+
+    fn new_log_engine(path: &TempDir) -> RaftLogEngine {
+        let mut cfg = Config::default();
+        cfg.dir = path.path().join("raft-log").to_str().unwrap().to_owned();
+        RaftLogEngine::new(cfg).unwrap()
+    }
+
+    #[test]
+    fn test_migrate_from_raft_db() {
+        let path = TempDir::new("test-migrate-raft-db").unwrap();
+        let raft_db = Arc::new(
+            new_engine(path.path().join("raft").to_str().unwrap(), &[CF_DEFAULT]).unwrap(),
+        );
+        put_raft_logs(&raft_db, &[1, 2, 3]);
+
+        let log_engine = new_log_engine(&path);
+        // Stale data left by an interrupted migration is dropped.
+        let mut batch = LogBatch::new();
+        let mut e = Entry::new();
+        e.set_index(20);
+        batch.add_entries(4, &[e]);
+        log_engine.write(batch, true).unwrap();
+
+        assert_eq!(migrate_from_raft_db(&raft_db, &log_engine).unwrap(), 3);
+        assert!(
+            raft_db
+                .get_value(keys::RAFT_LOG_ENGINE_KEY)
+                .unwrap()
+                .is_some()
+        );
+        for region_id in 1..4 {
+            assert!(
+                raft_db
+                    .get_value(&keys::raft_state_key(region_id))
+                    .unwrap()
+                    .is_none()
+            );
+            assert!(
+                raft_db
+                    .get_value(&keys::raft_log_key(region_id, 1))
+                    .unwrap()
+                    .is_none()
+            );
+        }
+        must_have_log_engine_logs(&log_engine, &[1, 2, 3]);
+        assert_eq!(log_engine.last_index(4), None);
+        assert_eq!(migrate_from_raft_db(&raft_db, &log_engine).unwrap(), 0);
+        must_have_log_engine_logs(&log_engine, &[1, 2, 3]);
+
+        // Logs left in the raft RocksDB after the migration is confirmed are
+        // stale, they are removed without touching the log engine.
+        put_raft_logs(&raft_db, &[5]);
+        assert_eq!(migrate_from_raft_db(&raft_db, &log_engine).unwrap(), 0);
+        assert!(
+            raft_db
+                .get_value(&keys::raft_state_key(5))
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(log_engine.last_index(5), None);
+    }
+
+    #[test]
+    fn test_migrate_to_raft_db() {
+        let path = TempDir::new("test-migrate-to-raft-db").unwrap();
+        let raft_db = Arc::new(
+            new_engine(path.path().join("raft").to_str().unwrap(), &[CF_DEFAULT]).unwrap(),
+        );
+        put_raft_logs(&raft_db, &[1, 2, 3]);
+        let log_engine = new_log_engine(&path);
+
+        // Nothing to roll back if the logs are never migrated.
+        assert_eq!(migrate_to_raft_db(&log_engine, &raft_db).unwrap(), 0);
+        must_have_raft_db_logs(&raft_db, &[1, 2, 3]);
+
+        assert_eq!(migrate_from_raft_db(&raft_db, &log_engine).unwrap(), 3);
+        // Logs left by an interrupted rollback are stale.
+        put_raft_logs(&raft_db, &[4]);
+        assert_eq!(migrate_to_raft_db(&log_engine, &raft_db).unwrap(), 3);
+        must_have_raft_db_logs(&raft_db, &[1, 2, 3]);
+        assert!(
+            raft_db
+                .get_value(&keys::raft_state_key(4))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            raft_db
+                .get_value(keys::RAFT_LOG_ENGINE_KEY)
+                .unwrap()
+                .is_none()
+        );
+        assert!(log_engine.region_ids().is_empty());
+
+        // Migrating again after the rollback starts over from the raft RocksDB.
+        assert_eq!(migrate_from_raft_db(&raft_db, &log_engine).unwrap(), 3);
+        must_have_log_engine_logs(&log_engine, &[1, 2, 3]);
+    }
+}
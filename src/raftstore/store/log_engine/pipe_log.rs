@@ -0,0 +1,341 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use byteorder::{ByteOrder, LittleEndian};
+use crc::crc32;
+
+use raftstore::Result;
+
+const LOG_SUFFIX: &'static str = ".raftlog";
+const FILE_NUM_LEN: usize = 16;
+const INIT_FILE_NUM: u64 = 1;
+
+/// Every record starts with a header of `len: u32 | checksum: u32`,
+/// both are in little endian.
+pub const RECORD_HEADER_LEN: usize = 8;
+
+fn file_name(file_num: u64) -> String {
+    format!("{:016}{}", file_num, LOG_SUFFIX)
+}
+
+fn parse_file_num(name: &str) -> Option<u64> {
+    if name.len() != FILE_NUM_LEN + LOG_SUFFIX.len() || !name.ends_with(LOG_SUFFIX) {
+        return None;
+    }
+    name[..FILE_NUM_LEN].parse().ok()
+}
+
+/// Encodes `content` into a record which can be appended to the log files.
+pub fn encode_record(content: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0; RECORD_HEADER_LEN + content.len()];
+    LittleEndian::write_u32(&mut buf[..4], content.len() as u32);
+    LittleEndian::write_u32(&mut buf[4..RECORD_HEADER_LEN], crc32::checksum_ieee(content));
+    buf[RECORD_HEADER_LEN..].copy_from_slice(content);
+    buf
+}
+
+/// Decodes the record at the beginning of `buf`. Returns `None` if the record
+/// is incomplete or corrupted.
+pub fn decode_record(buf: &[u8]) -> Option<&[u8]> {
+    if buf.len() < RECORD_HEADER_LEN {
+        return None;
+    }
+    let len = LittleEndian::read_u32(&buf[..4]) as usize;
+    let checksum = LittleEndian::read_u32(&buf[4..RECORD_HEADER_LEN]);
+    if buf.len() < RECORD_HEADER_LEN + len {
+        return None;
+    }
+    let content = &buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len];
+    if crc32::checksum_ieee(content) != checksum {
+        return None;
+    }
+    Some(content)
+}
+
+struct ActiveFile {
+    num: u64,
+    file: File,
+    size: u64,
+    // Sequence number of the last appended record.
+    seq: u64,
+}
+
+/// `PipeLog` manages the append-only log files. Records are only appended to
+/// the active file, a new one is created once it exceeds `target_file_size`.
+/// Space is reclaimed by removing the oldest files as a whole.
+pub struct PipeLog {
+    dir: PathBuf,
+    target_file_size: u64,
+    active: Mutex<ActiveFile>,
+    // All the files including the active one, for reading.
+    files: RwLock<BTreeMap<u64, Arc<File>>>,
+}
+
+impl PipeLog {
+    pub fn open(dir: &str, target_file_size: u64) -> Result<PipeLog> {
+        let dir = PathBuf::from(dir);
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let mut nums = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if let Some(num) = entry.file_name().to_str().and_then(parse_file_num) {
+                nums.push(num);
+            }
+        }
+        nums.sort();
+        for w in nums.windows(2) {
+            if w[0] + 1 != w[1] {
+                return Err(box_err!(
+                    "raft log file {} is missing in {}",
+                    w[0] + 1,
+                    dir.display()
+                ));
+            }
+        }
+
+        let mut files = BTreeMap::new();
+        for num in &nums {
+            let f = File::open(dir.join(file_name(*num)))?;
+            files.insert(*num, Arc::new(f));
+        }
+        let active_num = nums.last().cloned().unwrap_or(INIT_FILE_NUM);
+        let active_file = open_active_file(&dir, active_num)?;
+        if nums.is_empty() {
+            files.insert(active_num, Arc::new(active_file.try_clone()?));
+        }
+        let size = active_file.metadata()?.len();
+
+        Ok(PipeLog {
+            dir: dir,
+            target_file_size: target_file_size,
+            active: Mutex::new(ActiveFile {
+                num: active_num,
+                file: active_file,
+                size: size,
+                seq: 0,
+            }),
+            files: RwLock::new(files),
+        })
+    }
+
+    pub fn file_nums(&self) -> Vec<u64> {
+        self.files.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn first_file_num(&self) -> u64 {
+        *self.files.read().unwrap().keys().next().unwrap()
+    }
+
+    pub fn active_file_num(&self) -> u64 {
+        self.active.lock().unwrap().num
+    }
+
+    /// Approximate size of all the files.
+    pub fn total_size(&self) -> u64 {
+        let active = self.active.lock().unwrap();
+        let files_count = self.files.read().unwrap().len() as u64;
+        // Only the active file can be smaller than the target size.
+        (files_count - 1) * self.target_file_size + active.size
+    }
+
+    pub fn read_file(&self, num: u64) -> Result<Vec<u8>> {
+        let mut f = File::open(self.dir.join(file_name(num)))?;
+        let mut buf = vec![];
+        f.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Truncates the active file to `size`, used to drop the torn tail
+    /// after a crash.
+    pub fn truncate_active_file(&self, size: u64) -> Result<()> {
+        let mut active = self.active.lock().unwrap();
+        active.file.set_len(size)?;
+        active.file.sync_all()?;
+        active.size = size;
+        Ok(())
+    }
+
+    /// Appends a record encoded by `encode_record`. Returns the file number,
+    /// the offset of the record and the sequence number of this append.
+    pub fn append(&self, record: &[u8]) -> Result<(u64, u64, u64)> {
+        let mut active = self.active.lock().unwrap();
+        if active.size >= self.target_file_size {
+            self.rotate(&mut active)?;
+        }
+        active.file.write_all(record)?;
+        let offset = active.size;
+        active.size += record.len() as u64;
+        active.seq += 1;
+        Ok((active.num, offset, active.seq))
+    }
+
+    fn rotate(&self, active: &mut ActiveFile) -> Result<()> {
+        // Records in the old file may not be synced yet.
+        active.file.sync_all()?;
+        let num = active.num + 1;
+        let file = open_active_file(&self.dir, num)?;
+        sync_dir(&self.dir)?;
+        self.files
+            .write()
+            .unwrap()
+            .insert(num, Arc::new(file.try_clone()?));
+        active.num = num;
+        active.file = file;
+        active.size = 0;
+        Ok(())
+    }
+
+    /// Syncs all appended records, returns the sequence number of the last
+    /// synced record.
+    pub fn sync(&self) -> Result<u64> {
+        let (file, seq) = {
+            let active = self.active.lock().unwrap();
+            (active.file.try_clone()?, active.seq)
+        };
+        // Sync without holding the lock, so other records can still be appended.
+        file.sync_data()?;
+        Ok(seq)
+    }
+
+    pub fn read(&self, file_num: u64, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let file = match self.files.read().unwrap().get(&file_num) {
+            Some(f) => f.clone(),
+            None => return Err(box_err!("raft log file {} not found", file_num)),
+        };
+        let mut buf = vec![0; len as usize];
+        let mut read = 0;
+        while read < buf.len() {
+            let n = file.read_at(&mut buf[read..], offset + read as u64)?;
+            if n == 0 {
+                return Err(box_err!(
+                    "unexpected eof in raft log file {} at {}",
+                    file_num,
+                    offset + read as u64
+                ));
+            }
+            read += n;
+        }
+        Ok(buf)
+    }
+
+    /// Removes all files before `file_num`, the active file is never removed.
+    /// Returns the count of removed files.
+    pub fn purge_to(&self, file_num: u64) -> Result<usize> {
+        let active_num = self.active_file_num();
+        let file_num = if file_num > active_num {
+            active_num
+        } else {
+            file_num
+        };
+        let purged: Vec<u64> = {
+            let mut files = self.files.write().unwrap();
+            let nums: Vec<u64> = files.range(..file_num).map(|(n, _)| *n).collect();
+            for n in &nums {
+                files.remove(n);
+            }
+            nums
+        };
+        for n in &purged {
+            fs::remove_file(self.dir.join(file_name(*n)))?;
+        }
+        Ok(purged.len())
+    }
+}
+
+fn open_active_file(dir: &Path, num: u64) -> Result<File> {
+    let f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(dir.join(file_name(num)))?;
+    Ok(f)
+}
+
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_record_codec() {
+        let record = encode_record(b"hello");
+        assert_eq!(record.len(), RECORD_HEADER_LEN + 5);
+        assert_eq!(decode_record(&record), Some(&b"hello"[..]));
+        assert_eq!(decode_record(&record[..record.len() - 1]), None);
+        let mut corrupted = record.clone();
+        corrupted[RECORD_HEADER_LEN] = b'j';
+        assert_eq!(decode_record(&corrupted), None);
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(parse_file_num(&file_name(12)), Some(12));
+        assert_eq!(parse_file_num("12.raftlog"), None);
+        assert_eq!(parse_file_num("0000000000000012.sst"), None);
+    }
+
+    #[test]
+    fn test_pipe_log() {
+        let dir = TempDir::new("test-pipe-log").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let pipe_log = PipeLog::open(path, 120).unwrap();
+        assert_eq!(pipe_log.file_nums(), vec![INIT_FILE_NUM]);
+
+        let record = encode_record(&[b'x'; 42]);
+        let mut positions = vec![];
+        for i in 0..6 {
+            let (num, offset, seq) = pipe_log.append(&record).unwrap();
+            assert_eq!(seq, i + 1);
+            positions.push((num, offset));
+        }
+        // Every file can hold 3 records at most.
+        assert_eq!(pipe_log.file_nums(), vec![1, 2]);
+        assert_eq!(positions[3], (2, 0));
+        assert_eq!(pipe_log.sync().unwrap(), 6);
+        assert_eq!(pipe_log.total_size(), 120 + record.len() as u64 * 3);
+        for &(num, offset) in &positions {
+            let buf = pipe_log.read(num, offset, record.len() as u64).unwrap();
+            assert_eq!(buf, record);
+        }
+        drop(pipe_log);
+
+        // Reopen and append to the last file.
+        let pipe_log = PipeLog::open(path, 120).unwrap();
+        assert_eq!(pipe_log.file_nums(), vec![1, 2]);
+        assert_eq!(pipe_log.read_file(1).unwrap().len(), record.len() * 3);
+        pipe_log.append(&record).unwrap();
+        assert_eq!(pipe_log.file_nums(), vec![1, 2, 3]);
+
+        assert_eq!(pipe_log.purge_to(3).unwrap(), 2);
+        assert_eq!(pipe_log.first_file_num(), 3);
+        assert!(pipe_log.read(1, 0, 1).is_err());
+        // The active file can't be purged.
+        assert_eq!(pipe_log.purge_to(10).unwrap(), 0);
+        assert_eq!(pipe_log.file_nums(), vec![3]);
+    }
+}
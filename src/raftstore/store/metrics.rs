@@ -28,10 +28,11 @@ lazy_static! {
             &["type", "status"]
         ).unwrap();
 
-    pub static ref PEER_APPEND_LOG_HISTOGRAM: Histogram =
-        register_histogram!(
+    pub static ref PEER_APPEND_LOG_HISTOGRAM: HistogramVec =
+        register_histogram_vec!(
             "tikv_raftstore_append_log_duration_seconds",
             "Bucketed histogram of peer appending log duration",
+            &["poller"],
             exponential_buckets(0.0005, 2.0, 20).unwrap()
         ).unwrap();
 
@@ -77,6 +78,13 @@ lazy_static! {
             &["type"]
         ).unwrap();
 
+    pub static ref STORE_POLLER_REGION_GAUGE_VEC: GaugeVec =
+        register_gauge_vec!(
+            "tikv_raftstore_poller_region_count",
+            "Number of regions handled by each raftstore poller.",
+            &["poller", "type"]
+        ).unwrap();
+
     pub static ref STORE_SNAPSHOT_TRAFFIC_GAUGE_VEC: GaugeVec =
         register_gauge_vec!(
             "tikv_raftstore_snapshot_traffic_total",
@@ -95,7 +103,7 @@ lazy_static! {
         register_histogram_vec!(
             "tikv_raftstore_raft_process_duration_secs",
             "Bucketed histogram of peer processing raft duration",
-            &["type"],
+            &["poller", "type"],
             exponential_buckets(0.0005, 2.0, 20).unwrap()
         ).unwrap();
 
@@ -107,6 +115,14 @@ lazy_static! {
                     2097152.0, 4194304.0, 8388608.0, 16777216.0]
         ).unwrap();
 
+    pub static ref PEER_PROPOSE_COMPRESSED_LOG_SIZE_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_raftstore_propose_compressed_log_size",
+            "Bucketed histogram of peer proposing log size after compression",
+            vec![256.0, 512.0, 1024.0, 4096.0, 65536.0, 262144.0, 524288.0, 1048576.0,
+                    2097152.0, 4194304.0, 8388608.0, 16777216.0]
+        ).unwrap();
+
     pub static ref REGION_HASH_COUNTER_VEC: CounterVec =
         register_counter_vec!(
             "tikv_raftstore_hash_total",
@@ -179,6 +195,12 @@ lazy_static! {
             &["type"]
         ).unwrap();
 
+    pub static ref RAFT_ENTRY_CACHE_SIZE_GAUGE: Gauge =
+        register_gauge!(
+            "tikv_raftstore_entry_cache_size_bytes",
+            "Memory used by the raft entry caches of the store"
+        ).unwrap();
+
     pub static ref BATCH_SNAPSHOT_COMMANDS: Histogram =
         register_histogram!(
             "tikv_raftstore_batch_snapshot_commands_total",
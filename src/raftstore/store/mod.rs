@@ -20,6 +20,7 @@ pub mod bootstrap;
 pub mod cmd_resp;
 pub mod util;
 pub mod store;
+pub mod log_engine;
 
 mod peer;
 mod peer_storage;
@@ -28,19 +29,22 @@ mod worker;
 mod metrics;
 mod local_metrics;
 
-pub use self::msg::{BatchCallback, Callback, Msg, SignificantMsg, Tick};
-pub use self::store::{create_event_loop, Engines, Store, StoreChannel, StoreStat};
+pub use self::msg::{BatchCallback, Callback, Msg, PollerRouter, RouterSendCh, SignificantMsg,
+                    Tick};
+pub use self::store::{clear_stale_data, create_event_loop, create_event_loops, create_router,
+                      Engines, PollerContext, PollerStat, Store, StoreChannel, StoreMeta,
+                      StoreSchedulers, StoreStat, StoreWorkers};
 pub use self::config::Config;
 pub use self::transport::Transport;
 pub use self::peer::{Peer, PeerStat};
 pub use self::bootstrap::{bootstrap_store, clear_prepare_bootstrap, clear_prepare_bootstrap_state,
                           prepare_bootstrap, write_prepare_bootstrap};
 pub use self::engine::{Iterable, Mutable, Peekable};
-pub use self::peer_storage::{do_snapshot, write_peer_state, CacheQueryStats, PeerStorage,
-                             SnapState, RAFT_INIT_LOG_INDEX, RAFT_INIT_LOG_TERM};
+pub use self::peer_storage::{do_snapshot, write_peer_state, CacheQueryStats, EntryCacheMemory,
+                             PeerStorage, SnapState, RAFT_INIT_LOG_INDEX, RAFT_INIT_LOG_TERM};
 pub use self::snap::{check_abort, copy_snapshot, ApplyOptions, SnapEntry, SnapKey, SnapManager,
                      Snapshot, SnapshotDeleter, SnapshotStatistics};
 
 // Only used in tests
 #[cfg(test)]
-pub use self::worker::{SplitCheckRunner, SplitCheckTask};
+pub use self::worker::{PendingDeleteRanges, SplitCheckRunner, SplitCheckTask};
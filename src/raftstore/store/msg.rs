@@ -18,8 +18,11 @@ use std::fmt;
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
 use kvproto::metapb::RegionEpoch;
+use mio;
 use raft::SnapshotStatus;
 use util::escape;
+use util::transport::{NotifyError, RetryableSendCh, Sender};
+
 
 pub type Callback = Box<FnBox(RaftCmdResponse) + Send>;
 pub type BatchCallback = Box<FnBox(Vec<Option<RaftCmdResponse>>) + Send>;
@@ -86,6 +89,7 @@ pub enum Msg {
 
     // For region size
     ApproximateRegionSize { region_id: u64, region_size: u64 },
+    ApproximateRegionKeys { region_id: u64, region_keys: u64 },
 }
 
 impl fmt::Debug for Msg {
@@ -121,6 +125,15 @@ impl fmt::Debug for Msg {
                 region_id,
                 region_size
             ),
+            Msg::ApproximateRegionKeys {
+                region_id,
+                region_keys,
+            } => write!(
+                fmt,
+                "Approximate region keys [region_id: {}, region_keys: {}]",
+                region_id,
+                region_keys
+            ),
         }
     }
 }
@@ -144,12 +157,67 @@ impl Msg {
             on_finished: on_finished,
         }
     }
+
+    /// Returns the id of the region the message is sent to, messages which
+    /// aren't bound to a single region return `None`.
+    pub fn region_id(&self) -> Option<u64> {
+        match *self {
+            Msg::RaftMessage(ref data) => Some(data.get_region_id()),
+            Msg::RaftCmd { ref request, .. } => Some(request.get_header().get_region_id()),
+            Msg::ComputeHashResult { region_id, .. } |
+            Msg::SplitRegion { region_id, .. } |
+            Msg::ApproximateRegionSize { region_id, .. } |
+            Msg::ApproximateRegionKeys { region_id, .. } => Some(region_id),
+            Msg::BatchRaftSnapCmds { .. } |
+            Msg::Quit |
+            Msg::SnapshotStats => None,
+        }
+    }
+}
+
+/// `PollerRouter` sends messages to the raftstore pollers of a store. Regions
+/// are sharded across the pollers by `region_id % pool_size`, a message is
+/// sent to the poller owning its region directly, and messages which aren't
+/// bound to a region are sent to the first poller.
+#[derive(Clone)]
+pub struct PollerRouter {
+    pollers: Vec<mio::Sender<Msg>>,
+}
+
+impl PollerRouter {
+    pub fn new(pollers: Vec<mio::Sender<Msg>>) -> PollerRouter {
+        assert!(!pollers.is_empty());
+        PollerRouter { pollers: pollers }
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pollers.len()
+    }
+
+    pub fn owner_of(&self, region_id: u64) -> usize {
+        (region_id % self.pollers.len() as u64) as usize
+    }
+
+    /// Returns the channel of the given poller.
+    pub fn poller(&self, poller_id: usize) -> mio::Sender<Msg> {
+        self.pollers[poller_id].clone()
+    }
+}
+
+impl Sender<Msg> for PollerRouter {
+    fn send(&self, msg: Msg) -> Result<(), NotifyError<Msg>> {
+        let owner = msg.region_id().map_or(0, |id| self.owner_of(id));
+        Sender::send(&self.pollers[owner], msg)
+    }
 }
 
+pub type RouterSendCh = RetryableSendCh<Msg, PollerRouter>;
+
 #[cfg(test)]
 mod tests {
     use std::thread;
     use std::boxed::FnBox;
+    use std::sync::mpsc;
     use std::time::Duration;
 
     use mio::{EventLoop, Handler};
@@ -217,4 +285,72 @@ mod tests {
 
         t.join().unwrap();
     }
+
+    struct RecordHandler {
+        poller_id: usize,
+        tx: mpsc::Sender<(usize, Option<u64>)>,
+    }
+
+    impl Handler for RecordHandler {
+        type Timeout = ();
+        type Message = Msg;
+
+        fn notify(&mut self, event_loop: &mut EventLoop<Self>, msg: Self::Message) {
+            if let Msg::Quit = msg {
+                event_loop.shutdown();
+                return;
+            }
+            self.tx.send((self.poller_id, msg.region_id())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_poller_router() {
+        let (tx, rx) = mpsc::channel();
+        let mut pollers = vec![];
+        let mut handles = vec![];
+        for poller_id in 0..3 {
+            let mut event_loop = EventLoop::new().unwrap();
+            pollers.push(event_loop.channel());
+            let mut handler = RecordHandler {
+                poller_id: poller_id,
+                tx: tx.clone(),
+            };
+            handles.push(thread::spawn(move || {
+                event_loop.run(&mut handler).unwrap();
+            }));
+        }
+        let router = PollerRouter::new(pollers);
+        assert_eq!(router.pool_size(), 3);
+        let ch = RouterSendCh::new(router.clone(), "test-router");
+
+        for region_id in 1..7 {
+            let mut msg = RaftMessage::new();
+            msg.set_region_id(region_id);
+            ch.send(Msg::RaftMessage(msg)).unwrap();
+            let (poller_id, id) = rx.recv_timeout(Duration::from_secs(3)).unwrap();
+            assert_eq!(id, Some(region_id));
+            assert_eq!(poller_id, (region_id % 3) as usize);
+            assert_eq!(poller_id, router.owner_of(region_id));
+        }
+
+        let mut request = RaftCmdRequest::new();
+        request.mut_header().set_region_id(5);
+        ch.send(Msg::new_raft_cmd(request, box |_: RaftCmdResponse| {})).unwrap();
+        let res = rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert_eq!(res, (2, Some(5)));
+
+        // Messages not bound to a region are sent to the first poller.
+        ch.send(Msg::SnapshotStats).unwrap();
+        let res = rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert_eq!(res, (0, None));
+
+        for poller_id in 0..3 {
+            let ch = SendCh::new(router.poller(poller_id), "test-router");
+            ch.send(Msg::Quit).unwrap();
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
 }
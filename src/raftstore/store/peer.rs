@@ -21,7 +21,7 @@ use std::time::{Duration, Instant};
 use time::Timespec;
 use rocksdb::{WriteBatch, DB};
 use rocksdb::rocksdb_options::WriteOptions;
-use protobuf::{self, Message, MessageStatic};
+use protobuf::{self, Message, MessageStatic, RepeatedField};
 use kvproto::metapb;
 use kvproto::eraftpb::{self, ConfChangeType, MessageType};
 use kvproto::raft_cmdpb::{AdminCmdType, AdminResponse, CmdType, RaftCmdRequest, RaftCmdResponse,
@@ -36,7 +36,7 @@ use raftstore::store::Config;
 use raftstore::store::worker::{apply, Proposal, RegionProposal};
 use raftstore::store::worker::apply::ExecResult;
 
-use util::worker::{FutureWorker, Scheduler};
+use util::worker::{FutureScheduler, Scheduler};
 use raftstore::store::worker::{Apply, ApplyRes, ApplyTask};
 use util::{Either, MustConsumeVec};
 use util::time::monotonic_raw_now;
@@ -46,6 +46,7 @@ use pd::{PdTask, INVALID_ID};
 
 use super::store::{DestroyPeerJob, Store, StoreStat};
 use super::peer_storage::{write_peer_state, ApplySnapResult, InvokeContext, PeerStorage};
+use super::log_engine::LogBatch;
 use super::util;
 use super::msg::Callback;
 use super::cmd_resp;
@@ -133,9 +134,90 @@ impl ProposalQueue {
     }
 }
 
+/// Collects the write commands of a region proposed in the same round of
+/// the event loop, so that they can be proposed as one raft entry.
+#[derive(Default)]
+struct BatchRaftCmdRequestBuilder {
+    request: Option<RaftCmdRequest>,
+    // The callback, the count of requests and the uuid of every merged command.
+    callbacks: Vec<(Callback, usize, Vec<u8>)>,
+    size: u64,
+}
+
+impl BatchRaftCmdRequestBuilder {
+    fn can_batch(&self, req: &RaftCmdRequest, size: u64, limit: u64) -> bool {
+        let batch = match self.request {
+            None => return true,
+            Some(ref batch) => batch,
+        };
+        if self.size + size > limit {
+            return false;
+        }
+        let (batch_header, header) = (batch.get_header(), req.get_header());
+        batch_header.get_region_epoch() == header.get_region_epoch() &&
+            batch_header.get_term() == header.get_term() &&
+            batch_header.get_peer() == header.get_peer()
+    }
+
+    fn add(&mut self, mut req: RaftCmdRequest, size: u64, cb: Callback) {
+        let count = req.get_requests().len();
+        let uuid = req.get_header().get_uuid().to_vec();
+        match self.request {
+            Some(ref mut batch) => {
+                if req.get_header().get_sync_log() {
+                    batch.mut_header().set_sync_log(true);
+                }
+                for r in req.take_requests().into_iter() {
+                    batch.mut_requests().push(r);
+                }
+            }
+            None => self.request = Some(req),
+        }
+        self.callbacks.push((cb, count, uuid));
+        self.size += size;
+    }
+
+    fn build(&mut self) -> Option<(RaftCmdRequest, Callback)> {
+        let req = match self.request.take() {
+            None => return None,
+            Some(req) => req,
+        };
+        self.size = 0;
+        let mut callbacks = mem::replace(&mut self.callbacks, vec![]);
+        if callbacks.len() == 1 {
+            let (cb, _, _) = callbacks.pop().unwrap();
+            return Some((req, cb));
+        }
+        let cb: Callback =
+            Box::new(move |resp: RaftCmdResponse| split_batch_response(resp, callbacks));
+        Some((req, cb))
+    }
+}
+
+/// Splits the response of a batched command and invokes the callback of
+/// every merged command with its own part. An error is sent to all of them.
+fn split_batch_response(mut resp: RaftCmdResponse, callbacks: Vec<(Callback, usize, Vec<u8>)>) {
+    let total: usize = callbacks.iter().map(|&(_, count, _)| count).sum();
+    let mut header = resp.take_header();
+    let split = !header.has_error() && resp.get_responses().len() == total;
+    let mut responses = resp.take_responses().into_iter();
+    for (cb, count, uuid) in callbacks {
+        let mut resp = RaftCmdResponse::new();
+        header.set_uuid(uuid);
+        resp.set_header(header.clone());
+        if split {
+            let rs = responses.by_ref().take(count).collect();
+            resp.set_responses(RepeatedField::from_vec(rs));
+        }
+        cb(resp);
+    }
+}
+
 pub struct ReadyContext<'a, T: 'a> {
     pub kv_wb: WriteBatch,
     pub raft_wb: WriteBatch,
+    // Used instead of `raft_wb` when the raft log engine is enabled.
+    pub raft_log_batch: LogBatch,
     pub sync_log: bool,
     pub metrics: &'a mut RaftMetrics,
     pub trans: &'a T,
@@ -147,6 +229,7 @@ impl<'a, T> ReadyContext<'a, T> {
         ReadyContext {
             kv_wb: WriteBatch::new(),
             raft_wb: WriteBatch::with_capacity(DEFAULT_APPEND_WB_SIZE),
+            raft_log_batch: LogBatch::new(),
             sync_log: false,
             metrics: metrics,
             trans: t,
@@ -198,6 +281,7 @@ pub struct Peer {
     proposals: ProposalQueue,
     apply_proposals: Vec<Proposal>,
     pending_reads: ReadIndexQueue,
+    batch_req_builder: BatchRaftCmdRequestBuilder,
     // Record the last instant of each peer's heartbeat response.
     pub peer_heartbeats: FlatMap<u64, Instant>,
     coprocessor_host: Arc<CoprocessorHost>,
@@ -207,6 +291,7 @@ pub struct Peer {
     pub delete_keys_hint: u64,
     /// approximate region size.
     pub approximate_size: Option<u64>,
+    pub approximate_keys: Option<u64>,
 
     pub consistency_state: ConsistencyState,
 
@@ -249,6 +334,10 @@ pub struct Peer {
     // If a snapshot is being applied asynchronously, messages should not be sent.
     pending_messages: Vec<eraftpb::Message>,
 
+    // The ready whose raft logs are being written asynchronously, it's handled
+    // after the logs are persisted.
+    pub unpersisted_ready: Option<(Ready, InvokeContext)>,
+
     pub peer_stat: PeerStat,
 }
 
@@ -301,14 +390,19 @@ impl Peer {
         let peer_cache = FlatMap::default();
         let tag = format!("[region {}] {}", region.get_id(), peer_id);
 
-        let ps = PeerStorage::new(
+        let mut ps = PeerStorage::new(
             store.kv_engine(),
             store.raft_engine(),
+            store.raft_log_engine(),
             region,
             sched,
             tag.clone(),
             store.entry_cache_metries.clone(),
         )?;
+        let witness = util::is_witness_store(store.store_labels());
+        ps.witness = witness;
+        ps.pending_delete_ranges = store.pending_delete_ranges();
+        ps.set_entry_cache_memory(store.entry_cache_memory());
 
         let applied_index = ps.applied_index();
 
@@ -323,6 +417,9 @@ impl Peer {
             check_quorum: true,
             tag: tag.clone(),
             skip_bcast_commit: true,
+            witness: witness,
+            priority: cfg.election_priority(store.store_labels()),
+            election_delay_rounds: cfg.election_delay_rounds(store.store_labels()),
             ..Default::default()
         };
 
@@ -337,12 +434,14 @@ impl Peer {
             proposals: Default::default(),
             apply_proposals: vec![],
             pending_reads: Default::default(),
+            batch_req_builder: Default::default(),
             peer_cache: RefCell::new(peer_cache),
             peer_heartbeats: FlatMap::default(),
             coprocessor_host: store.coprocessor_host.clone(),
             size_diff_hint: 0,
             delete_keys_hint: 0,
             approximate_size: None,
+            approximate_keys: None,
             apply_scheduler: store.apply_scheduler(),
             pending_remove: false,
             marked_to_be_checked: false,
@@ -360,6 +459,7 @@ impl Peer {
             cfg: cfg,
             leader_lease_expired_time: None,
             pending_messages: vec![],
+            unpersisted_ready: None,
             peer_stat: PeerStat::default(),
         };
 
@@ -418,13 +518,19 @@ impl Peer {
         // Set Tombstone state explicitly
         let kv_wb = WriteBatch::new();
         let raft_wb = WriteBatch::new();
-        self.mut_store().clear_meta(&kv_wb, &raft_wb)?;
+        let mut raft_log_batch = LogBatch::new();
+        self.mut_store()
+            .clear_meta(&kv_wb, &raft_wb, &mut raft_log_batch)?;
         write_peer_state(&self.kv_engine, &kv_wb, &region, PeerState::Tombstone)?;
         // write kv rocksdb first in case of restart happen between two write
         let mut write_opts = WriteOptions::new();
         write_opts.set_sync(self.cfg.sync_log);
         self.kv_engine.write_opt(kv_wb, &write_opts)?;
-        self.raft_engine.write_opt(raft_wb, &write_opts)?;
+        if let Some(engine) = self.get_store().get_raft_log_engine() {
+            engine.write(raft_log_batch, self.cfg.sync_log)?;
+        } else {
+            self.raft_engine.write_opt(raft_wb, &write_opts)?;
+        }
 
         if self.get_store().is_initialized() {
             // If we meet panic when deleting data and raft log, the dirty data
@@ -444,6 +550,10 @@ impl Peer {
             apply::notify_req_region_removed(region.get_id(), proposal.cb);
         }
 
+        if let Some((_, cb)) = self.batch_req_builder.build() {
+            apply::notify_req_region_removed(region.get_id(), cb);
+        }
+
         info!("{} destroy itself, takes {:?}", self.tag, t.elapsed());
 
         Ok(())
@@ -481,6 +591,11 @@ impl Peer {
         self.raft_group.raft.state == StateRole::Leader
     }
 
+    #[inline]
+    pub fn is_witness(&self) -> bool {
+        self.raft_group.raft.is_witness()
+    }
+
     #[inline]
     pub fn get_store(&self) -> &PeerStorage {
         self.raft_group.get_store()
@@ -652,7 +767,7 @@ impl Peer {
         send_to_quorum_ts + self.cfg.raft_store_max_leader_lease()
     }
 
-    fn on_role_changed(&mut self, ready: &Ready, worker: &FutureWorker<PdTask>) {
+    fn on_role_changed(&mut self, ready: &Ready, worker: &FutureScheduler<PdTask>) {
         // Update leader lease when the Raft state changes.
         if let Some(ref ss) = ready.ss {
             match ss.raft_state {
@@ -715,12 +830,17 @@ impl Peer {
     pub fn handle_raft_ready_append<T: Transport>(
         &mut self,
         ctx: &mut ReadyContext<T>,
-        worker: &FutureWorker<PdTask>,
+        worker: &FutureScheduler<PdTask>,
     ) {
         self.marked_to_be_checked = false;
         if self.pending_remove {
             return;
         }
+        if self.unpersisted_ready.is_some() {
+            // Only one ready can be in flight, new readies are handled after the
+            // raft logs of the last one are persisted.
+            return;
+        }
         if self.mut_store().check_applying_snap() {
             // If we continue to handle all the messages, it may cause too many messages because
             // leader will send all the remaining messages to this follower, which can lead
@@ -1008,7 +1128,7 @@ impl Peer {
 
     pub fn maybe_campaign(
         &mut self,
-        last_peer: &Peer,
+        parent_is_leader: bool,
         pending_raft_groups: &mut HashSet<u64>,
     ) -> bool {
         if self.region().get_peers().len() <= 1 {
@@ -1016,7 +1136,7 @@ impl Peer {
             return false;
         }
 
-        if !last_peer.is_leader() {
+        if !parent_is_leader {
             return false;
         }
 
@@ -1044,7 +1164,7 @@ impl Peer {
         &mut self,
         cb: Callback,
         req: RaftCmdRequest,
-        mut err_resp: RaftCmdResponse,
+        err_resp: RaftCmdResponse,
         metrics: &mut RaftProposeMetrics,
     ) -> bool {
         if self.pending_remove {
@@ -1061,17 +1181,35 @@ impl Peer {
                 return false;
             }
             Ok(RequestPolicy::ReadIndex) => return self.read_index(req, cb, metrics),
-            Ok(RequestPolicy::ProposeNormal) => self.propose_normal(req, metrics),
+            Ok(RequestPolicy::ProposeNormal) => {
+                if self.is_batchable(&req) {
+                    return self.batch_raft_command(req, cb, metrics);
+                }
+                self.propose_batch_raft_command(metrics);
+                self.propose_normal(req, metrics)
+            }
             Ok(RequestPolicy::ProposeTransferLeader) => {
-                return self.propose_transfer_leader(req, cb, metrics)
+                self.propose_batch_raft_command(metrics);
+                return self.propose_transfer_leader(req, cb, metrics);
             }
             Ok(RequestPolicy::ProposeConfChange) => {
+                self.propose_batch_raft_command(metrics);
                 is_conf_change = true;
                 self.propose_conf_change(req, metrics)
             }
             Err(e) => Err(e),
         };
 
+        self.handle_propose_result(res, is_conf_change, cb, err_resp)
+    }
+
+    fn handle_propose_result(
+        &mut self,
+        res: Result<u64>,
+        is_conf_change: bool,
+        cb: Callback,
+        mut err_resp: RaftCmdResponse,
+    ) -> bool {
         match res {
             Err(e) => {
                 cmd_resp::bind_error(&mut err_resp, e);
@@ -1090,6 +1228,61 @@ impl Peer {
         }
     }
 
+    // Only the simple writes whose keys are all in the region are batched, so a
+    // bad command can't fail the others in the same batch.
+    fn is_batchable(&self, req: &RaftCmdRequest) -> bool {
+        if self.cfg.raft_proposal_batch_size_limit.0 == 0 || req.has_admin_request() {
+            return false;
+        }
+        let region = self.region();
+        req.get_requests().iter().all(|r| match r.get_cmd_type() {
+            CmdType::Put => util::check_key_in_region(r.get_put().get_key(), region).is_ok(),
+            CmdType::Delete => util::check_key_in_region(r.get_delete().get_key(), region).is_ok(),
+            CmdType::DeleteRange => {
+                let delete_range = r.get_delete_range();
+                util::check_key_in_region(delete_range.get_start_key(), region).is_ok() &&
+                    util::check_key_in_region_inclusive(delete_range.get_end_key(), region)
+                        .is_ok()
+            }
+            _ => false,
+        })
+    }
+
+    fn batch_raft_command(
+        &mut self,
+        req: RaftCmdRequest,
+        cb: Callback,
+        metrics: &mut RaftProposeMetrics,
+    ) -> bool {
+        let size = u64::from(req.compute_size());
+        let limit = self.cfg.raft_proposal_batch_size_limit.0;
+        if !self.batch_req_builder.can_batch(&req, size, limit) {
+            self.propose_batch_raft_command(metrics);
+        }
+        metrics.batch += 1;
+        self.batch_req_builder.add(req, size, cb);
+        // The batch is proposed when the peer is checked in the next raft ready round.
+        true
+    }
+
+    /// Propose the write commands that have been batched.
+    ///
+    /// Return true means the batch has been proposed successfully.
+    pub fn propose_batch_raft_command(&mut self, metrics: &mut RaftProposeMetrics) -> bool {
+        let (req, cb) = match self.batch_req_builder.build() {
+            None => return false,
+            Some(batch) => batch,
+        };
+        if self.pending_remove {
+            apply::notify_req_region_removed(self.region_id, cb);
+            return false;
+        }
+        let mut err_resp = RaftCmdResponse::new();
+        cmd_resp::bind_term(&mut err_resp, self.term());
+        let res = self.propose_normal(req, metrics);
+        self.handle_propose_result(res, false, cb, err_resp)
+    }
+
     /// Propose a snapshot request. Note that the `None` response means
     /// it requires the peer to perform a read-index. The request never
     /// be actual proposed to other nodes.
@@ -1407,6 +1600,12 @@ impl Peer {
             return Err(Error::RaftEntryTooLarge(self.region_id, data.len() as u64));
         }
 
+        let threshold = self.cfg.raft_entry_compression_threshold.0;
+        let data = util::compress_entry_data(data, threshold);
+        if threshold > 0 {
+            PEER_PROPOSE_COMPRESSED_LOG_SIZE_HISTOGRAM.observe(data.len() as f64);
+        }
+
         let sync_log = get_sync_log_from_request(&req);
         let propose_index = self.next_proposal_index();
         self.raft_group.propose(data, sync_log)?;
@@ -1603,7 +1802,7 @@ impl Peer {
         None
     }
 
-    pub fn heartbeat_pd(&self, worker: &FutureWorker<PdTask>) {
+    pub fn heartbeat_pd(&self, worker: &FutureScheduler<PdTask>) {
         let task = PdTask::Heartbeat {
             region: self.region().clone(),
             peer: self.peer.clone(),
@@ -1612,6 +1811,7 @@ impl Peer {
             written_bytes: self.peer_stat.written_bytes,
             written_keys: self.peer_stat.written_keys,
             region_size: self.approximate_size,
+            region_keys: self.approximate_keys,
         };
         if let Err(e) = worker.schedule(task) {
             error!("{} failed to notify pd: {}", self.tag, e);
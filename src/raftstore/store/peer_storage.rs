@@ -11,13 +11,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::{self, Arc};
+use std::sync::{self, Arc, Mutex};
 use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::{cmp, error, u64};
-use std::time::Instant;
+use std::{cmp, error, mem, u64, usize};
+use std::time::{Duration, Instant};
 use std::collections::VecDeque;
 
 use rocksdb::{Writable, WriteBatch, DB};
@@ -31,10 +31,11 @@ use util::worker::Scheduler;
 use util::{self, rocksdb};
 use raft::{self, Error as RaftError, RaftState, Ready, Storage, StorageError};
 use raftstore::{Error, Result};
-use super::worker::RegionTask;
+use super::worker::{PendingDeleteRanges, RegionTask};
 use super::keys::{self, enc_end_key, enc_start_key};
 use super::engine::{Iterable, Mutable, Peekable, Snapshot as DbSnapshot};
 use super::peer::ReadyContext;
+use super::log_engine::{LogBatch, RaftLogEngine};
 use super::metrics::*;
 use super::{SnapEntry, SnapKey, SnapManager, SnapshotStatistics};
 use storage::CF_RAFT;
@@ -44,6 +45,8 @@ use storage::CF_RAFT;
 pub const RAFT_INIT_LOG_TERM: u64 = 5;
 pub const RAFT_INIT_LOG_INDEX: u64 = 5;
 const MAX_SNAP_TRY_CNT: usize = 5;
+// A generated snapshot is shared with other peers requesting it within the duration.
+const SHARED_SNAP_DURATION_SECS: u64 = 60;
 const RAFT_LOG_MULTI_GET_CNT: u64 = 8;
 
 // One extra slot for VecDeque internal usage.
@@ -118,9 +121,46 @@ pub fn last_index(state: &RaftLocalState) -> u64 {
     state.get_last_index()
 }
 
+/// The memory budget shared by the entry caches of all the peers in the store.
+pub struct EntryCacheMemory {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl EntryCacheMemory {
+    pub fn new(limit: usize) -> EntryCacheMemory {
+        EntryCacheMemory {
+            limit: limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.used() > self.limit
+    }
+}
+
+impl Default for EntryCacheMemory {
+    fn default() -> EntryCacheMemory {
+        EntryCacheMemory::new(usize::MAX)
+    }
+}
+
+#[inline]
+fn entry_mem_size(e: &Entry) -> usize {
+    mem::size_of::<Entry>() + e.get_data().len() + e.get_context().len()
+}
+
 #[derive(Default)]
 struct EntryCache {
     cache: VecDeque<Entry>,
+    // Memory used by the entries in `cache`, it's also counted in `mem`.
+    mem_size: usize,
+    mem: Arc<EntryCacheMemory>,
 }
 
 impl EntryCache {
@@ -128,6 +168,58 @@ impl EntryCache {
         self.cache.front().map_or(u64::MAX, |e| e.get_index())
     }
 
+    fn push_back(&mut self, e: Entry) {
+        self.add_mem_size(entry_mem_size(&e));
+        self.cache.push_back(e);
+    }
+
+    fn drain_front(&mut self, count: usize) {
+        let size: usize = self.cache.drain(..count).map(|e| entry_mem_size(&e)).sum();
+        self.sub_mem_size(size);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        let size: usize = self.cache.iter().skip(len).map(entry_mem_size).sum();
+        self.cache.truncate(len);
+        self.sub_mem_size(size);
+    }
+
+    fn clear(&mut self) {
+        self.cache.clear();
+        let size = self.mem_size;
+        self.sub_mem_size(size);
+    }
+
+    fn add_mem_size(&mut self, size: usize) {
+        self.mem_size += size;
+        self.mem.used.fetch_add(size, Ordering::Relaxed);
+    }
+
+    fn sub_mem_size(&mut self, size: usize) {
+        self.mem_size -= size;
+        self.mem.used.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    // Evicts the oldest entries of the cache in proportion to its share of the memory
+    // used by the store. Every cache is evicted the same way on the raft base tick, so
+    // the exceeded memory is reclaimed from all the peers by their sizes instead of
+    // from the one which happens to append.
+    fn evict_exceeded(&mut self) {
+        let used = self.mem.used();
+        if used <= self.mem.limit || self.mem_size == 0 {
+            return;
+        }
+        let exceeded = (self.mem_size as u64).saturating_mul((used - self.mem.limit) as u64);
+        let evict_size = exceeded.saturating_add(used as u64 - 1) / used as u64;
+        let mut count = 0;
+        let mut size = 0;
+        while (size as u64) < evict_size && count < self.cache.len() {
+            size += entry_mem_size(&self.cache[count]);
+            count += 1;
+        }
+        self.drain_front(count);
+    }
+
     fn fetch_entries_to(
         &self,
         begin: u64,
@@ -180,10 +272,10 @@ impl EntryCache {
             let first_index = entries[0].get_index();
             if cache_last_index >= first_index {
                 if self.cache.front().unwrap().get_index() >= first_index {
-                    self.cache.clear();
+                    self.clear();
                 } else {
                     let left = self.cache.len() - (cache_last_index - first_index + 1) as usize;
-                    self.cache.truncate(left);
+                    self.truncate(left);
                 }
                 if self.cache.len() + entries.len() < SHRINK_CACHE_CAPACITY &&
                     self.cache.capacity() > SHRINK_CACHE_CAPACITY
@@ -202,15 +294,16 @@ impl EntryCache {
         let mut start_idx = 0;
         if let Some(len) = (self.cache.len() + entries.len()).checked_sub(MAX_CACHE_CAPACITY) {
             if len < self.cache.len() {
-                self.cache.drain(..len);
+                self.drain_front(len);
             } else {
                 start_idx = len - self.cache.len();
-                self.cache.clear();
+                self.clear();
             }
         }
         for e in &entries[start_idx..] {
-            self.cache.push_back(e.to_owned());
+            self.push_back(e.to_owned());
         }
+        self.evict_exceeded();
     }
 
     pub fn compact_to(&mut self, idx: u64) {
@@ -223,8 +316,7 @@ impl EntryCache {
             return;
         }
         let cache_last_idx = self.cache.back().unwrap().get_index();
-        self.cache
-            .drain(..(cmp::min(cache_last_idx, idx) - cache_first_idx) as usize);
+        self.drain_front((cmp::min(cache_last_idx, idx) - cache_first_idx) as usize);
         if self.cache.len() < SHRINK_CACHE_CAPACITY &&
             self.cache.capacity() > SHRINK_CACHE_CAPACITY
         {
@@ -235,6 +327,12 @@ impl EntryCache {
     }
 }
 
+impl Drop for EntryCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
 #[derive(Default)]
 pub struct CacheQueryStats {
     pub hit: u64,
@@ -259,16 +357,24 @@ impl CacheQueryStats {
 pub struct PeerStorage {
     pub kv_engine: Arc<DB>,
     pub raft_engine: Arc<DB>,
+    // If it's set, raft logs and raft state are stored in it instead of `raft_engine`.
+    pub raft_log_engine: Option<Arc<RaftLogEngine>>,
 
     pub region: metapb::Region,
     pub raft_state: RaftLocalState,
     pub apply_state: RaftApplyState,
     pub applied_index_term: u64,
     pub last_term: u64,
+    // A witness only keeps region meta and raft state, snapshot data is never ingested.
+    pub witness: bool,
+    // Shared by all the peers of the store, destroyed ranges are recorded here.
+    pub pending_delete_ranges: Arc<Mutex<PendingDeleteRanges>>,
 
     snap_state: RefCell<SnapState>,
     region_sched: Scheduler<RegionTask>,
     snap_tried_cnt: RefCell<usize>,
+    // The last generated snapshot and when it's generated.
+    shared_snap: RefCell<Option<(Snapshot, Instant)>>,
 
     cache: EntryCache,
     stats: Rc<RefCell<CacheQueryStats>>,
@@ -369,7 +475,9 @@ impl InvokeContext {
 pub fn recover_from_applying_state(
     kv_engine: &DB,
     raft_engine: &DB,
+    raft_log_engine: Option<&RaftLogEngine>,
     raft_wb: &WriteBatch,
+    raft_log_batch: &mut LogBatch,
     region_id: u64,
 ) -> Result<()> {
     let snapshot_raft_state_key = keys::snapshot_raft_state_key(region_id);
@@ -386,10 +494,10 @@ pub fn recover_from_applying_state(
         };
 
     let raft_state_key = keys::raft_state_key(region_id);
-    let raft_state: RaftLocalState = match box_try!(raft_engine.get_msg(&raft_state_key)) {
-        Some(state) => state,
-        None => RaftLocalState::new(),
-    };
+    let raft_state = match raft_log_engine {
+        Some(engine) => engine.get_raft_state(region_id),
+        None => box_try!(raft_engine.get_msg(&raft_state_key)),
+    }.unwrap_or_else(RaftLocalState::new);
 
     // if we recv append log when applying snapshot, last_index in raft_local_state will
     // larger than snapshot_index. since raft_local_state is written to raft engine, and
@@ -398,14 +506,26 @@ pub fn recover_from_applying_state(
     // (snapshot_raft_state), and set snapshot_raft_state.last_index = snapshot_index.
     // after restart, we need check last_index.
     if last_index(&snapshot_raft_state) > last_index(&raft_state) {
-        raft_wb.put_msg(&raft_state_key, &snapshot_raft_state)?;
+        if raft_log_engine.is_some() {
+            raft_log_batch.put_raft_state(region_id, &snapshot_raft_state);
+        } else {
+            raft_wb.put_msg(&raft_state_key, &snapshot_raft_state)?;
+        }
     }
     Ok(())
 }
 
-fn init_raft_state(raft_engine: &DB, region: &Region) -> Result<RaftLocalState> {
+fn init_raft_state(
+    raft_engine: &DB,
+    raft_log_engine: Option<&RaftLogEngine>,
+    region: &Region,
+) -> Result<RaftLocalState> {
     let state_key = keys::raft_state_key(region.get_id());
-    Ok(match raft_engine.get_msg(&state_key)? {
+    let state = match raft_log_engine {
+        Some(engine) => engine.get_raft_state(region.get_id()),
+        None => raft_engine.get_msg(&state_key)?,
+    };
+    Ok(match state {
         Some(s) => s,
         None => {
             let mut raft_state = RaftLocalState::new();
@@ -414,7 +534,13 @@ fn init_raft_state(raft_engine: &DB, region: &Region) -> Result<RaftLocalState>
                 raft_state.set_last_index(RAFT_INIT_LOG_INDEX);
                 raft_state.mut_hard_state().set_term(RAFT_INIT_LOG_TERM);
                 raft_state.mut_hard_state().set_commit(RAFT_INIT_LOG_INDEX);
-                raft_engine.put_msg(&state_key, &raft_state)?;
+                if let Some(engine) = raft_log_engine {
+                    let mut batch = LogBatch::new();
+                    batch.put_raft_state(region.get_id(), &raft_state);
+                    engine.write(batch, false)?;
+                } else {
+                    raft_engine.put_msg(&state_key, &raft_state)?;
+                }
             }
             raft_state
         }
@@ -441,6 +567,7 @@ fn init_apply_state(kv_engine: &DB, region: &Region) -> Result<RaftApplyState> {
 
 fn init_last_term(
     raft_engine: &DB,
+    raft_log_engine: Option<&RaftLogEngine>,
     region: &Region,
     raft_state: &RaftLocalState,
     apply_state: &RaftApplyState,
@@ -455,8 +582,16 @@ fn init_last_term(
     } else {
         assert!(last_idx > RAFT_INIT_LOG_INDEX);
     }
-    let last_log_key = keys::raft_log_key(region.get_id(), last_idx);
-    Ok(match raft_engine.get_msg::<Entry>(&last_log_key)? {
+    let term = match raft_log_engine {
+        Some(engine) => engine.term(region.get_id(), last_idx),
+        None => {
+            let last_log_key = keys::raft_log_key(region.get_id(), last_idx);
+            raft_engine
+                .get_msg::<Entry>(&last_log_key)?
+                .map(|e| e.get_term())
+        }
+    };
+    Ok(match term {
         None => {
             return Err(box_err!(
                 "[region {}] entry at {} doesn't exist, may lose data.",
@@ -464,7 +599,7 @@ fn init_last_term(
                 last_idx
             ))
         }
-        Some(e) => e.get_term(),
+        Some(term) => term,
     })
 }
 
@@ -472,13 +607,18 @@ impl PeerStorage {
     pub fn new(
         kv_engine: Arc<DB>,
         raft_engine: Arc<DB>,
+        raft_log_engine: Option<Arc<RaftLogEngine>>,
         region: &metapb::Region,
         region_sched: Scheduler<RegionTask>,
         tag: String,
         stats: Rc<RefCell<CacheQueryStats>>,
     ) -> Result<PeerStorage> {
         debug!("creating storage on {} for {:?}", kv_engine.path(), region);
-        let raft_state = init_raft_state(&raft_engine, region)?;
+        let raft_state = init_raft_state(
+            &raft_engine,
+            raft_log_engine.as_ref().map(|e| &**e),
+            region,
+        )?;
         let apply_state = init_apply_state(&kv_engine, region)?;
         if raft_state.get_last_index() < apply_state.get_applied_index() {
             panic!(
@@ -488,20 +628,32 @@ impl PeerStorage {
                 apply_state.get_applied_index()
             );
         }
-        let last_term = init_last_term(&raft_engine, region, &raft_state, &apply_state)?;
+        let last_term = init_last_term(
+            &raft_engine,
+            raft_log_engine.as_ref().map(|e| &**e),
+            region,
+            &raft_state,
+            &apply_state,
+        )?;
 
         Ok(PeerStorage {
             kv_engine: kv_engine,
             raft_engine: raft_engine,
+            raft_log_engine: raft_log_engine,
             region: region.clone(),
             raft_state: raft_state,
             apply_state: apply_state,
             snap_state: RefCell::new(SnapState::Relax),
             region_sched: region_sched,
             snap_tried_cnt: RefCell::new(0),
+            shared_snap: RefCell::new(None),
             tag: tag,
             applied_index_term: RAFT_INIT_LOG_TERM,
             last_term: last_term,
+            witness: false,
+            pending_delete_ranges: Arc::new(Mutex::new(
+                PendingDeleteRanges::new(Duration::from_secs(0)),
+            )),
             cache: EntryCache::default(),
             stats: stats,
         })
@@ -595,6 +747,15 @@ impl PeerStorage {
         max_size: u64,
         buf: &mut Vec<Entry>,
     ) -> raft::Result<u64> {
+        if let Some(ref engine) = self.raft_log_engine {
+            let region_id = self.get_region_id();
+            let total_size = box_try!(engine.fetch_entries_to(region_id, low, high, max_size, buf));
+            if buf.len() == (high - low) as usize || total_size > max_size {
+                return Ok(total_size);
+            }
+            return Err(RaftError::Store(StorageError::Unavailable));
+        }
+
         let mut total_size: u64 = 0;
         let mut next_index = low;
         let mut exceeded_max_size = false;
@@ -707,6 +868,39 @@ impl PeerStorage {
         DbSnapshot::new(self.kv_engine.clone())
     }
 
+    // Returns the snapshot generated recently if it's still valid, so peers requesting
+    // snapshots at nearly the same time don't generate them again and again.
+    fn get_shared_snapshot(&self) -> Option<Snapshot> {
+        let mut shared_snap = self.shared_snap.borrow_mut();
+        let valid = match *shared_snap {
+            Some((ref s, ts)) => {
+                ts.elapsed() < Duration::from_secs(SHARED_SNAP_DURATION_SECS) &&
+                    self.validate_snap(s)
+            }
+            None => return None,
+        };
+        if !valid {
+            *shared_snap = None;
+            return None;
+        }
+        debug!("{} reuse the snapshot generated recently", self.tag);
+        shared_snap.as_ref().map(|&(ref s, _)| s.clone())
+    }
+
+    /// Stops sharing the last generated snapshot, it should be called when sending
+    /// it fails, in case its files are broken.
+    pub fn drop_shared_snapshot(&self) {
+        *self.shared_snap.borrow_mut() = None;
+    }
+
+    /// Returns the key of the snapshot being shared.
+    pub fn shared_snapshot_key(&self) -> Option<SnapKey> {
+        self.shared_snap
+            .borrow()
+            .as_ref()
+            .and_then(|&(ref s, _)| SnapKey::from_snap(s).ok())
+    }
+
     fn validate_snap(&self, snap: &Snapshot) -> bool {
         let idx = snap.get_metadata().get_index();
         if idx < self.truncated_index() {
@@ -777,6 +971,7 @@ impl PeerStorage {
                 Some(s) => {
                     *tried_cnt = 0;
                     if self.validate_snap(&s) {
+                        *self.shared_snap.borrow_mut() = Some((s.clone(), Instant::now()));
                         return Ok(s);
                     }
                 }
@@ -794,6 +989,10 @@ impl PeerStorage {
             panic!("{} unexpected state: {:?}", self.tag, *snap_state);
         }
 
+        if let Some(s) = self.get_shared_snapshot() {
+            return Ok(s);
+        }
+
         if *tried_cnt >= MAX_SNAP_TRY_CNT {
             let cnt = *tried_cnt;
             *tried_cnt = 0;
@@ -844,21 +1043,29 @@ impl PeerStorage {
             (e.get_index(), e.get_term())
         };
 
-        for entry in entries {
-            if entry.get_sync_log() {
-                ready_ctx.sync_log = true;
-            }
-            ready_ctx.raft_wb.put_msg(
-                &keys::raft_log_key(self.get_region_id(), entry.get_index()),
-                entry,
-            )?;
+        if entries.iter().any(|e| e.get_sync_log()) {
+            ready_ctx.sync_log = true;
         }
-
-        // Delete any previously appended log entries which never committed.
-        for i in (last_index + 1)..(prev_last_index + 1) {
+        if self.raft_log_engine.is_some() {
+            // Previously appended log entries which never committed are
+            // overwritten by the log engine.
             ready_ctx
-                .raft_wb
-                .delete(&keys::raft_log_key(self.get_region_id(), i))?;
+                .raft_log_batch
+                .add_entries(self.get_region_id(), entries);
+        } else {
+            for entry in entries {
+                ready_ctx.raft_wb.put_msg(
+                    &keys::raft_log_key(self.get_region_id(), entry.get_index()),
+                    entry,
+                )?;
+            }
+
+            // Delete any previously appended log entries which never committed.
+            for i in (last_index + 1)..(prev_last_index + 1) {
+                ready_ctx
+                    .raft_wb
+                    .delete(&keys::raft_log_key(self.get_region_id(), i))?;
+            }
         }
 
         invoke_ctx.raft_state.set_last_index(last_index);
@@ -873,6 +1080,19 @@ impl PeerStorage {
         self.cache.compact_to(idx);
     }
 
+    /// Evicts the oldest cached entries if the entry caches of the store use more
+    /// memory than the limit.
+    pub fn evict_entry_cache(&mut self) {
+        self.cache.evict_exceeded();
+    }
+
+    /// Makes the entry cache count its memory in `mem`, it must be called before
+    /// any entry is cached.
+    pub fn set_entry_cache_memory(&mut self, mem: Arc<EntryCacheMemory>) {
+        assert!(self.cache.cache.is_empty());
+        self.cache.mem = mem;
+    }
+
     // Apply the peer with given snapshot.
     pub fn apply_snapshot(
         &mut self,
@@ -880,6 +1100,7 @@ impl PeerStorage {
         snap: &Snapshot,
         kv_wb: &WriteBatch,
         raft_wb: &WriteBatch,
+        raft_log_batch: &mut LogBatch,
     ) -> Result<()> {
         info!("{} begin to apply snapshot", self.tag);
 
@@ -899,7 +1120,7 @@ impl PeerStorage {
 
         if self.is_initialized() {
             // we can only delete the old data when the peer is initialized.
-            self.clear_meta(kv_wb, raft_wb)?;
+            self.clear_meta(kv_wb, raft_wb, raft_log_batch)?;
         }
 
         write_peer_state(&self.kv_engine, kv_wb, &region, PeerState::Applying)?;
@@ -928,18 +1149,28 @@ impl PeerStorage {
         Ok(())
     }
 
-    /// Delete all meta belong to the region. Results are stored in `wb`.
-    pub fn clear_meta(&mut self, kv_wb: &WriteBatch, raft_wb: &WriteBatch) -> Result<()> {
+    /// Delete all meta belong to the region. Results are stored in `wb`
+    /// or `raft_log_batch` if the raft log engine is used.
+    pub fn clear_meta(
+        &mut self,
+        kv_wb: &WriteBatch,
+        raft_wb: &WriteBatch,
+        raft_log_batch: &mut LogBatch,
+    ) -> Result<()> {
         let region_id = self.get_region_id();
-        clear_meta(
-            &self.kv_engine,
-            &self.raft_engine,
-            kv_wb,
-            raft_wb,
-            region_id,
-            &self.raft_state,
-        )?;
-        self.cache = EntryCache::default();
+        if self.raft_log_engine.is_some() {
+            clear_meta_in_log_engine(&self.kv_engine, kv_wb, raft_log_batch, region_id)?;
+        } else {
+            clear_meta(
+                &self.kv_engine,
+                &self.raft_engine,
+                kv_wb,
+                raft_wb,
+                region_id,
+                &self.raft_state,
+            )?;
+        }
+        self.cache.clear();
         Ok(())
     }
 
@@ -951,6 +1182,22 @@ impl PeerStorage {
             enc_end_key(self.get_region()),
         );
         let region_id = self.get_region_id();
+        self.schedule_destroy(region_id, start_key, end_key)
+    }
+
+    // The range is recorded before scheduling, so that snapshots accepted later
+    // never miss it, no matter which region worker applies them.
+    fn schedule_destroy(
+        &self,
+        region_id: u64,
+        start_key: Vec<u8>,
+        end_key: Vec<u8>,
+    ) -> Result<()> {
+        self.pending_delete_ranges.lock().unwrap().insert(
+            region_id,
+            start_key.clone(),
+            end_key.clone(),
+        );
         box_try!(
             self.region_sched
                 .schedule(RegionTask::destroy(region_id, start_key, end_key))
@@ -967,16 +1214,10 @@ impl PeerStorage {
         let (new_start_key, new_end_key) = (enc_start_key(new_region), enc_end_key(new_region));
         let region_id = new_region.get_id();
         if old_start_key < new_start_key {
-            box_try!(
-                self.region_sched
-                    .schedule(RegionTask::destroy(region_id, old_start_key, new_start_key))
-            );
+            self.schedule_destroy(region_id, old_start_key, new_start_key)?;
         }
         if new_end_key < old_end_key {
-            box_try!(
-                self.region_sched
-                    .schedule(RegionTask::destroy(region_id, new_end_key, old_end_key))
-            );
+            self.schedule_destroy(region_id, new_end_key, old_end_key)?;
         }
         Ok(())
     }
@@ -985,6 +1226,10 @@ impl PeerStorage {
         self.raft_engine.clone()
     }
 
+    pub fn get_raft_log_engine(&self) -> Option<Arc<RaftLogEngine>> {
+        self.raft_log_engine.clone()
+    }
+
     /// Check whether the storage has finished applying snapshot.
     #[inline]
     pub fn is_applying_snapshot(&self) -> bool {
@@ -1074,6 +1319,7 @@ impl PeerStorage {
         let task = RegionTask::Apply {
             region_id: self.get_region_id(),
             status: status,
+            witness: self.witness,
         };
         // TODO: gracefully remove region instead.
         self.region_sched
@@ -1103,6 +1349,7 @@ impl PeerStorage {
                 &ready.snapshot,
                 &ready_ctx.kv_wb,
                 &ready_ctx.raft_wb,
+                &mut ready_ctx.raft_log_batch,
             )?;
             fail_point!("raft_after_apply_snap");
 
@@ -1126,7 +1373,13 @@ impl PeerStorage {
         }
 
         if ctx.raft_state != self.raft_state {
-            ctx.save_raft_state_to(&mut ready_ctx.raft_wb)?;
+            if self.raft_log_engine.is_some() {
+                ready_ctx
+                    .raft_log_batch
+                    .put_raft_state(ctx.region_id, &ctx.raft_state);
+            } else {
+                ctx.save_raft_state_to(&mut ready_ctx.raft_wb)?;
+            }
             if snapshot_index > 0 {
                 // in case of restart happen when we just write region state to Applying,
                 // but not write raft_local_state to raft rocksdb in time.
@@ -1194,9 +1447,7 @@ pub fn clear_meta(
     raft_state: &RaftLocalState,
 ) -> Result<()> {
     let t = Instant::now();
-    let handle = rocksdb::get_cf_handle(kv_engine, CF_RAFT)?;
-    kv_wb.delete_cf(handle, &keys::region_state_key(region_id))?;
-    kv_wb.delete_cf(handle, &keys::apply_state_key(region_id))?;
+    clear_kv_meta(kv_engine, kv_wb, region_id)?;
 
     let last_index = last_index(raft_state);
     let mut first_index = last_index + 1;
@@ -1225,9 +1476,33 @@ pub fn clear_meta(
     Ok(())
 }
 
+/// Like `clear_meta`, but raft logs and raft state are stored in the raft log engine.
+pub fn clear_meta_in_log_engine(
+    kv_engine: &DB,
+    kv_wb: &WriteBatch,
+    raft_log_batch: &mut LogBatch,
+    region_id: u64,
+) -> Result<()> {
+    clear_kv_meta(kv_engine, kv_wb, region_id)?;
+    raft_log_batch.clean_region(region_id);
+    info!(
+        "[region {}] clear peer 1 meta key, 1 apply key and all raft logs in log engine",
+        region_id
+    );
+    Ok(())
+}
+
+fn clear_kv_meta(kv_engine: &DB, kv_wb: &WriteBatch, region_id: u64) -> Result<()> {
+    let handle = rocksdb::get_cf_handle(kv_engine, CF_RAFT)?;
+    kv_wb.delete_cf(handle, &keys::region_state_key(region_id))?;
+    kv_wb.delete_cf(handle, &keys::apply_state_key(region_id))?;
+    Ok(())
+}
+
 pub fn do_snapshot(
     mgr: SnapManager,
     raft_db: &DB,
+    raft_log_engine: Option<&RaftLogEngine>,
     snap: &DbSnapshot,
     region_id: u64,
 ) -> raft::Result<Snapshot> {
@@ -1248,11 +1523,15 @@ pub fn do_snapshot(
     let term = if idx == apply_state.get_truncated_state().get_index() {
         apply_state.get_truncated_state().get_term()
     } else {
-        match raft_db
-            .get_msg::<Entry>(&keys::raft_log_key(region_id, idx))?
-        {
+        let term = match raft_log_engine {
+            Some(engine) => engine.term(region_id, idx),
+            None => raft_db
+                .get_msg::<Entry>(&keys::raft_log_key(region_id, idx))?
+                .map(|e| e.get_term()),
+        };
+        match term {
             None => return Err(box_err!("entry {} of {} not found.", idx, region_id)),
-            Some(entry) => entry.get_term(),
+            Some(term) => term,
         }
     };
 
@@ -1405,6 +1684,7 @@ mod test {
     use storage::{ALL_CFS, CF_DEFAULT};
     use kvproto::eraftpb::HardState;
     use rocksdb::WriteBatch;
+    use raftstore::store::log_engine::Config as LogEngineConfig;
 
     use super::*;
 
@@ -1418,7 +1698,15 @@ mod test {
         bootstrap::bootstrap_store(&engines, 1, 1).expect("");
         let region = bootstrap::prepare_bootstrap(&engines, 1, 1, 1).expect("");
         let metrics = Rc::new(RefCell::new(CacheQueryStats::default()));
-        PeerStorage::new(kv_db, raft_db, &region, sched, "".to_owned(), metrics).unwrap()
+        PeerStorage::new(
+            kv_db,
+            raft_db,
+            None,
+            &region,
+            sched,
+            "".to_owned(),
+            metrics,
+        ).unwrap()
     }
 
     fn new_storage_from_ents(
@@ -1557,7 +1845,9 @@ mod test {
 
         let kv_wb = WriteBatch::new();
         let raft_wb = WriteBatch::new();
-        store.clear_meta(&kv_wb, &raft_wb).unwrap();
+        store
+            .clear_meta(&kv_wb, &raft_wb, &mut LogBatch::new())
+            .unwrap();
         store.kv_engine.write(kv_wb).unwrap();
         store.raft_engine.write(raft_wb).unwrap();
 
@@ -1682,7 +1972,16 @@ mod test {
         let mut worker = Worker::new("snap_manager");
         let sched = worker.scheduler();
         let mut s = new_storage_from_ents(sched, &td, &ents);
-        let runner = RegionRunner::new(s.kv_engine.clone(), s.raft_engine.clone(), mgr, 0);
+        let runner = RegionRunner::new(
+            s.kv_engine.clone(),
+            s.raft_engine.clone(),
+            None,
+            mgr,
+            0,
+            Arc::new(Mutex::new(PendingDeleteRanges::new(Duration::from_secs(0)))),
+            1,
+            1,
+        );
         worker.start(runner).unwrap();
         let snap = s.snapshot();
         let unavailable = RaftError::Store(StorageError::SnapshotTemporarilyUnavailable);
@@ -1712,6 +2011,11 @@ mod test {
         assert_eq!(s.snapshot(), Ok(snap.clone()));
         assert_eq!(*s.snap_tried_cnt.borrow(), 0);
 
+        // The generated snapshot is shared with later requests.
+        assert_eq!(s.snapshot(), Ok(snap.clone()));
+        assert_eq!(*s.snap_state.borrow(), SnapState::Relax);
+        s.drop_shared_snapshot();
+
         let mut ctx = InvokeContext::new(&s);
         let mut kv_wb = WriteBatch::new();
         let mut metrics = RaftMetrics::default();
@@ -1825,6 +2129,78 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_storage_with_raft_log_engine() {
+        let td = TempDir::new("tikv-store-test").unwrap();
+        let worker = Worker::new("snap_manager");
+        let sched = worker.scheduler();
+        let kv_db = Arc::new(new_engine(td.path().to_str().unwrap(), ALL_CFS).unwrap());
+        let raft_path = td.path().join(Path::new("raft"));
+        let raft_db = Arc::new(
+            new_engine(raft_path.to_str().unwrap(), &[CF_DEFAULT]).unwrap(),
+        );
+        let mut cfg = LogEngineConfig::default();
+        cfg.dir = td.path().join("raft-log").to_str().unwrap().to_owned();
+        let log_engine = Arc::new(RaftLogEngine::new(cfg).unwrap());
+        let engines = Engines::new(kv_db.clone(), raft_db.clone());
+        bootstrap::bootstrap_store(&engines, 1, 1).expect("");
+        let region = bootstrap::prepare_bootstrap(&engines, 1, 1, 1).expect("");
+        let new_store = || {
+            let metrics = Rc::new(RefCell::new(CacheQueryStats::default()));
+            PeerStorage::new(
+                kv_db.clone(),
+                raft_db.clone(),
+                Some(log_engine.clone()),
+                &region,
+                sched.clone(),
+                "".to_owned(),
+                metrics,
+            ).unwrap()
+        };
+        let mut store = new_store();
+        assert_eq!(store.last_index(), RAFT_INIT_LOG_INDEX);
+        assert_eq!(
+            log_engine.get_raft_state(1).unwrap().get_last_index(),
+            RAFT_INIT_LOG_INDEX
+        );
+
+        let ents = vec![new_entry(6, 6), new_entry(7, 6), new_entry(8, 7)];
+        let mut ctx = InvokeContext::new(&store);
+        let mut metrics = RaftMetrics::default();
+        let trans = 0;
+        let mut ready_ctx = ReadyContext::new(&mut metrics, &trans, ents.len());
+        store.append(&mut ctx, &ents, &mut ready_ctx).unwrap();
+        assert!(ready_ctx.raft_wb.is_empty());
+        ready_ctx
+            .raft_log_batch
+            .put_raft_state(ctx.region_id, &ctx.raft_state);
+        log_engine.write(ready_ctx.raft_log_batch, true).unwrap();
+        store.raft_state = ctx.raft_state;
+        store.last_term = ctx.last_term;
+
+        // Read from the log engine instead of the cache.
+        store.cache = EntryCache::default();
+        assert_eq!(store.entries(6, 9, u64::max_value()).unwrap(), ents);
+        assert_eq!(store.entries(6, 9, 0).unwrap(), &ents[..1]);
+        assert_eq!(store.term(7).unwrap(), 6);
+
+        let store = new_store();
+        assert_eq!(store.last_index(), 8);
+        assert_eq!(store.last_term, 7);
+        assert_eq!(store.entries(6, 9, u64::max_value()).unwrap(), ents);
+
+        let mut store = new_store();
+        let kv_wb = WriteBatch::new();
+        let raft_wb = WriteBatch::new();
+        let mut raft_log_batch = LogBatch::new();
+        store
+            .clear_meta(&kv_wb, &raft_wb, &mut raft_log_batch)
+            .unwrap();
+        log_engine.write(raft_log_batch, true).unwrap();
+        assert!(log_engine.get_raft_state(1).is_none());
+        assert!(log_engine.first_index(1).is_none());
+    }
+
     #[test]
     fn test_storage_cache_fetch() {
         let ents = vec![new_entry(3, 3), new_entry(4, 4), new_entry(5, 5)];
@@ -1832,7 +2208,7 @@ mod test {
         let worker = Worker::new("snap_manager");
         let sched = worker.scheduler();
         let mut store = new_storage_from_ents(sched, &td, &ents);
-        store.cache.cache.clear();
+        store.cache.clear();
         // empty cache should fetch data from rocksdb directly.
         let mut res = store.entries(4, 6, u64::max_value()).unwrap();
         assert_eq!(*res, ents[1..]);
@@ -1875,7 +2251,7 @@ mod test {
         let worker = Worker::new("snap_manager");
         let sched = worker.scheduler();
         let mut store = new_storage_from_ents(sched, &td, &ents);
-        store.cache.cache.clear();
+        store.cache.clear();
 
         // initial cache
         let mut entries = vec![new_entry(6, 5), new_entry(7, 5)];
@@ -1948,6 +2324,63 @@ mod test {
         assert!(store.cache.cache.capacity() < cap as usize);
     }
 
+    #[test]
+    fn test_storage_cache_memory_limit() {
+        let ents = vec![new_entry(3, 3), new_entry(4, 4), new_entry(5, 5)];
+        let td = TempDir::new("tikv-store-test").unwrap();
+        let worker = Worker::new("snap_manager");
+        let sched = worker.scheduler();
+        let mut store = new_storage_from_ents(sched, &td, &ents);
+        store.cache.clear();
+        let entry_size = entry_mem_size(&new_entry(6, 5));
+        let mem = Arc::new(EntryCacheMemory::new(entry_size * 2));
+        store.set_entry_cache_memory(mem.clone());
+
+        let entries = vec![new_entry(6, 5), new_entry(7, 5)];
+        append_ents(&mut store, &entries);
+        validate_cache(&store, &entries);
+        assert_eq!(mem.used(), entry_size * 2);
+
+        // the oldest entries are evicted when the limit is exceeded.
+        append_ents(&mut store, &[new_entry(8, 5)]);
+        validate_cache(&store, &[new_entry(7, 5), new_entry(8, 5)]);
+        assert_eq!(mem.used(), entry_size * 2);
+        // evicted entries are fetched from rocksdb.
+        let res = store.entries(6, 9, u64::max_value()).unwrap();
+        assert_eq!(res, vec![new_entry(6, 5), new_entry(7, 5), new_entry(8, 5)]);
+
+        store.compact_to(8);
+        validate_cache(&store, &[new_entry(8, 5)]);
+        assert_eq!(mem.used(), entry_size);
+
+        drop(store);
+        assert_eq!(mem.used(), 0);
+
+        // the caches sharing the memory are evicted in proportion to their sizes.
+        let mem = Arc::new(EntryCacheMemory::new(entry_size * 4));
+        let mut stores = vec![];
+        for _ in 0..2 {
+            let td = TempDir::new("tikv-store-test").unwrap();
+            let mut store = new_storage_from_ents(sched.clone(), &td, &ents);
+            store.cache.clear();
+            store.set_entry_cache_memory(mem.clone());
+            stores.push((td, store));
+        }
+        let entries: Vec<_> = (6..10).map(|i| new_entry(i, 5)).collect();
+        append_ents(&mut stores[0].1, &entries);
+        validate_cache(&stores[0].1, &entries);
+        append_ents(&mut stores[1].1, &entries[..2]);
+        // only the oldest entry of the appending cache is evicted.
+        validate_cache(&stores[1].1, &entries[1..2]);
+        assert_eq!(mem.used(), entry_size * 5);
+        stores[0].1.evict_entry_cache();
+        validate_cache(&stores[0].1, &entries[1..]);
+        assert_eq!(mem.used(), entry_size * 4);
+        // nothing is evicted within the limit.
+        stores[1].1.evict_entry_cache();
+        validate_cache(&stores[1].1, &entries[1..2]);
+    }
+
     #[test]
     fn test_storage_apply_snapshot() {
         let ents = vec![
@@ -1965,8 +2398,16 @@ mod test {
         let mut worker = Worker::new("snap_manager");
         let sched = worker.scheduler();
         let s1 = new_storage_from_ents(sched.clone(), &td1, &ents);
-        let runner =
-            RegionRunner::new(s1.kv_engine.clone(), s1.raft_engine.clone(), mgr.clone(), 0);
+        let runner = RegionRunner::new(
+            s1.kv_engine.clone(),
+            s1.raft_engine.clone(),
+            None,
+            mgr.clone(),
+            0,
+            Arc::new(Mutex::new(PendingDeleteRanges::new(Duration::from_secs(0)))),
+            1,
+            1,
+        );
         worker.start(runner).unwrap();
         assert!(s1.snapshot().is_err());
         let snap1 = match *s1.snap_state.borrow() {
@@ -1983,7 +2424,7 @@ mod test {
         assert_ne!(ctx.last_term, snap1.get_metadata().get_term());
         let kv_wb = WriteBatch::new();
         let raft_wb = WriteBatch::new();
-        s2.apply_snapshot(&mut ctx, &snap1, &kv_wb, &raft_wb)
+        s2.apply_snapshot(&mut ctx, &snap1, &kv_wb, &raft_wb, &mut LogBatch::new())
             .unwrap();
         assert_eq!(ctx.last_term, snap1.get_metadata().get_term());
         assert_eq!(ctx.apply_state.get_applied_index(), 6);
@@ -2001,7 +2442,7 @@ mod test {
         assert_ne!(ctx.last_term, snap1.get_metadata().get_term());
         let kv_wb = WriteBatch::new();
         let raft_wb = WriteBatch::new();
-        s3.apply_snapshot(&mut ctx, &snap1, &kv_wb, &raft_wb)
+        s3.apply_snapshot(&mut ctx, &snap1, &kv_wb, &raft_wb, &mut LogBatch::new())
             .unwrap();
         assert_eq!(ctx.last_term, snap1.get_metadata().get_term());
         assert_eq!(ctx.apply_state.get_applied_index(), 6);
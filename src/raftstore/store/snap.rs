@@ -11,8 +11,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp;
 use std::error;
-use std::io::{self, ErrorKind, Read, Write};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::fmt::{self, Display, Formatter};
 use std::fs::{self, Metadata};
 use std::sync::{Arc, RwLock};
@@ -20,7 +21,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::path::Path;
 use std::result;
 use std::str;
-use std::time;
+use std::time::{self, SystemTime};
 use std::thread;
 
 use protobuf::Message;
@@ -155,6 +156,7 @@ pub struct ApplyOptions {
     pub region: Region,
     pub abort: Arc<AtomicUsize>,
     pub write_batch_size: usize,
+    pub limiter: Option<Arc<IOLimiter>>,
 }
 
 /// `Snapshot` is a trait for snapshot.
@@ -180,6 +182,13 @@ pub trait Snapshot: Read + Write + Send {
     fn total_size(&self) -> io::Result<u64>;
     fn save(&mut self) -> io::Result<()>;
     fn apply(&mut self, options: ApplyOptions) -> Result<()>;
+    /// Returns the size of data that has been received.
+    fn received_size(&self) -> u64;
+    /// Keeps the partially received data when the snapshot is dropped,
+    /// so receiving can be resumed later.
+    fn keep_partial(&mut self);
+    /// Skips the first `offset` bytes of data for reading.
+    fn seek_to(&mut self, offset: u64) -> io::Result<()>;
 }
 
 // A helper function to copy snapshot.
@@ -338,6 +347,8 @@ pub struct Snap {
     meta_file: MetaFile,
     size_track: Arc<RwLock<u64>>,
     limiter: Option<Arc<IOLimiter>>,
+    // Whether to keep partially received files when dropped.
+    keep_partial: bool,
 }
 
 impl Snap {
@@ -393,6 +404,7 @@ impl Snap {
             meta_file: meta_file,
             size_track: size_track,
             limiter: limiter,
+            keep_partial: false,
         };
 
         // load snapshot meta if meta_file exists
@@ -467,22 +479,56 @@ impl Snap {
         if s.exists() {
             return Ok(s);
         }
+        let received = s.partial_received_size();
+        if received == 0 {
+            // Partially received data can't be resumed, clean them up.
+            s.delete();
+        }
         for cf_file in &mut s.cf_files {
             if cf_file.size == 0 {
                 continue;
             }
-            let f = OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                .open(&cf_file.tmp_path)?;
+            let mut digest = Digest::new(crc32::IEEE);
+            let f = if received > 0 && file_exists(&cf_file.tmp_path) {
+                // Rebuild the checksum of the data received before.
+                let mut f = OpenOptions::new()
+                    .read(true)
+                    .append(true)
+                    .open(&cf_file.tmp_path)?;
+                let mut buf = vec![0; DIGEST_BUFFER_SIZE];
+                loop {
+                    let n = f.read(&mut buf[..])?;
+                    if n == 0 {
+                        break;
+                    }
+                    digest.write(&buf[..n]);
+                    cf_file.written_size += n as u64;
+                }
+                f
+            } else {
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&cf_file.tmp_path)?
+            };
             cf_file.file = Some(f);
-            cf_file.write_digest = Some(Digest::new(crc32::IEEE));
+            cf_file.write_digest = Some(digest);
         }
-        let f = OpenOptions::new()
+        // Write the meta first, so partially received data can be checked
+        // against it before resuming.
+        let mut v = vec![];
+        s.meta_file.meta.write_to_vec(&mut v)?;
+        let mut f = OpenOptions::new()
             .write(true)
-            .create_new(true)
+            .create(true)
+            .truncate(true)
             .open(&s.meta_file.tmp_path)?;
+        f.write_all(&v[..])?;
+        f.sync_all()?;
         s.meta_file.file = Some(f);
+        if received > 0 {
+            info!("resume receiving {} from {}", s.path(), received);
+        }
         Ok(s)
     }
 
@@ -569,6 +615,63 @@ impl Snap {
         Ok(())
     }
 
+    fn read_partial_meta(&self) -> Option<SnapshotMeta> {
+        if !file_exists(&self.meta_file.tmp_path) {
+            return None;
+        }
+        let mut buf = vec![];
+        let res = File::open(&self.meta_file.tmp_path).and_then(|mut f| f.read_to_end(&mut buf));
+        if let Err(e) = res {
+            warn!("failed to read partial meta of {}: {:?}", self.path(), e);
+            return None;
+        }
+        let mut snapshot_meta = SnapshotMeta::new();
+        match snapshot_meta.merge_from_bytes(&buf) {
+            Ok(_) => Some(snapshot_meta),
+            Err(_) => None,
+        }
+    }
+
+    // Returns the size of partially received data which can be resumed. The data
+    // must belong to the same snapshot meta and be written in order.
+    fn partial_received_size(&self) -> u64 {
+        match self.read_partial_meta() {
+            Some(ref meta) if *meta == self.meta_file.meta => {}
+            _ => return 0,
+        }
+        let (mut received, mut completed) = (0, true);
+        for cf_file in &self.cf_files {
+            if cf_file.size == 0 {
+                continue;
+            }
+            let size = if file_exists(&cf_file.tmp_path) {
+                match get_file_size(&cf_file.tmp_path) {
+                    Ok(size) => size,
+                    Err(_) => return 0,
+                }
+            } else {
+                0
+            };
+            if size > cf_file.size || (!completed && size > 0) {
+                return 0;
+            }
+            completed = size == cf_file.size;
+            received += size;
+        }
+        received
+    }
+
+    // Returns the last time when partially received data was written, or `None`
+    // if there isn't any.
+    fn partial_modified(&self) -> Option<SystemTime> {
+        self.cf_files
+            .iter()
+            .map(|cf_file| &cf_file.tmp_path)
+            .chain(Some(&self.meta_file.tmp_path))
+            .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+            .max()
+    }
+
     fn load_snapshot_meta(&mut self) -> RaftStoreResult<()> {
         let snapshot_meta = self.read_snapshot_meta()?;
         self.set_snapshot_meta(snapshot_meta)?;
@@ -791,6 +894,12 @@ pub fn build_plain_cf_file<E: BytesEncoder>(
     Ok((cf_key_count, cf_size))
 }
 
+fn request_io(options: &ApplyOptions, bytes: usize) {
+    if let Some(ref limiter) = options.limiter {
+        limiter.request_in_batches(bytes as i64);
+    }
+}
+
 fn apply_plain_cf_file<D: CompactBytesDecoder>(
     decoder: &mut D,
     options: &ApplyOptions,
@@ -803,6 +912,7 @@ fn apply_plain_cf_file<D: CompactBytesDecoder>(
         let key = box_try!(decoder.decode_compact_bytes());
         if key.is_empty() {
             if batch_size > 0 {
+                request_io(options, batch_size);
                 box_try!(options.db.write(wb));
             }
             break;
@@ -813,6 +923,7 @@ fn apply_plain_cf_file<D: CompactBytesDecoder>(
         batch_size += value.len();
         box_try!(wb.put_cf(handle, &key, &value));
         if batch_size >= options.write_batch_size {
+            request_io(options, batch_size);
             box_try!(options.db.write(wb));
             wb = WriteBatch::new();
             batch_size = 0;
@@ -932,10 +1043,8 @@ impl Snapshot for Snap {
             let mut size_track = self.size_track.wl();
             *size_track = size_track.saturating_add(cf_file.size);
         }
-        // write meta file
-        let mut v = vec![];
-        self.meta_file.meta.write_to_vec(&mut v)?;
-        self.meta_file.file.take().unwrap().write_all(&v[..])?;
+        // meta file is written when the snapshot is created for receiving.
+        self.meta_file.file.take().unwrap();
         fs::rename(&self.meta_file.tmp_path, &self.meta_file.path)?;
         Ok(())
     }
@@ -960,6 +1069,7 @@ impl Snapshot for Snap {
                 // after changing logic in raft, ask for resending snapshot if applying fail.
                 // ingest_opt.move_files(true);
                 let path = cf_file.path.as_path().to_str().unwrap();
+                request_io(&options, cf_file.size as usize);
                 box_try!(
                     options
                         .db
@@ -969,6 +1079,44 @@ impl Snapshot for Snap {
         }
         Ok(())
     }
+
+    fn received_size(&self) -> u64 {
+        self.cf_files.iter().fold(0, |acc, x| acc + x.written_size)
+    }
+
+    fn keep_partial(&mut self) {
+        for cf_file in &mut self.cf_files {
+            if let Some(ref mut f) = cf_file.file {
+                if let Err(e) = f.sync_data() {
+                    warn!("failed to sync {}: {:?}", cf_file.tmp_path.display(), e);
+                }
+            }
+        }
+        self.keep_partial = true;
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        let mut left = offset;
+        for (i, cf_file) in self.cf_files.iter_mut().enumerate() {
+            if cf_file.size == 0 {
+                continue;
+            }
+            let pos = cmp::min(left, cf_file.size);
+            cf_file.file.as_mut().unwrap().seek(SeekFrom::Start(pos))?;
+            self.cf_index = i;
+            left -= pos;
+            if left == 0 {
+                return Ok(());
+            }
+        }
+        if left > 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("offset {} exceeds the size of {}", offset, self.display_path),
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Read for Snap {
@@ -1048,6 +1196,9 @@ impl Write for Snap {
 
 impl Drop for Snap {
     fn drop(&mut self) {
+        if self.keep_partial {
+            return;
+        }
         // cleanup if some of the cf files and meta file is partly written
         if self.cf_files
             .iter()
@@ -1086,6 +1237,14 @@ struct SnapManagerCore {
     snap_size: Arc<RwLock<u64>>,
 }
 
+impl SnapManagerCore {
+    fn is_receiving(&self, key: &SnapKey) -> bool {
+        self.registry
+            .get(key)
+            .map_or(false, |entries| entries.contains(&SnapEntry::Receiving))
+    }
+}
+
 fn notify_stats(ch: Option<&SendCh<Msg>>) {
     if let Some(ch) = ch {
         if let Err(e) = ch.try_send(Msg::SnapshotStats) {
@@ -1139,7 +1298,9 @@ impl SnapManager {
             let p = f?;
             if p.file_type()?.is_file() {
                 if let Some(s) = p.file_name().to_str() {
-                    if s.ends_with(TMP_FILE_SUFFIX) {
+                    // Partially received snapshots are kept for resuming, they
+                    // are cleaned up by snap gc if not resumed in time.
+                    if s.ends_with(TMP_FILE_SUFFIX) && !s.starts_with(SNAP_REV_PREFIX) {
                         fs::remove_file(p.path())?;
                     } else if s.ends_with(SST_FILE_SUFFIX) {
                         let len = p.metadata()?.len();
@@ -1202,11 +1363,23 @@ impl SnapManager {
         Ok(v)
     }
 
+    /// Returns the limiter shared by building and applying snapshots.
+    pub fn limiter(&self) -> Option<Arc<IOLimiter>> {
+        self.limiter.clone()
+    }
+
     #[inline]
     pub fn has_registered(&self, key: &SnapKey) -> bool {
         self.core.rl().registry.contains_key(key)
     }
 
+    /// Checks whether any snapshot of the region is being generated.
+    pub fn is_generating(&self, region_id: u64) -> bool {
+        self.core.rl().registry.iter().any(|(k, entries)| {
+            k.region_id == region_id && entries.contains(&SnapEntry::Generating)
+        })
+    }
+
     pub fn get_snapshot_for_building(
         &self,
         key: &SnapKey,
@@ -1257,6 +1430,55 @@ impl SnapManager {
         Ok(Box::new(f))
     }
 
+    /// Returns the size of received data of the snapshot, the sender can resume
+    /// sending from there.
+    pub fn get_snapshot_received_size(&self, key: &SnapKey, data: &[u8]) -> RaftStoreResult<u64> {
+        let core = self.core.rl();
+        if core.is_receiving(key) {
+            return Ok(0);
+        }
+        let mut snapshot_data = RaftSnapshotData::new();
+        snapshot_data.merge_from_bytes(data)?;
+        let mut s = Snap::new(
+            &core.base,
+            key,
+            core.snap_size.clone(),
+            false,
+            false,
+            Box::new(self.clone()),
+            None,
+        )?;
+        s.keep_partial = true;
+        s.set_snapshot_meta(snapshot_data.take_meta())?;
+        if s.exists() {
+            return Ok(s.total_size()?);
+        }
+        Ok(s.partial_received_size())
+    }
+
+    /// Returns the partially received snapshot and the last time it was written,
+    /// or `None` if there isn't any.
+    pub fn get_partial_received_snapshot(
+        &self,
+        key: &SnapKey,
+    ) -> RaftStoreResult<Option<(Box<Snapshot>, SystemTime)>> {
+        let core = self.core.rl();
+        let mut s = Snap::new_for_applying(
+            &core.base,
+            key,
+            core.snap_size.clone(),
+            Box::new(self.clone()),
+        )?;
+        s.keep_partial = true;
+        if s.exists() {
+            return Ok(None);
+        }
+        match s.partial_modified() {
+            Some(modified) => Ok(Some((Box::new(s), modified))),
+            None => Ok(None),
+        }
+    }
+
     pub fn get_snapshot_for_applying(&self, key: &SnapKey) -> RaftStoreResult<Box<Snapshot>> {
         let core = self.core.rl();
         let s = Snap::new_for_applying(
@@ -1283,14 +1505,18 @@ impl SnapManager {
         size
     }
 
-    pub fn register(&self, key: SnapKey, entry: SnapEntry) {
+    /// Registers the entry of the snapshot, returns false if it's already registered.
+    ///
+    /// A snapshot can be shared by several senders, so `SnapEntry::Sending` is counted
+    /// every time it's registered, and it's deregistered one by one.
+    pub fn register(&self, key: SnapKey, entry: SnapEntry) -> bool {
         debug!("register [key: {}, entry: {:?}]", key, entry);
         let mut core = self.core.wl();
         match core.registry.entry(key) {
             Entry::Occupied(mut e) => {
-                if e.get().contains(&entry) {
+                if entry != SnapEntry::Sending && e.get().contains(&entry) {
                     warn!("{} is registered more than 1 time!!!", e.key());
-                    return;
+                    return false;
                 }
                 e.get_mut().push(entry);
             }
@@ -1300,6 +1526,7 @@ impl SnapManager {
         }
 
         notify_stats(self.ch.as_ref());
+        true
     }
 
     pub fn deregister(&self, key: &SnapKey, entry: &SnapEntry) {
@@ -1308,10 +1535,11 @@ impl SnapManager {
         let mut handled = false;
         let mut core = self.core.wl();
         if let Some(e) = core.registry.get_mut(key) {
-            let last_len = e.len();
-            e.retain(|e| e != entry);
+            if let Some(pos) = e.iter().position(|e| e == entry) {
+                e.remove(pos);
+                handled = true;
+            }
             need_clean = e.is_empty();
-            handled = last_len > e.len();
         }
         if need_clean {
             core.registry.remove(key);
@@ -1646,6 +1874,7 @@ mod test {
             region: region.clone(),
             abort: Arc::new(AtomicUsize::new(JOB_STATUS_RUNNING)),
             write_batch_size: TEST_WRITE_BATCH_SIZE,
+            limiter: None,
         };
         // Verify thte snapshot applying is ok.
         assert!(s4.apply(options).is_ok());
@@ -1929,6 +2158,7 @@ mod test {
             region: region.clone(),
             abort: Arc::new(AtomicUsize::new(JOB_STATUS_RUNNING)),
             write_batch_size: TEST_WRITE_BATCH_SIZE,
+            limiter: None,
         };
         assert!(s5.apply(options).is_err());
 
@@ -2217,4 +2447,231 @@ mod test {
         dst_mgr.delete_snapshot(&key, s4.as_ref(), false);
         assert!(s5.exists());
     }
+
+    #[test]
+    fn test_shared_snap_deletion() {
+        let temp_dir = TempDir::new("test-shared-snap-deletion").unwrap();
+        let path = temp_dir.path().to_str().unwrap().to_owned();
+        let mgr = SnapManager::new(path.clone(), None, None);
+        mgr.init().unwrap();
+
+        let db_dir = TempDir::new("test-shared-snap-deletion-db").unwrap();
+        let snapshot = DbSnapshot::new(get_test_db(&db_dir).unwrap());
+        let key = SnapKey::new(1, 1, 1);
+        let region = get_test_region(1, 1, 1);
+        let mut s1 = mgr.get_snapshot_for_building(&key, &snapshot).unwrap();
+        let mut snap_data = RaftSnapshotData::new();
+        snap_data.set_region(region.clone());
+        let mut stat = SnapshotStatistics::new();
+        s1.build(
+            &snapshot,
+            &region,
+            &mut snap_data,
+            &mut stat,
+            Box::new(mgr.clone()),
+        ).unwrap();
+
+        // The snapshot is sent to two peers at the same time.
+        assert!(mgr.register(key.clone(), SnapEntry::Sending));
+        assert!(mgr.register(key.clone(), SnapEntry::Sending));
+        let s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let s3 = mgr.get_snapshot_for_sending(&key).unwrap();
+        assert_eq!(mgr.stats().sending_count, 1);
+
+        // Only the last sender deletes it.
+        assert!(!mgr.delete_snapshot(&key, s2.as_ref(), true));
+        mgr.deregister(&key, &SnapEntry::Sending);
+        assert!(mgr.has_registered(&key));
+        assert!(s3.exists());
+        assert!(mgr.delete_snapshot(&key, s3.as_ref(), true));
+        mgr.deregister(&key, &SnapEntry::Sending);
+        assert!(!mgr.has_registered(&key));
+        assert!(!s3.exists());
+    }
+
+    #[test]
+    fn test_snap_resume_receiving() {
+        let temp_dir = TempDir::new("test-snap-resume-receiving").unwrap();
+        let path = temp_dir.path().to_str().unwrap().to_owned();
+        let mgr = SnapManager::new(path.clone(), None, None);
+        mgr.init().unwrap();
+
+        let db_dir = TempDir::new("test-snap-resume-receiving-db").unwrap();
+        let snapshot = DbSnapshot::new(get_test_db(&db_dir).unwrap());
+        let key = SnapKey::new(1, 1, 1);
+        let size_track = Arc::new(RwLock::new(0));
+        let deleter = Box::new(mgr.clone());
+        let mut s1 = Snap::new_for_building(
+            &path,
+            &key,
+            &snapshot,
+            size_track.clone(),
+            deleter.clone(),
+            None,
+        ).unwrap();
+        let region = get_test_region(1, 1, 1);
+        let mut snap_data = RaftSnapshotData::new();
+        snap_data.set_region(region.clone());
+        let mut stat = SnapshotStatistics::new();
+        s1.build(
+            &snapshot,
+            &region,
+            &mut snap_data,
+            &mut stat,
+            deleter.clone(),
+        ).unwrap();
+        let mut data = vec![];
+        Snap::new_for_sending(&path, &key, size_track.clone(), deleter.clone())
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert!(data.len() > 2);
+        let raw_data = snap_data.write_to_bytes().unwrap();
+
+        // Nothing is received yet.
+        let recv_dir = TempDir::new("test-snap-resume-receiving-recv").unwrap();
+        let recv_path = recv_dir.path().to_str().unwrap().to_owned();
+        let recv_mgr = SnapManager::new(recv_path.clone(), None, None);
+        recv_mgr.init().unwrap();
+        assert_eq!(recv_mgr.get_snapshot_received_size(&key, &raw_data).unwrap(), 0);
+
+        // The receiving is interrupted.
+        let half = data.len() / 2;
+        let mut s2 = recv_mgr
+            .get_snapshot_for_receiving(&key, &raw_data)
+            .unwrap();
+        s2.write_all(&data[..half]).unwrap();
+        assert_eq!(s2.received_size(), half as u64);
+        s2.keep_partial();
+        drop(s2);
+        assert_eq!(
+            recv_mgr.get_snapshot_received_size(&key, &raw_data).unwrap(),
+            half as u64
+        );
+
+        // Partially received data are kept after restart.
+        let recv_mgr = SnapManager::new(recv_path.clone(), None, None);
+        recv_mgr.init().unwrap();
+        assert_eq!(
+            recv_mgr.get_snapshot_received_size(&key, &raw_data).unwrap(),
+            half as u64
+        );
+        let (_, modified) = recv_mgr
+            .get_partial_received_snapshot(&key)
+            .unwrap()
+            .unwrap();
+        assert!(modified.elapsed().is_ok());
+
+        // Resume sending from the received size.
+        let mut s =
+            Snap::new_for_sending(&path, &key, size_track.clone(), deleter.clone()).unwrap();
+        s.seek_to(half as u64).unwrap();
+        let mut s3 = recv_mgr
+            .get_snapshot_for_receiving(&key, &raw_data)
+            .unwrap();
+        assert_eq!(s3.received_size(), half as u64);
+        let n = io::copy(&mut s, &mut s3).unwrap();
+        assert_eq!(n, (data.len() - half) as u64);
+        // The checksum of the whole file is checked when saving.
+        s3.save().unwrap();
+        assert!(s3.exists());
+        assert!(recv_mgr.get_partial_received_snapshot(&key).unwrap().is_none());
+        assert_eq!(
+            recv_mgr.get_snapshot_received_size(&key, &raw_data).unwrap(),
+            data.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_snap_partial_data_dropped() {
+        let temp_dir = TempDir::new("test-snap-partial-data-dropped").unwrap();
+        let path = temp_dir.path().to_str().unwrap().to_owned();
+        let mgr = SnapManager::new(path.clone(), None, None);
+        mgr.init().unwrap();
+
+        let db_dir = TempDir::new("test-snap-partial-data-dropped-db").unwrap();
+        let snapshot = DbSnapshot::new(get_test_db(&db_dir).unwrap());
+        let key = SnapKey::new(1, 1, 1);
+        let size_track = Arc::new(RwLock::new(0));
+        let deleter = Box::new(mgr.clone());
+        let mut s1 = Snap::new_for_building(
+            &path,
+            &key,
+            &snapshot,
+            size_track.clone(),
+            deleter.clone(),
+            None,
+        ).unwrap();
+        let region = get_test_region(1, 1, 1);
+        let mut snap_data = RaftSnapshotData::new();
+        snap_data.set_region(region.clone());
+        let mut stat = SnapshotStatistics::new();
+        s1.build(
+            &snapshot,
+            &region,
+            &mut snap_data,
+            &mut stat,
+            deleter.clone(),
+        ).unwrap();
+        let mut data = vec![];
+        Snap::new_for_sending(&path, &key, size_track.clone(), deleter.clone())
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let raw_data = snap_data.write_to_bytes().unwrap();
+
+        let recv_dir = TempDir::new("test-snap-partial-data-dropped-recv").unwrap();
+        let recv_mgr = SnapManager::new(recv_dir.path().to_str().unwrap(), None, None);
+        recv_mgr.init().unwrap();
+
+        // Partially received data are deleted if not kept explicitly.
+        let mut s2 = recv_mgr
+            .get_snapshot_for_receiving(&key, &raw_data)
+            .unwrap();
+        s2.write_all(&data[..1]).unwrap();
+        drop(s2);
+        assert!(recv_mgr.get_partial_received_snapshot(&key).unwrap().is_none());
+
+        // Partially received data of a different snapshot meta can't be resumed.
+        let mut s3 = recv_mgr
+            .get_snapshot_for_receiving(&key, &raw_data)
+            .unwrap();
+        s3.write_all(&data[..1]).unwrap();
+        s3.keep_partial();
+        drop(s3);
+        let mut other_data = snap_data.clone();
+        {
+            let cf_file = &mut other_data.mut_meta().mut_cf_files()[0];
+            let checksum = cf_file.get_checksum();
+            cf_file.set_checksum(checksum.wrapping_add(1));
+        }
+        let other_data = other_data.write_to_bytes().unwrap();
+        assert_eq!(recv_mgr.get_snapshot_received_size(&key, &other_data).unwrap(), 0);
+        let s4 = recv_mgr
+            .get_snapshot_for_receiving(&key, &other_data)
+            .unwrap();
+        assert_eq!(s4.received_size(), 0);
+
+        drop(s4);
+
+        // Snapshot being received can't be received concurrently, and its partially
+        // received data can't be deleted by gc.
+        let mut s5 = recv_mgr
+            .get_snapshot_for_receiving(&key, &raw_data)
+            .unwrap();
+        s5.write_all(&data[..1]).unwrap();
+        s5.keep_partial();
+        drop(s5);
+        assert!(recv_mgr.register(key.clone(), SnapEntry::Receiving));
+        assert!(!recv_mgr.register(key.clone(), SnapEntry::Receiving));
+        let (s6, _) = recv_mgr
+            .get_partial_received_snapshot(&key)
+            .unwrap()
+            .unwrap();
+        assert!(!recv_mgr.delete_snapshot(&key, s6.as_ref(), false));
+        recv_mgr.deregister(&key, &SnapEntry::Receiving);
+        assert!(recv_mgr.get_partial_received_snapshot(&key).unwrap().is_some());
+        assert!(recv_mgr.delete_snapshot(&key, s6.as_ref(), false));
+        assert!(recv_mgr.get_partial_received_snapshot(&key).unwrap().is_none());
+    }
 }
@@ -11,15 +11,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
-use std::sync::mpsc::{self, Receiver as StdReceiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver as StdReceiver, Sender as StdSender, TryRecvError};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::collections::Bound::{Excluded, Included, Unbounded};
 use std::time::{Duration, Instant};
 use std::thread;
-use std::u64;
+use std::{cmp, u64};
+use std::mem;
 
 use rocksdb::{WriteBatch, DB};
 use rocksdb::rocksdb_options::WriteOptions;
@@ -33,31 +35,35 @@ use kvproto::eraftpb::{ConfChangeType, MessageType};
 use kvproto::pdpb::StoreStats;
 use util::{escape, rocksdb};
 use util::time::{duration_to_sec, SlowTimer};
-use pd::{PdClient, PdRunner, PdTask};
+use pd::{AutoSplitController, PdClient, PdRunner, PdTask};
 use kvproto::raft_cmdpb::{AdminCmdType, AdminRequest, RaftCmdRequest, RaftCmdResponse,
                           StatusCmdType, StatusResponse};
 use protobuf::Message;
-use raft::{self, SnapshotStatus, INVALID_INDEX};
+use raft::{self, Ready, SnapshotStatus, INVALID_INDEX};
 use raftstore::{Error, Result};
 use kvproto::metapb;
-use util::worker::{FutureWorker, Scheduler, Stopped, Worker};
+use util::worker::{Builder as WorkerBuilder, FutureScheduler, FutureWorker, Scheduler, Stopped,
+                   Worker};
 use util::transport::SendCh;
 use util::RingQueue;
 use util::collections::{HashMap, HashSet};
 use storage::{CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE};
 use raftstore::coprocessor::CoprocessorHost;
-use raftstore::coprocessor::split_observer::SplitObserver;
 use super::worker::{ApplyRunner, ApplyTask, ApplyTaskRes, CompactRunner, CompactTask,
-                    ConsistencyCheckRunner, ConsistencyCheckTask, RaftlogGcRunner, RaftlogGcTask,
-                    RegionRunner, RegionTask, SplitCheckRunner, SplitCheckTask};
+                    ConsistencyCheckRunner, ConsistencyCheckTask, PendingDeleteRanges,
+                    RaftlogGcRunner, RaftlogGcTask, RaftlogWriteRunner, RaftlogWriteTask,
+                    RegionRunner, RegionTask, SplitCheckRunner, SplitCheckTask,
+                    RAFTLOG_WRITE_BATCH_SIZE};
 use super::worker::apply::{ChangePeer, ExecResult};
 use super::{util, Msg, SignificantMsg, SnapKey, SnapManager, SnapshotDeleter, Tick};
 use super::keys::{self, data_end_key, data_key, enc_end_key, enc_start_key};
 use super::engine::{Iterable, Peekable, Snapshot as EngineSnapshot};
 use super::config::Config;
-use super::peer::{self, ConsistencyState, Peer, ReadyContext, StaleState};
-use super::peer_storage::{self, ApplySnapResult, CacheQueryStats};
-use super::msg::{BatchCallback, Callback};
+use super::peer::{self, ConsistencyState, Peer, PeerStat, ReadyContext, StaleState};
+use super::log_engine::{LogBatch, RaftLogEngine};
+use super::peer_storage::{self, ApplySnapResult, CacheQueryStats, EntryCacheMemory,
+                          InvokeContext};
+use super::msg::{BatchCallback, Callback, PollerRouter, RouterSendCh};
 use super::cmd_resp::{bind_term, new_error};
 use super::transport::Transport;
 use super::metrics::*;
@@ -72,6 +78,8 @@ const PENDING_VOTES_CAP: usize = 20;
 pub struct Engines {
     pub kv_engine: Arc<DB>,
     pub raft_engine: Arc<DB>,
+    // If it's set, raft logs are stored in it instead of `raft_engine`.
+    pub raft_log_engine: Option<Arc<RaftLogEngine>>,
 }
 
 impl Engines {
@@ -79,6 +87,7 @@ impl Engines {
         Engines {
             kv_engine: kv_engine,
             raft_engine: raft_engine,
+            raft_log_engine: None,
         }
     }
 }
@@ -87,6 +96,177 @@ impl Engines {
 pub struct StoreChannel {
     pub sender: Sender<Msg>,
     pub significant_msg_receiver: StdReceiver<SignificantMsg>,
+    pub split_region_receiver: StdReceiver<SplitRegionCreated>,
+}
+
+/// `SplitRegionCreated` asks the poller owning a new split region to create
+/// its peer, the split is applied in the poller of the parent region.
+pub struct SplitRegionCreated {
+    region: metapb::Region,
+    parent_is_leader: bool,
+    peer_stat: PeerStat,
+    right_derive: bool,
+}
+
+/// Statistics of a poller, which are collected by the first poller
+/// to send store heartbeats.
+#[derive(Default)]
+pub struct PollerStat {
+    pub region_count: usize,
+    pub leader_count: usize,
+    pub applying_snap_count: usize,
+    pub lock_cf_bytes_written: u64,
+    pub engine_bytes_written: u64,
+    pub engine_keys_written: u64,
+    pub is_busy: bool,
+}
+
+/// The meta data shared by all the pollers of a store.
+pub struct StoreMeta {
+    // region end key -> region id
+    pub region_ranges: BTreeMap<Key, u64>,
+    // region id -> region, only initialized regions are included.
+    pub regions: HashMap<u64, metapb::Region>,
+    // the regions with pending snapshots between two mio ticks, and
+    // the id of the poller which receives the snapshot.
+    pub pending_snapshot_regions: Vec<(usize, metapb::Region)>,
+    pub pending_votes: RingQueue<RaftMessage>,
+    pub poller_stats: Vec<PollerStat>,
+}
+
+impl StoreMeta {
+    pub fn new(pool_size: usize) -> StoreMeta {
+        StoreMeta {
+            region_ranges: BTreeMap::new(),
+            regions: HashMap::default(),
+            pending_snapshot_regions: vec![],
+            pending_votes: RingQueue::with_capacity(PENDING_VOTES_CAP),
+            poller_stats: (0..pool_size).map(|_| PollerStat::default()).collect(),
+        }
+    }
+
+    fn set_region(&mut self, region: &metapb::Region) {
+        self.regions.insert(region.get_id(), region.clone());
+    }
+
+    /// Returns the first region which overlaps with [start_key, end_key),
+    /// both of them are encoded keys.
+    fn overlapped_region(&self, start_key: &[u8], end_key: &[u8]) -> Option<&metapb::Region> {
+        let region_id = match self.region_ranges
+            .range((Excluded(start_key.to_vec()), Unbounded::<Key>))
+            .next()
+        {
+            Some((_, region_id)) => region_id,
+            None => return None,
+        };
+        let region = &self.regions[region_id];
+        if enc_start_key(region).as_slice() < end_key {
+            Some(region)
+        } else {
+            None
+        }
+    }
+}
+
+/// `PollerContext` tells a poller which regions it's in charge of and
+/// how to reach other pollers. Regions are sharded by `region_id % pool_size`.
+#[derive(Clone)]
+pub struct PollerContext {
+    pub poller_id: usize,
+    // Channels of all the pollers.
+    pub router: PollerRouter,
+    // Significant messages are received by the first poller and forwarded
+    // to the owner by these channels, the first one is always None.
+    pub significant_routers: Vec<Option<StdSender<SignificantMsg>>>,
+    // New split regions are sent to their owners by these channels. They are
+    // unbounded, as a split is already applied and can't be given up.
+    pub split_routers: Vec<StdSender<SplitRegionCreated>>,
+    pub meta: Arc<Mutex<StoreMeta>>,
+    // Data ranges of destroyed peers which are waiting to be cleaned up.
+    pub pending_delete_ranges: Arc<Mutex<PendingDeleteRanges>>,
+    // Updated by the first poller on store heartbeats, the store only accepts the
+    // commands reclaiming space when it's set.
+    pub disk_full: Arc<AtomicBool>,
+    // The memory budget of the raft entry caches of all the peers.
+    pub entry_cache_memory: Arc<EntryCacheMemory>,
+}
+
+impl PollerContext {
+    pub fn pool_size(&self) -> usize {
+        self.router.pool_size()
+    }
+
+    pub fn owner_of(&self, region_id: u64) -> usize {
+        self.router.owner_of(region_id)
+    }
+
+    pub fn is_owner(&self, region_id: u64) -> bool {
+        self.owner_of(region_id) == self.poller_id
+    }
+}
+
+/// Background workers shared by all the pollers of a store, they are
+/// started and stopped by the first poller.
+pub struct StoreWorkers {
+    split_check_worker: Worker<SplitCheckTask>,
+    region_worker: Worker<RegionTask>,
+    raftlog_gc_worker: Worker<RaftlogGcTask>,
+    compact_worker: Worker<CompactTask>,
+    consistency_check_worker: Worker<ConsistencyCheckTask>,
+    pd_worker: FutureWorker<PdTask>,
+}
+
+impl StoreWorkers {
+    pub fn new(pd_worker: FutureWorker<PdTask>) -> StoreWorkers {
+        StoreWorkers {
+            split_check_worker: Worker::new("split check worker"),
+            region_worker: Worker::new("snapshot worker"),
+            raftlog_gc_worker: Worker::new("raft gc worker"),
+            compact_worker: Worker::new("compact worker"),
+            consistency_check_worker: Worker::new("consistency check worker"),
+            pd_worker: pd_worker,
+        }
+    }
+
+    pub fn schedulers(&self) -> StoreSchedulers {
+        StoreSchedulers {
+            split_check: self.split_check_worker.scheduler(),
+            region: self.region_worker.scheduler(),
+            raftlog_gc: self.raftlog_gc_worker.scheduler(),
+            compact: self.compact_worker.scheduler(),
+            consistency_check: self.consistency_check_worker.scheduler(),
+            pd: self.pd_worker.scheduler(),
+        }
+    }
+}
+
+/// Schedulers of the `StoreWorkers`, every poller holds a copy.
+#[derive(Clone)]
+pub struct StoreSchedulers {
+    pub split_check: Scheduler<SplitCheckTask>,
+    pub region: Scheduler<RegionTask>,
+    pub raftlog_gc: Scheduler<RaftlogGcTask>,
+    pub compact: Scheduler<CompactTask>,
+    pub consistency_check: Scheduler<ConsistencyCheckTask>,
+    pub pd: FutureScheduler<PdTask>,
+}
+
+/// `clear_stale_data` clean up all possible garbage data. It should be called
+/// after all the pollers are initialized and before any of them starts.
+pub fn clear_stale_data(kv_engine: &DB, meta: &StoreMeta) -> Result<()> {
+    let t = Instant::now();
+    let mut last_start_key = keys::data_key(b"");
+    for region_id in meta.region_ranges.values() {
+        let region = &meta.regions[region_id];
+        let start_key = keys::enc_start_key(region);
+        rocksdb::roughly_cleanup_range(kv_engine, &last_start_key, &start_key)?;
+        last_start_key = keys::enc_end_key(region);
+    }
+
+    rocksdb::roughly_cleanup_range(kv_engine, &last_start_key, keys::DATA_MAX_KEY)?;
+
+    info!("cleans up garbage data, takes {:?}", t.elapsed());
+    Ok(())
 }
 
 pub struct StoreStat {
@@ -124,28 +304,43 @@ pub struct StoreInfo {
     pub capacity: u64,
 }
 
+// Collects the responses of a batch whose commands are handled by different
+// pollers, and invokes the original callback once all of them are finished.
+struct BatchCollector {
+    resps: Vec<Option<RaftCmdResponse>>,
+    remaining: usize,
+    on_finished: Option<BatchCallback>,
+}
+
 pub struct Store<T, C: 'static> {
     cfg: Rc<Config>,
     kv_engine: Arc<DB>,
     raft_engine: Arc<DB>,
+    raft_log_engine: Option<Arc<RaftLogEngine>>,
     store: metapb::Store,
     sendch: SendCh<Msg>,
 
     significant_msg_receiver: StdReceiver<SignificantMsg>,
+    split_region_receiver: StdReceiver<SplitRegionCreated>,
 
     // region_id -> peers
     region_peers: HashMap<u64, Peer>,
     pending_raft_groups: HashSet<u64>,
-    // region end key -> region id
-    region_ranges: BTreeMap<Key, u64>,
-    // the regions with pending snapshots between two mio ticks.
-    pending_snapshot_regions: Vec<metapb::Region>,
-    split_check_worker: Worker<SplitCheckTask>,
-    region_worker: Worker<RegionTask>,
-    raftlog_gc_worker: Worker<RaftlogGcTask>,
-    compact_worker: Worker<CompactTask>,
-    pd_worker: FutureWorker<PdTask>,
-    consistency_check_worker: Worker<ConsistencyCheckTask>,
+    poller: PollerContext,
+    // Only the first poller runs the shared workers.
+    workers: Option<StoreWorkers>,
+    split_check_scheduler: Scheduler<SplitCheckTask>,
+    region_scheduler: Scheduler<RegionTask>,
+    raftlog_gc_scheduler: Scheduler<RaftlogGcTask>,
+    // Only started when the raft logs are written asynchronously.
+    raftlog_write_worker: Worker<RaftlogWriteTask>,
+    raftlog_persisted_receiver: Option<StdReceiver<Vec<(u64, u64)>>>,
+    // Peers persisted by the write worker which are received while waiting for
+    // another peer, they are handled by the next poll.
+    persisted_peers: Vec<(u64, u64)>,
+    compact_scheduler: Scheduler<CompactTask>,
+    pd_scheduler: FutureScheduler<PdTask>,
+    consistency_check_scheduler: Scheduler<ConsistencyCheckTask>,
     pub apply_worker: Worker<ApplyTask>,
     apply_res_receiver: Option<StdReceiver<ApplyTaskRes>>,
 
@@ -164,8 +359,6 @@ pub struct Store<T, C: 'static> {
     start_time: Timespec,
     is_busy: bool,
 
-    pending_votes: RingQueue<RaftMessage>,
-
     store_stat: StoreStat,
 }
 
@@ -183,56 +376,83 @@ where
     Ok(event_loop)
 }
 
+/// Creates an event loop for every poller of the store.
+pub fn create_event_loops<T, C>(cfg: &Config) -> Result<Vec<EventLoop<Store<T, C>>>>
+where
+    T: Transport,
+    C: PdClient,
+{
+    let mut event_loops = Vec::with_capacity(cfg.store_pool_size);
+    for _ in 0..cfg.store_pool_size {
+        event_loops.push(create_event_loop(cfg)?);
+    }
+    Ok(event_loops)
+}
+
+/// Returns a channel which sends messages to the pollers owning the regions.
+pub fn create_router<T, C>(event_loops: &[EventLoop<Store<T, C>>]) -> RouterSendCh
+where
+    T: Transport,
+    C: PdClient,
+{
+    let pollers = event_loops.iter().map(|l| l.channel()).collect();
+    RouterSendCh::new(PollerRouter::new(pollers), "raftstore")
+}
+
 impl<T, C> Store<T, C> {
     #[allow(too_many_arguments)]
     pub fn new(
         ch: StoreChannel,
+        poller: PollerContext,
         meta: metapb::Store,
         cfg: Config,
         engines: Engines,
         trans: T,
         pd_client: Arc<C>,
         mgr: SnapManager,
-        pd_worker: FutureWorker<PdTask>,
-        mut coprocessor_host: CoprocessorHost,
+        workers: Option<StoreWorkers>,
+        schedulers: StoreSchedulers,
+        coprocessor_host: Arc<CoprocessorHost>,
     ) -> Result<Store<T, C>> {
         // TODO: we can get cluster meta regularly too later.
         cfg.validate()?;
 
         let sendch = SendCh::new(ch.sender, "raftstore");
-        let tag = format!("[store {}]", meta.get_id());
-
-        // TODO load coprocessors from configuration
-        coprocessor_host
-            .registry
-            .register_admin_observer(100, box SplitObserver);
+        let tag = format!("[store {}] [poller {}]", meta.get_id(), poller.poller_id);
+        let raft_metrics = RaftMetrics::new(poller.poller_id);
 
         let mut s = Store {
             cfg: Rc::new(cfg),
             store: meta,
             kv_engine: engines.kv_engine,
             raft_engine: engines.raft_engine,
+            raft_log_engine: engines.raft_log_engine,
             sendch: sendch,
             significant_msg_receiver: ch.significant_msg_receiver,
+            split_region_receiver: ch.split_region_receiver,
             region_peers: HashMap::default(),
             pending_raft_groups: HashSet::default(),
-            split_check_worker: Worker::new("split check worker"),
-            region_worker: Worker::new("snapshot worker"),
-            raftlog_gc_worker: Worker::new("raft gc worker"),
-            compact_worker: Worker::new("compact worker"),
-            pd_worker: pd_worker,
-            consistency_check_worker: Worker::new("consistency check worker"),
+            workers: workers,
+            split_check_scheduler: schedulers.split_check,
+            region_scheduler: schedulers.region,
+            raftlog_gc_scheduler: schedulers.raftlog_gc,
+            raftlog_write_worker: WorkerBuilder::new("raft log write worker")
+                .batch_size(RAFTLOG_WRITE_BATCH_SIZE)
+                .create(),
+            raftlog_persisted_receiver: None,
+            persisted_peers: vec![],
+            compact_scheduler: schedulers.compact,
+            pd_scheduler: schedulers.pd,
+            consistency_check_scheduler: schedulers.consistency_check,
             apply_worker: Worker::new("apply worker"),
             apply_res_receiver: None,
-            region_ranges: BTreeMap::new(),
-            pending_snapshot_regions: vec![],
+            poller: poller,
             trans: trans,
             pd_client: pd_client,
-            coprocessor_host: Arc::new(coprocessor_host),
+            coprocessor_host: coprocessor_host,
             snap_mgr: mgr,
-            raft_metrics: RaftMetrics::default(),
+            raft_metrics: raft_metrics,
             entry_cache_metries: Rc::new(RefCell::new(CacheQueryStats::default())),
-            pending_votes: RingQueue::with_capacity(PENDING_VOTES_CAP),
             tag: tag,
             start_time: time::get_time(),
             is_busy: false,
@@ -243,7 +463,8 @@ impl<T, C> Store<T, C> {
     }
 
     /// Initialize this store. It scans the db engine, loads all regions
-    /// and their peers from it, and schedules snapshot worker if necessary.
+    /// belonging to this poller and their peers from it, and schedules
+    /// snapshot worker if necessary.
     /// WARN: This store should not be used before initialized.
     fn init(&mut self) -> Result<()> {
         // Scan region meta to get saved regions.
@@ -257,6 +478,7 @@ impl<T, C> Store<T, C> {
         let t = Instant::now();
         let mut kv_wb = WriteBatch::new();
         let mut raft_wb = WriteBatch::new();
+        let mut raft_log_batch = LogBatch::new();
         let mut applying_regions = vec![];
         kv_engine.scan_cf(
             CF_RAFT,
@@ -265,7 +487,7 @@ impl<T, C> Store<T, C> {
             false,
             &mut |key, value| {
                 let (region_id, suffix) = keys::decode_region_meta_key(key)?;
-                if suffix != keys::REGION_STATE_SUFFIX {
+                if suffix != keys::REGION_STATE_SUFFIX || !self.poller.is_owner(region_id) {
                     return Ok(true);
                 }
 
@@ -280,7 +502,12 @@ impl<T, C> Store<T, C> {
                         region,
                         self.store_id()
                     );
-                    self.clear_stale_meta(&mut kv_wb, &mut raft_wb, region);
+                    self.clear_stale_meta(
+                        &mut kv_wb,
+                        &mut raft_wb,
+                        &mut raft_log_batch,
+                        region,
+                    );
                     return Ok(true);
                 }
                 if local_state.get_state() == PeerState::Applying {
@@ -289,7 +516,9 @@ impl<T, C> Store<T, C> {
                     peer_storage::recover_from_applying_state(
                         &self.kv_engine,
                         &self.raft_engine,
+                        self.raft_log_engine.as_ref().map(|e| &**e),
                         &raft_wb,
+                        &mut raft_log_batch,
                         region_id,
                     )?;
                     applying_count += 1;
@@ -298,7 +527,11 @@ impl<T, C> Store<T, C> {
                 }
 
                 let peer = Peer::create(self, region)?;
-                self.region_ranges.insert(enc_end_key(region), region_id);
+                {
+                    let mut meta = self.poller.meta.lock().unwrap();
+                    meta.region_ranges.insert(enc_end_key(region), region_id);
+                    meta.set_region(region);
+                }
                 // No need to check duplicated here, because we use region id as the key
                 // in DB.
                 self.region_peers.insert(region_id, peer);
@@ -314,6 +547,9 @@ impl<T, C> Store<T, C> {
             self.raft_engine.write(raft_wb).unwrap();
             self.raft_engine.sync_wal().unwrap();
         }
+        if let Some(ref engine) = self.raft_log_engine {
+            engine.write(raft_log_batch, true).unwrap();
+        }
 
         // schedule applying snapshot after raft writebatch were written.
         for region in applying_regions {
@@ -324,8 +560,12 @@ impl<T, C> Store<T, C> {
             );
             let mut peer = Peer::create(self, &region)?;
             peer.mut_store().schedule_applying_snapshot();
-            self.region_ranges
-                .insert(enc_end_key(&region), region.get_id());
+            {
+                let mut meta = self.poller.meta.lock().unwrap();
+                meta.region_ranges
+                    .insert(enc_end_key(&region), region.get_id());
+                meta.set_region(&region);
+            }
             self.region_peers.insert(region.get_id(), peer);
         }
 
@@ -339,8 +579,6 @@ impl<T, C> Store<T, C> {
             t.elapsed()
         );
 
-        self.clear_stale_data()?;
-
         Ok(())
     }
 
@@ -348,8 +586,25 @@ impl<T, C> Store<T, C> {
         &mut self,
         kv_wb: &mut WriteBatch,
         raft_wb: &mut WriteBatch,
+        raft_log_batch: &mut LogBatch,
         region: &metapb::Region,
     ) {
+        if let Some(ref engine) = self.raft_log_engine {
+            if engine.get_raft_state(region.get_id()).is_none() {
+                // it has been cleaned up.
+                return;
+            }
+            peer_storage::clear_meta_in_log_engine(
+                &self.kv_engine,
+                kv_wb,
+                raft_log_batch,
+                region.get_id(),
+            ).unwrap();
+            peer_storage::write_peer_state(&self.kv_engine, kv_wb, region, PeerState::Tombstone)
+                .unwrap();
+            return;
+        }
+
         let raft_key = keys::raft_state_key(region.get_id());
         let raft_state = match self.raft_engine.get_msg(&raft_key).unwrap() {
             // it has been cleaned up.
@@ -369,27 +624,6 @@ impl<T, C> Store<T, C> {
             .unwrap();
     }
 
-    /// `clear_stale_data` clean up all possible garbage data.
-    fn clear_stale_data(&mut self) -> Result<()> {
-        let t = Instant::now();
-        let mut last_start_key = keys::data_key(b"");
-        for region_id in self.region_ranges.values() {
-            let region = self.region_peers[region_id].region();
-            let start_key = keys::enc_start_key(region);
-            rocksdb::roughly_cleanup_range(&self.kv_engine, &last_start_key, &start_key)?;
-            last_start_key = keys::enc_end_key(region);
-        }
-
-        rocksdb::roughly_cleanup_range(&self.kv_engine, &last_start_key, keys::DATA_MAX_KEY)?;
-
-        info!(
-            "{} cleans up garbage data, takes {:?}",
-            self.tag,
-            t.elapsed()
-        );
-        Ok(())
-    }
-
     pub fn get_sendch(&self) -> SendCh<Msg> {
         self.sendch.clone()
     }
@@ -400,7 +634,7 @@ impl<T, C> Store<T, C> {
     }
 
     pub fn snap_scheduler(&self) -> Scheduler<RegionTask> {
-        self.region_worker.scheduler()
+        self.region_scheduler.clone()
     }
 
     pub fn apply_scheduler(&self) -> Scheduler<ApplyTask> {
@@ -415,10 +649,18 @@ impl<T, C> Store<T, C> {
         self.raft_engine.clone()
     }
 
+    pub fn raft_log_engine(&self) -> Option<Arc<RaftLogEngine>> {
+        self.raft_log_engine.clone()
+    }
+
     pub fn store_id(&self) -> u64 {
         self.store.get_id()
     }
 
+    pub fn store_labels(&self) -> &[metapb::StoreLabel] {
+        self.store.get_labels()
+    }
+
     pub fn get_peers(&self) -> &HashMap<u64, Peer> {
         &self.region_peers
     }
@@ -427,23 +669,20 @@ impl<T, C> Store<T, C> {
         self.cfg.clone()
     }
 
+    pub fn pending_delete_ranges(&self) -> Arc<Mutex<PendingDeleteRanges>> {
+        self.poller.pending_delete_ranges.clone()
+    }
+
+    pub fn entry_cache_memory(&self) -> Arc<EntryCacheMemory> {
+        self.poller.entry_cache_memory.clone()
+    }
+
     fn poll_significant_msg(&mut self) {
         // Poll all snapshot messages and handle them.
         loop {
             match self.significant_msg_receiver.try_recv() {
-                Ok(SignificantMsg::SnapshotStatus {
-                    region_id,
-                    to_peer_id,
-                    status,
-                }) => {
-                    // Report snapshot status to the corresponding peer.
-                    self.report_snapshot_status(region_id, to_peer_id, status);
-                }
-                Ok(SignificantMsg::Unreachable {
-                    region_id,
-                    to_peer_id,
-                }) => if let Some(peer) = self.region_peers.get_mut(&region_id) {
-                    peer.raft_group.report_unreachable(to_peer_id);
+                Ok(msg) => if let Some(msg) = self.route_significant_msg(msg) {
+                    self.handle_significant_msg(msg);
                 },
                 Err(TryRecvError::Empty) => {
                     // The snapshot status receiver channel is empty
@@ -461,6 +700,49 @@ impl<T, C> Store<T, C> {
         }
     }
 
+    // Forwards the message to its owner, returns it back if it belongs to this poller.
+    fn route_significant_msg(&self, msg: SignificantMsg) -> Option<SignificantMsg> {
+        let region_id = match msg {
+            SignificantMsg::SnapshotStatus { region_id, .. } |
+            SignificantMsg::Unreachable { region_id, .. } => region_id,
+        };
+        let owner = self.poller.owner_of(region_id);
+        if owner == self.poller.poller_id {
+            return Some(msg);
+        }
+        match self.poller.significant_routers[owner] {
+            Some(ref ch) => if let Err(e) = ch.send(msg) {
+                error!("{} failed to forward significant msg: {:?}", self.tag, e);
+            },
+            None => error!(
+                "{} no route to poller {} for significant msg {:?}",
+                self.tag,
+                owner,
+                msg
+            ),
+        }
+        None
+    }
+
+    fn handle_significant_msg(&mut self, msg: SignificantMsg) {
+        match msg {
+            SignificantMsg::SnapshotStatus {
+                region_id,
+                to_peer_id,
+                status,
+            } => {
+                // Report snapshot status to the corresponding peer.
+                self.report_snapshot_status(region_id, to_peer_id, status);
+            }
+            SignificantMsg::Unreachable {
+                region_id,
+                to_peer_id,
+            } => if let Some(peer) = self.region_peers.get_mut(&region_id) {
+                peer.raft_group.report_unreachable(to_peer_id);
+            },
+        }
+    }
+
     fn report_snapshot_status(&mut self, region_id: u64, to_peer_id: u64, status: SnapshotStatus) {
         if let Some(peer) = self.region_peers.get_mut(&region_id) {
             let to_peer = match peer.get_peer_from_cache(to_peer_id) {
@@ -482,6 +764,17 @@ impl<T, C> Store<T, C> {
                 to_peer,
                 status
             );
+            match status {
+                SnapshotStatus::Failure => peer.get_store().drop_shared_snapshot(),
+                SnapshotStatus::Finish => {
+                    // The last sender deletes the snapshot, so it can't be shared anymore.
+                    if let Some(key) = peer.get_store().shared_snapshot_key() {
+                        if !self.snap_mgr.has_registered(&key) {
+                            peer.get_store().drop_shared_snapshot();
+                        }
+                    }
+                }
+            }
             peer.raft_group.report_snapshot(to_peer_id, status)
         }
     }
@@ -489,7 +782,10 @@ impl<T, C> Store<T, C> {
 
 impl<T: Transport, C: PdClient> Store<T, C> {
     pub fn run(&mut self, event_loop: &mut EventLoop<Self>) -> Result<()> {
-        self.snap_mgr.init()?;
+        // The snap manager is shared by all the pollers of the store.
+        if self.poller.poller_id == 0 {
+            self.snap_mgr.init()?;
+        }
 
         self.register_raft_base_tick(event_loop);
         self.register_raft_gc_log_tick(event_loop);
@@ -498,56 +794,85 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.register_pd_store_heartbeat_tick(event_loop);
         self.register_pd_heartbeat_tick(event_loop);
         self.register_snap_mgr_gc_tick(event_loop);
-        self.register_compact_lock_cf_tick(event_loop);
+        if self.poller.poller_id == 0 {
+            self.register_compact_lock_cf_tick(event_loop);
+        }
         self.register_consistency_check_tick(event_loop);
 
+        if self.cfg.async_write_raft_log {
+            let (tx, rx) = mpsc::channel();
+            let raftlog_write_runner =
+                RaftlogWriteRunner::new(self.raft_engine.clone(), self.raft_log_engine.clone(), tx);
+            self.raftlog_persisted_receiver = Some(rx);
+            box_try!(self.raftlog_write_worker.start(raftlog_write_runner));
+        }
+
+        if let Some(mut workers) = self.workers.take() {
+            self.start_store_workers(&mut workers)?;
+            self.workers = Some(workers);
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let apply_runner = ApplyRunner::new(self, tx, self.cfg.sync_log);
+        self.apply_res_receiver = Some(rx);
+        box_try!(self.apply_worker.start(apply_runner));
+
+        event_loop.run(self)?;
+        Ok(())
+    }
+
+    fn start_store_workers(&self, workers: &mut StoreWorkers) -> Result<()> {
+        // Results of the shared workers are sent to the owners of the regions.
+        let router = RouterSendCh::new(self.poller.router.clone(), "raftstore");
+
         let split_check_runner = SplitCheckRunner::new(
             self.kv_engine.clone(),
-            self.sendch.clone(),
+            router.clone(),
             self.coprocessor_host.clone(),
         );
-
-        box_try!(self.split_check_worker.start(split_check_runner));
+        box_try!(workers.split_check_worker.start(split_check_runner));
 
         let runner = RegionRunner::new(
             self.kv_engine.clone(),
             self.raft_engine.clone(),
+            self.raft_log_engine.clone(),
             self.snap_mgr.clone(),
             self.cfg.snap_apply_batch_size.0 as usize,
+            self.poller.pending_delete_ranges.clone(),
+            self.cfg.snap_generator_pool_size,
+            self.cfg.snap_apply_pool_size,
         );
-        box_try!(self.region_worker.start(runner));
+        box_try!(workers.region_worker.start(runner));
 
         let raftlog_gc_runner = RaftlogGcRunner::new(None);
-        box_try!(self.raftlog_gc_worker.start(raftlog_gc_runner));
+        box_try!(workers.raftlog_gc_worker.start(raftlog_gc_runner));
 
         let compact_runner = CompactRunner::new(self.kv_engine.clone());
-        box_try!(self.compact_worker.start(compact_runner));
+        box_try!(workers.compact_worker.start(compact_runner));
 
         let pd_runner = PdRunner::new(
-            self.store_id(),
+            self.store.get_id(),
             self.pd_client.clone(),
-            self.sendch.clone(),
+            router.clone(),
             self.kv_engine.clone(),
+            AutoSplitController::new(
+                self.cfg.region_split_qps_threshold,
+                self.cfg.region_split_qps_detect_times,
+            ),
         );
-        box_try!(self.pd_worker.start(pd_runner));
+        box_try!(workers.pd_worker.start(pd_runner));
 
-        let consistency_check_runner = ConsistencyCheckRunner::new(self.sendch.clone());
+        let consistency_check_runner = ConsistencyCheckRunner::new(router);
         box_try!(
-            self.consistency_check_worker
+            workers
+                .consistency_check_worker
                 .start(consistency_check_runner)
         );
-
-        let (tx, rx) = mpsc::channel();
-        let apply_runner = ApplyRunner::new(self, tx, self.cfg.sync_log);
-        self.apply_res_receiver = Some(rx);
-        box_try!(self.apply_worker.start(apply_runner));
-
-        event_loop.run(self)?;
         Ok(())
     }
 
     fn stop(&mut self) {
-        info!("start to stop raftstore.");
+        info!("{} start to stop raftstore.", self.tag);
 
         // Applying snapshot may take an unexpected long time.
         for peer in self.region_peers.values_mut() {
@@ -556,12 +881,15 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
         // Wait all workers finish.
         let mut handles: Vec<Option<thread::JoinHandle<()>>> = vec![];
-        handles.push(self.split_check_worker.stop());
-        handles.push(self.region_worker.stop());
-        handles.push(self.raftlog_gc_worker.stop());
-        handles.push(self.compact_worker.stop());
-        handles.push(self.pd_worker.stop());
-        handles.push(self.consistency_check_worker.stop());
+        if let Some(ref mut workers) = self.workers {
+            handles.push(workers.split_check_worker.stop());
+            handles.push(workers.region_worker.stop());
+            handles.push(workers.raftlog_gc_worker.stop());
+            handles.push(workers.compact_worker.stop());
+            handles.push(workers.pd_worker.stop());
+            handles.push(workers.consistency_check_worker.stop());
+        }
+        handles.push(self.raftlog_write_worker.stop());
         handles.push(self.apply_worker.stop());
 
         for h in handles {
@@ -570,9 +898,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             }
         }
 
-        self.coprocessor_host.shutdown();
+        if self.poller.poller_id == 0 {
+            self.coprocessor_host.shutdown();
+        }
 
-        info!("stop raftstore finished.");
+        info!("{} stop raftstore finished.", self.tag);
     }
 
     fn register_raft_base_tick(&self, event_loop: &mut EventLoop<Self>) {
@@ -589,7 +919,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
     fn on_raft_base_tick(&mut self, event_loop: &mut EventLoop<Self>) {
         let timer = self.raft_metrics.process_tick.start_coarse_timer();
+        let evict_entry_cache = self.poller.entry_cache_memory.is_exceeded();
         for peer in &mut self.region_peers.values_mut() {
+            if evict_entry_cache {
+                peer.mut_store().evict_entry_cache();
+            }
             if peer.pending_remove {
                 continue;
             }
@@ -633,7 +967,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                     peer: peer.peer.clone(),
                     region: peer.region().clone(),
                 };
-                if let Err(e) = self.pd_worker.schedule(task) {
+                if let Err(e) = self.pd_scheduler.schedule(task) {
                     error!("{} failed to notify pd: {}", peer.tag, e)
                 }
             }
@@ -645,6 +979,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
         self.raft_metrics.flush();
         self.entry_cache_metries.borrow_mut().flush();
+        RAFT_ENTRY_CACHE_SIZE_GAUGE.set(self.poller.entry_cache_memory.used() as f64);
 
         self.register_raft_base_tick(event_loop);
     }
@@ -731,16 +1066,15 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             return Ok(false);
         }
 
-        let start_key = data_key(msg.get_start_key());
-        if let Some((_, &exist_region_id)) = self.region_ranges
-            .range((Excluded(start_key), Unbounded::<Key>))
-            .next()
         {
-            let exist_region = self.region_peers[&exist_region_id].region();
-            if enc_start_key(exist_region) < data_end_key(msg.get_end_key()) {
+            let mut meta = self.poller.meta.lock().unwrap();
+            let start_key = data_key(msg.get_start_key());
+            let end_key = data_end_key(msg.get_end_key());
+            let exist_region = meta.overlapped_region(&start_key, &end_key).cloned();
+            if let Some(exist_region) = exist_region {
                 debug!("msg {:?} is overlapped with region {:?}", msg, exist_region);
                 if util::is_first_vote_msg(msg) {
-                    self.pending_votes.push(msg.to_owned());
+                    meta.pending_votes.push(msg.to_owned());
                 }
                 self.raft_metrics.message_dropped.region_overlap += 1;
                 return Ok(false);
@@ -882,7 +1216,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 // Maybe split, but not registered yet.
                 raft_metrics.message_dropped.region_nonexistent += 1;
                 if util::is_first_vote_msg(msg) {
-                    self.pending_votes.push(msg.to_owned());
+                    self.poller
+                        .meta
+                        .lock()
+                        .unwrap()
+                        .pending_votes
+                        .push(msg.to_owned());
                     info!(
                         "[region {}] doesn't exist yet, wait for it to be split",
                         region_id
@@ -1035,18 +1374,17 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             self.raft_metrics.message_dropped.region_no_peer += 1;
             return Ok(Some(key));
         }
-        if let Some((_, &exist_region_id)) = self.region_ranges
-            .range((Excluded(enc_start_key(&snap_region)), Unbounded::<Key>))
-            .next()
+        // Regions of other pollers may be changed concurrently, so the meta is
+        // locked until the snapshot region is recorded as pending.
+        let mut meta = self.poller.meta.lock().unwrap();
+        if let Some(exist_region) =
+            meta.overlapped_region(&enc_start_key(&snap_region), &enc_end_key(&snap_region))
         {
-            let exist_region = self.region_peers[&exist_region_id].region();
-            if enc_start_key(exist_region) < enc_end_key(&snap_region) {
-                info!("region overlapped {:?}, {:?}", exist_region, snap_region);
-                self.raft_metrics.message_dropped.region_overlap += 1;
-                return Ok(Some(key));
-            }
+            info!("region overlapped {:?}, {:?}", exist_region, snap_region);
+            self.raft_metrics.message_dropped.region_overlap += 1;
+            return Ok(Some(key));
         }
-        for region in &self.pending_snapshot_regions {
+        for &(_, ref region) in &meta.pending_snapshot_regions {
             if enc_start_key(region) < enc_end_key(&snap_region) &&
                enc_end_key(region) > enc_start_key(&snap_region) &&
                // Same region can overlap, we will apply the latest version of snapshot.
@@ -1060,7 +1398,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         // check if snapshot file exists.
         self.snap_mgr.get_snapshot_for_applying(&key)?;
 
-        self.pending_snapshot_regions.push(snap_region);
+        meta.pending_snapshot_regions
+            .push((self.poller.poller_id, snap_region));
 
         Ok(None)
     }
@@ -1073,17 +1412,24 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.raft_metrics.ready.pending_region += pending_count as u64;
 
         let mut region_proposals = Vec::with_capacity(pending_count);
-        let (kv_wb, raft_wb, append_res, sync_log) = {
+        let (kv_wb, raft_wb, raft_log_batch, append_res, sync_log) = {
             let mut ctx = ReadyContext::new(&mut self.raft_metrics, &self.trans, pending_count);
             for region_id in self.pending_raft_groups.drain() {
                 if let Some(peer) = self.region_peers.get_mut(&region_id) {
+                    peer.propose_batch_raft_command(&mut ctx.metrics.propose);
                     if let Some(region_proposal) = peer.take_apply_proposals() {
                         region_proposals.push(region_proposal);
                     }
-                    peer.handle_raft_ready_append(&mut ctx, &self.pd_worker);
+                    peer.handle_raft_ready_append(&mut ctx, &self.pd_scheduler);
                 }
             }
-            (ctx.kv_wb, ctx.raft_wb, ctx.ready_res, ctx.sync_log)
+            (
+                ctx.kv_wb,
+                ctx.raft_wb,
+                ctx.raft_log_batch,
+                ctx.ready_res,
+                ctx.sync_log,
+            )
         };
 
         if !region_proposals.is_empty() {
@@ -1115,6 +1461,56 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         }
         fail_point!("raft_between_save");
 
+        let ready_count = append_res.len();
+        let persisted_res = if self.cfg.async_write_raft_log {
+            self.schedule_raft_log_write(raft_wb, raft_log_batch, sync_log, append_res);
+            None
+        } else {
+            self.write_raft_log(raft_wb, raft_log_batch, sync_log);
+            Some(append_res)
+        };
+
+        self.raft_metrics
+            .append_log
+            .observe(duration_to_sec(t.elapsed()) as f64);
+
+        slow_log!(
+            t,
+            "{} handle {} pending peers include {} ready, {} entries, {} messages and {} \
+             snapshots",
+            self.tag,
+            pending_count,
+            ready_count,
+            self.raft_metrics.ready.append - previous_ready_metrics.append,
+            self.raft_metrics.ready.message - previous_ready_metrics.message,
+            self.raft_metrics.ready.snapshot - previous_ready_metrics.snapshot
+        );
+
+        if let Some(append_res) = persisted_res {
+            self.post_raft_ready(append_res);
+        }
+
+        let dur = t.elapsed();
+        if !self.is_busy {
+            let election_timeout = Duration::from_millis(
+                self.cfg.raft_base_tick_interval.as_millis() *
+                    self.cfg.raft_election_timeout_ticks as u64,
+            );
+            if dur >= election_timeout {
+                self.is_busy = true;
+            }
+        }
+
+        self.raft_metrics
+            .process_ready
+            .observe(duration_to_sec(dur) as f64);
+
+        self.trans.flush();
+
+        slow_log!(t, "{} on {} regions raft ready", self.tag, pending_count);
+    }
+
+    fn write_raft_log(&self, raft_wb: WriteBatch, raft_log_batch: LogBatch, sync_log: bool) {
         if !raft_wb.is_empty() {
             // RaftLocalState, Raft Log Entry
             let mut write_opts = WriteOptions::new();
@@ -1125,8 +1521,48 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                     panic!("{} failed to save raft append result: {:?}", self.tag, e);
                 });
         }
+        if let Some(ref engine) = self.raft_log_engine {
+            // RaftLocalState, Raft Log Entry
+            engine
+                .write(raft_log_batch, self.cfg.sync_log || sync_log)
+                .unwrap_or_else(|e| {
+                    panic!("{} failed to save raft append result: {:?}", self.tag, e);
+                });
+        }
         fail_point!("raft_after_save");
+    }
 
+    // Schedules the raft logs to the write worker, messages to the followers are
+    // sent already, the remaining work of the readies is done after the logs are
+    // persisted.
+    fn schedule_raft_log_write(
+        &mut self,
+        raft_wb: WriteBatch,
+        raft_log_batch: LogBatch,
+        sync_log: bool,
+        append_res: Vec<(Ready, InvokeContext)>,
+    ) {
+        if append_res.is_empty() {
+            return;
+        }
+        let mut peers = Vec::with_capacity(append_res.len());
+        for (ready, invoke_ctx) in append_res {
+            let region_id = invoke_ctx.region_id;
+            let peer = self.region_peers.get_mut(&region_id).unwrap();
+            peers.push((region_id, peer.peer_id()));
+            peer.unpersisted_ready = Some((ready, invoke_ctx));
+        }
+        let task = RaftlogWriteTask {
+            raft_wb: raft_wb,
+            raft_log_batch: raft_log_batch,
+            sync: self.cfg.sync_log || sync_log,
+            peers: peers,
+        };
+        self.raftlog_write_worker.schedule(task).unwrap();
+    }
+
+    // Handles the readies whose raft logs are persisted.
+    fn post_raft_ready(&mut self, append_res: Vec<(Ready, InvokeContext)>) {
         let mut ready_results = Vec::with_capacity(append_res.len());
         for (mut ready, invoke_ctx) in append_res {
             let region_id = invoke_ctx.region_id;
@@ -1143,22 +1579,6 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             ready_results.push((region_id, ready, res));
         }
 
-        self.raft_metrics
-            .append_log
-            .observe(duration_to_sec(t.elapsed()) as f64);
-
-        slow_log!(
-            t,
-            "{} handle {} pending peers include {} ready, {} entries, {} messages and {} \
-             snapshots",
-            self.tag,
-            pending_count,
-            ready_results.capacity(),
-            self.raft_metrics.ready.append - previous_ready_metrics.append,
-            self.raft_metrics.ready.message - previous_ready_metrics.message,
-            self.raft_metrics.ready.snapshot - previous_ready_metrics.snapshot
-        );
-
         let mut apply_tasks = Vec::with_capacity(ready_results.len());
         for (region_id, ready, res) in ready_results {
             self.region_peers
@@ -1172,25 +1592,62 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.apply_worker
             .schedule(ApplyTask::applies(apply_tasks))
             .unwrap();
+    }
 
-        let dur = t.elapsed();
-        if !self.is_busy {
-            let election_timeout = Duration::from_millis(
-                self.cfg.raft_base_tick_interval.as_millis() *
-                    self.cfg.raft_election_timeout_ticks as u64,
-            );
-            if dur >= election_timeout {
-                self.is_busy = true;
+    fn on_raft_log_persisted(&mut self, peers: Vec<(u64, u64)>) {
+        let t = SlowTimer::new();
+        let mut append_res = Vec::with_capacity(peers.len());
+        for (region_id, peer_id) in peers {
+            // The peer may be destroyed, and even be created again, after the logs
+            // are written.
+            if let Some(peer) = self.region_peers.get_mut(&region_id) {
+                if peer.peer_id() != peer_id {
+                    continue;
+                }
+                if let Some(res) = peer.unpersisted_ready.take() {
+                    append_res.push(res);
+                    // New readies may be skipped while the logs are being written.
+                    self.pending_raft_groups.insert(region_id);
+                }
             }
         }
-
-        self.raft_metrics
-            .process_ready
-            .observe(duration_to_sec(dur) as f64);
-
+        let count = append_res.len();
+        self.post_raft_ready(append_res);
         self.trans.flush();
+        slow_log!(t, "{} on {} regions raft logs persisted", self.tag, count);
+    }
 
-        slow_log!(t, "{} on {} regions raft ready", self.tag, pending_count);
+    fn poll_raft_log_persisted(&mut self) {
+        let mut peers = mem::replace(&mut self.persisted_peers, vec![]);
+        if let Some(ref rx) = self.raftlog_persisted_receiver {
+            loop {
+                match rx.try_recv() {
+                    Ok(persisted) => peers.extend(persisted),
+                    Err(TryRecvError::Empty) => break,
+                    Err(e) => panic!("unexpected error {:?}", e),
+                }
+            }
+        }
+        if !peers.is_empty() {
+            self.on_raft_log_persisted(peers);
+        }
+    }
+
+    // Waits until the raft logs of the peer scheduled to the write worker are
+    // persisted. Other peers received meanwhile are kept for the next poll.
+    fn wait_raft_log_persisted(&mut self, region_id: u64, peer_id: u64) {
+        if self.persisted_peers.contains(&(region_id, peer_id)) {
+            return;
+        }
+        let rx = self.raftlog_persisted_receiver.as_ref().unwrap();
+        loop {
+            let persisted = rx.recv().unwrap();
+            let found = persisted.contains(&(region_id, peer_id));
+            self.persisted_peers.extend(persisted);
+            if found {
+                return;
+            }
+        }
     }
 
     fn handle_destroy_peer(&mut self, job: DestroyPeerJob) -> bool {
@@ -1236,10 +1693,14 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         info!("[region {}] destroy peer {:?}", region_id, peer);
         // We can't destroy a peer which is applying snapshot.
         assert!(!p.is_applying_snapshot());
+        if p.unpersisted_ready.take().is_some() {
+            // Otherwise the raft logs may be written after the peer is destroyed.
+            self.wait_raft_log_persisted(region_id, peer.get_id());
+        }
         let task = PdTask::DestroyPeer {
             region_id: region_id,
         };
-        if let Err(e) = self.pd_worker.schedule(task) {
+        if let Err(e) = self.pd_scheduler.schedule(task) {
             error!("{} failed to notify pd: {}", self.tag, e);
         }
         let is_initialized = p.is_initialized();
@@ -1257,18 +1718,20 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             );
         }
 
-        if is_initialized &&
-            self.region_ranges
+        if is_initialized {
+            let mut meta = self.poller.meta.lock().unwrap();
+            if meta.region_ranges
                 .remove(&enc_end_key(p.region()))
                 .is_none()
-        {
-            panic!(
-                "[region {}] remove peer {:?} in store {}",
-                region_id,
-                peer,
-                self.store_id()
-            );
-
+            {
+                panic!(
+                    "[region {}] remove peer {:?} in store {}",
+                    region_id,
+                    peer,
+                    self.store.get_id()
+                );
+            }
+            meta.regions.remove(&region_id);
         }
     }
 
@@ -1282,6 +1745,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 return;
             }
             p.mut_store().region = cp.region;
+            self.poller.meta.lock().unwrap().set_region(p.region());
             if p.is_leader() {
                 // Notify pd immediately.
                 info!(
@@ -1289,7 +1753,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                     p.tag,
                     p.region()
                 );
-                p.heartbeat_pd(&self.pd_worker);
+                p.heartbeat_pd(&self.pd_scheduler);
             }
 
             match change_type {
@@ -1337,13 +1801,14 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         peer.raft_log_size_hint = peer.raft_log_size_hint * remain_cnt / total_cnt;
         let task = RaftlogGcTask {
             raft_engine: peer.get_store().get_raft_engine().clone(),
+            raft_log_engine: peer.get_store().get_raft_log_engine(),
             region_id: peer.get_store().get_region_id(),
             start_idx: peer.last_compacted_idx,
             end_idx: state.get_index() + 1,
         };
         peer.last_compacted_idx = task.end_idx;
         peer.mut_store().compact_to(task.end_idx);
-        if let Err(e) = self.raftlog_gc_worker.schedule(task) {
+        if let Err(e) = self.raftlog_gc_scheduler.schedule(task) {
             error!(
                 "[region {}] failed to schedule compact task: {}",
                 region_id,
@@ -1365,11 +1830,90 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             (left.clone(), right.clone())
         };
 
-        self.region_peers
-            .get_mut(&region_id)
-            .unwrap()
-            .mut_store()
-            .region = origin_region.clone();
+        let (is_leader, peer_stat) = {
+            let origin_peer = self.region_peers.get_mut(&region_id).unwrap();
+            origin_peer.mut_store().region = origin_region;
+            // To prevent from big region, the right region need run split
+            // check again after split.
+            if right_derive {
+                origin_peer.size_diff_hint = self.cfg.region_split_check_diff.0;
+            }
+            (origin_peer.is_leader(), origin_peer.peer_stat.clone())
+        };
+
+        // Insert new regions and validation. The new region is registered here
+        // even if it belongs to another poller, so no overlapped region can be
+        // created before the new peer is created.
+        info!("insert new regions left: {:?}, right:{:?}", left, right);
+        {
+            let mut meta = self.poller.meta.lock().unwrap();
+            if meta.region_ranges
+                .insert(enc_end_key(&left), left.get_id())
+                .is_some()
+            {
+                panic!("region should not exist, {:?}", left);
+            }
+            if meta.region_ranges
+                .insert(enc_end_key(&right), right.get_id())
+                .is_none()
+            {
+                panic!("region should exist, {:?}", right);
+            }
+            meta.set_region(&left);
+            meta.set_region(&right);
+        }
+
+        if is_leader {
+            // Notify pd immediately to let it update the region meta.
+            self.region_peers[&region_id].heartbeat_pd(&self.pd_scheduler);
+            self.report_split_pd(&left, &right);
+        }
+
+        let new_region_id = new_region.get_id();
+        if self.poller.is_owner(new_region_id) {
+            self.on_split_region_created(new_region, is_leader, peer_stat, right_derive);
+            return;
+        }
+        let owner = self.poller.owner_of(new_region_id);
+        let msg = SplitRegionCreated {
+            region: new_region,
+            parent_is_leader: is_leader,
+            peer_stat: peer_stat,
+            right_derive: right_derive,
+        };
+        if self.poller.split_routers[owner].send(msg).is_err() {
+            // The owner is only gone when the store is stopping.
+            warn!(
+                "{} poller {} is stopped, skip creating split region {}",
+                self.tag,
+                owner,
+                new_region_id
+            );
+        }
+    }
+
+    fn poll_split_region_created(&mut self) {
+        loop {
+            match self.split_region_receiver.try_recv() {
+                Ok(msg) => self.on_split_region_created(
+                    msg.region,
+                    msg.parent_is_leader,
+                    msg.peer_stat,
+                    msg.right_derive,
+                ),
+                Err(TryRecvError::Empty) => break,
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        }
+    }
+
+    fn on_split_region_created(
+        &mut self,
+        new_region: metapb::Region,
+        parent_is_leader: bool,
+        peer_stat: PeerStat,
+        right_derive: bool,
+    ) {
         let new_region_id = new_region.get_id();
         if let Some(peer) = self.region_peers.get(&new_region_id) {
             // If the store received a raft msg with the new region raft group
@@ -1380,99 +1924,59 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             }
         }
 
-        let mut campaigned = false;
-        let peer;
-        match Peer::create(self, &new_region) {
+        let mut new_peer = match Peer::create(self, &new_region) {
             Err(e) => {
                 // peer information is already written into db, can't recover.
                 // there is probably a bug.
                 panic!("create new split region {:?} err {:?}", new_region, e);
             }
-            Ok(mut new_peer) => {
-                for peer in new_region.get_peers() {
-                    // Add this peer to cache.
-                    new_peer.insert_peer_cache(peer.clone());
-                }
-                peer = new_peer.peer.clone();
-                if let Some(origin_peer) = self.region_peers.get(&region_id) {
-                    // New peer derive write flow from parent region,
-                    // this will be used by balance write flow.
-                    new_peer.peer_stat = origin_peer.peer_stat.clone();
-
-                    campaigned =
-                        new_peer.maybe_campaign(origin_peer, &mut self.pending_raft_groups);
-
-                    if origin_peer.is_leader() {
-                        // Notify pd immediately to let it update the region meta.
-                        if right_derive {
-                            self.report_split_pd(&new_peer, origin_peer);
-                        } else {
-                            self.report_split_pd(origin_peer, &new_peer);
-                        }
-                    }
-                }
-
-                // Insert new regions and validation
-                info!("insert new regions left: {:?}, right:{:?}", left, right);
-                if self.region_ranges
-                    .insert(enc_end_key(&left), left.get_id())
-                    .is_some()
-                {
-                    panic!("region should not exist, {:?}", left);
-                }
-                if self.region_ranges
-                    .insert(enc_end_key(&right), right.get_id())
-                    .is_none()
-                {
-                    panic!("region should exist, {:?}", right);
-                }
+            Ok(new_peer) => new_peer,
+        };
+        for peer in new_region.get_peers() {
+            // Add this peer to cache.
+            new_peer.insert_peer_cache(peer.clone());
+        }
+        let peer = new_peer.peer.clone();
 
-                // To prevent from big region, the right region need run split
-                // check again after split.
-                if right_derive {
-                    self.region_peers
-                        .get_mut(&region_id)
-                        .unwrap()
-                        .size_diff_hint = self.cfg.region_split_check_diff.0;
-                } else {
-                    new_peer.size_diff_hint = self.cfg.region_split_check_diff.0;
-                }
-                self.apply_worker
-                    .schedule(ApplyTask::register(&new_peer))
-                    .unwrap();
-                self.region_peers.insert(new_region_id, new_peer);
-            }
+        // New peer derive write flow from parent region,
+        // this will be used by balance write flow.
+        new_peer.peer_stat = peer_stat;
+        let campaigned = new_peer.maybe_campaign(parent_is_leader, &mut self.pending_raft_groups);
+        if parent_is_leader {
+            new_peer.heartbeat_pd(&self.pd_scheduler);
         }
+        if !right_derive {
+            new_peer.size_diff_hint = self.cfg.region_split_check_diff.0;
+        }
+        self.apply_worker
+            .schedule(ApplyTask::register(&new_peer))
+            .unwrap();
+        self.region_peers.insert(new_region_id, new_peer);
 
         if !campaigned {
-            if let Some(msg) = self.pending_votes
-                .swap_remove_front(|m| m.get_to_peer() == &peer)
-            {
+            let msg = self.poller
+                .meta
+                .lock()
+                .unwrap()
+                .pending_votes
+                .swap_remove_front(|m| m.get_to_peer() == &peer);
+            if let Some(msg) = msg {
                 let _ = self.on_raft_message(msg);
             }
         }
     }
 
-    fn report_split_pd(&self, left: &Peer, right: &Peer) {
-        let left_region = left.region();
-        let right_region = right.region();
-
-        info!(
-            "notify pd with split left {:?}, right {:?}",
-            left_region,
-            right_region
-        );
-        right.heartbeat_pd(&self.pd_worker);
-        left.heartbeat_pd(&self.pd_worker);
+    fn report_split_pd(&self, left: &metapb::Region, right: &metapb::Region) {
+        info!("notify pd with split left {:?}, right {:?}", left, right);
 
         // Now pd only uses ReportSplit for history operation show,
         // so we send it independently here.
         let task = PdTask::ReportSplit {
-            left: left_region.clone(),
-            right: right_region.clone(),
+            left: left.clone(),
+            right: right.clone(),
         };
 
-        if let Err(e) = self.pd_worker.schedule(task) {
+        if let Err(e) = self.pd_scheduler.schedule(task) {
             error!("{} failed to notify pd: {}", self.tag, e);
         }
     }
@@ -1495,8 +1999,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 prev_region,
                 region
             );
+        }
+
+        let mut meta = self.poller.meta.lock().unwrap();
+        if !prev_region.get_peers().is_empty() {
             // we have already initialized the peer, so it must exist in region_ranges.
-            if self.region_ranges
+            if meta.region_ranges
                 .remove(&enc_end_key(&prev_region))
                 .is_none()
             {
@@ -1508,8 +2016,9 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             }
         }
 
-        self.region_ranges
+        meta.region_ranges
             .insert(enc_end_key(&region), region.get_id());
+        meta.set_region(&region);
     }
 
     fn on_ready_result(&mut self, region_id: u64, exec_results: Vec<ExecResult>) {
@@ -1567,6 +2076,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             _ => (),
         }
 
+        if self.poller.disk_full.load(Ordering::Relaxed) && !util::is_allowed_on_disk_full(&msg) {
+            cb.call_box((new_error(Error::DiskFull(self.store_id())),));
+            return;
+        }
+
         // Note:
         // The peer that is being checked is a leader. It might step down to be a follower later. It
         // doesn't matter whether the peer is a leader or not. If it's not a leader, the proposing
@@ -1657,8 +2171,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             // updated.
             let sibling_region_id = self.find_sibling_region(peer.region());
             if let Some(sibling_region_id) = sibling_region_id {
-                let sibling_region = self.region_peers[&sibling_region_id].region();
-                new_regions.push(sibling_region.to_owned());
+                // The sibling region may be owned by another poller.
+                let meta = self.poller.meta.lock().unwrap();
+                if let Some(sibling_region) = meta.regions.get(&sibling_region_id) {
+                    new_regions.push(sibling_region.to_owned());
+                }
             }
             return Err(Error::StaleEpoch(msg, new_regions));
         }
@@ -1671,7 +2188,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         } else {
             Excluded(enc_end_key(region))
         };
-        self.region_ranges
+        self.poller
+            .meta
+            .lock()
+            .unwrap()
+            .region_ranges
             .range((start, Unbounded::<Key>))
             .next()
             .map(|(_, &region_id)| region_id)
@@ -1695,7 +2216,10 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         let mut total_gc_logs = 0;
 
         for (&region_id, peer) in &mut self.region_peers {
+            let applied_idx = peer.get_store().applied_index();
             if !peer.is_leader() {
+                // Followers only read the cached entries to apply them.
+                peer.mut_store().compact_to(applied_idx + 1);
                 continue;
             }
 
@@ -1729,7 +2253,10 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 );
                 REGION_MAX_LOG_LAG.observe((last_idx - replicated_idx) as f64);
             }
-            let applied_idx = peer.get_store().applied_index();
+            // The entries replicated to all the followers and applied are unlikely to
+            // be fetched again.
+            peer.mut_store()
+                .compact_to(cmp::min(replicated_idx, applied_idx) + 1);
             let first_idx = peer.get_store().first_index();
             let mut compact_idx;
             if applied_idx > first_idx &&
@@ -1788,7 +2315,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         // To avoid frequent scan, we only add new scan tasks if all previous tasks
         // have finished.
         // TODO: check whether a gc progress has been started.
-        if self.split_check_worker.is_busy() {
+        if self.split_check_scheduler.is_busy() {
             self.register_split_region_check_tick(event_loop);
             return;
         }
@@ -1800,13 +2327,13 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             // split check will first check the region size, and then
             // check whether the region should split.  This should
             // work even if we change the region max size.
-            if peer.approximate_size.is_some() &&
+            if peer.approximate_size.is_some() && peer.approximate_keys.is_some() &&
                 peer.size_diff_hint < self.cfg.region_split_check_diff.0
             {
                 continue;
             }
             let task = SplitCheckTask::new(peer.region());
-            if let Err(e) = self.split_check_worker.schedule(task) {
+            if let Err(e) = self.split_check_scheduler.schedule(task) {
                 error!("{} failed to schedule split check: {}", self.tag, e);
             }
             peer.size_diff_hint = 0;
@@ -1836,7 +2363,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                     start_key: Some(keys::enc_start_key(peer.region())),
                     end_key: Some(keys::enc_end_key(peer.region())),
                 };
-                if let Err(e) = self.compact_worker.schedule(task) {
+                if let Err(e) = self.compact_scheduler.schedule(task) {
                     error!("{} failed to schedule compact task: {}", self.tag, e);
                 }
             }
@@ -1867,7 +2394,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             right_derive: self.cfg.right_derive_when_split,
             callback: cb,
         };
-        if let Err(Stopped(t)) = self.pd_worker.schedule(task) {
+        if let Err(Stopped(t)) = self.pd_scheduler.schedule(task) {
             error!("{} failed to notify pd to split: Stopped", peer.tag);
             match t {
                 PdTask::AskSplit { callback, .. } => {
@@ -1951,6 +2478,21 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         peer.approximate_size = Some(region_size);
     }
 
+    fn on_approximate_region_keys(&mut self, region_id: u64, region_keys: u64) {
+        let peer = match self.region_peers.get_mut(&region_id) {
+            Some(peer) => peer,
+            None => {
+                warn!(
+                    "[region {}] receive stale approximate keys {}",
+                    region_id,
+                    region_keys,
+                );
+                return;
+            }
+        };
+        peer.approximate_keys = Some(region_keys);
+    }
+
     fn on_pd_heartbeat_tick(&mut self, event_loop: &mut EventLoop<Self>) {
         for peer in self.region_peers.values_mut() {
             peer.check_peers();
@@ -1959,16 +2501,37 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         for peer in self.region_peers.values() {
             if peer.is_leader() {
                 leader_count += 1;
-                peer.heartbeat_pd(&self.pd_worker);
+                peer.heartbeat_pd(&self.pd_scheduler);
             }
         }
-        STORE_PD_HEARTBEAT_GAUGE_VEC
-            .with_label_values(&["leader"])
+        let poller_label = self.poller.poller_id.to_string();
+        STORE_POLLER_REGION_GAUGE_VEC
+            .with_label_values(&[&poller_label, "leader"])
             .set(leader_count as f64);
-        STORE_PD_HEARTBEAT_GAUGE_VEC
-            .with_label_values(&["region"])
+        STORE_POLLER_REGION_GAUGE_VEC
+            .with_label_values(&[&poller_label, "region"])
             .set(self.region_peers.len() as f64);
 
+        let (total_leader_count, total_region_count) = {
+            let mut meta = self.poller.meta.lock().unwrap();
+            {
+                let stat = &mut meta.poller_stats[self.poller.poller_id];
+                stat.leader_count = leader_count;
+                stat.region_count = self.region_peers.len();
+            }
+            meta.poller_stats.iter().fold((0, 0), |(l, r), s| {
+                (l + s.leader_count, r + s.region_count)
+            })
+        };
+        if self.poller.poller_id == 0 {
+            STORE_PD_HEARTBEAT_GAUGE_VEC
+                .with_label_values(&["leader"])
+                .set(total_leader_count as f64);
+            STORE_PD_HEARTBEAT_GAUGE_VEC
+                .with_label_values(&["region"])
+                .set(total_region_count as f64);
+        }
+
         self.register_pd_heartbeat_tick(event_loop);
     }
 
@@ -1983,13 +2546,47 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         };
     }
 
+    // Folds the statistics of this poller into the store meta, so the first
+    // poller can report them to pd on behalf of the whole store.
+    fn flush_poller_stat(&mut self) {
+        let mut apply_snapshot_count = 0;
+        for peer in self.region_peers.values_mut() {
+            if peer.mut_store().check_applying_snap() {
+                apply_snapshot_count += 1;
+            }
+        }
+
+        let bytes_written = self.store_stat.engine_total_bytes_written -
+            self.store_stat.engine_last_total_bytes_written;
+        let keys_written = self.store_stat.engine_total_keys_written -
+            self.store_stat.engine_last_total_keys_written;
+        self.store_stat.engine_last_total_bytes_written =
+            self.store_stat.engine_total_bytes_written;
+        self.store_stat.engine_last_total_keys_written = self.store_stat.engine_total_keys_written;
+
+        let mut meta = self.poller.meta.lock().unwrap();
+        let stat = &mut meta.poller_stats[self.poller.poller_id];
+        stat.region_count = self.region_peers.len();
+        stat.applying_snap_count = apply_snapshot_count;
+        stat.lock_cf_bytes_written += self.store_stat.lock_cf_bytes_written;
+        self.store_stat.lock_cf_bytes_written = 0;
+        stat.engine_bytes_written += bytes_written;
+        stat.engine_keys_written += keys_written;
+        stat.is_busy |= self.is_busy;
+        self.is_busy = false;
+    }
+
     fn store_heartbeat_pd(&mut self) {
+        self.flush_poller_stat();
+        if self.poller.poller_id != 0 {
+            return;
+        }
+
         let mut stats = StoreStats::new();
 
         let used_size = self.snap_mgr.get_total_snap_size();
         stats.set_used_size(used_size);
         stats.set_store_id(self.store_id());
-        stats.set_region_count(self.region_peers.len() as u32);
 
         let snap_stats = self.snap_mgr.stats();
         stats.set_sending_snap_count(snap_stats.sending_count as u32);
@@ -2001,13 +2598,24 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             .with_label_values(&["receiving"])
             .set(snap_stats.receiving_count as f64);
 
-        let mut apply_snapshot_count = 0;
-        for peer in self.region_peers.values_mut() {
-            if peer.mut_store().check_applying_snap() {
-                apply_snapshot_count += 1;
+        let (mut region_count, mut apply_snapshot_count) = (0, 0);
+        let (mut bytes_written, mut keys_written, mut is_busy) = (0, 0, false);
+        {
+            let mut meta = self.poller.meta.lock().unwrap();
+            for stat in &mut meta.poller_stats {
+                region_count += stat.region_count;
+                apply_snapshot_count += stat.applying_snap_count;
+                // report store write flow to pd
+                bytes_written += stat.engine_bytes_written;
+                keys_written += stat.engine_keys_written;
+                is_busy |= stat.is_busy;
+                stat.engine_bytes_written = 0;
+                stat.engine_keys_written = 0;
+                stat.is_busy = false;
             }
         }
 
+        stats.set_region_count(region_count as u32);
         stats.set_applying_snap_count(apply_snapshot_count as u32);
         STORE_SNAPSHOT_TRAFFIC_GAUGE_VEC
             .with_label_values(&["applying"])
@@ -2015,21 +2623,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
         stats.set_start_time(self.start_time.sec as u32);
 
-        // report store write flow to pd
-        stats.set_bytes_written(
-            self.store_stat.engine_total_bytes_written -
-                self.store_stat.engine_last_total_bytes_written,
-        );
-        stats.set_keys_written(
-            self.store_stat.engine_total_keys_written -
-                self.store_stat.engine_last_total_keys_written,
-        );
-        self.store_stat.engine_last_total_bytes_written =
-            self.store_stat.engine_total_bytes_written;
-        self.store_stat.engine_last_total_keys_written = self.store_stat.engine_total_keys_written;
-
-        stats.set_is_busy(self.is_busy);
-        self.is_busy = false;
+        stats.set_bytes_written(bytes_written);
+        stats.set_keys_written(keys_written);
+        // PD avoids scheduling regions to busy stores, which is what we want when
+        // the disk is full.
+        let disk_full = self.check_disk_full(used_size);
+        stats.set_is_busy(is_busy || disk_full);
 
         let store_info = StoreInfo {
             engine: self.kv_engine.clone(),
@@ -2040,11 +2639,50 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             stats: stats,
             store_info: store_info,
         };
-        if let Err(e) = self.pd_worker.schedule(task) {
+        if let Err(e) = self.pd_scheduler.schedule(task) {
             error!("{} failed to notify pd: {}", self.tag, e);
         }
     }
 
+    // Updates whether the disk is full, `extra_used` is the size used by snapshots.
+    fn check_disk_full(&mut self, extra_used: u64) -> bool {
+        if self.cfg.min_available_ratio == 0.0 {
+            return false;
+        }
+        let stats = match rocksdb::get_disk_stats(
+            self.kv_engine.clone(),
+            self.cfg.capacity.0,
+            extra_used,
+        ) {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!("{} failed to get disk stats: {:?}", self.tag, e);
+                return self.poller.disk_full.load(Ordering::Relaxed);
+            }
+        };
+        let disk_full =
+            (stats.available as f64) < stats.capacity as f64 * self.cfg.min_available_ratio;
+        if self.poller.disk_full.swap(disk_full, Ordering::Relaxed) != disk_full {
+            if disk_full {
+                warn!(
+                    "{} disk is full, available {} of capacity {}, only deletes, commits, \
+                     rollbacks and admin commands are accepted",
+                    self.tag,
+                    stats.available,
+                    stats.capacity
+                );
+            } else {
+                info!(
+                    "{} disk is available again, available {} of capacity {}",
+                    self.tag,
+                    stats.available,
+                    stats.capacity
+                );
+            }
+        }
+        disk_full
+    }
+
     fn on_pd_store_heartbeat_tick(&mut self, event_loop: &mut EventLoop<Self>) {
         self.store_heartbeat_pd();
         self.register_pd_store_heartbeat_tick(event_loop);
@@ -2056,8 +2694,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             return Ok(());
         }
         let (mut last_region_id, mut compacted_idx, mut compacted_term) = (0, u64::MAX, u64::MAX);
-        let mut is_applying_snap = false;
+        let (mut is_applying_snap, mut region_exists) = (false, false);
         for (key, is_sending) in snap_keys {
+            if !self.poller.is_owner(key.region_id) {
+                // Leave it to the poller which owns the region.
+                continue;
+            }
             if last_region_id != key.region_id {
                 last_region_id = key.region_id;
                 match self.region_peers.get(&key.region_id) {
@@ -2066,12 +2708,14 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                         compacted_idx = u64::MAX;
                         compacted_term = u64::MAX;
                         is_applying_snap = false;
+                        region_exists = false;
                     }
                     Some(peer) => {
                         let s = peer.get_store();
                         compacted_idx = s.truncated_index();
                         compacted_term = s.truncated_term();
                         is_applying_snap = s.is_applying_snapshot();
+                        region_exists = true;
                     }
                 };
             }
@@ -2098,6 +2742,26 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                         }
                     }
                 }
+            } else if let Some((s, modified)) = self.snap_mgr.get_partial_received_snapshot(&key)? {
+                // Partially received snapshots are kept for resuming, delete them if
+                // they are stale or haven't been resumed for a long time. The peer
+                // may not be created before the snapshot is received, so they are
+                // kept for peers not existing until expired. Those being received
+                // are skipped by `list_idle_snap` and `delete_snapshot`.
+                let expired = modified
+                    .elapsed()
+                    .map(|elapsed| elapsed > self.cfg.snap_gc_timeout.0)
+                    .unwrap_or(false);
+                let stale = region_exists &&
+                    (key.term < compacted_term || key.idx <= compacted_idx);
+                if stale || expired {
+                    info!(
+                        "[region {}] partially received snap file {} is stale, delete.",
+                        key.region_id,
+                        key
+                    );
+                    self.snap_mgr.delete_snapshot(&key, s.as_ref(), false);
+                }
             } else if key.term <= compacted_term &&
                 (key.idx < compacted_idx || key.idx == compacted_idx && !is_applying_snap)
             {
@@ -2117,19 +2781,35 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         if let Err(e) = self.handle_snap_mgr_gc() {
             error!("{} failed to gc snap manager: {:?}", self.tag, e);
         }
+        if let Err(e) = self.region_scheduler.schedule(RegionTask::CleanStaleRanges) {
+            error!("{} failed to schedule clean stale ranges: {:?}", self.tag, e);
+        }
         self.register_snap_mgr_gc_tick(event_loop);
     }
 
     fn on_compact_lock_cf(&mut self, event_loop: &mut EventLoop<Self>) {
         // Create a compact lock cf task(compact whole range) and schedule directly.
-        if self.store_stat.lock_cf_bytes_written > self.cfg.lock_cf_compact_bytes_threshold.0 {
+        // Only the first poller registers this tick, so the bytes written by all
+        // the pollers are counted.
+        let lock_cf_bytes_written = {
+            let meta = self.poller.meta.lock().unwrap();
+            self.store_stat.lock_cf_bytes_written +
+                meta.poller_stats
+                    .iter()
+                    .map(|s| s.lock_cf_bytes_written)
+                    .sum::<u64>()
+        };
+        if lock_cf_bytes_written > self.cfg.lock_cf_compact_bytes_threshold.0 {
             self.store_stat.lock_cf_bytes_written = 0;
+            for stat in &mut self.poller.meta.lock().unwrap().poller_stats {
+                stat.lock_cf_bytes_written = 0;
+            }
             let task = CompactTask {
                 cf_name: String::from(CF_LOCK),
                 start_key: None,
                 end_key: None,
             };
-            if let Err(e) = self.compact_worker.schedule(task) {
+            if let Err(e) = self.compact_scheduler.schedule(task) {
                 error!(
                     "{} failed to schedule compact lock cf task: {:?}",
                     self.tag,
@@ -2259,7 +2939,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
     }
 
     fn on_consistency_check_tick(&mut self, event_loop: &mut EventLoop<Self>) {
-        if self.consistency_check_worker.is_busy() {
+        if self.consistency_check_scheduler.is_busy() {
             // To avoid frequent scan, schedule new check only when all the
             // scheduled check is done.
             self.register_consistency_check_tick(event_loop);
@@ -2295,14 +2975,16 @@ impl<T: Transport, C: PdClient> Store<T, C> {
 
     fn on_ready_compute_hash(&mut self, region: metapb::Region, index: u64, snap: EngineSnapshot) {
         let region_id = region.get_id();
-        self.region_peers
-            .get_mut(&region_id)
-            .unwrap()
-            .consistency_state
-            .last_check_time = Instant::now();
+        let peer = self.region_peers.get_mut(&region_id).unwrap();
+        peer.consistency_state.last_check_time = Instant::now();
+        if peer.is_witness() {
+            // A witness stores no data, so it's not checked.
+            debug!("{} skip computing hash at {} for witness", peer.tag, index);
+            return;
+        }
         let task = ConsistencyCheckTask::compute_hash(region, index, snap);
         info!("[region {}] schedule {}", region_id, task);
-        if let Err(e) = self.consistency_check_worker.schedule(task) {
+        if let Err(e) = self.consistency_check_scheduler.schedule(task) {
             error!("[region {}] schedule failed: {:?}", region_id, e);
         }
     }
@@ -2322,7 +3004,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 );
                 return;
             }
-            Some(p) => &mut p.consistency_state,
+            Some(p) => {
+                if p.is_witness() {
+                    return;
+                }
+                &mut p.consistency_state
+            }
         };
 
         verify_and_store_hash(region_id, state, expected_index, expected_hash);
@@ -2434,6 +3121,10 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
     type Message = Msg;
 
     fn notify(&mut self, event_loop: &mut EventLoop<Self>, msg: Msg) {
+        let msg = match self.route_msg(msg) {
+            Some(msg) => msg,
+            None => return,
+        };
         match msg {
             Msg::RaftMessage(data) => if let Err(e) = self.on_raft_message(data) {
                 error!("{} handle raft message err: {:?}", self.tag, e);
@@ -2490,6 +3181,10 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
                 region_id,
                 region_size,
             } => self.on_approximate_region_size(region_id, region_size),
+            Msg::ApproximateRegionKeys {
+                region_id,
+                region_keys,
+            } => self.on_approximate_region_keys(region_id, region_keys),
         }
     }
 
@@ -2522,12 +3217,149 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
         }
 
         self.poll_apply();
+        self.poll_split_region_created();
+        self.poll_raft_log_persisted();
 
-        self.pending_snapshot_regions.clear();
+        let poller_id = self.poller.poller_id;
+        self.poller
+            .meta
+            .lock()
+            .unwrap()
+            .pending_snapshot_regions
+            .retain(|&(id, _)| id != poller_id);
     }
 }
 
 impl<T: Transport, C: PdClient> Store<T, C> {
+    // Messages sent by the `PollerRouter` are received by their owners directly,
+    // but messages sent to the channel of a poller may belong to other pollers,
+    // forwards them to the owners. Returns the message if it should be handled
+    // by the current poller.
+    fn route_msg(&mut self, msg: Msg) -> Option<Msg> {
+        if self.poller.pool_size() == 1 {
+            return Some(msg);
+        }
+        let owner = match msg.region_id() {
+            Some(region_id) => self.poller.owner_of(region_id),
+            None => return self.route_store_msg(msg),
+        };
+        if owner != self.poller.poller_id {
+            self.forward_msg(owner, msg);
+            return None;
+        }
+        Some(msg)
+    }
+
+    fn route_store_msg(&mut self, msg: Msg) -> Option<Msg> {
+        match msg {
+            Msg::BatchRaftSnapCmds {
+                send_time,
+                batch,
+                on_finished,
+            } => self.route_batch_raft_snapshot_command(send_time, batch, on_finished),
+            Msg::Quit => {
+                if self.poller.poller_id == 0 {
+                    for owner in 1..self.poller.pool_size() {
+                        self.forward_msg(owner, Msg::Quit);
+                    }
+                }
+                Some(Msg::Quit)
+            }
+            // Store heartbeats are sent by the first poller.
+            Msg::SnapshotStats => if self.poller.poller_id != 0 {
+                self.forward_msg(0, Msg::SnapshotStats);
+                None
+            } else {
+                Some(Msg::SnapshotStats)
+            },
+            msg => Some(msg),
+        }
+    }
+
+    fn route_batch_raft_snapshot_command(
+        &mut self,
+        send_time: Instant,
+        batch: Vec<RaftCmdRequest>,
+        on_finished: BatchCallback,
+    ) -> Option<Msg> {
+        let total = batch.len();
+        let mut sub_batches: Vec<Vec<(usize, RaftCmdRequest)>> =
+            (0..self.poller.pool_size()).map(|_| vec![]).collect();
+        for (i, req) in batch.into_iter().enumerate() {
+            let owner = self.poller.owner_of(req.get_header().get_region_id());
+            sub_batches[owner].push((i, req));
+        }
+
+        if sub_batches[self.poller.poller_id].len() == total {
+            let batch = sub_batches[self.poller.poller_id]
+                .drain(..)
+                .map(|(_, req)| req)
+                .collect();
+            return Some(Msg::BatchRaftSnapCmds {
+                send_time: send_time,
+                batch: batch,
+                on_finished: on_finished,
+            });
+        }
+
+        let pending = sub_batches.iter().filter(|b| !b.is_empty()).count();
+        let collector = Arc::new(Mutex::new(BatchCollector {
+            resps: vec![None; total],
+            remaining: pending,
+            on_finished: Some(on_finished),
+        }));
+        let mut local = None;
+        for (owner, sub_batch) in sub_batches.into_iter().enumerate() {
+            if sub_batch.is_empty() {
+                continue;
+            }
+            let (indexes, batch): (Vec<usize>, Vec<RaftCmdRequest>) =
+                sub_batch.into_iter().unzip();
+            let collector = collector.clone();
+            let on_finished: BatchCallback = box move |resps: Vec<Option<RaftCmdResponse>>| {
+                let finished = {
+                    let mut collector = collector.lock().unwrap();
+                    for (i, resp) in indexes.into_iter().zip(resps) {
+                        collector.resps[i] = resp;
+                    }
+                    collector.remaining -= 1;
+                    if collector.remaining == 0 {
+                        let resps = mem::replace(&mut collector.resps, vec![]);
+                        collector.on_finished.take().map(|cb| (cb, resps))
+                    } else {
+                        None
+                    }
+                };
+                if let Some((on_finished, resps)) = finished {
+                    on_finished.call_box((resps,));
+                }
+            };
+            let msg = Msg::BatchRaftSnapCmds {
+                send_time: send_time,
+                batch: batch,
+                on_finished: on_finished,
+            };
+            if owner == self.poller.poller_id {
+                local = Some(msg);
+            } else {
+                self.forward_msg(owner, msg);
+            }
+        }
+        local
+    }
+
+    fn forward_msg(&self, owner: usize, msg: Msg) {
+        let ch = SendCh::new(self.poller.router.poller(owner), "raftstore");
+        if let Err(e) = ch.send(msg) {
+            error!(
+                "{} failed to forward msg to poller {}: {:?}",
+                self.tag,
+                owner,
+                e
+            );
+        }
+    }
+
     /// load the target peer of request as mutable borrow.
     fn mut_target_peer(&mut self, request: &RaftCmdRequest) -> Result<&mut Peer> {
         let region_id = request.get_header().get_region_id();
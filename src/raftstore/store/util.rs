@@ -11,21 +11,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
 use std::option::Option;
 
 use kvproto::metapb;
 use kvproto::eraftpb::{self, ConfChangeType, MessageType};
 use kvproto::raft_serverpb::RaftMessage;
+use kvproto::raft_cmdpb::{CmdType, RaftCmdRequest};
 use raftstore::{Error, Result};
 use raftstore::store::keys;
 use rocksdb::{Range, TablePropertiesCollection, Writable, WriteBatch, DB};
-use storage::LARGE_CFS;
-use util::properties::SizeProperties;
+use storage::{CF_WRITE, LARGE_CFS};
+use util::codec::number::{NumberDecoder, NumberEncoder};
+use util::lz4;
+use util::properties::{RowsProperties, SizeProperties};
 use util::rocksdb as rocksdb_util;
 use super::engine::{IterOption, Iterable};
 
 use super::peer_storage;
 
+/// The store label marks peers on the store as witnesses if its value is "true".
+pub const WITNESS_LABEL_KEY: &'static str = "witness";
+
+/// Checks whether peers on the store with `labels` are witnesses, which vote and keep
+/// the raft log but never become leader nor store any user data.
+pub fn is_witness_store(labels: &[metapb::StoreLabel]) -> bool {
+    labels
+        .iter()
+        .any(|l| l.get_key() == WITNESS_LABEL_KEY && l.get_value() == "true")
+}
+
 pub fn find_peer(region: &metapb::Region, store_id: u64) -> Option<&metapb::Peer> {
     for peer in region.get_peers() {
         if peer.get_store_id() == store_id {
@@ -80,6 +95,68 @@ pub fn is_first_vote_msg(msg: &RaftMessage) -> bool {
         msg.get_message().get_term() == peer_storage::RAFT_INIT_LOG_TERM + 1
 }
 
+/// Checks whether the command can be proposed when the disk is full. Commands
+/// which help reclaim space or finish existing transactions are allowed, like
+/// deletes, admin commands, and commits, rollbacks and lock resolving, which only
+/// put to the write cf. New prewrites, which put to the lock and default cfs, and
+/// raw puts are rejected.
+pub fn is_allowed_on_disk_full(msg: &RaftCmdRequest) -> bool {
+    if msg.has_admin_request() {
+        return true;
+    }
+    msg.get_requests().iter().all(|req| match req.get_cmd_type() {
+        CmdType::Delete | CmdType::DeleteRange | CmdType::Get | CmdType::Snap => true,
+        CmdType::Put => req.get_put().get_cf() == CF_WRITE,
+        CmdType::Prewrite | CmdType::Invalid => false,
+    })
+}
+
+// The first byte of compressed entry data. An encoded `RaftCmdRequest` never starts
+// with it because 0 is not a valid protobuf field tag.
+const COMPRESSED_ENTRY_FLAG: u8 = 0;
+const ENTRY_COMPRESSION_LZ4: u8 = 1;
+
+/// Compresses the data of a normal entry with lz4 if its size reaches `threshold`.
+/// The compressed data is `[flag, compression type, var u64 original size, block]`,
+/// the data is left as is if it can't be made smaller.
+pub fn compress_entry_data(data: Vec<u8>, threshold: u64) -> Vec<u8> {
+    if threshold == 0 || (data.len() as u64) < threshold {
+        return data;
+    }
+    let block = match lz4::compress(&data) {
+        Ok(block) => block,
+        Err(e) => {
+            warn!("failed to compress entry of {} bytes: {:?}", data.len(), e);
+            return data;
+        }
+    };
+    let mut compressed = Vec::with_capacity(block.len() + 12);
+    compressed.push(COMPRESSED_ENTRY_FLAG);
+    compressed.push(ENTRY_COMPRESSION_LZ4);
+    compressed.encode_var_u64(data.len() as u64).unwrap();
+    if compressed.len() + block.len() >= data.len() {
+        return data;
+    }
+    compressed.extend_from_slice(&block);
+    compressed
+}
+
+/// Returns the original data of a normal entry, which may be compressed by
+/// `compress_entry_data`.
+pub fn decompress_entry_data(data: &[u8]) -> Result<Cow<[u8]>> {
+    if data.is_empty() || data[0] != COMPRESSED_ENTRY_FLAG {
+        return Ok(Cow::Borrowed(data));
+    }
+    match data.get(1) {
+        Some(&ENTRY_COMPRESSION_LZ4) => {}
+        t => return Err(box_err!("unknown compression type {:?} of entry data", t)),
+    }
+    let mut block = &data[2..];
+    let size = box_try!(block.decode_var_u64());
+    let decompressed = box_try!(lz4::decompress(block, size as usize));
+    Ok(Cow::Owned(decompressed))
+}
+
 const STR_CONF_CHANGE_ADD_NODE: &'static str = "AddNode";
 const STR_CONF_CHANGE_REMOVE_NODE: &'static str = "RemoveNode";
 
@@ -177,6 +254,30 @@ pub fn get_region_approximate_size(db: &DB, region: &metapb::Region) -> Result<u
     Ok(size)
 }
 
+pub fn get_region_approximate_keys_cf(
+    db: &DB,
+    cfname: &str,
+    region: &metapb::Region,
+) -> Result<u64> {
+    let cf = rocksdb_util::get_cf_handle(db, cfname)?;
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let range = Range::new(&start, &end);
+    let (mut keys, _) = db.get_approximate_memtable_stats_cf(cf, &range);
+    let collection = db.get_properties_of_tables_in_range(cf, &[range])?;
+    for (_, v) in &*collection {
+        let props = RowsProperties::decode(v.user_collected_properties())?;
+        keys += props.get_approximate_rows_in_range(&start, &end);
+    }
+    Ok(keys)
+}
+
+/// Every committed key has at least one version in write cf, so the rows
+/// properties of write cf are used to estimate the number of keys.
+pub fn get_region_approximate_keys(db: &DB, region: &metapb::Region) -> Result<u64> {
+    get_region_approximate_keys_cf(db, CF_WRITE, region)
+}
+
 #[cfg(test)]
 mod tests {
     use std::process;
@@ -184,16 +285,91 @@ mod tests {
     use kvproto::metapb;
     use kvproto::raft_serverpb::RaftMessage;
     use kvproto::eraftpb::{ConfChangeType, Message, MessageType};
+    use kvproto::raft_cmdpb::{AdminCmdType, Request};
+    use protobuf;
 
     use super::*;
     use raftstore::store::peer_storage;
-    use util::properties::SizePropertiesCollectorFactory;
+    use util::properties::{MvccPropertiesCollectorFactory, SizePropertiesCollectorFactory};
+    use storage::Key;
+    use storage::mvcc::{Write, WriteType};
 
     use rocksdb::{ColumnFamilyOptions, DBOptions, SeekKey, Writable, WriteBatch, DB};
     use util::rocksdb::{get_cf_handle, new_engine_opt, CFOptions};
-    use storage::ALL_CFS;
+    use storage::{ALL_CFS, CF_DEFAULT, CF_LOCK};
     use tempdir::TempDir;
 
+    #[test]
+    fn test_is_allowed_on_disk_full() {
+        let new_req = |cmd_type| {
+            let mut req = Request::new();
+            req.set_cmd_type(cmd_type);
+            req
+        };
+        let new_put = |cf: &str| {
+            let mut req = new_req(CmdType::Put);
+            req.mut_put().set_cf(cf.to_owned());
+            req
+        };
+
+        let mut msg = RaftCmdRequest::new();
+        msg.mut_requests().push(new_req(CmdType::Delete));
+        msg.mut_requests().push(new_req(CmdType::DeleteRange));
+        assert!(is_allowed_on_disk_full(&msg));
+
+        // Commits and rollbacks.
+        msg.mut_requests().push(new_put(CF_WRITE));
+        assert!(is_allowed_on_disk_full(&msg));
+
+        // Prewrites and raw puts.
+        for cf in &[CF_LOCK, CF_DEFAULT, ""] {
+            let mut msg = msg.clone();
+            msg.mut_requests().push(new_put(cf));
+            assert!(!is_allowed_on_disk_full(&msg));
+        }
+        msg.mut_requests().push(new_req(CmdType::Prewrite));
+        assert!(!is_allowed_on_disk_full(&msg));
+
+        let mut msg = RaftCmdRequest::new();
+        msg.mut_admin_request()
+            .set_cmd_type(AdminCmdType::TransferLeader);
+        assert!(is_allowed_on_disk_full(&msg));
+    }
+
+    #[test]
+    fn test_compress_entry_data() {
+        let mut msg = RaftCmdRequest::new();
+        let mut req = Request::new();
+        req.set_cmd_type(CmdType::Put);
+        req.mut_put().set_key(b"k1".to_vec());
+        req.mut_put().set_value(vec![b'v'; 4096]);
+        msg.mut_requests().push(req);
+        let data = protobuf::Message::write_to_bytes(&msg).unwrap();
+
+        // disabled or too small.
+        assert_eq!(compress_entry_data(data.clone(), 0), data);
+        let threshold = data.len() as u64 + 1;
+        assert_eq!(compress_entry_data(data.clone(), threshold), data);
+
+        let compressed = compress_entry_data(data.clone(), 1024);
+        assert_eq!(compressed[0], COMPRESSED_ENTRY_FLAG);
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(*decompress_entry_data(&compressed).unwrap(), *data);
+        // uncompressed data is returned as is.
+        assert_eq!(*decompress_entry_data(&data).unwrap(), *data);
+        assert!(decompress_entry_data(&[]).unwrap().is_empty());
+
+        // incompressible data is left as is.
+        let data: Vec<u8> = (0..64).map(|i| (i * 37 % 256) as u8).collect();
+        assert_eq!(compress_entry_data(data.clone(), 1), data);
+
+        assert!(decompress_entry_data(&[COMPRESSED_ENTRY_FLAG]).is_err());
+        assert!(decompress_entry_data(&[COMPRESSED_ENTRY_FLAG, 100, 1, 0]).is_err());
+        let mut corrupted = compressed.clone();
+        corrupted.truncate(compressed.len() / 2);
+        assert!(decompress_entry_data(&corrupted).is_err());
+    }
+
     // Tests the util function `check_key_in_region`.
     #[test]
     fn test_check_key_in_region() {
@@ -234,6 +410,25 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_is_witness_store() {
+        let new_label = |k: &str, v: &str| {
+            let mut label = metapb::StoreLabel::new();
+            label.set_key(k.to_owned());
+            label.set_value(v.to_owned());
+            label
+        };
+
+        assert!(!is_witness_store(&[]));
+        assert!(!is_witness_store(&[new_label("zone", "z1")]));
+        assert!(!is_witness_store(&[new_label(WITNESS_LABEL_KEY, "false")]));
+        assert!(!is_witness_store(&[new_label("zone", "true")]));
+        assert!(is_witness_store(&[
+            new_label("zone", "z1"),
+            new_label(WITNESS_LABEL_KEY, "true"),
+        ]));
+    }
+
     #[test]
     fn test_first_vote_msg() {
         let tbl = vec![
@@ -350,6 +545,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_region_approximate_keys() {
+        let path = TempDir::new("_test_raftstore_region_approximate_keys").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        cf_opts.set_level_zero_file_num_compaction_trigger(10);
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-properties-collector", f);
+        let cfs_opts = LARGE_CFS
+            .iter()
+            .map(|cf| CFOptions::new(cf, cf_opts.clone()))
+            .collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        let cases = [("a", 1024), ("b", 2048), ("c", 4096)];
+        for &(key, vlen) in &cases {
+            let cf = db.cf_handle(CF_WRITE).unwrap();
+            // The first row of each file is used as the start handle.
+            let k = Key::from_raw(b" ").append_ts(2);
+            let v = Write::new(WriteType::Put, 1, None).to_bytes();
+            db.put_cf(cf, &keys::data_key(k.encoded()), &v).unwrap();
+            for i in 0..vlen {
+                let k = Key::from_raw(format!("{}{:04}", key, i).as_bytes()).append_ts(2);
+                db.put_cf(cf, &keys::data_key(k.encoded()), &v).unwrap();
+            }
+            db.flush_cf(cf, true).unwrap();
+        }
+
+        let region = make_region(1, vec![], vec![]);
+        let keys = get_region_approximate_keys(&db, &region).unwrap();
+        assert_eq!(keys, 1024 + 2048 + 4096);
+    }
+
     fn check_data(db: &DB, cfs: &[&str], expected: &[(&[u8], &[u8])]) {
         for cf in cfs {
             let handle = get_cf_handle(db, cf).unwrap();
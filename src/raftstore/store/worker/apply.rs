@@ -300,6 +300,8 @@ pub struct ApplyDelegate {
     // if we remove ourself in ChangePeer remove, we should set this flag, then
     // any following committed logs in same Ready should be applied failed.
     pending_remove: bool,
+    // a witness keeps no user data, so data writes are skipped.
+    witness: bool,
     // we write apply_state to kv rocksdb, in one writebatch together with kv data.
     // because if we write it to raft rocksdb, apply_state and kv data (Put, Delete) are in
     // separate WAL file. when power failure, for current raft log, apply_index may synced
@@ -332,6 +334,7 @@ impl ApplyDelegate {
             engine: db,
             region: reg.region,
             pending_remove: false,
+            witness: reg.witness,
             apply_state: reg.apply_state,
             applied_index_term: reg.applied_index_term,
             term: reg.term,
@@ -420,10 +423,12 @@ impl ApplyDelegate {
     ) -> Option<ExecResult> {
         let index = entry.get_index();
         let term = entry.get_term();
-        let data = entry.get_data();
+        let data = util::decompress_entry_data(entry.get_data()).unwrap_or_else(|e| {
+            panic!("{} data is corrupted at {}: {:?}", self.tag, index, e);
+        });
 
         if !data.is_empty() {
-            let cmd = parse_data_at(data, index, &self.tag);
+            let cmd = parse_data_at(&data, index, &self.tag);
 
             if should_flush_to_engine(&cmd, apply_ctx.wb.count()) {
                 self.write_apply_state(&apply_ctx.wb);
@@ -1027,6 +1032,10 @@ impl ApplyDelegate {
         for req in requests {
             let cmd_type = req.get_cmd_type();
             let mut resp = match cmd_type {
+                // Witnesses still advance the apply index, but never touch kv data.
+                CmdType::Put | CmdType::Delete | CmdType::DeleteRange if self.witness => {
+                    Ok(Response::new())
+                }
                 CmdType::Put => self.handle_put(ctx, req),
                 CmdType::Delete => self.handle_delete(ctx, req),
                 CmdType::DeleteRange => self.handle_delete_range(req, &mut ranges),
@@ -1323,6 +1332,7 @@ pub struct Registration {
     pub apply_state: RaftApplyState,
     pub applied_index_term: u64,
     pub region: Region,
+    pub witness: bool,
 }
 
 impl Registration {
@@ -1333,6 +1343,7 @@ impl Registration {
             apply_state: peer.get_store().apply_state.clone(),
             applied_index_term: peer.get_store().applied_index_term,
             region: peer.region().clone(),
+            witness: peer.is_witness(),
         }
     }
 }
@@ -62,4 +62,18 @@ lazy_static! {
             "Proposal count of all regions in a mio tick",
             exponential_buckets(1.0, 2.0, 20).unwrap()
         ).unwrap();
+
+    pub static ref RAFT_LOG_WRITE_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_raftstore_async_write_raft_log_duration_seconds",
+            "Bucketed histogram of raft log writes handled by the write worker",
+            exponential_buckets(0.0005, 2.0, 20).unwrap()
+        ).unwrap();
+
+    pub static ref RAFT_LOG_WRITE_BATCH: Histogram =
+        register_histogram!(
+            "tikv_raftstore_async_write_raft_log_batch_size",
+            "Bucketed histogram of write tasks grouped in one fsync",
+            exponential_buckets(1.0, 2.0, 10).unwrap()
+        ).unwrap();
 }
@@ -14,7 +14,7 @@
 
 use raftstore::store::msg::Msg;
 use raftstore;
-use util::transport::SendCh;
+use util::transport::{RetryableSendCh, Sender as ChSender};
 use std::sync::mpsc::Sender;
 
 pub trait MsgSender {
@@ -23,13 +23,13 @@ pub trait MsgSender {
     fn try_send(&self, msg: Msg) -> raftstore::Result<()>;
 }
 
-impl MsgSender for SendCh<Msg> {
+impl<C: ChSender<Msg>> MsgSender for RetryableSendCh<Msg, C> {
     fn send(&self, msg: Msg) -> raftstore::Result<()> {
-        SendCh::send(self, msg).map_err(|e| box_err!("{:?}", e))
+        RetryableSendCh::send(self, msg).map_err(|e| box_err!("{:?}", e))
     }
 
     fn try_send(&self, msg: Msg) -> raftstore::Result<()> {
-        SendCh::try_send(self, msg).map_err(|e| box_err!("{:?}", e))
+        RetryableSendCh::try_send(self, msg).map_err(|e| box_err!("{:?}", e))
     }
 }
 
@@ -49,14 +49,17 @@ mod region;
 mod split_check;
 mod compact;
 mod raftlog_gc;
+mod raftlog_write;
 mod metrics;
 mod consistency_check;
 pub mod apply;
 
-pub use self::region::{Runner as RegionRunner, Task as RegionTask};
+pub use self::region::{PendingDeleteRanges, Runner as RegionRunner, Task as RegionTask};
 pub use self::split_check::{Runner as SplitCheckRunner, Task as SplitCheckTask};
 pub use self::compact::{Runner as CompactRunner, Task as CompactTask};
 pub use self::raftlog_gc::{Runner as RaftlogGcRunner, Task as RaftlogGcTask};
+pub use self::raftlog_write::{Runner as RaftlogWriteRunner, Task as RaftlogWriteTask,
+                              WRITE_BATCH_SIZE as RAFTLOG_WRITE_BATCH_SIZE};
 pub use self::consistency_check::{Runner as ConsistencyCheckRunner, Task as ConsistencyCheckTask};
 pub use self::apply::{Apply, ApplyMetrics, ApplyRes, Proposal, RegionProposal, Registration,
                       Runner as ApplyRunner, Task as ApplyTask, TaskRes as ApplyTaskRes};
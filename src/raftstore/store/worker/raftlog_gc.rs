@@ -13,16 +13,22 @@
 
 use raftstore::store::keys;
 use raftstore::store::engine::Iterable;
+use raftstore::store::log_engine::RaftLogEngine;
 use util::worker::Runnable;
 
 use rocksdb::{Writable, WriteBatch, DB};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::fmt::{self, Display, Formatter};
 use std::error;
 use std::sync::mpsc::Sender;
 
+// Expired log files are purged at most once in this interval.
+const PURGE_LOG_FILES_INTERVAL_SECS: u64 = 10;
+
 pub struct Task {
     pub raft_engine: Arc<DB>,
+    pub raft_log_engine: Option<Arc<RaftLogEngine>>,
     pub region_id: u64,
     pub start_idx: u64,
     pub end_idx: u64,
@@ -58,11 +64,34 @@ quick_error! {
 
 pub struct Runner {
     ch: Option<Sender<TaskRes>>,
+    last_purge: Instant,
 }
 
 impl Runner {
     pub fn new(ch: Option<Sender<TaskRes>>) -> Runner {
-        Runner { ch: ch }
+        Runner {
+            ch: ch,
+            last_purge: Instant::now(),
+        }
+    }
+
+    /// Compacts the raft logs in the log engine and removes the files
+    /// which are no longer referenced.
+    fn gc_raft_log_in_log_engine(
+        &mut self,
+        raft_log_engine: &RaftLogEngine,
+        region_id: u64,
+        end_idx: u64,
+    ) -> Result<u64, Error> {
+        let collected = box_try!(raft_log_engine.compact_to(region_id, end_idx));
+        if self.last_purge.elapsed() >= Duration::from_secs(PURGE_LOG_FILES_INTERVAL_SECS) {
+            self.last_purge = Instant::now();
+            let purged = box_try!(raft_log_engine.purge_expired_files());
+            if purged > 0 {
+                info!("purged {} raft log files", purged);
+            }
+        }
+        Ok(collected)
     }
 
     /// Do the gc job and return the count of log collected.
@@ -116,12 +145,16 @@ impl Runnable<Task> for Runner {
             task.region_id,
             task.end_idx
         );
-        match self.gc_raft_log(
-            task.raft_engine,
-            task.region_id,
-            task.start_idx,
-            task.end_idx,
-        ) {
+        let res = match task.raft_log_engine {
+            Some(ref e) => self.gc_raft_log_in_log_engine(e, task.region_id, task.end_idx),
+            None => self.gc_raft_log(
+                task.raft_engine,
+                task.region_id,
+                task.start_idx,
+                task.end_idx,
+            ),
+        };
+        match res {
             Err(e) => {
                 error!("[region {}] failed to gc: {:?}", task.region_id, e);
                 self.report_collected(0);
@@ -165,6 +198,7 @@ mod test {
             (
                 Task {
                     raft_engine: raft_db.clone(),
+                    raft_log_engine: None,
                     region_id: region_id,
                     start_idx: 0,
                     end_idx: 10,
@@ -176,6 +210,7 @@ mod test {
             (
                 Task {
                     raft_engine: raft_db.clone(),
+                    raft_log_engine: None,
                     region_id: region_id,
                     start_idx: 0,
                     end_idx: 50,
@@ -187,6 +222,7 @@ mod test {
             (
                 Task {
                     raft_engine: raft_db.clone(),
+                    raft_log_engine: None,
                     region_id: region_id,
                     start_idx: 50,
                     end_idx: 50,
@@ -198,6 +234,7 @@ mod test {
             (
                 Task {
                     raft_engine: raft_db.clone(),
+                    raft_log_engine: None,
                     region_id: region_id,
                     start_idx: 50,
                     end_idx: 60,
@@ -0,0 +1,147 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+
+use rocksdb::{WriteBatch, DB};
+
+use raftstore::store::log_engine::{LogBatch, RaftLogEngine};
+use util::time::{duration_to_sec, SlowTimer};
+use util::worker::BatchRunnable;
+
+use super::metrics::*;
+
+// Max count of write tasks which are grouped into one fsync.
+pub const WRITE_BATCH_SIZE: usize = 64;
+
+pub struct Task {
+    pub raft_wb: WriteBatch,
+    // Used instead of `raft_wb` when the raft log engine is enabled.
+    pub raft_log_batch: LogBatch,
+    pub sync: bool,
+    // The (region_id, peer_id) of the peers whose readies are persisted by this task.
+    pub peers: Vec<(u64, u64)>,
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Raft Log Write Task [peers: {}, sync: {}]",
+            self.peers.len(),
+            self.sync
+        )
+    }
+}
+
+/// Writes the raft logs of the readies for the raftstore. The tasks which are
+/// queued together are written with a single fsync, then the peers are sent
+/// to the raftstore to handle the persisted readies.
+pub struct Runner {
+    raft_engine: Arc<DB>,
+    raft_log_engine: Option<Arc<RaftLogEngine>>,
+    // Unbounded, so a busy raftstore never makes the worker drop a notification.
+    notifier: Sender<Vec<(u64, u64)>>,
+    // The write batches of the current group, they can't be merged like `LogBatch`.
+    wbs: Vec<WriteBatch>,
+    raft_log_batch: LogBatch,
+    sync: bool,
+    peers: Vec<(u64, u64)>,
+    tasks: usize,
+}
+
+impl Runner {
+    pub fn new(
+        raft_engine: Arc<DB>,
+        raft_log_engine: Option<Arc<RaftLogEngine>>,
+        notifier: Sender<Vec<(u64, u64)>>,
+    ) -> Runner {
+        Runner {
+            raft_engine: raft_engine,
+            raft_log_engine: raft_log_engine,
+            notifier: notifier,
+            wbs: vec![],
+            raft_log_batch: LogBatch::new(),
+            sync: false,
+            peers: vec![],
+            tasks: 0,
+        }
+    }
+
+    fn add_write(&mut self, raft_wb: WriteBatch, raft_log_batch: LogBatch, sync: bool) {
+        if !raft_wb.is_empty() {
+            self.wbs.push(raft_wb);
+        }
+        self.raft_log_batch.merge(raft_log_batch);
+        self.sync |= sync;
+        self.tasks += 1;
+    }
+
+    fn flush(&mut self) {
+        if self.tasks == 0 {
+            return;
+        }
+        let t = SlowTimer::new();
+        let has_wb = !self.wbs.is_empty();
+        for wb in self.wbs.drain(..) {
+            self.raft_engine.write(wb).unwrap_or_else(|e| {
+                panic!("failed to save raft append result: {:?}", e);
+            });
+        }
+        // A sync of the WAL also persists the writes before it.
+        if self.sync && has_wb {
+            self.raft_engine.sync_wal().unwrap_or_else(|e| {
+                panic!("failed to sync raft append result: {:?}", e);
+            });
+        }
+        if let Some(ref engine) = self.raft_log_engine {
+            let batch = mem::replace(&mut self.raft_log_batch, LogBatch::new());
+            engine.write(batch, self.sync).unwrap_or_else(|e| {
+                panic!("failed to save raft append result: {:?}", e);
+            });
+        }
+        RAFT_LOG_WRITE_HISTOGRAM.observe(duration_to_sec(t.elapsed()) as f64);
+        RAFT_LOG_WRITE_BATCH.observe(self.tasks as f64);
+        slow_log!(
+            t,
+            "write raft logs of {} tasks, {} peers, sync {}",
+            self.tasks,
+            self.peers.len(),
+            self.sync
+        );
+
+        self.sync = false;
+        self.tasks = 0;
+        let peers = mem::replace(&mut self.peers, vec![]);
+        if peers.is_empty() {
+            return;
+        }
+        if self.notifier.send(peers).is_err() {
+            // The raftstore is stopping, nothing to do.
+            warn!("failed to notify raft logs persisted, the receiver is dropped");
+        }
+    }
+}
+
+impl BatchRunnable<Task> for Runner {
+    fn run_batch(&mut self, tasks: &mut Vec<Task>) {
+        for task in tasks.drain(..) {
+            self.add_write(task.raft_wb, task.raft_log_batch, task.sync);
+            self.peers.extend(task.peers);
+        }
+        self.flush();
+    }
+}
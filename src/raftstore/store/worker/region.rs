@@ -12,11 +12,14 @@
 // limitations under the License.
 
 
+use std::collections::BTreeMap;
+use std::collections::Bound::{Excluded, Included, Unbounded};
 use std::fmt::{self, Display, Formatter};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::SyncSender;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use rocksdb::{Writable, WriteBatch, DB};
 use kvproto::raft_serverpb::{PeerState, RaftApplyState, RegionLocalState};
@@ -31,13 +34,15 @@ use raftstore::store::peer_storage::{JOB_STATUS_CANCELLED, JOB_STATUS_CANCELLING
                                      JOB_STATUS_RUNNING};
 use raftstore::store::{self, check_abort, keys, ApplyOptions, Peekable, SnapEntry, SnapKey,
                        SnapManager};
+use raftstore::store::log_engine::RaftLogEngine;
 use raftstore::store::snap::{Error, Result};
 use storage::CF_RAFT;
 
 use super::metrics::*;
 use super::super::util;
 
-const GENERATE_POOL_SIZE: usize = 2;
+// Interval to check whether the stale data of an overlapped range is still being deleted.
+const CLEANING_CHECK_INTERVAL_MILLIS: u64 = 50;
 
 /// region related task.
 pub enum Task {
@@ -48,6 +53,7 @@ pub enum Task {
     Apply {
         region_id: u64,
         status: Arc<AtomicUsize>,
+        witness: bool,
     },
     /// Destroy data between [start_key, end_key).
     ///
@@ -57,6 +63,8 @@ pub enum Task {
         start_key: Vec<u8>,
         end_key: Vec<u8>,
     },
+    /// Clean up the destroyed ranges whose delay has passed.
+    CleanStaleRanges,
 }
 
 impl Task {
@@ -85,16 +93,136 @@ impl Display for Task {
                 escape(start_key),
                 escape(end_key)
             ),
+            Task::CleanStaleRanges => write!(f, "Clean stale ranges"),
         }
     }
 }
 
+/// `PendingDeleteRanges` records the data ranges of destroyed peers that are not
+/// cleaned up yet. The ranges never overlap each other.
+///
+/// Ranges are recorded by raftstore directly when peers are destroyed, so that a
+/// snapshot accepted afterwards by any poller always sees them before applying.
+pub struct PendingDeleteRanges {
+    delay: Duration,
+    // start_key -> (region_id, end_key, the time when the range can be cleaned up)
+    ranges: BTreeMap<Vec<u8>, (u64, Vec<u8>, Instant)>,
+    // The ranges being deleted.
+    cleaning: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl PendingDeleteRanges {
+    pub fn new(delay: Duration) -> PendingDeleteRanges {
+        PendingDeleteRanges {
+            delay: delay,
+            ranges: BTreeMap::new(),
+            cleaning: vec![],
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Removes and returns all the ranges overlapping with [start_key, end_key).
+    pub fn drain_overlap_ranges(
+        &mut self,
+        start_key: &[u8],
+        end_key: &[u8],
+    ) -> Vec<(u64, Vec<u8>, Vec<u8>)> {
+        let mut overlaps = vec![];
+        // Only the last range before `start_key` may cover it.
+        if let Some((s, &(_, ref e, _))) = self.ranges
+            .range((Unbounded, Excluded(start_key.to_vec())))
+            .next_back()
+        {
+            if e.as_slice() > start_key {
+                overlaps.push(s.clone());
+            }
+        }
+        overlaps.extend(
+            self.ranges
+                .range((Included(start_key.to_vec()), Excluded(end_key.to_vec())))
+                .map(|(s, _)| s.clone()),
+        );
+        overlaps
+            .into_iter()
+            .map(|s| {
+                let (region_id, e, _) = self.ranges.remove(&s).unwrap();
+                (region_id, s, e)
+            })
+            .collect()
+    }
+
+    /// Inserts a range which can be cleaned up after the delay. The ranges overlapping
+    /// with it are merged into it.
+    pub fn insert(&mut self, region_id: u64, start_key: Vec<u8>, end_key: Vec<u8>) {
+        let overlaps = self.drain_overlap_ranges(&start_key, &end_key);
+        let (mut start_key, mut end_key) = (start_key, end_key);
+        for (_, s_key, e_key) in overlaps {
+            if s_key < start_key {
+                start_key = s_key;
+            }
+            if e_key > end_key {
+                end_key = e_key;
+            }
+        }
+        let ts = Instant::now() + self.delay;
+        self.ranges.insert(start_key, (region_id, end_key, ts));
+    }
+
+    /// Removes and returns the ranges which can be cleaned up at `now` and are accepted
+    /// by `f`. They are regarded as being cleaned up until `finish_cleaning` is called.
+    pub fn drain_stale_ranges<F>(&mut self, now: Instant, f: F) -> Vec<(u64, Vec<u8>, Vec<u8>)>
+    where
+        F: Fn(u64) -> bool,
+    {
+        let stale: Vec<_> = self.ranges
+            .iter()
+            .filter(|&(_, &(region_id, _, ts))| ts <= now && f(region_id))
+            .map(|(s, _)| s.clone())
+            .collect();
+        stale
+            .into_iter()
+            .map(|s| {
+                let (region_id, e, _) = self.ranges.remove(&s).unwrap();
+                self.cleaning.push((s.clone(), e.clone()));
+                (region_id, s, e)
+            })
+            .collect()
+    }
+
+    pub fn finish_cleaning(&mut self, start_key: &[u8], end_key: &[u8]) {
+        self.cleaning
+            .retain(|&(ref s, ref e)| s.as_slice() != start_key || e.as_slice() != end_key);
+    }
+
+    /// Checks whether any range overlapping with [start_key, end_key) is being cleaned up.
+    pub fn is_cleaning(&self, start_key: &[u8], end_key: &[u8]) -> bool {
+        self.cleaning
+            .iter()
+            .any(|&(ref s, ref e)| s.as_slice() < end_key && e.as_slice() > start_key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
 #[derive(Clone)]
 struct SnapContext {
     kv_db: Arc<DB>,
     raft_db: Arc<DB>,
+    raft_log_engine: Option<Arc<RaftLogEngine>>,
     batch_size: usize,
     mgr: SnapManager,
+    // Shared by the region workers of all the pollers, so a snapshot applied by
+    // any of them can take over the ranges destroyed by the others.
+    pending_delete_ranges: Arc<Mutex<PendingDeleteRanges>>,
 }
 
 impl SnapContext {
@@ -106,6 +234,7 @@ impl SnapContext {
         let snap = box_try!(store::do_snapshot(
             self.mgr.clone(),
             &raft_db,
+            self.raft_log_engine.as_ref().map(|e| &**e),
             &raw_snap,
             region_id
         ));
@@ -141,7 +270,7 @@ impl SnapContext {
         timer.observe_duration();
     }
 
-    fn apply_snap(&self, region_id: u64, abort: Arc<AtomicUsize>) -> Result<()> {
+    fn apply_snap(&self, region_id: u64, abort: Arc<AtomicUsize>, witness: bool) -> Result<()> {
         info!("[region {}] begin apply snap data", region_id);
         fail_point!("apply_snap");
         check_abort(&abort)?;
@@ -162,6 +291,9 @@ impl SnapContext {
         let start_key = keys::enc_start_key(&region);
         let end_key = keys::enc_end_key(&region);
         check_abort(&abort)?;
+        // The stale data of destroyed peers must be cleaned up before the new data is
+        // written, otherwise the delayed cleanup may delete the new data later.
+        self.cleanup_overlap_ranges(&start_key, &end_key, &abort)?;
         box_try!(util::delete_all_in_range(&self.kv_db, &start_key, &end_key));
         check_abort(&abort)?;
 
@@ -176,26 +308,30 @@ impl SnapContext {
                     ))
                 }
             };
-        let term = apply_state.get_truncated_state().get_term();
-        let idx = apply_state.get_truncated_state().get_index();
-        let snap_key = SnapKey::new(region_id, term, idx);
-        self.mgr.register(snap_key.clone(), SnapEntry::Applying);
-        defer!({
-            self.mgr.deregister(&snap_key, &SnapEntry::Applying);
-        });
-        let mut s = box_try!(self.mgr.get_snapshot_for_applying(&snap_key));
-        if !s.exists() {
-            return Err(box_err!("missing snapshot file {}", s.path()));
-        }
-        check_abort(&abort)?;
         let timer = Instant::now();
-        let options = ApplyOptions {
-            db: self.kv_db.clone(),
-            region: region.clone(),
-            abort: abort.clone(),
-            write_batch_size: self.batch_size,
-        };
-        s.apply(options)?;
+        // A witness stores no data, only the region meta and raft states are kept.
+        if !witness {
+            let term = apply_state.get_truncated_state().get_term();
+            let idx = apply_state.get_truncated_state().get_index();
+            let snap_key = SnapKey::new(region_id, term, idx);
+            self.mgr.register(snap_key.clone(), SnapEntry::Applying);
+            defer!({
+                self.mgr.deregister(&snap_key, &SnapEntry::Applying);
+            });
+            let mut s = box_try!(self.mgr.get_snapshot_for_applying(&snap_key));
+            if !s.exists() {
+                return Err(box_err!("missing snapshot file {}", s.path()));
+            }
+            check_abort(&abort)?;
+            let options = ApplyOptions {
+                db: self.kv_db.clone(),
+                region: region.clone(),
+                abort: abort.clone(),
+                write_batch_size: self.batch_size,
+                limiter: self.mgr.limiter(),
+            };
+            s.apply(options)?;
+        }
 
         let wb = WriteBatch::new();
         region_state.set_state(PeerState::Normal);
@@ -216,13 +352,13 @@ impl SnapContext {
         Ok(())
     }
 
-    fn handle_apply(&self, region_id: u64, status: Arc<AtomicUsize>) {
+    fn handle_apply(&self, region_id: u64, status: Arc<AtomicUsize>, witness: bool) {
         status.compare_and_swap(JOB_STATUS_PENDING, JOB_STATUS_RUNNING, Ordering::SeqCst);
         SNAP_COUNTER_VEC.with_label_values(&["apply", "all"]).inc();
         let apply_histogram = SNAP_HISTOGRAM.with_label_values(&["apply"]);
         let timer = apply_histogram.start_coarse_timer();
 
-        match self.apply_snap(region_id, status.clone()) {
+        match self.apply_snap(region_id, status.clone(), witness) {
             Ok(()) => {
                 status.swap(JOB_STATUS_FINISHED, Ordering::SeqCst);
                 SNAP_COUNTER_VEC.with_label_values(&["apply", "success"]).inc();
@@ -247,40 +383,137 @@ impl SnapContext {
         timer.observe_duration();
     }
 
+    fn cleanup_overlap_ranges(
+        &self,
+        start_key: &[u8],
+        end_key: &[u8],
+        abort: &AtomicUsize,
+    ) -> Result<()> {
+        // Snapshots are applied concurrently with cleaning up stale ranges, wait until
+        // the overlapped ones are done, otherwise the new data may be deleted.
+        let overlaps = loop {
+            {
+                let mut ranges = self.pending_delete_ranges.lock().unwrap();
+                if !ranges.is_cleaning(start_key, end_key) {
+                    break ranges.drain_overlap_ranges(start_key, end_key);
+                }
+            }
+            check_abort(abort)?;
+            thread::sleep(Duration::from_millis(CLEANING_CHECK_INTERVAL_MILLIS));
+        };
+        for (region_id, s_key, e_key) in overlaps {
+            info!(
+                "[region {}] deleting stale data in [{}, {}) before applying snapshot",
+                region_id,
+                escape(&s_key),
+                escape(&e_key)
+            );
+            box_try!(util::delete_all_in_range(&self.kv_db, &s_key, &e_key));
+        }
+        Ok(())
+    }
+
     fn handle_destroy(&mut self, region_id: u64, start_key: Vec<u8>, end_key: Vec<u8>) {
+        // The range has been recorded in `pending_delete_ranges` by raftstore.
+        let delay = self.pending_delete_ranges.lock().unwrap().delay();
         info!(
-            "[region {}] deleting data in [{}, {})",
+            "[region {}] delay deleting data in [{}, {}) for {:?}",
             region_id,
             escape(&start_key),
-            escape(&end_key)
+            escape(&end_key),
+            delay
         );
-        if let Err(e) = util::delete_all_in_range(&self.kv_db, &start_key, &end_key) {
-            error!(
-                "failed to delete data in [{}, {}): {:?}",
+        if delay == Duration::from_secs(0) {
+            self.clean_stale_ranges();
+        }
+    }
+
+    /// Deletes the data of destroyed ranges by deleting the whole sst files first and
+    /// then compacting the range, so that no tombstones are left behind. Ranges whose
+    /// region is still generating a snapshot are skipped, because deleting files is
+    /// not protected by RocksDB snapshots.
+    fn clean_stale_ranges(&self) {
+        let mgr = &self.mgr;
+        let stale_ranges = self.pending_delete_ranges
+            .lock()
+            .unwrap()
+            .drain_stale_ranges(Instant::now(), |region_id| !mgr.is_generating(region_id));
+        for (region_id, start_key, end_key) in stale_ranges {
+            info!(
+                "[region {}] deleting data in [{}, {})",
+                region_id,
+                escape(&start_key),
+                escape(&end_key)
+            );
+            let timer = Instant::now();
+            let res = self.delete_range(&start_key, &end_key);
+            self.pending_delete_ranges
+                .lock()
+                .unwrap()
+                .finish_cleaning(&start_key, &end_key);
+            if let Err(e) = res {
+                error!(
+                    "failed to delete data in [{}, {}): {:?}",
+                    escape(&start_key),
+                    escape(&end_key),
+                    e
+                );
+                continue;
+            }
+            info!(
+                "[region {}] delete data in [{}, {}) takes {:?}",
+                region_id,
                 escape(&start_key),
                 escape(&end_key),
-                e
+                timer.elapsed()
             );
         }
     }
+
+    fn delete_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<()> {
+        box_try!(rocksdb::roughly_cleanup_range(&self.kv_db, start_key, end_key));
+        // Data in the sst files crossing the range boundaries are left.
+        box_try!(util::delete_all_in_range(&self.kv_db, start_key, end_key));
+        for cf in self.kv_db.cf_names() {
+            let handle = box_try!(rocksdb::get_cf_handle(&self.kv_db, cf));
+            rocksdb::compact_range(&self.kv_db, handle, Some(start_key), Some(end_key), false);
+        }
+        Ok(())
+    }
 }
 
 pub struct Runner {
-    pool: ThreadPool<DefaultContext>,
+    gen_pool: ThreadPool<DefaultContext>,
+    apply_pool: ThreadPool<DefaultContext>,
     ctx: SnapContext,
 }
 
 impl Runner {
-    pub fn new(kv_db: Arc<DB>, raft_db: Arc<DB>, mgr: SnapManager, batch_size: usize) -> Runner {
+    #[allow(too_many_arguments)]
+    pub fn new(
+        kv_db: Arc<DB>,
+        raft_db: Arc<DB>,
+        raft_log_engine: Option<Arc<RaftLogEngine>>,
+        mgr: SnapManager,
+        batch_size: usize,
+        pending_delete_ranges: Arc<Mutex<PendingDeleteRanges>>,
+        gen_pool_size: usize,
+        apply_pool_size: usize,
+    ) -> Runner {
         Runner {
-            pool: ThreadPoolBuilder::with_default_factory(thd_name!("snap generator"))
-                .thread_count(GENERATE_POOL_SIZE)
+            gen_pool: ThreadPoolBuilder::with_default_factory(thd_name!("snap generator"))
+                .thread_count(gen_pool_size)
+                .build(),
+            apply_pool: ThreadPoolBuilder::with_default_factory(thd_name!("snap applier"))
+                .thread_count(apply_pool_size)
                 .build(),
             ctx: SnapContext {
                 kv_db: kv_db,
                 raft_db: raft_db,
+                raft_log_engine: raft_log_engine,
                 mgr: mgr,
                 batch_size: batch_size,
+                pending_delete_ranges: pending_delete_ranges,
             },
         }
     }
@@ -296,21 +529,76 @@ impl Runnable<Task> for Runner {
                 // It safe for now to handle generating and applying snapshot concurrently,
                 // but it may not when merge is implemented.
                 let ctx = self.ctx.clone();
-                self.pool
+                self.gen_pool
                     .execute(move |_| ctx.handle_gen(region_id, notifier))
             }
-            Task::Apply { region_id, status } => self.ctx.handle_apply(region_id, status),
+            Task::Apply {
+                region_id,
+                status,
+                witness,
+            } => {
+                // Snapshots of different regions never overlap, and the overlapped
+                // stale ranges are taken care of by `cleanup_overlap_ranges`.
+                let ctx = self.ctx.clone();
+                self.apply_pool
+                    .execute(move |_| ctx.handle_apply(region_id, status, witness))
+            }
             Task::Destroy {
                 region_id,
                 start_key,
                 end_key,
             } => self.ctx.handle_destroy(region_id, start_key, end_key),
+            Task::CleanStaleRanges => self.ctx.clean_stale_ranges(),
         }
     }
 
     fn shutdown(&mut self) {
-        if let Err(e) = self.pool.stop() {
+        if let Err(e) = self.gen_pool.stop() {
             warn!("Stop threadpool failed with {:?}", e);
         }
+        if let Err(e) = self.apply_pool.stop() {
+            warn!("Stop threadpool failed with {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn check_ranges(ranges: Vec<(u64, Vec<u8>, Vec<u8>)>, expect: &[(u64, &[u8], &[u8])]) {
+        let expect: Vec<_> = expect
+            .iter()
+            .map(|&(id, s, e)| (id, s.to_vec(), e.to_vec()))
+            .collect();
+        assert_eq!(ranges, expect);
+    }
+
+    #[test]
+    fn test_pending_delete_ranges() {
+        let mut ranges = PendingDeleteRanges::new(Duration::from_secs(60));
+        ranges.insert(1, b"a".to_vec(), b"c".to_vec());
+        ranges.insert(2, b"m".to_vec(), b"n".to_vec());
+        ranges.insert(3, b"x".to_vec(), b"z".to_vec());
+        assert_eq!(ranges.len(), 3);
+
+        check_ranges(ranges.drain_overlap_ranges(b"c", b"m"), &[]);
+        check_ranges(
+            ranges.drain_overlap_ranges(b"b", b"y"),
+            &[(1, b"a", b"c"), (2, b"m", b"n"), (3, b"x", b"z")],
+        );
+        assert!(ranges.is_empty());
+
+        ranges.insert(1, b"a".to_vec(), b"c".to_vec());
+        ranges.insert(2, b"b".to_vec(), b"e".to_vec());
+        ranges.insert(3, b"x".to_vec(), b"z".to_vec());
+        assert_eq!(ranges.len(), 2);
+        check_ranges(ranges.drain_stale_ranges(Instant::now(), |_| true), &[]);
+        let later = Instant::now() + Duration::from_secs(60);
+        check_ranges(ranges.drain_stale_ranges(later, |id| id != 3), &[(2, b"a", b"e")]);
+        check_ranges(ranges.drain_stale_ranges(later, |_| true), &[(3, b"x", b"z")]);
+        assert!(ranges.is_empty());
     }
 }
@@ -35,20 +35,22 @@ use super::metrics::*;
 struct KeyEntry {
     key: Option<Vec<u8>>,
     pos: usize,
+    cf: CfName,
     value_size: usize,
 }
 
 impl KeyEntry {
-    fn new(key: Vec<u8>, pos: usize, value_size: usize) -> KeyEntry {
+    fn new(key: Vec<u8>, pos: usize, cf: CfName, value_size: usize) -> KeyEntry {
         KeyEntry {
             key: Some(key),
             pos: pos,
+            cf: cf,
             value_size: value_size,
         }
     }
 
     fn take(&mut self) -> KeyEntry {
-        KeyEntry::new(self.key.take().unwrap(), self.pos, self.value_size)
+        KeyEntry::new(self.key.take().unwrap(), self.pos, self.cf, self.value_size)
     }
 }
 
@@ -72,7 +74,7 @@ impl Ord for KeyEntry {
 }
 
 struct MergedIterator<'a> {
-    iters: Vec<DBIterator<&'a DB>>,
+    iters: Vec<(CfName, DBIterator<&'a DB>)>,
     heap: BinaryHeap<KeyEntry>,
 }
 
@@ -86,14 +88,19 @@ impl<'a> MergedIterator<'a> {
     ) -> Result<MergedIterator<'a>> {
         let mut iters = Vec::with_capacity(cfs.len());
         let mut heap = BinaryHeap::with_capacity(cfs.len());
-        for (pos, cf) in cfs.into_iter().enumerate() {
+        for (pos, &cf) in cfs.iter().enumerate() {
             let iter_opt =
                 IterOption::new(Some(start_key.to_vec()), Some(end_key.to_vec()), fill_cache);
             let mut iter = db.new_iterator_cf(cf, iter_opt)?;
             if iter.seek(start_key.into()) {
-                heap.push(KeyEntry::new(iter.key().to_vec(), pos, iter.value().len()));
+                heap.push(KeyEntry::new(
+                    iter.key().to_vec(),
+                    pos,
+                    cf,
+                    iter.value().len(),
+                ));
             }
-            iters.push(iter);
+            iters.push((cf, iter));
         }
         Ok(MergedIterator {
             iters: iters,
@@ -106,10 +113,10 @@ impl<'a> MergedIterator<'a> {
             None => return None,
             Some(e) => e.pos,
         };
-        let iter = &mut self.iters[pos];
+        let (cf, ref mut iter) = self.iters[pos];
         if iter.next() {
             // TODO: avoid copy key.
-            let e = KeyEntry::new(iter.key().to_vec(), pos, iter.value().len());
+            let e = KeyEntry::new(iter.key().to_vec(), pos, cf, iter.value().len());
             let mut front = self.heap.peek_mut().unwrap();
             let res = front.take();
             *front = e;
@@ -185,6 +192,7 @@ impl<C: Sender<Msg>> Runner<C> {
                 if let Some(key) = coprocessor.on_split_check(
                     region,
                     &mut split_ctx,
+                    e.cf,
                     e.key.as_ref().unwrap(),
                     e.value_size as u64,
                 ) {
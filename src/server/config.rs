@@ -58,6 +58,9 @@ pub struct Config {
     // Server advertise listening address for outer communication.
     // If not set, we will use listening address instead.
     pub advertise_addr: String,
+
+    // The address of the HTTP status server, it's disabled if empty.
+    pub status_addr: String,
     pub notify_capacity: usize,
     pub messages_per_tick: usize,
     pub grpc_concurrency: usize,
@@ -69,6 +72,8 @@ pub struct Config {
     pub end_point_stack_size: ReadableSize,
     pub end_point_recursion_limit: u32,
     pub end_point_batch_row_limit: usize,
+    // Whether to run DAG requests with the batch execution engine, it is experimental.
+    pub end_point_enable_batch_exec: bool,
     pub snap_max_write_bytes_per_sec: ReadableSize,
 
     // Server labels to specify some attributes about this server.
@@ -89,6 +94,7 @@ impl Default for Config {
             addr: DEFAULT_LISTENING_ADDR.to_owned(),
             labels: HashMap::default(),
             advertise_addr: DEFAULT_ADVERTISE_LISTENING_ADDR.to_owned(),
+            status_addr: String::new(),
             notify_capacity: DEFAULT_NOTIFY_CAPACITY,
             messages_per_tick: DEFAULT_MESSAGES_PER_TICK,
             grpc_concurrency: DEFAULT_GRPC_CONCURRENCY,
@@ -100,6 +106,7 @@ impl Default for Config {
             end_point_stack_size: ReadableSize::mb(DEFAULT_ENDPOINT_STACK_SIZE_MB),
             end_point_recursion_limit: 1000,
             end_point_batch_row_limit: DEFAULT_ENDPOINT_BATCH_ROW_LIMIT,
+            end_point_enable_batch_exec: false,
             snap_max_write_bytes_per_sec: ReadableSize(DEFAULT_SNAP_MAX_BYTES_PER_SEC),
         }
     }
@@ -121,6 +128,10 @@ impl Config {
             ));
         }
 
+        if !self.status_addr.is_empty() {
+            box_try!(config::check_addr(&self.status_addr));
+        }
+
         if self.end_point_concurrency == 0 {
             return Err(box_err!("server.end-point-concurrency should not be 0."));
         }
@@ -192,6 +203,12 @@ mod tests {
         cfg.validate().unwrap();
         assert_eq!(cfg.addr, cfg.advertise_addr);
 
+        let mut invalid_cfg = cfg.clone();
+        invalid_cfg.status_addr = "127.0.0.1".to_owned();
+        assert!(invalid_cfg.validate().is_err());
+        invalid_cfg.status_addr = "127.0.0.1:20180".to_owned();
+        invalid_cfg.validate().unwrap();
+
         let mut invalid_cfg = cfg.clone();
         invalid_cfg.end_point_concurrency = 0;
         assert!(invalid_cfg.validate().is_err());
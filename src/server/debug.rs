@@ -126,8 +126,14 @@ impl Debugger {
     }
 
     pub fn raft_log(&self, region_id: u64, log_index: u64) -> Result<Entry> {
-        let key = keys::raft_log_key(region_id, log_index);
-        match self.engines.raft_engine.get_msg(&key) {
+        let res = match self.engines.raft_log_engine {
+            Some(ref log_engine) => log_engine.get_entry(region_id, log_index),
+            None => {
+                let key = keys::raft_log_key(region_id, log_index);
+                self.engines.raft_engine.get_msg(&key)
+            }
+        };
+        match res {
             Ok(Some(entry)) => Ok(entry),
             Ok(None) => Err(Error::NotFound(format!(
                 "raft log for region {} at index {}",
@@ -139,12 +145,17 @@ impl Debugger {
     }
 
     pub fn region_info(&self, region_id: u64) -> Result<RegionInfo> {
-        let raft_state_key = keys::raft_state_key(region_id);
-        let raft_state = box_try!(
-            self.engines
-                .raft_engine
-                .get_msg::<RaftLocalState>(&raft_state_key)
-        );
+        let raft_state = match self.engines.raft_log_engine {
+            Some(ref log_engine) => log_engine.get_raft_state(region_id),
+            None => {
+                let raft_state_key = keys::raft_state_key(region_id);
+                box_try!(
+                    self.engines
+                        .raft_engine
+                        .get_msg::<RaftLocalState>(&raft_state_key)
+                )
+            }
+        };
 
         let apply_state_key = keys::apply_state_key(region_id);
         let apply_state = box_try!(
@@ -464,6 +475,7 @@ mod tests {
     use tempdir::TempDir;
 
     use raftstore::store::engine::Mutable;
+    use raftstore::store::log_engine::{Config as LogEngineConfig, LogBatch, RaftLogEngine};
     use storage::{CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE};
     use storage::mvcc::{Lock, LockType};
     use util::rocksdb::{self as rocksdb_util, CFOptions};
@@ -555,6 +567,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_raft_log_engine() {
+        let mut debugger = new_debugger();
+        let tmp = TempDir::new("test_debug_raft_log").unwrap();
+        let mut cfg = LogEngineConfig::default();
+        cfg.dir = tmp.path().to_str().unwrap().to_owned();
+        let log_engine = Arc::new(RaftLogEngine::new(cfg).unwrap());
+        debugger.engines.raft_log_engine = Some(log_engine.clone());
+
+        let region_id = 1;
+        let mut entry = Entry::new();
+        entry.set_term(1);
+        entry.set_index(1);
+        entry.set_data(vec![42]);
+        let mut raft_state = RaftLocalState::new();
+        raft_state.set_last_index(1);
+        let mut batch = LogBatch::new();
+        batch.add_entries(region_id, &[entry.clone()]);
+        batch.put_raft_state(region_id, &raft_state);
+        log_engine.write(batch, true).unwrap();
+
+        assert_eq!(debugger.raft_log(region_id, 1).unwrap(), entry);
+        match debugger.raft_log(region_id, 2) {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("expect Error::NotFound(_)"),
+        }
+        assert_eq!(
+            debugger.region_info(region_id).unwrap(),
+            RegionInfo::new(Some(raft_state), None, None)
+        );
+    }
+
     #[test]
     fn test_region_info() {
         let debugger = new_debugger();
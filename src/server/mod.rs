@@ -25,6 +25,7 @@ pub mod node;
 pub mod resolve;
 pub mod snap;
 pub mod debug;
+pub mod status_server;
 
 pub use self::config::{Config, DEFAULT_CLUSTER_ID, DEFAULT_LISTENING_ADDR};
 pub use self::errors::{Error, Result};
@@ -33,5 +34,6 @@ pub use self::transport::{ServerRaftStoreRouter, ServerTransport};
 pub use self::node::{create_raft_storage, Node};
 pub use self::resolve::{PdStoreAddrResolver, StoreAddrResolver};
 pub use self::raft_client::RaftClient;
+pub use self::status_server::StatusServer;
 
 pub type OnResponse = Box<FnBox(Response) + Send>;
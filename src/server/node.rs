@@ -12,9 +12,10 @@
 // limitations under the License.
 
 use std::thread;
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::Receiver;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::process;
 
 use mio::EventLoop;
@@ -27,8 +28,11 @@ use protobuf::RepeatedField;
 use util::transport::SendCh;
 use util::worker::FutureWorker;
 use raftstore::coprocessor::dispatcher::CoprocessorHost;
-use raftstore::store::{self, keys, Config as StoreConfig, Engines, Msg, Peekable, SignificantMsg,
-                       SnapManager, Store, StoreChannel, Transport};
+use raftstore::coprocessor::split_observer::SplitObserver;
+use raftstore::store::{self, keys, Config as StoreConfig, EntryCacheMemory, Engines, Msg,
+                       PendingDeleteRanges, Peekable, PollerContext, PollerRouter, RouterSendCh,
+                       SignificantMsg, SnapManager, Store, StoreChannel, StoreMeta, StoreWorkers,
+                       Transport};
 use super::Result;
 use server::Config as ServerConfig;
 use storage::{Config as StorageConfig, RaftKv, Storage};
@@ -72,8 +76,9 @@ pub struct Node<C: PdClient + 'static> {
     cluster_id: u64,
     store: metapb::Store,
     store_cfg: StoreConfig,
-    store_handle: Option<thread::JoinHandle<()>>,
+    store_handles: Vec<thread::JoinHandle<()>>,
     ch: SendCh<Msg>,
+    router: PollerRouter,
 
     pd_client: Arc<C>,
 }
@@ -83,7 +88,7 @@ where
     C: PdClient,
 {
     pub fn new<T>(
-        event_loop: &mut EventLoop<Store<T, C>>,
+        event_loops: &[EventLoop<Store<T, C>>],
         cfg: &ServerConfig,
         store_cfg: &StoreConfig,
         pd_client: Arc<C>,
@@ -108,21 +113,23 @@ where
         }
         store.set_labels(RepeatedField::from_vec(labels));
 
-        let ch = SendCh::new(event_loop.channel(), "raftstore");
+        let ch = SendCh::new(event_loops[0].channel(), "raftstore");
+        let router = PollerRouter::new(event_loops.iter().map(|l| l.channel()).collect());
         Node {
             cluster_id: cfg.cluster_id,
             store: store,
             store_cfg: store_cfg.clone(),
-            store_handle: None,
+            store_handles: vec![],
             pd_client: pd_client,
             ch: ch,
+            router: router,
         }
     }
 
     #[allow(too_many_arguments)]
     pub fn start<T>(
         &mut self,
-        event_loop: EventLoop<Store<T, C>>,
+        event_loops: Vec<EventLoop<Store<T, C>>>,
         engines: Engines,
         trans: T,
         snap_mgr: SnapManager,
@@ -160,7 +167,7 @@ where
         // inform pd.
         self.pd_client.put_store(self.store.clone())?;
         self.start_store(
-            event_loop,
+            event_loops,
             store_id,
             engines,
             trans,
@@ -180,6 +187,11 @@ where
         self.ch.clone()
     }
 
+    /// Returns a channel which sends messages to the pollers owning the regions.
+    pub fn get_router(&self) -> RouterSendCh {
+        RouterSendCh::new(self.router.clone(), "raftstore")
+    }
+
     // check store, return store id for the engine.
     // If the store is not bootstrapped, use INVALID_ID.
     fn check_store(&self, engines: &Engines) -> Result<u64> {
@@ -314,72 +326,169 @@ where
     #[allow(too_many_arguments)]
     fn start_store<T>(
         &mut self,
-        mut event_loop: EventLoop<Store<T, C>>,
+        event_loops: Vec<EventLoop<Store<T, C>>>,
         store_id: u64,
         engines: Engines,
         trans: T,
         snap_mgr: SnapManager,
         significant_msg_receiver: Receiver<SignificantMsg>,
         pd_worker: FutureWorker<PdTask>,
-        coprocessor_host: CoprocessorHost,
+        mut coprocessor_host: CoprocessorHost,
     ) -> Result<()>
     where
         T: Transport + 'static,
     {
         info!("start raft store {} thread", store_id);
 
-        if self.store_handle.is_some() {
+        if !self.store_handles.is_empty() {
             return Err(box_err!("{} is already started", store_id));
         }
 
-        let cfg = self.store_cfg.clone();
-        let pd_client = self.pd_client.clone();
-        let store = self.store.clone();
-        let sender = event_loop.channel();
-
-        let (tx, rx) = mpsc::channel();
-        let builder = thread::Builder::new().name(thd_name!(format!("raftstore-{}", store_id)));
-        let h = builder.spawn(move || {
-            let ch = StoreChannel {
-                sender,
-                significant_msg_receiver,
-            };
-            let mut store = match Store::new(
-                ch,
-                store,
-                cfg,
-                engines,
-                trans,
-                pd_client,
-                snap_mgr,
-                pd_worker,
-                coprocessor_host,
-            ) {
-                Err(e) => panic!("construct store {} err {:?}", store_id, e),
-                Ok(s) => s,
-            };
-            tx.send(0).unwrap();
-            if let Err(e) = store.run(&mut event_loop) {
-                error!("store {} run err {:?}", store_id, e);
+        if let Some(ref log_engine) = engines.raft_log_engine {
+            let t = Instant::now();
+            let count = store::log_engine::migrate_from_raft_db(&engines.raft_engine, log_engine)?;
+            if count > 0 {
+                info!(
+                    "store {} migrates raft logs of {} regions to raft log engine, takes {:?}",
+                    store_id,
+                    count,
+                    t.elapsed()
+                );
+            }
+        }
+
+        coprocessor_host
+            .registry
+            .register_admin_observer(100, box SplitObserver);
+        let coprocessor_host = Arc::new(coprocessor_host);
+
+        let pool_size = event_loops.len();
+        if pool_size != self.router.pool_size() {
+            return Err(box_err!(
+                "expect {} event loops, but got {}",
+                self.router.pool_size(),
+                pool_size
+            ));
+        }
+        let mut significant_receivers = vec![significant_msg_receiver];
+        let mut significant_routers = vec![None];
+        for _ in 1..pool_size {
+            let (tx, rx) = mpsc::channel();
+            significant_routers.push(Some(tx));
+            significant_receivers.push(rx);
+        }
+        let mut split_routers = Vec::with_capacity(pool_size);
+        let mut split_receivers = Vec::with_capacity(pool_size);
+        for _ in 0..pool_size {
+            let (tx, rx) = mpsc::channel();
+            split_routers.push(tx);
+            split_receivers.push(rx);
+        }
+        let meta = Arc::new(Mutex::new(StoreMeta::new(pool_size)));
+        let disk_full = Arc::new(AtomicBool::new(false));
+        let entry_cache_memory = Arc::new(EntryCacheMemory::new(
+            self.store_cfg.raft_entry_cache_limit.0 as usize,
+        ));
+        let pending_delete_ranges = Arc::new(Mutex::new(PendingDeleteRanges::new(
+            self.store_cfg.clean_stale_peer_delay.0,
+        )));
+        let workers = StoreWorkers::new(pd_worker);
+        let schedulers = workers.schedulers();
+        let mut workers = Some(workers);
+
+        let (init_tx, init_rx) = mpsc::channel();
+        let mut start_txs = Vec::with_capacity(pool_size);
+        let receivers = significant_receivers.into_iter().zip(split_receivers);
+        let pollers = event_loops.into_iter().zip(receivers);
+        for (poller_id, (mut event_loop, (significant_msg_receiver, split_region_receiver))) in
+            pollers.enumerate()
+        {
+            let poller = PollerContext {
+                poller_id: poller_id,
+                router: self.router.clone(),
+                significant_routers: significant_routers.clone(),
+                split_routers: split_routers.clone(),
+                meta: meta.clone(),
+                pending_delete_ranges: pending_delete_ranges.clone(),
+                disk_full: disk_full.clone(),
+                entry_cache_memory: entry_cache_memory.clone(),
             };
-        })?;
-        // wait for store to be initialized
-        rx.recv().unwrap();
+            let cfg = self.store_cfg.clone();
+            let pd_client = self.pd_client.clone();
+            let store = self.store.clone();
+            let engines = engines.clone();
+            let trans = trans.clone();
+            let snap_mgr = snap_mgr.clone();
+            // Only the first poller runs the shared workers.
+            let workers = workers.take();
+            let schedulers = schedulers.clone();
+            let coprocessor_host = coprocessor_host.clone();
+            let init_tx = init_tx.clone();
+            let (start_tx, start_rx) = mpsc::channel();
+            start_txs.push(start_tx);
+
+            let builder = thread::Builder::new().name(thd_name!(
+                format!("raftstore-{}-{}", store_id, poller_id)
+            ));
+            let h = builder.spawn(move || {
+                let ch = StoreChannel {
+                    sender: event_loop.channel(),
+                    significant_msg_receiver,
+                    split_region_receiver,
+                };
+                let mut store = match Store::new(
+                    ch,
+                    poller,
+                    store,
+                    cfg,
+                    engines,
+                    trans,
+                    pd_client,
+                    snap_mgr,
+                    workers,
+                    schedulers,
+                    coprocessor_host,
+                ) {
+                    Err(e) => panic!("construct store {} err {:?}", store_id, e),
+                    Ok(s) => s,
+                };
+                init_tx.send(poller_id).unwrap();
+                // wait for all the pollers to be initialized
+                if start_rx.recv().is_err() {
+                    return;
+                }
+                if let Err(e) = store.run(&mut event_loop) {
+                    error!("store {} poller {} run err {:?}", store_id, poller_id, e);
+                };
+            })?;
+            self.store_handles.push(h);
+        }
+        drop(init_tx);
 
-        self.store_handle = Some(h);
+        // wait for stores to be initialized
+        for _ in 0..pool_size {
+            init_rx.recv().unwrap();
+        }
+        // Garbage data can only be found after all the regions are loaded.
+        store::clear_stale_data(&engines.kv_engine, &meta.lock().unwrap())?;
+        for tx in start_txs {
+            tx.send(()).unwrap();
+        }
         Ok(())
     }
 
     fn stop_store(&mut self, store_id: u64) -> Result<()> {
         info!("stop raft store {} thread", store_id);
-        let h = match self.store_handle.take() {
-            None => return Ok(()),
-            Some(h) => h,
-        };
+        if self.store_handles.is_empty() {
+            return Ok(());
+        }
 
+        // The first poller forwards the quit message to the others.
         box_try!(self.ch.send(Msg::Quit));
-        if let Err(e) = h.join() {
-            return Err(box_err!("join store {} thread err {:?}", store_id, e));
+        for h in self.store_handles.drain(..) {
+            if let Err(e) = h.join() {
+                return Err(box_err!("join store {} thread err {:?}", store_id, e));
+            }
         }
 
         Ok(())
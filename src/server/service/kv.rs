@@ -15,7 +15,7 @@ use std::boxed::FnBox;
 use std::fmt::Debug;
 use std::io::Write;
 use std::iter::{self, FromIterator};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use mio::Token;
 use grpc::{ClientStreamingSink, RequestStream, RpcContext, RpcStatus, RpcStatusCode,
@@ -37,7 +37,7 @@ use storage::txn::Error as TxnError;
 use storage::mvcc::{Error as MvccError, Write as MvccWrite, WriteType};
 use storage::engine::Error as EngineError;
 use server::transport::RaftStoreRouter;
-use server::snap::Task as SnapTask;
+use server::snap::{self, ResumeMsg, Task as SnapTask};
 use server::metrics::*;
 use server::Error;
 use raftstore::store::Msg as StoreMessage;
@@ -815,14 +815,31 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
         let token = Token(self.token.fetch_add(1, Ordering::SeqCst));
         let sched = self.snap_scheduler.clone();
         let sched2 = sched.clone();
+        // It's set if the sender only queries the size of received data.
+        let query = Arc::new(Mutex::new(None));
+        let query2 = query.clone();
         ctx.spawn(
             stream
                 .map_err(Error::from)
                 .for_each(move |mut chunk| {
                     let res = if chunk.has_message() {
-                        sched
-                            .schedule(SnapTask::Register(token, chunk.take_message()))
-                            .map_err(Error::from)
+                        let msg = chunk.take_message();
+                        match ResumeMsg::decode(chunk.get_data()) {
+                            Ok(Some(ResumeMsg::QueryReceivedSize)) => {
+                                let (cb, future) = make_callback();
+                                *query.lock().unwrap() = Some(future);
+                                sched
+                                    .schedule(SnapTask::QueryReceivedSize(msg, cb))
+                                    .map_err(Error::from)
+                            }
+                            Ok(Some(ResumeMsg::ResumeFrom(offset))) => sched
+                                .schedule(SnapTask::Register(token, msg, offset))
+                                .map_err(Error::from),
+                            Ok(None) => sched
+                                .schedule(SnapTask::Register(token, msg, 0))
+                                .map_err(Error::from),
+                            Err(e) => Err(e),
+                        }
                     } else if !chunk.get_data().is_empty() {
                         // TODO: Remove PipeBuffer or take good use of it.
                         let mut b = PipeBuffer::new(chunk.get_data().len());
@@ -836,16 +853,50 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
                     future::result(res)
                 })
                 .then(move |res| {
+                    let query = query2.lock().unwrap().take();
+                    if let Some(future) = query {
+                        // The received size is replied by the status.
+                        let reply = future.map_err(Error::from).then(move |res| {
+                            let status = match res {
+                                Ok(size) => snap::received_size_reply(size),
+                                Err(e) => {
+                                    RpcStatus::new(RpcStatusCode::Unknown, Some(format!("{}", e)))
+                                }
+                            };
+                            sink.fail(status).map_err(Error::from)
+                        });
+                        return future::Either::A(reply);
+                    }
                     let res = match res {
-                        Ok(_) => sched2.schedule(SnapTask::Close(token)),
+                        Ok(_) => {
+                            let (cb, future) = make_callback();
+                            sched2
+                                .schedule(SnapTask::Close(token, cb))
+                                .map(|_| future)
+                                .map_err(Error::from)
+                        }
                         Err(e) => {
                             error!("receive snapshot err: {}", e);
-                            sched2.schedule(SnapTask::Discard(token))
+                            if let Err(e) = sched2.schedule(SnapTask::Discard(token)) {
+                                error!("failed to discard snapshot: {:?}", e);
+                            }
+                            Err(e)
                         }
                     };
-                    future::result(res.map_err(Error::from))
+                    // The sender is told explicitly if the snapshot isn't received.
+                    let done = future::result(res)
+                        .and_then(|future| future.map_err(Error::from))
+                        .then(move |res| match res.and_then(|r| r) {
+                            Ok(_) => sink.success(Done::new()),
+                            Err(e) => {
+                                let status =
+                                    RpcStatus::new(RpcStatusCode::Unknown, Some(format!("{}", e)));
+                                sink.fail(status)
+                            }
+                        })
+                        .map_err(Error::from);
+                    future::Either::B(done)
                 })
-                .and_then(|_| sink.success(Done::new()).map_err(Error::from))
                 .then(|_| future::ok::<_, ()>(())),
         );
     }
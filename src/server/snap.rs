@@ -19,39 +19,120 @@ use std::sync::{Arc, RwLock};
 use mio::Token;
 use futures::{Async, Future, Poll, Stream};
 use futures::stream::{self, Once};
-use grpc::{ChannelBuilder, Environment, WriteFlags};
+use grpc::{ChannelBuilder, Environment, Error as GrpcError, RpcStatus, RpcStatusCode, WriteFlags};
 use kvproto::raft_serverpb::SnapshotChunk;
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::tikvpb_grpc::TikvClient;
 
-use raftstore::store::{SnapEntry, SnapKey, SnapManager, Snapshot};
+use raftstore::store::{SnapEntry, SnapKey, SnapManager, Snapshot, SnapshotDeleter};
 use util::threadpool::{DefaultContext, ThreadPool, ThreadPoolBuilder};
 use util::worker::Runnable;
 use util::buf::PipeBuffer;
 use util::security::SecurityManager;
 use util::collections::{HashMap, HashMapEntry as Entry};
 use util::HandyRwLock;
+use util::codec::number::{NumberDecoder, NumberEncoder};
 
 use super::metrics::*;
 use super::{Error, Result};
 use super::transport::RaftStoreRouter;
 
 pub type Callback = Box<FnBox(Result<()>) + Send>;
+pub type ReceivedSizeCallback = Box<FnBox(u64) + Send>;
 
 const DEFAULT_SENDER_POOL_SIZE: usize = 3;
 
+/// The magic prefix of `ResumeMsg`.
+const RESUME_MSG_MAGIC: &'static [u8] = b"\x00TiKVSnapResume";
+const RESUME_MSG_QUERY_RECEIVED_SIZE: u8 = 1;
+const RESUME_MSG_RESUME_FROM: u8 = 2;
+/// The prefix of the details of the status which replies the received size.
+const RECEIVED_SIZE_REPLY_PREFIX: &'static str = "snap_received_size:";
+
+/// Messages to resume sending a snapshot.
+///
+/// `SnapshotChunk` doesn't have a field for them, so they are carried in the data
+/// of the first chunk, which is empty if the whole snapshot is sent.
+#[derive(Debug, PartialEq)]
+pub enum ResumeMsg {
+    /// Asks for the size of received data, no data follows. The size is replied
+    /// by a status built by `received_size_reply`.
+    QueryReceivedSize,
+    /// Data from the offset follows.
+    ResumeFrom(u64),
+}
+
+impl ResumeMsg {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = RESUME_MSG_MAGIC.to_vec();
+        match *self {
+            ResumeMsg::QueryReceivedSize => data.push(RESUME_MSG_QUERY_RECEIVED_SIZE),
+            ResumeMsg::ResumeFrom(offset) => {
+                data.push(RESUME_MSG_RESUME_FROM);
+                data.encode_u64(offset).unwrap();
+            }
+        }
+        data
+    }
+
+    /// Decodes the message from the data of the first chunk, returns `None` if the
+    /// whole snapshot is sent.
+    pub fn decode(data: &[u8]) -> Result<Option<ResumeMsg>> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+        if !data.starts_with(RESUME_MSG_MAGIC) || data.len() == RESUME_MSG_MAGIC.len() {
+            return Err(box_err!("invalid resume message {:?}", data));
+        }
+        let mut rest = &data[RESUME_MSG_MAGIC.len() + 1..];
+        let msg = match data[RESUME_MSG_MAGIC.len()] {
+            RESUME_MSG_QUERY_RECEIVED_SIZE => ResumeMsg::QueryReceivedSize,
+            RESUME_MSG_RESUME_FROM => ResumeMsg::ResumeFrom(rest.decode_u64()?),
+            tp => return Err(box_err!("unknown resume message type {}", tp)),
+        };
+        if !rest.is_empty() {
+            return Err(box_err!("invalid resume message {:?}", data));
+        }
+        Ok(Some(msg))
+    }
+}
+
+/// Builds the status which replies the received size to `ResumeMsg::QueryReceivedSize`.
+pub fn received_size_reply(size: u64) -> RpcStatus {
+    RpcStatus::new(
+        RpcStatusCode::FailedPrecondition,
+        Some(format!("{}{}", RECEIVED_SIZE_REPLY_PREFIX, size)),
+    )
+}
+
+/// Parses the received size from the reply, returns `None` if it's not a reply of
+/// `ResumeMsg::QueryReceivedSize`.
+fn parse_received_size_reply(status: &RpcStatus) -> Option<u64> {
+    match status.details {
+        Some(ref d)
+            if status.status == RpcStatusCode::FailedPrecondition &&
+                d.starts_with(RECEIVED_SIZE_REPLY_PREFIX) =>
+        {
+            d[RECEIVED_SIZE_REPLY_PREFIX.len()..].parse().ok()
+        }
+        _ => None,
+    }
+}
+
 /// `Task` that `Runner` can handle.
 ///
-/// `Register` register a pending snapshot file with token;
+/// `Register` register a pending snapshot file with token and the offset data start from;
 /// `Write` write data to snapshot file;
-/// `Close` save the snapshot file;
-/// `Discard` discard all the unsaved changes made to snapshot file;
+/// `Close` save the snapshot file and report whether it's received successfully;
+/// `Discard` keep the received data of snapshot file for resuming and discard the file;
+/// `QueryReceivedSize` query the size of received data of snapshot file;
 /// `SendTo` send the snapshot file to specified address.
 pub enum Task {
-    Register(Token, RaftMessage),
+    Register(Token, RaftMessage, u64),
     Write(Token, PipeBuffer),
-    Close(Token),
+    Close(Token, Callback),
     Discard(Token),
+    QueryReceivedSize(RaftMessage, ReceivedSizeCallback),
     SendTo {
         addr: String,
         msg: RaftMessage,
@@ -62,10 +143,17 @@ pub enum Task {
 impl Display for Task {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match *self {
-            Task::Register(token, ref meta) => write!(f, "Register {:?} token: {:?}", meta, token),
+            Task::Register(token, ref meta, offset) => write!(
+                f,
+                "Register {:?} token: {:?} offset: {}",
+                meta,
+                token,
+                offset
+            ),
             Task::Write(token, _) => write!(f, "Write snap for {:?}", token),
-            Task::Close(token) => write!(f, "Close file {:?}", token),
+            Task::Close(token, _) => write!(f, "Close file {:?}", token),
             Task::Discard(token) => write!(f, "Discard file {:?}", token),
+            Task::QueryReceivedSize(ref meta, _) => write!(f, "QueryReceivedSize {:?}", meta),
             Task::SendTo {
                 ref addr, ref msg, ..
             } => write!(f, "SendTo Snap[to: {}, snap: {:?}]", addr, msg),
@@ -105,9 +193,34 @@ impl Stream for SnapChunk {
     }
 }
 
+/// Ask the receiver for the size of data it has received for the snapshot.
+fn query_received_size(client: &TikvClient, msg: RaftMessage) -> Result<u64> {
+    let mut chunk = SnapshotChunk::new();
+    chunk.set_message(msg);
+    chunk.set_data(ResumeMsg::QueryReceivedSize.encode());
+    let (sink, receiver) = client.snapshot();
+    let query: Once<(SnapshotChunk, _), Error> = stream::once(Ok((chunk, WriteFlags::default())));
+    let res = query
+        .forward(sink)
+        .and_then(|_| receiver.map_err(Error::from))
+        .wait();
+    match res {
+        // The receiver doesn't support resuming, send the whole snapshot.
+        Ok(_) => Ok(0),
+        Err(Error::Grpc(GrpcError::RpcFailure(status))) => {
+            match parse_received_size_reply(&status) {
+                Some(size) => Ok(size),
+                None => Err(Error::Grpc(GrpcError::RpcFailure(status))),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
 /// Send the snapshot to specified address.
 ///
-/// It will first send the normal raft snapshot message and then send the snapshot file.
+/// It will first ask the receiver for the size of data already received, then send the
+/// normal raft snapshot message and the rest of the snapshot file.
 fn send_snap(
     env: Arc<Environment>,
     mgr: SnapManager,
@@ -128,43 +241,68 @@ fn send_snap(
     defer!({
         mgr.deregister(&key, &SnapEntry::Sending);
     });
-    let s = box_try!(mgr.get_snapshot_for_sending(&key));
+    let mut s = box_try!(mgr.get_snapshot_for_sending(&key));
     if !s.exists() {
         return Err(box_err!("missing snap file: {:?}", s.path()));
     }
     let total_size = s.total_size()?;
 
+    let cb = ChannelBuilder::new(env);
+    let channel = security_mgr.connect(cb, addr);
+    let client = TikvClient::new(channel);
+
+    let offset = query_received_size(&client, msg.clone())?;
+    if offset > total_size {
+        return Err(box_err!(
+            "received size {} of snap {} exceeds total size {}",
+            offset,
+            key,
+            total_size
+        ));
+    }
+    if offset > 0 {
+        info!(
+            "[region {}] resume sending snapshot {} from {}",
+            key.region_id,
+            key,
+            offset
+        );
+        s.seek_to(offset)?;
+    }
+
     // snapshot file has been validated when created, so no need to validate again.
     let s = Arc::new(RwLock::new(s));
 
     let chunks = {
         let snap_chunk = SnapChunk {
             snap: s.clone(),
-            remain_bytes: total_size as usize,
+            remain_bytes: (total_size - offset) as usize,
         };
         let first: Once<(SnapshotChunk, _), Error> = stream::once({
             let mut chunk = SnapshotChunk::new();
             chunk.set_message(msg);
+            if offset > 0 {
+                chunk.set_data(ResumeMsg::ResumeFrom(offset).encode());
+            }
             Ok((chunk, WriteFlags::default().buffer_hint(true)))
         });
         first.chain(snap_chunk)
     };
 
-    let cb = ChannelBuilder::new(env);
-    let channel = security_mgr.connect(cb, addr);
-    let client = TikvClient::new(channel);
     let (sink, receiver) = client.snapshot();
     let send = chunks.forward(sink);
     let res = send.and_then(|_| receiver.map_err(Error::from))
         .and_then(|_| {
             info!(
-                "[region {}] sent snapshot {} [size: {}, dur: {:?}]",
+                "[region {}] sent snapshot {} [size: {}, resumed from: {}, dur: {:?}]",
                 key.region_id,
                 key,
                 total_size,
+                offset,
                 timer.elapsed()
             );
-            s.wl().delete();
+            // The snapshot may be shared by other senders, only the last one deletes it.
+            mgr.delete_snapshot(&key, s.rl().as_ref(), true);
             Ok(())
         })
         .wait()
@@ -178,6 +316,8 @@ pub struct Runner<R: RaftStoreRouter + 'static> {
     env: Arc<Environment>,
     snap_mgr: SnapManager,
     files: HashMap<Token, (Box<Snapshot>, RaftMessage)>,
+    // Tokens failed to receive snapshots, they are reported when closed.
+    failures: HashMap<Token, String>,
     pool: ThreadPool<DefaultContext>,
     raft_router: R,
     security_mgr: Arc<SecurityManager>,
@@ -194,6 +334,7 @@ impl<R: RaftStoreRouter + 'static> Runner<R> {
             env: env,
             snap_mgr: snap_mgr,
             files: map![],
+            failures: map![],
             pool: ThreadPoolBuilder::with_default_factory(thd_name!("snap sender"))
                 .thread_count(DEFAULT_SENDER_POOL_SIZE)
                 .build(),
@@ -201,48 +342,74 @@ impl<R: RaftStoreRouter + 'static> Runner<R> {
             security_mgr: security_mgr,
         }
     }
+
+    fn register(&mut self, token: Token, meta: RaftMessage, offset: u64) -> Result<()> {
+        let key = SnapKey::from_snap(meta.get_message().get_snapshot())?;
+        // Register it before opening the file, so the partially received data won't
+        // be deleted by snap gc or received by others concurrently.
+        if !self.snap_mgr.register(key.clone(), SnapEntry::Receiving) {
+            return Err(box_err!("snapshot {} is being received", key));
+        }
+        let res = {
+            let data = meta.get_message().get_snapshot().get_data();
+            let mgr = &self.snap_mgr;
+            mgr.get_snapshot_for_receiving(&key, data).and_then(|snap| {
+                if !snap.exists() && offset == 0 && snap.received_size() > 0 {
+                    // The sender sends from the beginning, so the data received before
+                    // are dropped with the snapshot file, and the file is recreated.
+                    drop(snap);
+                    mgr.get_snapshot_for_receiving(&key, data)
+                } else {
+                    Ok(snap)
+                }
+            })
+        };
+        let mut snap = match res {
+            Ok(snap) => snap,
+            Err(e) => {
+                self.snap_mgr.deregister(&key, &SnapEntry::Receiving);
+                return Err(box_err!("failed to create snapshot file: {:?}", e));
+            }
+        };
+        if snap.exists() {
+            info!(
+                "snapshot file {} already exists, skip receiving.",
+                snap.path()
+            );
+            drop(snap);
+            self.snap_mgr.deregister(&key, &SnapEntry::Receiving);
+            if let Err(e) = self.raft_router.send_raft_msg(meta) {
+                error!("send snapshot for key {} token {:?}: {:?}", key, token, e);
+            }
+            return Ok(());
+        }
+        let received_size = snap.received_size();
+        if received_size != offset {
+            // Keep the received data, the sender can query the size and resume again.
+            snap.keep_partial();
+            drop(snap);
+            self.snap_mgr.deregister(&key, &SnapEntry::Receiving);
+            return Err(box_err!(
+                "received size {} of snapshot {} mismatches offset {}",
+                received_size,
+                key,
+                offset
+            ));
+        }
+        debug!("begin to receive snap {:?} from {}", meta, offset);
+        self.files.insert(token, (snap, meta));
+        Ok(())
+    }
 }
 
 impl<R: RaftStoreRouter + 'static> Runnable<Task> for Runner<R> {
     fn run(&mut self, task: Task) {
         match task {
-            Task::Register(token, meta) => {
+            Task::Register(token, meta, offset) => {
                 SNAP_TASK_COUNTER.with_label_values(&["register"]).inc();
-                let mgr = self.snap_mgr.clone();
-                let key = match SnapKey::from_snap(meta.get_message().get_snapshot()) {
-                    Ok(k) => k,
-                    Err(e) => {
-                        error!("failed to create snap key for token {:?}: {:?}", token, e);
-                        return;
-                    }
-                };
-                match mgr.get_snapshot_for_receiving(
-                    &key,
-                    meta.get_message().get_snapshot().get_data(),
-                ) {
-                    Ok(snap) => {
-                        if snap.exists() {
-                            info!(
-                                "snapshot file {} already exists, skip receiving.",
-                                snap.path()
-                            );
-                            if let Err(e) = self.raft_router.send_raft_msg(meta) {
-                                error!("send snapshot for key {} token {:?}: {:?}", key, token, e);
-                            }
-                            return;
-                        }
-                        debug!("begin to receive snap {:?}", meta);
-                        mgr.register(key, SnapEntry::Receiving);
-                        self.files.insert(token, (snap, meta));
-                    }
-                    Err(e) => {
-                        error!(
-                            "failed to create snapshot file for token {:?}: {:?}",
-                            token,
-                            e
-                        );
-                        return;
-                    }
+                if let Err(e) = self.register(token, meta, offset) {
+                    error!("failed to register snap for token {:?}: {:?}", token, e);
+                    self.failures.insert(token, format!("{}", e));
                 }
             }
             Task::Write(token, mut data) => {
@@ -259,13 +426,22 @@ impl<R: RaftStoreRouter + 'static> Runnable<Task> for Runner<R> {
                             let (_, msg) = e.remove();
                             let key = SnapKey::from_snap(msg.get_message().get_snapshot()).unwrap();
                             self.snap_mgr.deregister(&key, &SnapEntry::Receiving);
+                            self.failures.insert(token, format!("{}", err));
+                        }
+                    }
+                    Entry::Vacant(_) => {
+                        if !self.failures.contains_key(&token) {
+                            error!("invalid snap token {:?}", token);
                         }
                     }
-                    Entry::Vacant(_) => error!("invalid snap token {:?}", token),
                 }
             }
-            Task::Close(token) => {
+            Task::Close(token, cb) => {
                 SNAP_TASK_COUNTER.with_label_values(&["close"]).inc();
+                if let Some(e) = self.failures.remove(&token) {
+                    cb(Err(box_err!("failed to receive snapshot: {}", e)));
+                    return;
+                }
                 match self.files.remove(&token) {
                     Some((mut snap, msg)) => {
                         let key = SnapKey::from_snap(msg.get_message().get_snapshot()).unwrap();
@@ -280,24 +456,52 @@ impl<R: RaftStoreRouter + 'static> Runnable<Task> for Runner<R> {
                                 token,
                                 e
                             );
+                            cb(Err(box_err!("failed to save snapshot: {:?}", e)));
                             return;
                         }
                         if let Err(e) = self.raft_router.send_raft_msg(msg) {
                             error!("send snapshot for token {:?} err {:?}", token, e);
                         }
+                        cb(Ok(()))
+                    }
+                    None => {
+                        error!("invalid snap token {:?}", token);
+                        cb(Ok(()))
                     }
-                    None => error!("invalid snap token {:?}", token),
                 }
             }
             Task::Discard(token) => {
                 SNAP_TASK_COUNTER.with_label_values(&["discard"]).inc();
-                if let Some((_, msg)) = self.files.remove(&token) {
+                self.failures.remove(&token);
+                if let Some((mut snap, msg)) = self.files.remove(&token) {
                     debug!("discard snapshot: {:?}", msg);
+                    // Keep the received data, so the sender can resume sending later.
+                    snap.keep_partial();
                     // because token is inserted, following can't panic.
                     let key = SnapKey::from_snap(msg.get_message().get_snapshot()).unwrap();
                     self.snap_mgr.deregister(&key, &SnapEntry::Receiving);
                 }
             }
+            Task::QueryReceivedSize(msg, cb) => {
+                SNAP_TASK_COUNTER.with_label_values(&["query"]).inc();
+                let snapshot = msg.get_message().get_snapshot();
+                let size = match SnapKey::from_snap(snapshot) {
+                    Ok(key) => match self.snap_mgr
+                        .get_snapshot_received_size(&key, snapshot.get_data())
+                    {
+                        Ok(size) => size,
+                        Err(e) => {
+                            error!("failed to get received size of snap {}: {:?}", key, e);
+                            0
+                        }
+                    },
+                    Err(e) => {
+                        error!("failed to create snap key for {:?}: {:?}", msg, e);
+                        0
+                    }
+                };
+                cb(size)
+            }
             Task::SendTo { addr, msg, cb } => {
                 SNAP_TASK_COUNTER.with_label_values(&["send"]).inc();
                 let env = self.env.clone();
@@ -314,3 +518,42 @@ impl<R: RaftStoreRouter + 'static> Runnable<Task> for Runner<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_msg() {
+        assert_eq!(ResumeMsg::decode(b"").unwrap(), None);
+        for msg in vec![
+            ResumeMsg::QueryReceivedSize,
+            ResumeMsg::ResumeFrom(0),
+            ResumeMsg::ResumeFrom(1024),
+        ] {
+            let data = msg.encode();
+            assert_eq!(ResumeMsg::decode(&data).unwrap(), Some(msg));
+        }
+
+        let mut data = ResumeMsg::ResumeFrom(1024).encode();
+        data.push(0);
+        assert!(ResumeMsg::decode(&data).is_err());
+        data.truncate(RESUME_MSG_MAGIC.len() + 1);
+        assert!(ResumeMsg::decode(&data).is_err());
+        data.truncate(RESUME_MSG_MAGIC.len());
+        assert!(ResumeMsg::decode(&data).is_err());
+        // The legacy query of received size isn't supported.
+        assert!(ResumeMsg::decode(b"received_size_query").is_err());
+    }
+
+    #[test]
+    fn test_received_size_reply() {
+        let status = received_size_reply(1024);
+        assert_eq!(parse_received_size_reply(&status), Some(1024));
+
+        let status = RpcStatus::new(RpcStatusCode::FailedPrecondition, Some("1024".to_owned()));
+        assert_eq!(parse_received_size_reply(&status), None);
+        let status = RpcStatus::new(RpcStatusCode::Unknown, status.details.clone());
+        assert_eq!(parse_received_size_reply(&status), None);
+    }
+}
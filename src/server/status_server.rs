@@ -0,0 +1,318 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A tiny HTTP server exposing the local status of the store.
+//!
+//! Supported requests:
+//! - `GET /regions/hot?type=read|write&limit=N`, the regions with the most
+//!   bytes read or written per second during their last heartbeat interval.
+//!
+//! Connections are served by a small thread pool, and waiting for the pd worker
+//! doesn't occupy a thread, so a slow client doesn't block the others.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
+
+use futures::{future, Future};
+use futures::future::Either;
+use futures::sync::oneshot;
+use futures_cpupool::{Builder as PoolBuilder, CpuPool};
+use serde_json;
+use tokio_timer::Timer;
+use url::form_urlencoded;
+
+use pd::{FlowType, HotRegion, PdTask};
+use util::worker::FutureScheduler;
+
+use super::Result;
+
+const MAX_REQUEST_HEADER_SIZE: usize = 8 * 1024;
+const DEFAULT_HOT_REGIONS_LIMIT: usize = 10;
+const IO_TIMEOUT_SECS: u64 = 3;
+const POOL_SIZE: usize = 4;
+
+// The status code and the body of a response.
+type ResponseFuture = Box<Future<Item = (u16, String), Error = ()> + Send>;
+
+pub struct StatusServer {
+    pd_scheduler: FutureScheduler<PdTask>,
+    addr: Option<SocketAddr>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    pool: CpuPool,
+    timer: Timer,
+}
+
+impl StatusServer {
+    pub fn new(pd_scheduler: FutureScheduler<PdTask>) -> StatusServer {
+        let pool = PoolBuilder::new()
+            .name_prefix(thd_name!("status-server"))
+            .pool_size(POOL_SIZE)
+            .create();
+        StatusServer {
+            pd_scheduler: pd_scheduler,
+            addr: None,
+            stopped: Arc::new(AtomicBool::new(false)),
+            handle: None,
+            pool: pool,
+            timer: Timer::default(),
+        }
+    }
+
+    pub fn start(&mut self, addr: &str) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        self.addr = Some(listener.local_addr()?);
+        let stopped = self.stopped.clone();
+        let pd_scheduler = self.pd_scheduler.clone();
+        let pool = self.pool.clone();
+        let timer = self.timer.clone();
+        let h = Builder::new()
+            .name(thd_name!("status-server-accept"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let pd_scheduler = pd_scheduler.clone();
+                            let timer = timer.clone();
+                            pool.spawn_fn(move || handle_connection(stream, &pd_scheduler, &timer))
+                                .forget();
+                        }
+                        Err(e) => warn!("status server failed to accept connection: {:?}", e),
+                    }
+                }
+            })?;
+        self.handle = Some(h);
+        info!("status server listening on {}", self.addr.unwrap());
+        Ok(())
+    }
+
+    /// Returns the address the server is listening on, if it's started.
+    pub fn listening_addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    pub fn stop(&mut self) {
+        let h = match self.handle.take() {
+            None => return,
+            Some(h) => h,
+        };
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake up the blocking accept.
+        if let Err(e) = TcpStream::connect(self.addr.unwrap()) {
+            error!("failed to wake up status server: {:?}", e);
+            return;
+        }
+        if let Err(e) = h.join() {
+            error!("join status server failed {:?}", e);
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    pd_scheduler: &FutureScheduler<PdTask>,
+    timer: &Timer,
+) -> Box<Future<Item = (), Error = ()> + Send> {
+    let timeout = Some(Duration::from_secs(IO_TIMEOUT_SECS));
+    if let Err(e) = stream
+        .set_read_timeout(timeout)
+        .and_then(|_| stream.set_write_timeout(timeout))
+    {
+        warn!("status server failed to set timeout: {:?}", e);
+        return box future::ok(());
+    }
+    let resp = match read_request_line(&mut stream) {
+        Ok(line) => handle_request(&line, pd_scheduler, timer),
+        Err(e) => {
+            debug!("status server failed to read request: {:?}", e);
+            box future::ok((400, "bad request".to_owned()))
+        }
+    };
+    box resp.map(move |(status, body)| {
+        if let Err(e) = write_response(&mut stream, status, &body) {
+            debug!("status server failed to write response: {:?}", e);
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    })
+}
+
+// Reads the request header, and returns its first line.
+fn read_request_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_HEADER_SIZE {
+            return Err(io::Error::new(io::ErrorKind::Other, "request header is too large"));
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let header = String::from_utf8_lossy(&buf);
+    Ok(header.lines().next().unwrap_or_default().to_owned())
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let content_type = if status == 200 {
+        "application/json"
+    } else {
+        "text/plain"
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn handle_request(
+    request_line: &str,
+    pd_scheduler: &FutureScheduler<PdTask>,
+    timer: &Timer,
+) -> ResponseFuture {
+    match route_request(request_line) {
+        Ok((flow_type, limit)) => get_hot_regions(pd_scheduler, timer, flow_type, limit),
+        Err(resp) => box future::ok(resp),
+    }
+}
+
+// Returns the parameters of the hot regions request, or the response of an invalid request.
+fn route_request(request_line: &str) -> ::std::result::Result<(FlowType, usize), (u16, String)> {
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return Err((400, "bad request".to_owned())),
+    };
+    let (path, query) = match target.find('?') {
+        Some(pos) => (&target[..pos], &target[pos + 1..]),
+        None => (target, ""),
+    };
+    if path != "/regions/hot" {
+        return Err((404, format!("{} not found", path)));
+    }
+    if method != "GET" {
+        return Err((405, format!("{} is not allowed", method)));
+    }
+    parse_hot_regions_query(query).map_err(|e| (400, e))
+}
+
+fn parse_hot_regions_query(query: &str) -> ::std::result::Result<(FlowType, usize), String> {
+    let mut flow_type = FlowType::Read;
+    let mut limit = DEFAULT_HOT_REGIONS_LIMIT;
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "type" => {
+                flow_type = match &*value {
+                    "read" => FlowType::Read,
+                    "write" => FlowType::Write,
+                    _ => return Err(format!("invalid type {:?}", value)),
+                }
+            }
+            "limit" => {
+                limit = value
+                    .parse()
+                    .map_err(|_| format!("invalid limit {:?}", value))?
+            }
+            _ => return Err(format!("unknown parameter {:?}", key)),
+        }
+    }
+    Ok((flow_type, limit))
+}
+
+fn get_hot_regions(
+    pd_scheduler: &FutureScheduler<PdTask>,
+    timer: &Timer,
+    flow_type: FlowType,
+    limit: usize,
+) -> ResponseFuture {
+    let (tx, rx) = oneshot::channel();
+    let task = PdTask::GetHotRegions {
+        flow_type: flow_type,
+        limit: limit,
+        callback: Box::new(move |regions: Vec<HotRegion>| {
+            let _ = tx.send(regions);
+        }),
+    };
+    if let Err(e) = pd_scheduler.schedule(task) {
+        return box future::ok((500, format!("failed to schedule pd task: {:?}", e)));
+    }
+    let timeout = timer.sleep(Duration::from_secs(IO_TIMEOUT_SECS));
+    box rx.select2(timeout).then(|res| {
+        let resp = match res {
+            Ok(Either::A((regions, _))) => match serde_json::to_string(&regions) {
+                Ok(body) => (200, body),
+                Err(e) => (500, format!("failed to encode hot regions: {:?}", e)),
+            },
+            Ok(Either::B(_)) => (500, "get hot regions timeout".to_owned()),
+            Err(Either::A((e, _))) => (500, format!("failed to get hot regions: {:?}", e)),
+            Err(Either::B((e, _))) => (500, format!("failed to get hot regions: {:?}", e)),
+        };
+        Ok::<_, ()>(resp)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pd::FlowType;
+    use super::*;
+
+    #[test]
+    fn test_parse_hot_regions_query() {
+        assert_eq!(
+            parse_hot_regions_query("").unwrap(),
+            (FlowType::Read, DEFAULT_HOT_REGIONS_LIMIT)
+        );
+        assert_eq!(
+            parse_hot_regions_query("type=write&limit=3").unwrap(),
+            (FlowType::Write, 3)
+        );
+        assert!(parse_hot_regions_query("type=scan").is_err());
+        assert!(parse_hot_regions_query("limit=-1").is_err());
+        assert!(parse_hot_regions_query("foo=bar").is_err());
+    }
+
+    #[test]
+    fn test_route_request() {
+        assert_eq!(
+            route_request("GET /regions/hot?type=write HTTP/1.1").unwrap(),
+            (FlowType::Write, DEFAULT_HOT_REGIONS_LIMIT)
+        );
+        let status = |line: &str| route_request(line).unwrap_err().0;
+        assert_eq!(status("GET"), 400);
+        assert_eq!(status("GET /regions HTTP/1.1"), 404);
+        assert_eq!(status("POST /regions/hot HTTP/1.1"), 405);
+        assert_eq!(status("GET /regions/hot?limit=x HTTP/1.1"), 400);
+    }
+}
@@ -16,12 +16,12 @@ use std::sync::mpsc::Sender;
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::raft_cmdpb::RaftCmdRequest;
 
-use util::transport::SendCh;
 use util::HandyRwLock;
 use util::worker::{Scheduler, Stopped};
 use util::collections::HashSet;
 use raft::SnapshotStatus;
-use raftstore::store::{BatchCallback, Callback, Msg as StoreMsg, SignificantMsg, Transport};
+use raftstore::store::{BatchCallback, Callback, Msg as StoreMsg, RouterSendCh, SignificantMsg,
+                       Transport};
 use raftstore::Result as RaftStoreResult;
 use server::raft_client::RaftClient;
 use server::Result;
@@ -83,13 +83,13 @@ pub trait RaftStoreRouter: Send + Clone {
 
 #[derive(Clone)]
 pub struct ServerRaftStoreRouter {
-    pub ch: SendCh<StoreMsg>,
+    pub ch: RouterSendCh,
     pub significant_msg_sender: Sender<SignificantMsg>,
 }
 
 impl ServerRaftStoreRouter {
     pub fn new(
-        ch: SendCh<StoreMsg>,
+        ch: RouterSendCh,
         significant_msg_sender: Sender<SignificantMsg>,
     ) -> ServerRaftStoreRouter {
         ServerRaftStoreRouter {
@@ -19,9 +19,13 @@ use std::error;
 use std::sync::{Arc, Mutex};
 use std::io::Error as IoError;
 use std::u64;
+use rand::{self, Rng};
 use kvproto::kvrpcpb::{CommandPri, LockInfo};
 use kvproto::errorpb;
 use util::collections::HashMap;
+use util::worker::FutureScheduler;
+use pd::PdTask;
+use raftstore::errors::DISK_IS_FULL;
 use self::metrics::*;
 
 pub mod engine;
@@ -424,6 +428,27 @@ impl Command {
         }
     }
 
+    /// Returns a key touched by the command, it's used to sample the load of regions.
+    /// For commands on many keys, one of them is chosen uniformly at random so that
+    /// every key has the same chance to be sampled.
+    pub fn sample_key(&self) -> Option<&Key> {
+        match *self {
+            Command::Get { ref key, .. } |
+            Command::Cleanup { ref key, .. } |
+            Command::RawGet { ref key, .. } => Some(key),
+            Command::Scan { ref start_key, .. } | Command::RawScan { ref start_key, .. } => {
+                Some(start_key)
+            }
+            Command::BatchGet { ref keys, .. } |
+            Command::Commit { ref keys, .. } |
+            Command::Rollback { ref keys, .. } => rand::thread_rng().choose(keys),
+            Command::Prewrite { ref mutations, .. } => {
+                rand::thread_rng().choose(mutations).map(|m| m.key())
+            }
+            _ => None,
+        }
+    }
+
     pub fn write_bytes(&self) -> usize {
         let mut bytes = 0;
         match *self {
@@ -487,6 +512,9 @@ pub struct Storage {
     // Storage configurations.
     gc_ratio_threshold: f64,
     max_key_size: usize,
+
+    // Used to report the load of regions to pd worker.
+    pd_sender: Option<FutureScheduler<PdTask>>,
 }
 
 impl Storage {
@@ -504,6 +532,7 @@ impl Storage {
             })),
             gc_ratio_threshold: config.gc_ratio_threshold,
             max_key_size: config.max_key_size,
+            pd_sender: None,
         })
     }
 
@@ -512,6 +541,12 @@ impl Storage {
         Storage::from_engine(engine, config)
     }
 
+    /// Set the pd worker scheduler, the load of regions will be reported to
+    /// it for load-based split. It must be set before `start`.
+    pub fn set_pd_sender(&mut self, pd_sender: FutureScheduler<PdTask>) {
+        self.pd_sender = Some(pd_sender);
+    }
+
     pub fn start(&mut self, config: &Config) -> Result<()> {
         let mut handle = self.handle.lock().unwrap();
         if handle.handle.is_some() {
@@ -525,6 +560,7 @@ impl Storage {
         let sched_worker_pool_size = config.scheduler_worker_pool_size;
         let sched_pending_write_threshold = config.scheduler_pending_write_threshold.0 as usize;
         let ch = self.sendch.clone();
+        let pd_sender = self.pd_sender.clone();
         let h = builder.spawn(move || {
             let mut sched = Scheduler::new(
                 engine,
@@ -532,6 +568,7 @@ impl Storage {
                 sched_concurrency,
                 sched_worker_pool_size,
                 sched_pending_write_threshold,
+                pd_sender,
             );
             if let Err(e) = sched.run(rx) {
                 panic!("scheduler run err:{:?}", e);
@@ -917,6 +954,7 @@ impl Clone for Storage {
             handle: self.handle.clone(),
             gc_ratio_threshold: self.gc_ratio_threshold,
             max_key_size: self.max_key_size,
+            pd_sender: self.pd_sender.clone(),
         }
     }
 }
@@ -975,6 +1013,8 @@ pub fn get_tag_from_header(header: &errorpb::Error) -> &'static str {
         "stale_epoch"
     } else if header.has_server_is_busy() {
         "server_is_busy"
+    } else if header.get_message().starts_with(DISK_IS_FULL) {
+        "disk_full"
     } else {
         "other"
     }
@@ -52,9 +52,12 @@ use storage::engine::{self, Callback as EngineCallback, CbContext, Error as Engi
                       Result as EngineResult};
 use raftstore::store::engine::IterOption;
 use util::transport::{Error as TransportError, SyncSendCh};
-use util::threadpool::{Context as ThreadContext, ThreadPool, ThreadPoolBuilder};
+use util::threadpool::{Context as ThreadContext, ContextFactory, ThreadPool, ThreadPoolBuilder};
 use util::time::SlowTimer;
 use util::collections::HashMap;
+use util::worker::FutureScheduler;
+use pd::{self, PdTask, RegionLoads, RegionReadFlows};
+use pd::split_controller;
 
 use super::Result;
 use super::Error;
@@ -375,6 +378,7 @@ impl Scheduler {
         concurrency: usize,
         worker_pool_size: usize,
         sched_pending_write_threshold: usize,
+        pd_sender: Option<FutureScheduler<PdTask>>,
     ) -> Scheduler {
         Scheduler {
             engine: engine,
@@ -387,11 +391,18 @@ impl Scheduler {
             id_alloc: 0,
             latches: Latches::new(concurrency),
             sched_pending_write_threshold: sched_pending_write_threshold,
-            worker_pool: ThreadPoolBuilder::with_default_factory(thd_name!("sched-worker-pool"))
-                .thread_count(worker_pool_size)
+            worker_pool: ThreadPoolBuilder::new(
+                thd_name!("sched-worker-pool"),
+                SchedContextFactory {
+                    pd_sender: pd_sender.clone(),
+                },
+            ).thread_count(worker_pool_size)
                 .build(),
-            high_priority_pool: ThreadPoolBuilder::with_default_factory(
+            high_priority_pool: ThreadPoolBuilder::new(
                 thd_name!("sched-high-pri-pool"),
+                SchedContextFactory {
+                    pd_sender: pd_sender,
+                },
             ).build(),
             has_gc_command: false,
             running_write_bytes: 0,
@@ -1001,27 +1012,53 @@ fn process_write_impl(
     Ok(())
 }
 
-struct SchedContext {
-    stats: HashMap<&'static str, StatisticsSummary>,
-    processing_read_duration: LocalHistogramVec,
-    processing_write_duration: LocalHistogramVec,
+struct SchedContextFactory {
+    pd_sender: Option<FutureScheduler<PdTask>>,
 }
 
-impl Default for SchedContext {
-    fn default() -> SchedContext {
+impl ContextFactory<SchedContext> for SchedContextFactory {
+    fn create(&self) -> SchedContext {
         SchedContext {
             stats: HashMap::default(),
+            loads: HashMap::default(),
+            read_flows: HashMap::default(),
+            pd_sender: self.pd_sender.clone(),
             processing_read_duration: SCHED_PROCESSING_READ_HISTOGRAM_VEC.local(),
             processing_write_duration: SCHED_PROCESSING_WRITE_HISTOGRAM_VEC.local(),
         }
     }
 }
 
+struct SchedContext {
+    stats: HashMap<&'static str, StatisticsSummary>,
+    loads: RegionLoads,
+    read_flows: RegionReadFlows,
+    pd_sender: Option<FutureScheduler<PdTask>>,
+    processing_read_duration: LocalHistogramVec,
+    processing_write_duration: LocalHistogramVec,
+}
+
 impl SchedContext {
     fn add_statistics(&mut self, cmd_tag: &'static str, stat: &Statistics) {
         let entry = self.stats.entry(cmd_tag).or_insert_with(Default::default);
         entry.add_statistics(stat);
     }
+
+    fn add_load(&mut self, region_id: u64, key: Option<Vec<u8>>) {
+        if self.pd_sender.is_none() {
+            return;
+        }
+        if let Some(key) = key {
+            split_controller::record_load(&mut self.loads, region_id, &key);
+        }
+    }
+
+    fn add_read_flow(&mut self, region_id: u64, stat: &Statistics) {
+        if self.pd_sender.is_none() {
+            return;
+        }
+        pd::record_read_flow(&mut self.read_flows, region_id, stat);
+    }
 }
 
 impl ThreadContext for SchedContext {
@@ -1038,6 +1075,25 @@ impl ThreadContext for SchedContext {
         }
         self.processing_read_duration.flush();
         self.processing_write_duration.flush();
+
+        if let Some(ref pd_sender) = self.pd_sender {
+            if !self.loads.is_empty() {
+                let mut loads = HashMap::default();
+                mem::swap(&mut loads, &mut self.loads);
+                if let Err(e) = pd_sender.schedule(PdTask::LoadStats { loads: loads }) {
+                    error!("send scheduler load statistics: {:?}", e);
+                }
+            }
+            if !self.read_flows.is_empty() {
+                let mut read_flows = HashMap::default();
+                mem::swap(&mut read_flows, &mut self.read_flows);
+                if let Err(e) = pd_sender.schedule(PdTask::ReadStats {
+                    read_stats: read_flows,
+                }) {
+                    error!("send scheduler read statistics: {:?}", e);
+                }
+            }
+        }
     }
 }
 
@@ -1111,6 +1167,8 @@ impl Scheduler {
         let readcmd = cmd.readonly();
         let worker_pool = self.fetch_worker_pool(cmd.priority());
         let tag = cmd.tag();
+        let region_id = cmd.get_context().get_region_id();
+        let sample_key = cmd.sample_key().map(|k| k.encoded().clone());
         if readcmd {
             worker_pool.execute(move |ctx: &mut SchedContext| {
                 let _processing_read_timer = ctx.processing_read_duration
@@ -1119,6 +1177,8 @@ impl Scheduler {
 
                 let s = process_read(cid, cmd, ch, snapshot);
                 ctx.add_statistics(tag, &s);
+                ctx.add_read_flow(region_id, &s);
+                ctx.add_load(region_id, sample_key);
             });
         } else {
             worker_pool.execute(move |ctx: &mut SchedContext| {
@@ -1128,6 +1188,7 @@ impl Scheduler {
 
                 let s = process_write(cid, cmd, ch, snapshot);
                 ctx.add_statistics(tag, &s);
+                ctx.add_load(region_id, sample_key);
             });
         }
     }
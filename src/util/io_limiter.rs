@@ -42,6 +42,17 @@ impl IOLimiter {
         self.inner.request(bytes, PRIORITY_HIGH)
     }
 
+    /// Requests `bytes` piece by piece, so it can be larger than the max bytes per time.
+    pub fn request_in_batches(&self, bytes: i64) {
+        let single = self.get_max_bytes_per_time();
+        let mut remain = bytes;
+        while remain > 0 {
+            let curr = if remain > single { single } else { remain };
+            self.request(curr);
+            remain -= curr;
+        }
+    }
+
     pub fn get_max_bytes_per_time(&self) -> i64 {
         if self.inner.get_singleburst_bytes() > SNAP_MAX_BYTES_PER_TIME {
             SNAP_MAX_BYTES_PER_TIME
@@ -128,6 +139,14 @@ mod test {
         assert_eq!(limiter.get_total_bytes_through(), 1024 * 1024);
 
         assert_eq!(limiter.get_total_requests(), 1);
+
+        let single = limiter.get_max_bytes_per_time();
+        limiter.request_in_batches(single * 2 + 1);
+        assert_eq!(
+            limiter.get_total_bytes_through(),
+            1024 * 1024 + single * 2 + 1
+        );
+        assert_eq!(limiter.get_total_requests(), 4);
     }
 
     #[test]
@@ -0,0 +1,97 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::i32;
+use std::io::{self, ErrorKind};
+
+use libc::{c_char, c_int};
+use lz4_sys;
+
+/// Compresses `data` into a lz4 block. The size of `data` is not recorded,
+/// the caller should keep it for decompression.
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() > i32::MAX as usize {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("data of {} bytes is too large to compress", data.len()),
+        ));
+    }
+    unsafe {
+        let bound = lz4_sys::LZ4_compressBound(data.len() as c_int);
+        let mut buf: Vec<u8> = Vec::with_capacity(bound as usize);
+        let size = lz4_sys::LZ4_compress_default(
+            data.as_ptr() as *const c_char,
+            buf.as_mut_ptr() as *mut c_char,
+            data.len() as c_int,
+            bound,
+        );
+        if size <= 0 {
+            return Err(io::Error::new(ErrorKind::Other, "lz4 compress failed"));
+        }
+        buf.set_len(size as usize);
+        Ok(buf)
+    }
+}
+
+/// Decompresses a lz4 block whose original size is `size`.
+pub fn decompress(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    if data.len() > i32::MAX as usize || size > i32::MAX as usize {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("lz4 block of {} bytes to {} bytes is too large", data.len(), size),
+        ));
+    }
+    unsafe {
+        let mut buf: Vec<u8> = Vec::with_capacity(size);
+        let n = lz4_sys::LZ4_decompress_safe(
+            data.as_ptr() as *const c_char,
+            buf.as_mut_ptr() as *mut c_char,
+            data.len() as c_int,
+            size as c_int,
+        );
+        if n < 0 || n as usize != size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("lz4 decompress failed, expect {} bytes, got {}", size, n),
+            ));
+        }
+        buf.set_len(size);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz4() {
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            b"a".to_vec(),
+            b"abcdefgh".iter().cycle().take(8192).cloned().collect(),
+            (0..4096).map(|i| (i * 7 % 251) as u8).collect(),
+        ];
+        for data in cases {
+            let compressed = compress(&data).unwrap();
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+            if !data.is_empty() {
+                assert!(decompress(&compressed, data.len() + 1).is_err());
+            }
+        }
+        assert!(decompress(b"invalid", 100).is_err());
+
+        let data: Vec<u8> = b"abcdefgh".iter().cycle().take(8192).cloned().collect();
+        assert!(compress(&data).unwrap().len() < data.len() / 10);
+    }
+}
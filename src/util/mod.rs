@@ -43,6 +43,7 @@ pub mod threadpool;
 pub mod collections;
 pub mod time;
 pub mod io_limiter;
+pub mod lz4;
 pub mod security;
 
 pub use self::rocksdb::properties;
@@ -20,10 +20,12 @@ pub use self::event_listener::EventListener;
 pub use self::metrics_flusher::MetricsFlusher;
 
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::sync::Arc;
 use std::str::FromStr;
 
+use fs2;
 use storage::{ALL_CFS, CF_DEFAULT, CF_LOCK};
 use rocksdb::{ColumnFamilyOptions, CompactOptions, DBCompressionType, DBOptions, ReadOptions,
               SliceTransform, Writable, WriteBatch, DB};
@@ -194,6 +196,44 @@ pub fn db_exist(path: &str) -> bool {
     fs::read_dir(&path).unwrap().next().is_some()
 }
 
+pub struct DiskStats {
+    pub capacity: u64,
+    pub used_size: u64,
+    pub available: u64,
+}
+
+/// Gets the disk stats of the engine. `capacity` 0 means using the whole disk, and
+/// `extra_used` is the size used outside of the engine, like snapshot files.
+pub fn get_disk_stats(engine: Arc<DB>, capacity: u64, extra_used: u64) -> io::Result<DiskStats> {
+    let disk_stats = fs2::statvfs(engine.path())?;
+    let disk_cap = disk_stats.total_space();
+    let capacity = if capacity == 0 || disk_cap < capacity {
+        disk_cap
+    } else {
+        capacity
+    };
+
+    let used_size = extra_used + get_engine_used_size(engine);
+    let mut available = if capacity > used_size {
+        capacity - used_size
+    } else {
+        warn!("no available space");
+        0
+    };
+
+    // We only care rocksdb SST file size, so we should
+    // check disk available here.
+    if available > disk_stats.free_space() {
+        available = disk_stats.free_space();
+    }
+
+    Ok(DiskStats {
+        capacity: capacity,
+        used_size: used_size,
+        available: available,
+    })
+}
+
 pub fn get_engine_used_size(engine: Arc<DB>) -> u64 {
     let mut used_size: u64 = 0;
     for cf in ALL_CFS {
@@ -21,6 +21,7 @@ use tikv::pd::Config as PdConfig;
 use tikv::server::Config as ServerConfig;
 use tikv::raftstore::store::Config as RaftstoreConfig;
 use tikv::raftstore::coprocessor::Config as CopConfig;
+use tikv::raftstore::store::log_engine::Config as RaftLogEngineConfig;
 use tikv::config::*;
 use tikv::storage::Config as StorageConfig;
 use tikv::util::config::{ReadableDuration, ReadableSize};
@@ -58,6 +59,7 @@ fn test_serde_custom_tikv_config() {
         addr: "example.com:443".to_owned(),
         labels: map!{ "a".to_owned() => "b".to_owned() },
         advertise_addr: "example.com:443".to_owned(),
+        status_addr: "example.com:8443".to_owned(),
         notify_capacity: 12_345,
         messages_per_tick: 123,
         grpc_concurrency: 123,
@@ -69,6 +71,7 @@ fn test_serde_custom_tikv_config() {
         end_point_stack_size: ReadableSize::mb(12),
         end_point_recursion_limit: 100,
         end_point_batch_row_limit: 64,
+        end_point_enable_batch_exec: true,
         snap_max_write_bytes_per_sec: ReadableSize::mb(10),
     };
     value.metric = MetricConfig {
@@ -78,14 +81,19 @@ fn test_serde_custom_tikv_config() {
     };
     value.raft_store = RaftstoreConfig {
         sync_log: false,
+        async_write_raft_log: true,
         raftdb_path: "/var".to_owned(),
         capacity: ReadableSize(123),
+        min_available_ratio: 0.2,
         raft_base_tick_interval: ReadableDuration::secs(12),
         raft_heartbeat_ticks: 1,
         raft_election_timeout_ticks: 12,
         raft_max_size_per_msg: ReadableSize::mb(12),
         raft_max_inflight_msgs: 123,
         raft_entry_max_size: ReadableSize::mb(12),
+        raft_entry_cache_limit: ReadableSize::mb(12),
+        raft_entry_compression_threshold: ReadableSize::kb(12),
+        raft_proposal_batch_size_limit: ReadableSize::kb(12),
         raft_log_gc_tick_interval: ReadableDuration::secs(12),
         raft_log_gc_threshold: 12,
         raft_log_gc_count_limit: 12,
@@ -99,10 +107,14 @@ fn test_serde_custom_tikv_config() {
         notify_capacity: 12_345,
         snap_mgr_gc_tick_interval: ReadableDuration::minutes(12),
         snap_gc_timeout: ReadableDuration::hours(12),
+        clean_stale_peer_delay: ReadableDuration::minutes(12),
         messages_per_tick: 12_345,
+        store_pool_size: 3,
         max_peer_down_duration: ReadableDuration::minutes(12),
         max_leader_missing_duration: ReadableDuration::hours(12),
         snap_apply_batch_size: ReadableSize::mb(12),
+        snap_generator_pool_size: 3,
+        snap_apply_pool_size: 4,
         lock_cf_compact_interval: ReadableDuration::minutes(12),
         lock_cf_compact_bytes_threshold: ReadableSize::mb(123),
         consistency_check_interval: ReadableDuration::secs(12),
@@ -110,6 +122,10 @@ fn test_serde_custom_tikv_config() {
         raft_store_max_leader_lease: ReadableDuration::secs(12),
         right_derive_when_split: false,
         allow_remove_leader: true,
+        region_split_qps_threshold: 1_234,
+        region_split_qps_detect_times: 12,
+        election_priority_label: "zone".to_owned(),
+        election_priorities: map!{ "z1".to_owned() => 2 },
         region_max_size: ReadableSize(0),
         region_split_size: ReadableSize(0),
     };
@@ -334,6 +350,14 @@ fn test_serde_custom_tikv_config() {
         split_region_on_table: true,
         region_max_size: ReadableSize::mb(12),
         region_split_size: ReadableSize::mb(12),
+        region_max_keys: 100000,
+        region_split_keys: 100000,
+    };
+    value.raft_log_engine = RaftLogEngineConfig {
+        enabled: true,
+        dir: "/var".to_owned(),
+        target_file_size: ReadableSize::mb(64),
+        purge_threshold: ReadableSize::gb(1),
     };
     value.security = SecurityConfig {
         ca_path: "invalid path".to_owned(),
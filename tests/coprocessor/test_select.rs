@@ -1230,6 +1230,43 @@ fn test_aggr_count() {
     end_point.stop().unwrap().join().unwrap();
 }
 
+#[test]
+fn test_stream_aggr() {
+    let data = vec![
+        (1, Some("name:0"), 2),
+        (2, Some("name:3"), 3),
+        (4, Some("name:0"), 1),
+        (5, Some("name:5"), 4),
+        (6, Some("name:5"), 4),
+        (7, None, 4),
+    ];
+
+    let product = ProductTable::new();
+    let (_, mut end_point) = init_with_data(&product, &data);
+
+    // Aggregations without group by items are executed as streams.
+    let req = DAGSelect::from(&product.table)
+        .count()
+        .sum(product.count)
+        .max(product.name)
+        .build();
+    let mut resp = handle_select(&end_point, req);
+    let mut spliter = DAGChunkSpliter::new(resp.take_chunks().into_vec(), 3);
+    let row = spliter.next().unwrap();
+    let expected_datum = vec![
+        Datum::U64(data.len() as u64),
+        Datum::Dec(18.into()),
+        Datum::Bytes(b"name:5".to_vec()),
+    ];
+    assert_eq!(
+        &*datum::encode_value(&row).unwrap(),
+        &*datum::encode_value(&expected_datum).unwrap()
+    );
+    assert!(spliter.next().is_none());
+
+    end_point.stop().unwrap().join().unwrap();
+}
+
 #[test]
 fn test_aggr_first() {
     let data = vec![
@@ -2414,6 +2451,72 @@ fn test_where_for_dag() {
     end_point.stop().unwrap().join().unwrap();
 }
 
+#[test]
+fn test_batch_exec() {
+    let data: Vec<_> = (0i64..200)
+        .map(|i| (i, Some(if i % 3 == 0 { "name:0" } else { "name:1" }), i % 7))
+        .collect();
+    let product = ProductTable::new();
+    let cols = product.table.get_table_columns();
+    let build_reqs = || {
+        let cond = {
+            let mut col = Expr::new();
+            col.set_tp(ExprType::ColumnRef);
+            let count_offset = offset_for_column(&cols, product.count.id);
+            col.mut_val().encode_i64(count_offset).unwrap();
+
+            let mut value = Expr::new();
+            value.set_tp(ExprType::Int64);
+            value.mut_val().encode_i64(5).unwrap();
+
+            let mut cond = Expr::new();
+            cond.set_tp(ExprType::ScalarFunc);
+            cond.set_sig(ScalarFuncSig::LTInt);
+            cond.mut_children().push(col);
+            cond.mut_children().push(value);
+            cond
+        };
+        vec![
+            DAGSelect::from(&product.table).build(),
+            DAGSelect::from(&product.table)
+                .where_expr(cond.clone())
+                .limit(50)
+                .build(),
+            DAGSelect::from(&product.table)
+                .where_expr(cond.clone())
+                .order_by(product.count, true)
+                .limit(10)
+                .build(),
+            DAGSelect::from(&product.table)
+                .where_expr(cond)
+                .count()
+                .sum(product.count)
+                .group_by(&[product.name])
+                .build(),
+            DAGSelect::from_index(&product.table, product.name)
+                .limit(70)
+                .build(),
+        ]
+    };
+
+    let mut responses = vec![];
+    for &enable_batch_exec in &[true, false] {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let mut cfg = Config::default();
+        cfg.end_point_enable_batch_exec = enable_batch_exec;
+        let (_, mut end_point) =
+            init_data_with_details(Context::new(), engine, &product, &data, true, cfg);
+        let chunks: Vec<_> = build_reqs()
+            .into_iter()
+            .map(|req| handle_select(&end_point, req).take_chunks().into_vec())
+            .collect();
+        responses.push(chunks);
+        end_point.stop().unwrap().join().unwrap();
+    }
+    assert!(responses[0].iter().all(|chunks| !chunks.is_empty()));
+    assert_eq!(responses[0], responses[1]);
+}
+
 #[test]
 fn test_handle_truncate() {
     let data = vec![
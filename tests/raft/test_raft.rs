@@ -3429,3 +3429,95 @@ fn test_transfer_non_member() {
         .expect("");;
     assert_eq!(raft.state, StateRole::Follower);
 }
+
+// test_witness_never_becomes_leader verifies that a witness votes for others
+// but never campaigns or accepts leadership transfer.
+#[test]
+fn test_witness_never_becomes_leader() {
+    let mut cfg = new_test_config(1, vec![1, 2, 3], 10, 1);
+    cfg.witness = true;
+    let witness = Interface::new(Raft::new(&cfg, new_storage()));
+    let mut nt = Network::new(vec![Some(witness), None, None]);
+
+    nt.send(vec![new_message(1, 1, MessageType::MsgHup, 0)]);
+    assert_eq!(nt.peers[&1].state, StateRole::Follower);
+    assert!(nt.peers[&1].is_witness());
+
+    nt.send(vec![new_message(2, 2, MessageType::MsgHup, 0)]);
+    assert_eq!(nt.peers[&2].state, StateRole::Leader);
+    assert_eq!(nt.peers[&1].leader_id, 2);
+
+    nt.send(vec![new_message(1, 2, MessageType::MsgTransferLeader, 0)]);
+    assert_eq!(nt.peers[&1].state, StateRole::Follower);
+    assert_eq!(nt.peers[&2].state, StateRole::Leader);
+}
+
+// test_election_delay_rounds verifies that a peer with a lower election priority
+// waits extra election timeouts before campaigning.
+#[test]
+fn test_election_delay_rounds() {
+    let mut cfg = new_test_config(1, vec![1], 10, 1);
+    cfg.election_delay_rounds = 1;
+    let mut sm = Interface::new(Raft::new(&cfg, new_storage()));
+
+    let timeout = sm.get_randomized_election_timeout();
+    assert!(timeout >= 20 && timeout < 30, "timeout {}", timeout);
+
+    for _ in 0..19 {
+        sm.tick();
+    }
+    assert_eq!(sm.state, StateRole::Follower);
+
+    for _ in 19..30 {
+        sm.tick();
+    }
+    assert_eq!(sm.state, StateRole::Leader);
+}
+
+fn new_priority_raft(id: u64, priority: u64, pre_vote: bool) -> Interface {
+    let mut cfg = new_test_config(id, vec![1, 2, 3], 10, 1);
+    cfg.priority = priority;
+    cfg.pre_vote = pre_vote;
+    new_test_raft_with_config(&cfg, new_storage())
+}
+
+fn test_election_priority_with_pre_vote(pre_vote: bool) {
+    let mut nt = Network::new(vec![
+        Some(new_priority_raft(1, 2, pre_vote)),
+        Some(new_priority_raft(2, 1, pre_vote)),
+        Some(new_priority_raft(3, 2, pre_vote)),
+    ]);
+
+    // Peers don't vote for a candidate with a lower priority and the same log.
+    nt.send(vec![new_message(2, 2, MessageType::MsgHup, 0)]);
+    assert_ne!(nt.peers[&2].state, StateRole::Leader);
+
+    nt.send(vec![new_message(1, 1, MessageType::MsgHup, 0)]);
+    assert_eq!(nt.peers[&1].state, StateRole::Leader);
+
+    // Leader transfer isn't limited by priority.
+    nt.send(vec![new_message(2, 1, MessageType::MsgTransferLeader, 0)]);
+    assert_eq!(nt.peers[&2].state, StateRole::Leader);
+    nt.send(vec![new_message(1, 2, MessageType::MsgTransferLeader, 0)]);
+    assert_eq!(nt.peers[&1].state, StateRole::Leader);
+
+    // A candidate with a lower priority is voted if its log is newer.
+    nt.cut(1, 3);
+    nt.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    nt.recover();
+    nt.isolate(1);
+    nt.send(vec![new_message(2, 2, MessageType::MsgHup, 0)]);
+    assert_eq!(nt.peers[&2].state, StateRole::Leader);
+}
+
+// test_election_priority verifies that a peer only votes for a candidate with a
+// lower priority if the candidate's log is newer.
+#[test]
+fn test_election_priority() {
+    test_election_priority_with_pre_vote(false);
+}
+
+#[test]
+fn test_election_priority_pre_vote() {
+    test_election_priority_with_pre_vote(true);
+}
@@ -80,6 +80,8 @@ pub struct Cluster<T: Simulator> {
 
     // node id -> {db, raft_db} engine.
     pub engines: HashMap<u64, Engines>,
+    // node id -> extra server labels of the node.
+    labels: HashMap<u64, HashMap<String, String>>,
 
     pub sim: Arc<RwLock<T>>,
     pub pd_client: Arc<TestPdClient>,
@@ -100,6 +102,7 @@ impl<T: Simulator> Cluster<T> {
             paths: vec![],
             dbs: vec![],
             engines: HashMap::new(),
+            labels: HashMap::new(),
             sim: sim,
             pd_client: pd_client,
         };
@@ -166,12 +169,24 @@ impl<T: Simulator> Cluster<T> {
 
     pub fn run_node(&mut self, node_id: u64) {
         debug!("starting node {}", node_id);
+        let mut cfg = self.cfg.clone();
+        if let Some(labels) = self.labels.get(&node_id) {
+            cfg.server.labels.extend(labels.clone());
+        }
         self.sim
             .wl()
-            .run_node(node_id, self.cfg.clone(), self.engines[&node_id].clone());
+            .run_node(node_id, cfg, self.engines[&node_id].clone());
         debug!("node {} started", node_id);
     }
 
+    // Add a server label to the node, it takes effect when the node is started.
+    pub fn add_label(&mut self, node_id: u64, key: &str, value: &str) {
+        self.labels
+            .entry(node_id)
+            .or_insert_with(HashMap::new)
+            .insert(key.to_owned(), value.to_owned());
+    }
+
     pub fn stop_node(&mut self, node_id: u64) {
         debug!("stopping node {}", node_id);
         self.sim.wl().stop_node(node_id);
@@ -155,13 +155,13 @@ impl Simulator for NodeCluster {
     fn run_node(&mut self, node_id: u64, cfg: TiKvConfig, engines: Engines) -> u64 {
         assert!(node_id == 0 || !self.nodes.contains_key(&node_id));
 
-        let mut event_loop = create_event_loop(&cfg.raft_store).unwrap();
+        let event_loops = create_event_loops(&cfg.raft_store).unwrap();
         let (snap_status_sender, snap_status_receiver) = mpsc::channel();
         let pd_worker = FutureWorker::new("test-pd-worker");
 
         let simulate_trans = SimulateTransport::new(self.trans.clone());
         let mut node = Node::new(
-            &mut event_loop,
+            &event_loops,
             &cfg.server,
             &cfg.raft_store,
             self.pd_client.clone(),
@@ -180,10 +180,10 @@ impl Simulator for NodeCluster {
             };
 
         // Create coprocessor.
-        let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_sendch());
+        let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_router());
 
         node.start(
-            event_loop,
+            event_loops,
             engines.clone(),
             simulate_trans.clone(),
             snap_mgr.clone(),
@@ -213,7 +213,7 @@ impl Simulator for NodeCluster {
         }
 
         let node_id = node.id();
-        let router = ServerRaftStoreRouter::new(node.get_sendch(), snap_status_sender.clone());
+        let router = ServerRaftStoreRouter::new(node.get_router(), snap_status_sender.clone());
         self.trans
             .wl()
             .routers
@@ -107,10 +107,11 @@ impl Simulator for ServerCluster {
         }
 
         // Initialize raftstore channels.
-        let mut event_loop = store::create_event_loop(&cfg.raft_store).unwrap();
-        let store_sendch = SendCh::new(event_loop.channel(), "raftstore");
+        let event_loops = store::create_event_loops(&cfg.raft_store).unwrap();
+        let store_sendch = SendCh::new(event_loops[0].channel(), "raftstore");
         let (snap_status_sender, snap_status_receiver) = mpsc::channel();
-        let raft_router = ServerRaftStoreRouter::new(store_sendch.clone(), snap_status_sender);
+        let raft_router =
+            ServerRaftStoreRouter::new(store::create_router(&event_loops), snap_status_sender);
         let sim_router = SimulateTransport::new(raft_router);
 
         // Create storage.
@@ -145,17 +146,17 @@ impl Simulator for ServerCluster {
 
         // Create node.
         let mut node = Node::new(
-            &mut event_loop,
+            &event_loops,
             &cfg.server,
             &cfg.raft_store,
             self.pd_client.clone(),
         );
 
         // Create coprocessor.
-        let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_sendch());
+        let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_router());
 
         node.start(
-            event_loop,
+            event_loops,
             engines,
             simulate_trans.clone(),
             snap_mgr.clone(),
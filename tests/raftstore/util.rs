@@ -95,6 +95,9 @@ pub fn new_store_cfg() -> Config {
         report_region_flow_interval: ReadableDuration::millis(100),
         raft_store_max_leader_lease: ReadableDuration::millis(MAX_LEADER_LEASE),
         allow_remove_leader: true,
+        clean_stale_peer_delay: ReadableDuration::secs(0),
+        // Disk space of testing machines is unpredictable.
+        min_available_ratio: 0.0,
         ..Config::default()
     }
 }
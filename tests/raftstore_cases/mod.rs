@@ -30,5 +30,7 @@ mod test_stale_peer;
 mod test_lease_read;
 mod test_bootstrap;
 mod test_service;
+mod test_store_pool;
+mod test_witness;
 
 use raftstore::*;
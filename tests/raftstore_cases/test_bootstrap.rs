@@ -13,7 +13,7 @@
 
 use std::sync::{mpsc, Arc};
 use std::path::Path;
-use tikv::raftstore::store::{bootstrap_store, create_event_loop, keys, Engines, Peekable,
+use tikv::raftstore::store::{bootstrap_store, create_event_loops, keys, Engines, Peekable,
                              SnapManager};
 use tikv::server::Node;
 use tikv::storage::{ALL_CFS, CF_RAFT};
@@ -50,7 +50,7 @@ fn test_node_bootstrap_with_prepared_data() {
     let pd_client = Arc::new(TestPdClient::new(0));
     let cfg = new_tikv_config(0);
 
-    let mut event_loop = create_event_loop(&cfg.raft_store).unwrap();
+    let event_loops = create_event_loops(&cfg.raft_store).unwrap();
     let simulate_trans = SimulateTransport::new(ChannelTransport::new());
     let tmp_path = TempDir::new("test_cluster").unwrap();
     let engine = Arc::new(
@@ -64,7 +64,7 @@ fn test_node_bootstrap_with_prepared_data() {
     let tmp_mgr = TempDir::new("test_cluster").unwrap();
 
     let mut node = Node::new(
-        &mut event_loop,
+        &event_loops,
         &cfg.server,
         &cfg.raft_store,
         pd_client.clone(),
@@ -100,11 +100,11 @@ fn test_node_bootstrap_with_prepared_data() {
     );
 
     // Create coprocessor.
-    let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_sendch());
+    let coprocessor_host = CoprocessorHost::new(cfg.coprocessor, node.get_router());
 
     // try to restart this node, will clear the prepare data
     node.start(
-        event_loop,
+        event_loops,
         engines,
         simulate_trans,
         snap_mgr,
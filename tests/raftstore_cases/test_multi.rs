@@ -795,3 +795,104 @@ fn test_server_batch_write() {
     let mut cluster = new_server_cluster(0, 3);
     test_batch_write(&mut cluster);
 }
+
+#[test]
+fn test_node_async_write_raft_log() {
+    let count = 5;
+    let mut cluster = new_node_cluster(0, count);
+    cluster.cfg.raft_store.async_write_raft_log = true;
+    test_multi_base(&mut cluster);
+}
+
+#[test]
+fn test_node_async_write_raft_log_random_restart() {
+    let count = 5;
+    let mut cluster = new_node_cluster(0, count);
+    cluster.cfg.raft_store.async_write_raft_log = true;
+    test_multi_random_restart(&mut cluster, count, 10);
+}
+
+fn test_compress_raft_entry<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.cfg.raft_store.raft_entry_compression_threshold = ReadableSize::kb(1);
+    cluster.run();
+
+    let (key, value) = (b"k1", vec![b'v'; 64 * 1024]);
+    cluster.must_put(key, &value);
+    for engines in cluster.engines.values() {
+        must_get_equal(&engines.kv_engine, key, &value);
+    }
+
+    // small entries are not compressed.
+    cluster.must_put(b"k2", b"v2");
+    for engines in cluster.engines.values() {
+        must_get_equal(&engines.kv_engine, b"k2", b"v2");
+    }
+}
+
+#[test]
+fn test_node_compress_raft_entry() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_compress_raft_entry(&mut cluster);
+}
+
+#[test]
+fn test_server_compress_raft_entry() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_compress_raft_entry(&mut cluster);
+}
+
+fn test_batch_proposals<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.run();
+    cluster.must_put(b"k0", b"v0");
+
+    let region = cluster.get_region(b"");
+    let leader = cluster.leader_of_region(region.get_id()).unwrap();
+    let ch = cluster
+        .sim
+        .rl()
+        .get_store_sendch(leader.get_store_id())
+        .unwrap();
+
+    // Send the commands without waiting so that they can be batched.
+    let count = 20;
+    let (tx, rx) = mpsc::channel();
+    for i in 0..count {
+        let key = format!("k{}", i);
+        let mut req = new_request(
+            region.get_id(),
+            region.get_region_epoch().clone(),
+            vec![new_put_cmd(key.as_bytes(), b"v1")],
+            false,
+        );
+        req.mut_header().set_peer(leader.clone());
+        req.mut_header().set_uuid(key.clone().into_bytes());
+        let tx = tx.clone();
+        let cb = Box::new(move |resp: RaftCmdResponse| tx.send((key, resp)).unwrap());
+        ch.try_send(Msg::new_raft_cmd(req, cb)).unwrap();
+    }
+
+    for _ in 0..count {
+        let (key, resp) = rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert!(!resp.get_header().has_error(), "{:?}", resp);
+        assert_eq!(resp.get_header().get_uuid(), key.as_bytes());
+        assert_eq!(resp.get_responses().len(), 1);
+    }
+    for i in 0..count {
+        let key = format!("k{}", i);
+        for engines in cluster.engines.values() {
+            must_get_equal(&engines.kv_engine, key.as_bytes(), b"v1");
+        }
+    }
+}
+
+#[test]
+fn test_node_batch_proposals() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_batch_proposals(&mut cluster);
+}
+
+#[test]
+fn test_server_batch_proposals() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_batch_proposals(&mut cluster);
+}
@@ -0,0 +1,102 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use super::cluster::{Cluster, Simulator};
+use super::node::new_node_cluster;
+use super::server::new_server_cluster;
+use super::util::*;
+
+const POOL_SIZE: usize = 3;
+
+fn test_store_pool<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.cfg.raft_store.store_pool_size = POOL_SIZE;
+    cluster.run();
+    cluster.must_put(b"k0", b"v0");
+
+    // Split regions are created by the pollers owning them, which are
+    // usually not the owner of the parent region.
+    let keys: Vec<_> = (1..7).map(|i| format!("k{}", i).into_bytes()).collect();
+    for key in &keys {
+        let region = cluster.get_region(key);
+        cluster.must_split(&region, key);
+    }
+    let mut region_ids = vec![];
+    for key in &keys {
+        let region_id = cluster.get_region_id(key);
+        assert!(!region_ids.contains(&region_id));
+        region_ids.push(region_id);
+        cluster.must_put(key, b"v1");
+        assert_eq!(cluster.get(key), Some(b"v1".to_vec()));
+    }
+    for id in 1..4 {
+        for key in &keys {
+            must_get_equal(&cluster.get_engine(id), key, b"v1");
+        }
+    }
+
+    // Leaders of the regions owned by different pollers are moved to the
+    // same store.
+    for key in &keys {
+        let region = cluster.get_region(key);
+        let peer = region
+            .get_peers()
+            .iter()
+            .find(|p| p.get_store_id() == 2)
+            .unwrap()
+            .clone();
+        cluster.must_transfer_leader(region.get_id(), peer);
+    }
+    for key in &keys {
+        cluster.must_put(key, b"v2");
+    }
+
+    // Every poller loads the regions it owns after restart.
+    cluster.stop_node(2);
+    cluster.run_node(2);
+    for key in &keys {
+        cluster.must_put(key, b"v3");
+    }
+    for key in &keys {
+        must_get_equal(&cluster.get_engine(2), key, b"v3");
+    }
+
+    // Commands sent to the store are received by the owners of the regions.
+    for (key, region_id) in keys.iter().zip(region_ids) {
+        let leader = cluster.leader_of_region(region_id).unwrap();
+        let req = new_request(
+            region_id,
+            cluster.get_region_epoch(region_id),
+            vec![new_get_cmd(key)],
+            false,
+        );
+        let resp = cluster
+            .call_command_on_node(leader.get_store_id(), req, Duration::from_secs(3))
+            .unwrap();
+        assert!(!resp.get_header().has_error(), "{:?}", resp);
+        assert_eq!(resp.get_responses()[0].get_get().get_value(), b"v3");
+    }
+}
+
+#[test]
+fn test_node_store_pool() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_store_pool(&mut cluster);
+}
+
+#[test]
+fn test_server_store_pool() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_store_pool(&mut cluster);
+}
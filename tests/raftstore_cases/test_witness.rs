@@ -0,0 +1,74 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::thread;
+use std::time::Duration;
+
+use tikv::raftstore::store::util::{find_peer, WITNESS_LABEL_KEY};
+use tikv::util::config::*;
+
+use super::cluster::{Cluster, Simulator};
+use super::node::new_node_cluster;
+use super::server::new_server_cluster;
+use super::util::*;
+
+fn test_witness<T: Simulator>(cluster: &mut Cluster<T>) {
+    // Make sure the consistency check runs during the test.
+    cluster.cfg.raft_store.consistency_check_interval = ReadableDuration::millis(100);
+    cluster.add_label(3, WITNESS_LABEL_KEY, "true");
+    cluster.run();
+
+    cluster.must_put(b"k1", b"v1");
+    must_get_equal(&cluster.get_engine(1), b"k1", b"v1");
+    must_get_equal(&cluster.get_engine(2), b"k1", b"v1");
+    // The witness stores no data.
+    must_get_none(&cluster.get_engine(3), b"k1");
+
+    // The witness never becomes leader.
+    let region = cluster.get_region(b"k1");
+    let region_id = region.get_id();
+    let witness = find_peer(&region, 3).unwrap().clone();
+    cluster.transfer_leader(region_id, witness.clone());
+    thread::sleep(Duration::from_millis(500));
+    cluster.reset_leader_of_region(region_id);
+    assert_ne!(cluster.leader_of_region(region_id), Some(witness.clone()));
+
+    // The consistency check skips the witness.
+    for i in 0..10 {
+        let key = format!("k{}", i + 2).into_bytes();
+        cluster.must_put(&key, b"v2");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // The witness votes, so the region is still available with one replica down.
+    let leader = cluster.leader_of_region(region_id).unwrap();
+    cluster.stop_node(leader.get_store_id());
+    cluster.must_put(b"k1", b"v3");
+    let new_leader = cluster.leader_of_region(region_id).unwrap();
+    assert_ne!(new_leader.get_store_id(), leader.get_store_id());
+    assert_ne!(new_leader, witness);
+    must_get_equal(&cluster.get_engine(new_leader.get_store_id()), b"k1", b"v3");
+    must_get_none(&cluster.get_engine(3), b"k1");
+}
+
+#[test]
+fn test_node_witness() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_witness(&mut cluster);
+}
+
+#[test]
+fn test_server_witness() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_witness(&mut cluster);
+}